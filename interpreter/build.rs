@@ -0,0 +1,23 @@
+use std::process::Command;
+
+// Tells `monkey --version --verbose` which commit it was built from and with which features.
+fn main() {
+    let commit = Command::new("git")
+        .args(["rev-parse", "--short", "HEAD"])
+        .output()
+        .ok()
+        .filter(|output| output.status.success())
+        .and_then(|output| String::from_utf8(output.stdout).ok())
+        .map(|commit| String::from(commit.trim()))
+        .unwrap_or_default();
+    println!("cargo:rustc-env=MONKEY_COMMIT={}", commit);
+
+    let mut features: Vec<String> = std::env::vars()
+        .filter_map(|(name, _)| name.strip_prefix("CARGO_FEATURE_").map(|feature| feature.to_lowercase()))
+        .collect();
+    features.sort();
+    println!("cargo:rustc-env=MONKEY_FEATURES={}", features.join(","));
+
+    println!("cargo:rerun-if-changed=../.git/HEAD");
+    println!("cargo:rerun-if-changed=../.git/refs/heads");
+}
@@ -0,0 +1,217 @@
+use core::fmt;
+use std::rc::Rc;
+
+use crate::interner::Symbol;
+use crate::lexical_analyzer::Span;
+
+#[derive(Clone, PartialEq)]
+pub struct Program {
+    pub statements: Vec<Statement>,
+    // Where each statement starts, for runtime errors to tell where they happened.
+    pub spans: Vec<Span>,
+}
+
+#[derive(Clone, PartialEq)]
+pub struct BlockStatement {
+    pub statements: Vec<Statement>,
+    pub spans: Vec<Span>,
+}
+
+#[derive(Clone, PartialEq)]
+pub enum Statement {
+    Let(Symbol, Expression),
+    Return(Expression),
+    Break,
+    Continue,
+    Expression(Expression),
+}
+
+#[derive(Clone, PartialEq)]
+pub enum Expression {
+    Identifier(Symbol),
+    Integer(i32),
+    Float(f64),
+    Boolean(bool),
+    String(String),
+    Null,
+    Array(Vec<Expression>),
+    Hash(Vec<(Expression, Expression)>),
+    Prefix(PrefixOperator, Box<Expression>),
+    Infix(InfixOperator, Box<Expression>, Box<Expression>),
+    Assign(Symbol, Box<Expression>),
+    If {
+        condition: Box<Expression>,
+        consequence: BlockStatement,
+        alternative: Option<BlockStatement>,
+    },
+    While {
+        condition: Box<Expression>,
+        body: BlockStatement,
+    },
+    For {
+        variables: Vec<Symbol>,
+        iterable: Box<Expression>,
+        body: BlockStatement,
+    },
+    Try {
+        body: BlockStatement,
+        variable: Symbol,
+        handler: BlockStatement,
+    },
+    // The body is shared with the functions made from it, so that hooks see the same statements
+    // whether they run at the top level or in a call.
+    Function {
+        parameters: Vec<Symbol>,
+        body: Rc<BlockStatement>,
+    },
+    // The span is where the callee starts, for stack traces.
+    Call {
+        function: Box<Expression>,
+        arguments: Vec<Expression>,
+        span: Span,
+    },
+    Index(Box<Expression>, Box<Expression>),
+}
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum PrefixOperator {
+    Bang,
+    Minus,
+}
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum InfixOperator {
+    Plus,
+    Minus,
+    Star,
+    Slash,
+    Equals,
+    NotEquals,
+    LessThan,
+    GreaterThan,
+    And,
+    Or,
+    Range,
+}
+
+// Writes a string back in source form, the inverse of the lexer's escape handling.
+pub fn quote_string(data: &str) -> String {
+    let mut quoted = String::from("\"");
+    for character in data.chars() {
+        match character {
+            '"' => quoted.push_str("\\\""),
+            '\\' => quoted.push_str("\\\\"),
+            '\n' => quoted.push_str("\\n"),
+            '\t' => quoted.push_str("\\t"),
+            character => quoted.push(character),
+        }
+    }
+    quoted.push('"');
+    quoted
+}
+
+pub fn join<T: fmt::Display>(items: &[T], separator: &str) -> String {
+    items.iter().map(|item| item.to_string()).collect::<Vec<String>>().join(separator)
+}
+
+impl fmt::Display for Program {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", join(&self.statements, " "))
+    }
+}
+
+impl fmt::Display for BlockStatement {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.statements.is_empty() {
+            return write!(f, "{{ }}");
+        }
+        write!(f, "{{ {} }}", join(&self.statements, " "))
+    }
+}
+
+impl fmt::Display for Statement {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Let(name, value) => write!(f, "let {} = {};", name, value),
+            Self::Return(value) => write!(f, "return {};", value),
+            Self::Break => write!(f, "break;"),
+            Self::Continue => write!(f, "continue;"),
+            Self::Expression(expression) => write!(f, "{}", expression),
+        }
+    }
+}
+
+impl fmt::Display for Expression {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Identifier(name) => write!(f, "{}", name),
+            Self::Integer(value) => write!(f, "{}", value),
+            Self::Float(value) => write!(f, "{:?}", value),
+            Self::Boolean(value) => write!(f, "{}", value),
+            Self::String(value) => write!(f, "{}", quote_string(value)),
+            Self::Null => write!(f, "null"),
+            Self::Array(elements) => write!(f, "[{}]", join(elements, ", ")),
+            Self::Hash(pairs) => {
+                let pairs = pairs.iter().map(|(key, value)| format!("{}: {}", key, value)).collect::<Vec<String>>();
+                write!(f, "{{{}}}", pairs.join(", "))
+            }
+            Self::Prefix(operator, right) => write!(f, "({}{})", operator, right),
+            Self::Infix(operator, left, right) => write!(f, "({} {} {})", left, operator, right),
+            Self::Assign(name, value) => write!(f, "({} = {})", name, value),
+            Self::If { condition, consequence, alternative } => {
+                write!(f, "if {} {}", condition, consequence)?;
+                if let Some(alternative) = alternative {
+                    write!(f, " else {}", alternative)?;
+                }
+                Ok(())
+            }
+            Self::While { condition, body } => write!(f, "while {} {}", condition, body),
+            Self::For { variables, iterable, body } => {
+                write!(f, "for {} in {} {}", join(variables, ", "), iterable, body)
+            }
+            Self::Try { body, variable, handler } => write!(f, "try {} catch ({}) {}", body, variable, handler),
+            Self::Function { parameters, body } => write!(f, "fn({}) {}", join(parameters, ", "), body),
+            Self::Call { function, arguments, .. } => write!(f, "{}({})", function, join(arguments, ", ")),
+            Self::Index(left, index) => write!(f, "({}[{}])", left, index),
+        }
+    }
+}
+
+impl fmt::Display for PrefixOperator {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Bang => write!(f, "!"),
+            Self::Minus => write!(f, "-"),
+        }
+    }
+}
+
+impl fmt::Display for InfixOperator {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Plus => write!(f, "+"),
+            Self::Minus => write!(f, "-"),
+            Self::Star => write!(f, "*"),
+            Self::Slash => write!(f, "/"),
+            Self::Equals => write!(f, "=="),
+            Self::NotEquals => write!(f, "!="),
+            Self::LessThan => write!(f, "<"),
+            Self::GreaterThan => write!(f, ">"),
+            Self::And => write!(f, "&&"),
+            Self::Or => write!(f, "||"),
+            Self::Range => write!(f, ".."),
+        }
+    }
+}
+
+impl fmt::Debug for Program {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self)
+    }
+}
+
+impl fmt::Debug for Expression {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self)
+    }
+}
@@ -0,0 +1,159 @@
+use crate::clock::Clock;
+
+// How often a benchmark ran in the time it was given, and how many allocations it made while
+// doing so.
+#[derive(Debug, PartialEq)]
+pub struct Measurement {
+    pub name: String,
+    pub iterations: u64,
+    pub seconds: f64,
+    pub allocations: u64,
+}
+
+impl Measurement {
+    pub fn per_second(&self) -> f64 {
+        match self.seconds > 0.0 {
+            true => self.iterations as f64 / self.seconds,
+            false => 0.0,
+        }
+    }
+
+    pub fn allocations_per_iteration(&self) -> f64 {
+        self.allocations as f64 / self.iterations.max(1) as f64
+    }
+}
+
+// A measurement saved from an earlier run, to compare new ones against.
+#[derive(Debug, PartialEq)]
+pub struct Baseline {
+    pub name: String,
+    pub per_second: f64,
+    pub allocations_per_iteration: f64,
+}
+
+// Calls `run` once to warm up, then over and over until `seconds` have passed on the clock.
+// `allocations` reads a counter of all allocations made so far. The first error ends it.
+pub fn measure<E>(
+    name: &str,
+    clock: &mut Clock,
+    seconds: f64,
+    allocations: impl Fn() -> u64,
+    mut run: impl FnMut() -> Result<(), E>,
+) -> Result<Measurement, E> {
+    run()?;
+    let (start, allocated) = (clock.now(), allocations());
+    let mut iterations = 0;
+    let mut now = start;
+    while iterations == 0 || now - start < seconds {
+        run()?;
+        iterations += 1;
+        now = clock.now();
+    }
+    Ok(Measurement {
+        name: String::from(name),
+        iterations,
+        seconds: now - start,
+        allocations: allocations() - allocated,
+    })
+}
+
+// One line per benchmark: its name, the iterations per second and the allocations per iteration.
+pub fn format_baseline(measurements: &[Measurement]) -> String {
+    measurements
+        .iter()
+        .map(|measurement| {
+            format!(
+                "{} {:.2} {:.2}\n",
+                measurement.name,
+                measurement.per_second(),
+                measurement.allocations_per_iteration()
+            )
+        })
+        .collect()
+}
+
+pub fn parse_baseline(source: &str) -> Result<Vec<Baseline>, String> {
+    let mut baselines = vec![];
+    for (index, line) in source.lines().enumerate() {
+        let fields: Vec<&str> = line.split_whitespace().collect();
+        let baseline = match fields[..] {
+            [] => continue,
+            [name, per_second, allocations] => match (per_second.parse(), allocations.parse()) {
+                (Ok(per_second), Ok(allocations_per_iteration)) => {
+                    Baseline { name: String::from(name), per_second, allocations_per_iteration }
+                }
+                _ => return Err(format!("line {}: expected numbers after {}", index + 1, name)),
+            },
+            _ => return Err(format!("line {}: expected a name and two numbers", index + 1)),
+        };
+        baselines.push(baseline);
+    }
+    Ok(baselines)
+}
+
+// How much a measurement changed from its baseline, as `+12.5%` or `-3.0%`.
+pub fn change(now: f64, before: f64) -> String {
+    match before == 0.0 {
+        true if now == 0.0 => String::from("+0.0%"),
+        true => String::from("new"),
+        // So that a change too small to show is not printed as `-0.0%`.
+        false => match (now - before) / before * 100.0 {
+            percent if percent.abs() < 0.05 => String::from("+0.0%"),
+            percent => format!("{:+.1}%", percent),
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::Cell;
+
+    #[test]
+    fn it_measures_until_the_time_is_up() {
+        let calls = Cell::new(0);
+        let mut clock = Clock::Fake { now: 0.0, step: 0.25 };
+        let measurement = measure("bench_a", &mut clock, 1.0, || calls.get() * 2, || {
+            calls.set(calls.get() + 1);
+            Ok::<(), String>(())
+        });
+        assert_eq!(
+            measurement,
+            Ok(Measurement { name: String::from("bench_a"), iterations: 4, seconds: 1.0, allocations: 8 })
+        );
+        assert_eq!(calls.get(), 5);
+        assert_eq!(measurement.unwrap().per_second(), 4.0);
+
+        let mut clock = Clock::Fake { now: 0.0, step: 0.25 };
+        let failure = measure("bench_b", &mut clock, 1.0, || 0, || Err(String::from("boom")));
+        assert_eq!(failure, Err(String::from("boom")));
+    }
+
+    #[test]
+    fn it_works_on_baselines() {
+        let measurements = vec![
+            Measurement { name: String::from("a::bench_x"), iterations: 300, seconds: 1.5, allocations: 900 },
+            Measurement { name: String::from("a::bench_y"), iterations: 1, seconds: 0.5, allocations: 0 },
+        ];
+        let saved = format_baseline(&measurements);
+        assert_eq!(saved, "a::bench_x 200.00 3.00\na::bench_y 2.00 0.00\n");
+        assert_eq!(
+            parse_baseline(&saved),
+            Ok(vec![
+                Baseline { name: String::from("a::bench_x"), per_second: 200.0, allocations_per_iteration: 3.0 },
+                Baseline { name: String::from("a::bench_y"), per_second: 2.0, allocations_per_iteration: 0.0 },
+            ])
+        );
+        assert_eq!(parse_baseline("\na b c"), Err(String::from("line 2: expected numbers after a")));
+        assert_eq!(parse_baseline("a 1"), Err(String::from("line 1: expected a name and two numbers")));
+    }
+
+    #[test]
+    fn it_works_on_changes() {
+        assert_eq!(change(110.0, 100.0), "+10.0%");
+        assert_eq!(change(75.0, 100.0), "-25.0%");
+        assert_eq!(change(0.0, 0.0), "+0.0%");
+        assert_eq!(change(99.99, 100.0), "+0.0%");
+        assert_eq!(change(3.0, 0.0), "new");
+    }
+}
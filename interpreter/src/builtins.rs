@@ -0,0 +1,1027 @@
+use std::fs;
+use std::rc::Rc;
+
+use crate::diagnostics::{
+    ASSERTION_FAILED, BAD_FORMAT_OR_RANGE, CANNOT_CONVERT, IO_FAILED, UNSUPPORTED_ARGUMENT, WRONG_ARGUMENT_COUNT,
+};
+use crate::evaluator::Evaluator;
+use crate::object::{Builtin, InspectOptions, Object};
+
+pub fn standard_builtins() -> Vec<Builtin> {
+    Vec::from([
+        Builtin {
+            name: "map",
+            parameters: "iterable, fn(element)",
+            description: "Calls the function on every element of an array or range and returns the array of results.",
+            function: map,
+        },
+        Builtin {
+            name: "filter",
+            parameters: "iterable, fn(element)",
+            description: "Returns the array of elements the function returns something truthy for.",
+            function: filter,
+        },
+        Builtin {
+            name: "reduce",
+            parameters: "iterable, initial, fn(accumulator, element)",
+            description: "Folds the elements from the left, starting from `initial`.",
+            function: reduce,
+        },
+        Builtin {
+            name: "len",
+            parameters: "value",
+            description: "Counts the characters of a string or the elements of an array or hash.",
+            function: len,
+        },
+        Builtin {
+            name: "split",
+            parameters: "string, separator",
+            description: "Returns the pieces between separators, an empty separator splits into characters.",
+            function: split,
+        },
+        Builtin {
+            name: "join",
+            parameters: "strings, separator",
+            description: "Joins an array of strings with the separator between them.",
+            function: join,
+        },
+        Builtin {
+            name: "contains",
+            parameters: "string or array, value",
+            description: "Whether a string holds the substring, or an array holds the element.",
+            function: contains,
+        },
+        Builtin {
+            name: "replace",
+            parameters: "string, from, to",
+            description: "Replaces every occurrence of `from` with `to`.",
+            function: replace,
+        },
+        Builtin {
+            name: "trim",
+            parameters: "string",
+            description: "Removes whitespace from both ends.",
+            function: trim,
+        },
+        Builtin {
+            name: "upper",
+            parameters: "string",
+            description: "Returns the string in uppercase.",
+            function: upper,
+        },
+        Builtin {
+            name: "lower",
+            parameters: "string",
+            description: "Returns the string in lowercase.",
+            function: lower,
+        },
+        Builtin {
+            name: "chars",
+            parameters: "string",
+            description: "Returns the array of the string's characters, as one character strings.",
+            function: chars,
+        },
+        Builtin {
+            name: "type",
+            parameters: "value",
+            description: "Returns the name of the value's type, as used in error messages.",
+            function: type_of,
+        },
+        Builtin {
+            name: "int",
+            parameters: "value",
+            description: "Converts an integer, float (truncating it), boolean or decimal string to an INTEGER.",
+            function: int,
+        },
+        Builtin {
+            name: "float",
+            parameters: "value",
+            description: "Converts an integer, float or decimal string to a FLOAT.",
+            function: float,
+        },
+        Builtin {
+            name: "str",
+            parameters: "value",
+            description: "Returns the string the value prints as.",
+            function: str,
+        },
+        Builtin {
+            name: "format",
+            parameters: "template, values...",
+            description: "Replaces every `{}` in the template with the next value, `{{` and `}}` stand for braces.",
+            function: format,
+        },
+        Builtin {
+            name: "printf",
+            parameters: "template, values...",
+            description: "Writes the formatted template like `format`, without adding a newline.",
+            function: printf,
+        },
+        Builtin {
+            name: "puts",
+            parameters: "values...",
+            description: "Writes every value on a line of its own.",
+            function: puts,
+        },
+        Builtin {
+            name: "input",
+            parameters: "[prompt]",
+            description: "Returns the next line of input, or null once it runs out.",
+            function: input,
+        },
+        Builtin {
+            name: "error",
+            parameters: "message",
+            description: "Raises an error with the message, which `try` can catch.",
+            function: error,
+        },
+        Builtin {
+            name: "is_null",
+            parameters: "value",
+            description: "Whether the value is null.",
+            function: is_null,
+        },
+        Builtin {
+            name: "inspect",
+            parameters: "value, [max_depth]",
+            description: "Returns the value as it would be written in source, strings quoted.",
+            function: inspect,
+        },
+        Builtin {
+            name: "random",
+            parameters: "[low], [high]",
+            description: "A FLOAT in [0, 1) with no arguments, otherwise an INTEGER in [0, high) or [low, high).",
+            function: random,
+        },
+        Builtin {
+            name: "clock",
+            parameters: "",
+            description: "Returns the seconds since the Unix epoch as a FLOAT.",
+            function: clock,
+        },
+        Builtin {
+            name: "args",
+            parameters: "",
+            description: "Returns the script's command line arguments as an array of strings.",
+            function: args,
+        },
+        Builtin {
+            name: "assert",
+            parameters: "condition, [message]",
+            description: "Fails with where it was called when the condition is falsy.",
+            function: assert,
+        },
+    ])
+}
+
+// Only registered by `Evaluator::with_filesystem_access`.
+pub fn filesystem_builtins() -> Vec<Builtin> {
+    Vec::from([
+        Builtin {
+            name: "read_file",
+            parameters: "path",
+            description: "Returns the file's contents as a string.",
+            function: read_file,
+        },
+        Builtin {
+            name: "write_file",
+            parameters: "path, contents",
+            description: "Replaces the file's contents, creating it when needed.",
+            function: write_file,
+        },
+    ])
+}
+
+// Only registered by `Evaluator::with_environment_access`.
+// Every builtin there is, whether a given evaluator has it or not, in the order the compiler
+// numbers them for the virtual machine.
+pub fn all_builtin_names() -> Vec<&'static str> {
+    let builtins = [standard_builtins(), filesystem_builtins(), environment_builtins()].concat();
+    builtins.iter().map(|builtin| builtin.name).collect()
+}
+
+pub fn environment_builtins() -> Vec<Builtin> {
+    Vec::from([Builtin {
+        name: "env",
+        parameters: "name",
+        description: "Returns the environment variable's value, or null when it is not set.",
+        function: env,
+    }])
+}
+
+fn check_argument_count(arguments: &[Object], expected: usize) -> Result<(), Object> {
+    if arguments.len() != expected {
+        return Err(Object::error(WRONG_ARGUMENT_COUNT, format!(
+            "wrong number of arguments: expected {}, got {}",
+            expected,
+            arguments.len()
+        )));
+    }
+
+    Ok(())
+}
+
+// Ranges are walked without collecting them first, `map(0..2147483647, f)` can still be stopped
+// by the step budget or an interrupt before it runs out of memory.
+fn elements_of<'a>(name: &str, iterable: &'a Object) -> Result<Box<dyn Iterator<Item = Object> + 'a>, Object> {
+    match iterable {
+        Object::Array(elements) => Ok(Box::new(elements.iter().cloned())),
+        Object::Range(start, end) => Ok(Box::new((*start..*end).map(Object::Integer))),
+        other => Err(Object::error(UNSUPPORTED_ARGUMENT, format!(
+            "argument to `{}` must be ARRAY or RANGE, got {}",
+            name,
+            other.type_name()
+        ))),
+    }
+}
+
+fn string_argument<'a>(name: &str, argument: &'a Object) -> Result<&'a str, Object> {
+    match argument {
+        Object::String(value) => Ok(value),
+        other => Err(Object::error(UNSUPPORTED_ARGUMENT, format!(
+            "argument to `{}` must be STRING, got {}",
+            name,
+            other.type_name()
+        ))),
+    }
+}
+
+fn strings(values: Vec<String>) -> Object {
+    Object::Array(values.into_iter().map(Object::String).collect::<Vec<Object>>().into())
+}
+
+// Calls back into a Monkey function, turning its error into this builtin's error. What it returns
+// is held to the memory limit here, a tail call in it having left that to the caller.
+fn call(evaluator: &mut Evaluator, function: &Object, arguments: Vec<Object>) -> Result<Object, Object> {
+    let result = evaluator.apply_function(function.clone(), arguments);
+    let result = evaluator.check_size(result);
+    if result.is_error() {
+        return Err(result);
+    }
+
+    Ok(result)
+}
+
+// map(iterable, fn(element)) returns the array of results.
+fn map(evaluator: &mut Evaluator, arguments: Vec<Object>) -> Result<Object, Object> {
+    check_argument_count(&arguments, 2)?;
+
+    let mut results = vec![];
+    for element in elements_of("map", &arguments[0])? {
+        results.push(call(evaluator, &arguments[1], vec![element])?);
+    }
+
+    Ok(Object::Array(results.into()))
+}
+
+// filter(iterable, fn(element)) returns the array of elements the function is truthy for.
+fn filter(evaluator: &mut Evaluator, arguments: Vec<Object>) -> Result<Object, Object> {
+    check_argument_count(&arguments, 2)?;
+
+    let mut results = vec![];
+    for element in elements_of("filter", &arguments[0])? {
+        if call(evaluator, &arguments[1], vec![element.clone()])?.is_truthy() {
+            results.push(element);
+        }
+    }
+
+    Ok(Object::Array(results.into()))
+}
+
+// reduce(iterable, initial, fn(accumulator, element)) folds the elements from the left.
+fn reduce(evaluator: &mut Evaluator, arguments: Vec<Object>) -> Result<Object, Object> {
+    check_argument_count(&arguments, 3)?;
+
+    let mut accumulator = arguments[1].clone();
+    for element in elements_of("reduce", &arguments[0])? {
+        accumulator = call(evaluator, &arguments[2], vec![accumulator, element])?;
+    }
+
+    Ok(accumulator)
+}
+
+// len(string) counts characters, len(array) and len(hash) count elements.
+fn len(_: &mut Evaluator, arguments: Vec<Object>) -> Result<Object, Object> {
+    check_argument_count(&arguments, 1)?;
+
+    let length = match &arguments[0] {
+        Object::String(value) => value.chars().count(),
+        Object::Array(elements) => elements.len(),
+        Object::Hash(pairs) => pairs.len(),
+        other => {
+            return Err(Object::error(UNSUPPORTED_ARGUMENT, format!(
+                "argument to `len` not supported, got {}",
+                other.type_name()
+            )))
+        }
+    };
+
+    Ok(Object::Integer(length as i32))
+}
+
+// split(string, separator) returns the pieces between separators, an empty separator splits
+// into characters.
+fn split(_: &mut Evaluator, arguments: Vec<Object>) -> Result<Object, Object> {
+    check_argument_count(&arguments, 2)?;
+    let value = string_argument("split", &arguments[0])?;
+    let separator = string_argument("split", &arguments[1])?;
+
+    if separator.is_empty() {
+        return Ok(strings(value.chars().map(String::from).collect()));
+    }
+    Ok(strings(value.split(separator).map(String::from).collect()))
+}
+
+// join(array of strings, separator) is the inverse of split.
+fn join(_: &mut Evaluator, arguments: Vec<Object>) -> Result<Object, Object> {
+    check_argument_count(&arguments, 2)?;
+    let elements = match &arguments[0] {
+        Object::Array(elements) => elements,
+        other => {
+            return Err(Object::error(UNSUPPORTED_ARGUMENT, format!(
+                "argument to `join` must be ARRAY, got {}",
+                other.type_name()
+            )))
+        }
+    };
+    let separator = string_argument("join", &arguments[1])?;
+
+    let mut pieces = vec![];
+    for element in elements.iter() {
+        pieces.push(string_argument("join", element)?);
+    }
+
+    Ok(Object::String(pieces.join(separator)))
+}
+
+// contains(string, substring) or contains(array, element).
+fn contains(_: &mut Evaluator, arguments: Vec<Object>) -> Result<Object, Object> {
+    check_argument_count(&arguments, 2)?;
+
+    match &arguments[0] {
+        Object::String(value) => Ok(Object::Boolean(value.contains(string_argument("contains", &arguments[1])?))),
+        Object::Array(elements) => Ok(Object::Boolean(
+            elements.iter().any(|element| element.equals(&arguments[1]) == Some(true)),
+        )),
+        other => Err(Object::error(UNSUPPORTED_ARGUMENT, format!(
+            "argument to `contains` must be STRING or ARRAY, got {}",
+            other.type_name()
+        ))),
+    }
+}
+
+// replace(string, from, to) replaces every occurrence.
+fn replace(_: &mut Evaluator, arguments: Vec<Object>) -> Result<Object, Object> {
+    check_argument_count(&arguments, 3)?;
+    let value = string_argument("replace", &arguments[0])?;
+    let from = string_argument("replace", &arguments[1])?;
+    let to = string_argument("replace", &arguments[2])?;
+
+    if from.is_empty() {
+        return Ok(Object::String(String::from(value)));
+    }
+    Ok(Object::String(value.replace(from, to)))
+}
+
+fn trim(_: &mut Evaluator, arguments: Vec<Object>) -> Result<Object, Object> {
+    check_argument_count(&arguments, 1)?;
+    Ok(Object::String(String::from(string_argument("trim", &arguments[0])?.trim())))
+}
+
+fn upper(_: &mut Evaluator, arguments: Vec<Object>) -> Result<Object, Object> {
+    check_argument_count(&arguments, 1)?;
+    Ok(Object::String(string_argument("upper", &arguments[0])?.to_uppercase()))
+}
+
+fn lower(_: &mut Evaluator, arguments: Vec<Object>) -> Result<Object, Object> {
+    check_argument_count(&arguments, 1)?;
+    Ok(Object::String(string_argument("lower", &arguments[0])?.to_lowercase()))
+}
+
+// chars(string) returns the array of one character strings.
+fn chars(_: &mut Evaluator, arguments: Vec<Object>) -> Result<Object, Object> {
+    check_argument_count(&arguments, 1)?;
+    Ok(strings(string_argument("chars", &arguments[0])?.chars().map(String::from).collect()))
+}
+
+// type(value) returns the name used for the value in error messages.
+fn type_of(_: &mut Evaluator, arguments: Vec<Object>) -> Result<Object, Object> {
+    check_argument_count(&arguments, 1)?;
+    Ok(Object::String(String::from(arguments[0].type_name())))
+}
+
+// int(value) accepts integers, floats which it truncates, booleans and strings holding a
+// decimal integer.
+fn int(_: &mut Evaluator, arguments: Vec<Object>) -> Result<Object, Object> {
+    check_argument_count(&arguments, 1)?;
+
+    match &arguments[0] {
+        Object::Integer(value) => Ok(Object::Integer(*value)),
+        Object::Float(value) => {
+            let value = value.trunc();
+            if value.is_nan() || value < i32::MIN as f64 || value > i32::MAX as f64 {
+                return Err(Object::error(CANNOT_CONVERT, format!("cannot convert {:?} to INTEGER", value)));
+            }
+            Ok(Object::Integer(value as i32))
+        }
+        Object::Boolean(value) => Ok(Object::Integer(*value as i32)),
+        Object::String(value) => match value.trim().parse::<i32>() {
+            Ok(value) => Ok(Object::Integer(value)),
+            Err(_) => Err(Object::error(CANNOT_CONVERT, format!("cannot convert \"{}\" to INTEGER", value))),
+        },
+        other => Err(Object::error(UNSUPPORTED_ARGUMENT, format!(
+            "argument to `int` not supported, got {}",
+            other.type_name()
+        ))),
+    }
+}
+
+// float(value) accepts integers, floats and strings holding a decimal number.
+fn float(_: &mut Evaluator, arguments: Vec<Object>) -> Result<Object, Object> {
+    check_argument_count(&arguments, 1)?;
+
+    match &arguments[0] {
+        Object::Integer(value) => Ok(Object::Float(*value as f64)),
+        Object::Float(value) => Ok(Object::Float(*value)),
+        Object::String(value) => match value.trim().parse::<f64>() {
+            Ok(parsed) if parsed.is_finite() => Ok(Object::Float(parsed)),
+            _ => Err(Object::error(CANNOT_CONVERT, format!("cannot convert \"{}\" to FLOAT", value))),
+        },
+        other => Err(Object::error(UNSUPPORTED_ARGUMENT, format!(
+            "argument to `float` not supported, got {}",
+            other.type_name()
+        ))),
+    }
+}
+
+// str(value) returns the string the value prints as.
+fn str(_: &mut Evaluator, arguments: Vec<Object>) -> Result<Object, Object> {
+    check_argument_count(&arguments, 1)?;
+    Ok(Object::String(arguments[0].to_string()))
+}
+
+fn write_output(evaluator: &mut Evaluator, text: &str) -> Result<(), Object> {
+    let output = evaluator.output();
+    output
+        .write_all(text.as_bytes())
+        .and_then(|_| output.flush())
+        .map_err(|error| Object::error(IO_FAILED, format!("cannot write output: {}", error)))
+}
+
+// Replaces every `{}` in the template with the next argument as `str` would print it, `{{`
+// and `}}` stand for literal braces.
+fn format_arguments(name: &str, arguments: &[Object]) -> Result<String, Object> {
+    if arguments.is_empty() {
+        return Err(Object::error(WRONG_ARGUMENT_COUNT, String::from(
+            "wrong number of arguments: expected at least 1, got 0",
+        )));
+    }
+    let template = string_argument(name, &arguments[0])?;
+    let mut values = arguments[1..].iter();
+
+    let mut formatted = String::new();
+    let mut placeholders = 0;
+    let mut characters = template.chars().peekable();
+    while let Some(character) = characters.next() {
+        match (character, characters.peek()) {
+            ('{', Some('{')) | ('}', Some('}')) => {
+                characters.next();
+                formatted.push(character);
+            }
+            ('{', Some('}')) => {
+                characters.next();
+                placeholders += 1;
+                if let Some(value) = values.next() {
+                    formatted.push_str(&value.to_string());
+                }
+            }
+            ('{', _) | ('}', _) => {
+                return Err(Object::error(BAD_FORMAT_OR_RANGE, format!("unmatched `{}` in format string", character)));
+            }
+            (character, _) => formatted.push(character),
+        }
+    }
+
+    if placeholders != arguments.len() - 1 {
+        return Err(Object::error(BAD_FORMAT_OR_RANGE, format!(
+            "format string has {} placeholders, got {} values",
+            placeholders,
+            arguments.len() - 1
+        )));
+    }
+
+    Ok(formatted)
+}
+
+// format(template, values...) returns the formatted string.
+fn format(_: &mut Evaluator, arguments: Vec<Object>) -> Result<Object, Object> {
+    Ok(Object::String(format_arguments("format", &arguments)?))
+}
+
+// printf(template, values...) writes the formatted string without adding a newline.
+fn printf(evaluator: &mut Evaluator, arguments: Vec<Object>) -> Result<Object, Object> {
+    let formatted = format_arguments("printf", &arguments)?;
+    write_output(evaluator, &formatted)?;
+    Ok(Object::Null)
+}
+
+// puts(values...) writes every value on a line of its own.
+fn puts(evaluator: &mut Evaluator, arguments: Vec<Object>) -> Result<Object, Object> {
+    let mut lines = String::new();
+    for argument in &arguments {
+        lines.push_str(&argument.to_string());
+        lines.push('\n');
+    }
+    write_output(evaluator, &lines)?;
+    Ok(Object::Null)
+}
+
+// input() or input(prompt) returns the next line read, or null once the input is exhausted.
+fn input(evaluator: &mut Evaluator, arguments: Vec<Object>) -> Result<Object, Object> {
+    match arguments.len() {
+        0 => {}
+        1 => {
+            let prompt = string_argument("input", &arguments[0])?;
+            write_output(evaluator, prompt)?;
+        }
+        count => {
+            return Err(Object::error(WRONG_ARGUMENT_COUNT, format!(
+                "wrong number of arguments: expected 0 or 1, got {}",
+                count
+            )))
+        }
+    }
+
+    match evaluator.read_line() {
+        Ok(Some(line)) => Ok(Object::String(line)),
+        Ok(None) => Ok(Object::Null),
+        Err(error) => Err(Object::error(IO_FAILED, format!("cannot read input: {}", error))),
+    }
+}
+
+// read_file(path) returns the file's contents as a string.
+fn read_file(_: &mut Evaluator, arguments: Vec<Object>) -> Result<Object, Object> {
+    check_argument_count(&arguments, 1)?;
+    let path = string_argument("read_file", &arguments[0])?;
+
+    match fs::read_to_string(path) {
+        Ok(contents) => Ok(Object::String(contents)),
+        Err(error) => Err(Object::error(IO_FAILED, format!("cannot read file {}: {}", path, error))),
+    }
+}
+
+// write_file(path, contents) replaces the file's contents, creating it when needed.
+fn write_file(_: &mut Evaluator, arguments: Vec<Object>) -> Result<Object, Object> {
+    check_argument_count(&arguments, 2)?;
+    let path = string_argument("write_file", &arguments[0])?;
+    let contents = string_argument("write_file", &arguments[1])?;
+
+    match fs::write(path, contents) {
+        Ok(()) => Ok(Object::Null),
+        Err(error) => Err(Object::error(IO_FAILED, format!("cannot write file {}: {}", path, error))),
+    }
+}
+
+// env(name) returns the value of an environment variable, null when it is not set or not valid
+// unicode.
+fn env(_: &mut Evaluator, arguments: Vec<Object>) -> Result<Object, Object> {
+    check_argument_count(&arguments, 1)?;
+    let name = string_argument("env", &arguments[0])?;
+
+    match std::env::var(name) {
+        Ok(value) => Ok(Object::String(value)),
+        Err(_) => Ok(Object::Null),
+    }
+}
+
+// error(message) raises an error that `try` can catch.
+fn error(_: &mut Evaluator, arguments: Vec<Object>) -> Result<Object, Object> {
+    check_argument_count(&arguments, 1)?;
+    Err(Object::raised(String::from(string_argument("error", &arguments[0])?)))
+}
+
+fn is_null(_: &mut Evaluator, arguments: Vec<Object>) -> Result<Object, Object> {
+    check_argument_count(&arguments, 1)?;
+    Ok(Object::Boolean(matches!(arguments[0], Object::Null)))
+}
+
+// inspect(value) or inspect(value, max_depth) returns the value as it would be written in
+// source, strings quoted.
+fn inspect(_: &mut Evaluator, arguments: Vec<Object>) -> Result<Object, Object> {
+    let mut options = InspectOptions::default();
+    match arguments.get(1) {
+        _ if arguments.is_empty() || arguments.len() > 2 => {
+            return Err(Object::error(WRONG_ARGUMENT_COUNT, format!(
+                "wrong number of arguments: expected 1 or 2, got {}",
+                arguments.len()
+            )))
+        }
+        None => {}
+        Some(Object::Integer(max_depth)) if *max_depth >= 0 => options.max_depth = *max_depth as usize,
+        Some(other) => {
+            return Err(Object::error(UNSUPPORTED_ARGUMENT, format!(
+                "argument to `inspect` must be a non-negative INTEGER, got {}",
+                other
+            )))
+        }
+    }
+
+    Ok(Object::String(arguments[0].inspect(options)))
+}
+
+// random() returns a FLOAT in [0, 1), random(n) an INTEGER in [0, n) and random(low, high)
+// an INTEGER in [low, high).
+fn random(evaluator: &mut Evaluator, arguments: Vec<Object>) -> Result<Object, Object> {
+    let (low, high) = match arguments.as_slice() {
+        [] => return Ok(Object::Float(evaluator.random().next_float())),
+        [Object::Integer(high)] => (0, *high),
+        [Object::Integer(low), Object::Integer(high)] => (*low, *high),
+        [_] | [_, _] => {
+            let other = arguments.iter().find(|argument| !matches!(argument, Object::Integer(_))).unwrap();
+            return Err(Object::error(UNSUPPORTED_ARGUMENT, format!(
+                "argument to `random` must be INTEGER, got {}",
+                other.type_name()
+            )));
+        }
+        _ => {
+            return Err(Object::error(WRONG_ARGUMENT_COUNT, format!(
+                "wrong number of arguments: expected at most 2, got {}",
+                arguments.len()
+            )))
+        }
+    };
+
+    if low >= high {
+        return Err(Object::error(BAD_FORMAT_OR_RANGE, format!("empty range for `random`: {}..{}", low, high)));
+    }
+    let offset = evaluator.random().below((high as i64 - low as i64) as u64);
+    Ok(Object::Integer((low as i64 + offset as i64) as i32))
+}
+
+// clock() returns the time in seconds since the Unix epoch as a FLOAT.
+fn clock(evaluator: &mut Evaluator, arguments: Vec<Object>) -> Result<Object, Object> {
+    check_argument_count(&arguments, 0)?;
+    Ok(Object::Float(evaluator.clock().now()))
+}
+
+fn args(evaluator: &mut Evaluator, arguments: Vec<Object>) -> Result<Object, Object> {
+    check_argument_count(&arguments, 0)?;
+    let strings = evaluator.arguments().iter().map(|argument| Object::String(argument.clone())).collect();
+    Ok(Object::Array(Rc::new(strings)))
+}
+
+// assert(condition) or assert(condition, message) fails with where it was called when the
+// condition is falsy, and returns null otherwise.
+fn assert(evaluator: &mut Evaluator, arguments: Vec<Object>) -> Result<Object, Object> {
+    let message = match arguments.as_slice() {
+        [_] => None,
+        [_, message] => Some(string_argument("assert", message)?),
+        _ => {
+            return Err(Object::error(WRONG_ARGUMENT_COUNT, format!(
+                "wrong number of arguments: expected 1 or 2, got {}",
+                arguments.len()
+            )))
+        }
+    };
+
+    if arguments[0].is_truthy() {
+        return Ok(Object::Null);
+    }
+    Err(Object::error(ASSERTION_FAILED, match message {
+        None => format!("assertion failed at {}", evaluator.call_site()),
+        Some(message) => format!("assertion failed at {}: {}", evaluator.call_site(), message),
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::RefCell;
+    use std::env;
+    use std::fs;
+    use std::io::{self, Write};
+    use std::rc::Rc;
+
+    use crate::clock::Clock;
+    use crate::environment::Environment;
+    use crate::evaluator::{evaluate, Evaluator};
+    use crate::syntax_analyzer::parse;
+
+    fn evaluate_input(input: &str) -> String {
+        let env = Rc::new(RefCell::new(Environment::new()));
+        evaluate(&parse(input).unwrap(), &env).to_string()
+    }
+
+    #[derive(Clone, Default)]
+    struct SharedOutput(Rc<RefCell<Vec<u8>>>);
+
+    impl Write for SharedOutput {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.borrow_mut().write(buf)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    // Returns what the program wrote followed by the value it evaluated to.
+    fn evaluate_printing(input: &str) -> (String, String) {
+        evaluate_interactively(input, "")
+    }
+
+    fn evaluate_interactively(input: &str, stdin: &str) -> (String, String) {
+        let output = SharedOutput::default();
+        let mut evaluator = Evaluator::new()
+            .with_output(output.clone())
+            .with_input(io::Cursor::new(String::from(stdin)));
+        let env = Rc::new(RefCell::new(Environment::new()));
+        let result = evaluator.eval_program(&parse(input).unwrap(), &env).to_string();
+        let written = String::from_utf8(output.0.borrow().clone()).unwrap();
+        (written, result)
+    }
+
+    #[test]
+    fn it_works_on_map() {
+        assert_eq!(evaluate_input("map([1, 2, 3], fn(x) { x * 2 })"), "[2, 4, 6]");
+        assert_eq!(evaluate_input("map(0..3, fn(x) { x + 1 })"), "[1, 2, 3]");
+        assert_eq!(evaluate_input("map([], crash)"), "ERROR: identifier not found: crash");
+        assert_eq!(evaluate_input("map([], fn(x) { crash() })"), "[]");
+        assert_eq!(evaluate_input("map([1], fn(x) { crash() })"), "ERROR: identifier not found: crash");
+        assert_eq!(evaluate_input("map([1], fn(x, y) { x })"), "ERROR: wrong number of arguments: expected 2, got 1");
+        assert_eq!(evaluate_input("map(1, fn(x) { x })"), "ERROR: argument to `map` must be ARRAY or RANGE, got INTEGER");
+        assert_eq!(evaluate_input("map([1], 2)"), "ERROR: not a function: INTEGER");
+        assert_eq!(evaluate_input("map([1])"), "ERROR: wrong number of arguments: expected 2, got 1");
+    }
+
+    #[test]
+    fn it_works_on_filter() {
+        assert_eq!(evaluate_input("filter(0..10, fn(x) { x > 6 })"), "[7, 8, 9]");
+        assert_eq!(evaluate_input("filter([1, 2], fn(x) { false })"), "[]");
+        assert_eq!(evaluate_input("filter([true], fn(x) { x + 1 })"), "ERROR: type mismatch: BOOLEAN + INTEGER");
+    }
+
+    #[test]
+    fn it_works_on_reduce() {
+        assert_eq!(evaluate_input("reduce([1, 2, 3, 4], 0, fn(sum, x) { sum + x })"), "10");
+        assert_eq!(evaluate_input("reduce([], 42, fn(sum, x) { sum + x })"), "42");
+        assert_eq!(
+            evaluate_input("reduce(1..4, [], fn(acc, x) { [acc, x] })"),
+            "[[[[], 1], 2], 3]",
+        );
+    }
+
+    #[test]
+    fn it_composes_into_pipelines() {
+        assert_eq!(
+            evaluate_input(
+                "let square = fn(x) { x * x };
+                 let isEven = fn(x) { x / 2 * 2 == x };
+                 reduce(map(filter(1..7, isEven), square), 0, fn(sum, x) { sum + x })"
+            ),
+            "56",
+        );
+        assert_eq!(evaluate_input("let map = fn(xs, f) { 1 }; map([1], fn(x) { x })"), "1");
+        assert_eq!(evaluate_input("let apply = fn(f) { f([1], fn(x) { -x }) }; apply(map)"), "[-1]");
+    }
+
+    #[test]
+    fn it_works_on_len() {
+        assert_eq!(evaluate_input(r#"len("héllo")"#), "5");
+        assert_eq!(evaluate_input(r#"len([1, "two"])"#), "2");
+        assert_eq!(evaluate_input(r#"len({"a": 1})"#), "1");
+        assert_eq!(evaluate_input("len(1)"), "ERROR: argument to `len` not supported, got INTEGER");
+    }
+
+    #[test]
+    fn it_works_on_split_and_join() {
+        assert_eq!(evaluate_input(r#"len(split("a,b,,c", ","))"#), "4");
+        assert_eq!(evaluate_input(r#"join(split("a,b,,c", ","), "-")"#), "a-b--c");
+        assert_eq!(evaluate_input(r#"join(split("abc", ""), " ")"#), "a b c");
+        assert_eq!(evaluate_input(r#"join([], ", ")"#), "");
+        assert_eq!(evaluate_input(r#"join(["a", 1], ", ")"#), "ERROR: argument to `join` must be STRING, got INTEGER");
+        assert_eq!(evaluate_input(r#"join("a", ", ")"#), "ERROR: argument to `join` must be ARRAY, got STRING");
+        assert_eq!(evaluate_input(r#"split(1, ",")"#), "ERROR: argument to `split` must be STRING, got INTEGER");
+    }
+
+    #[test]
+    fn it_works_on_contains_and_replace() {
+        assert_eq!(evaluate_input(r#"contains("monkey", "key")"#), "true");
+        assert_eq!(evaluate_input(r#"contains("monkey", "donkey")"#), "false");
+        assert_eq!(evaluate_input(r#"contains([1, "a", true], "a")"#), "true");
+        assert_eq!(evaluate_input("contains([1, 2], 3)"), "false");
+        assert_eq!(evaluate_input("contains([[1], {2: 3}], {2: 3})"), "true");
+        assert_eq!(evaluate_input("contains([1], 1.0)"), "false");
+        assert_eq!(evaluate_input(r#"contains("a", 1)"#), "ERROR: argument to `contains` must be STRING, got INTEGER");
+        assert_eq!(evaluate_input("contains(1, 1)"), "ERROR: argument to `contains` must be STRING or ARRAY, got INTEGER");
+        assert_eq!(evaluate_input(r#"replace("a-b-c", "-", "+")"#), "a+b+c");
+        assert_eq!(evaluate_input(r#"replace("abc", "", "+")"#), "abc");
+        assert_eq!(evaluate_input(r#"replace("abc", "b")"#), "ERROR: wrong number of arguments: expected 3, got 2");
+    }
+
+    #[test]
+    fn it_works_on_case_and_whitespace_helpers() {
+        assert_eq!(evaluate_input(r#"trim("  padded\t\n")"#), "padded");
+        assert_eq!(evaluate_input(r#"upper("Monkey")"#), "MONKEY");
+        assert_eq!(evaluate_input(r#"lower("Monkey")"#), "monkey");
+        assert_eq!(evaluate_input(r#"join(chars("abc"), "|")"#), "a|b|c");
+        assert_eq!(evaluate_input("upper([])"), "ERROR: argument to `upper` must be STRING, got ARRAY");
+    }
+
+    #[test]
+    fn it_works_on_type() {
+        assert_eq!(evaluate_input("type(1)"), "INTEGER");
+        assert_eq!(evaluate_input("type(true)"), "BOOLEAN");
+        assert_eq!(evaluate_input(r#"type("a")"#), "STRING");
+        assert_eq!(evaluate_input("type([])"), "ARRAY");
+        assert_eq!(evaluate_input("type({})"), "HASH");
+        assert_eq!(evaluate_input("type(0..1)"), "RANGE");
+        assert_eq!(evaluate_input("type(fn() {})"), "FUNCTION");
+        assert_eq!(evaluate_input("type(type)"), "BUILTIN");
+        assert_eq!(evaluate_input("type(if false { 1 })"), "NULL");
+        assert_eq!(evaluate_input(r#"type(type(1)) == "STRING""#), "true");
+        assert_eq!(evaluate_input("type()"), "ERROR: wrong number of arguments: expected 1, got 0");
+    }
+
+    #[test]
+    fn it_works_on_conversions() {
+        assert_eq!(evaluate_input(r#"int(" 42 ") + 1"#), "43");
+        assert_eq!(evaluate_input("int(-2.9)"), "-2");
+        assert_eq!(evaluate_input("int(true) + int(false)"), "1");
+        assert_eq!(evaluate_input(r#"int("abc")"#), r#"ERROR: cannot convert "abc" to INTEGER"#);
+        assert_eq!(evaluate_input("int(10000000000.5)"), "ERROR: cannot convert 10000000000.0 to INTEGER");
+        assert_eq!(evaluate_input("int([])"), "ERROR: argument to `int` not supported, got ARRAY");
+
+        assert_eq!(evaluate_input("float(3)"), "3.0");
+        assert_eq!(evaluate_input(r#"float("2.5") * 2.0"#), "5.0");
+        assert_eq!(evaluate_input(r#"float("inf")"#), r#"ERROR: cannot convert "inf" to FLOAT"#);
+        assert_eq!(evaluate_input("float(true)"), "ERROR: argument to `float` not supported, got BOOLEAN");
+
+        assert_eq!(evaluate_input(r#"str(1) + str(2.5) + str(true)"#), "12.5true");
+        assert_eq!(evaluate_input(r#"str([1, "a"])"#), r#"[1, "a"]"#);
+        assert_eq!(evaluate_input("int(str(123))"), "123");
+    }
+
+    #[test]
+    fn it_works_on_format() {
+        assert_eq!(evaluate_input(r#"format("x = {}, y = {}", 1, [2.5, "a"])"#), r#"x = 1, y = [2.5, "a"]"#);
+        assert_eq!(evaluate_input(r#"format("{{}} {}", true)"#), "{} true");
+        assert_eq!(evaluate_input(r#"format("none")"#), "none");
+        assert_eq!(evaluate_input(r#"format("{}, {}", 1)"#), "ERROR: format string has 2 placeholders, got 1 values");
+        assert_eq!(evaluate_input(r#"format("{", 1)"#), "ERROR: unmatched `{` in format string");
+        assert_eq!(evaluate_input("format(1)"), "ERROR: argument to `format` must be STRING, got INTEGER");
+        assert_eq!(evaluate_input("format()"), "ERROR: wrong number of arguments: expected at least 1, got 0");
+    }
+
+    #[test]
+    fn it_works_on_printf_and_puts() {
+        assert_eq!(
+            evaluate_printing(r#"printf("{} + {} = ", 1, 2); printf("{}\n", 3); puts("a", [1])"#),
+            (String::from("1 + 2 = 3\na\n[1]\n"), String::from("null")),
+        );
+        assert_eq!(
+            evaluate_printing(r#"puts("before"); printf("{}"); puts("after")"#),
+            (
+                String::from("before\n"),
+                String::from("ERROR: format string has 1 placeholders, got 0 values")
+            ),
+        );
+    }
+
+    #[test]
+    fn it_works_on_input() {
+        assert_eq!(
+            evaluate_interactively(r#"let name = input("name? "); format("hi {}", name)"#, "monkey\nrest\n"),
+            (String::from("name? "), String::from("hi monkey")),
+        );
+        assert_eq!(
+            evaluate_interactively("[input(), input(), input(), input()]", "a\r\nb\n\nc"),
+            (String::new(), String::from(r#"["a", "b", "", "c"]"#)),
+        );
+        assert_eq!(evaluate_interactively("input()", ""), (String::new(), String::from("null")));
+        assert_eq!(
+            evaluate_interactively("input(1)", ""),
+            (String::new(), String::from("ERROR: argument to `input` must be STRING, got INTEGER")),
+        );
+    }
+
+    #[test]
+    fn it_works_on_file_builtins_only_when_allowed() {
+        let env = Rc::new(RefCell::new(Environment::new()));
+        assert_eq!(
+            evaluate(&parse(r#"read_file("Cargo.toml")"#).unwrap(), &env).to_string(),
+            "ERROR: identifier not found: read_file"
+        );
+
+        let path = env::temp_dir().join(format!("monkey-write-file-{}.txt", std::process::id()));
+        let path = path.to_str().unwrap().replace('\\', "/");
+        let program = format!(
+            r#"write_file("{0}", "line\n"); let contents = read_file("{0}"); write_file("{0}", contents + contents); read_file("{0}")"#,
+            path
+        );
+        let mut evaluator = Evaluator::new().with_filesystem_access();
+        assert_eq!(evaluator.eval_program(&parse(&program).unwrap(), &env).to_string(), "line\nline\n");
+        fs::remove_file(&path).unwrap();
+
+        let program = format!(r#"read_file("{}")"#, path);
+        assert!(evaluator
+            .eval_program(&parse(&program).unwrap(), &env)
+            .to_string()
+            .starts_with(&format!("ERROR: cannot read file {}: ", path)));
+        assert_eq!(
+            evaluator.eval_program(&parse("write_file(1, 2)").unwrap(), &env).to_string(),
+            "ERROR: argument to `write_file` must be STRING, got INTEGER"
+        );
+    }
+
+    #[test]
+    fn it_works_on_env_only_when_allowed() {
+        let env = Rc::new(RefCell::new(Environment::new()));
+        let program = parse(r#"env("CARGO_MANIFEST_DIR")"#).unwrap();
+        assert_eq!(evaluate(&program, &env).to_string(), "ERROR: identifier not found: env");
+
+        let mut evaluator = Evaluator::new().with_environment_access();
+        assert_eq!(evaluator.eval_program(&program, &env).to_string(), env!("CARGO_MANIFEST_DIR"));
+        let program = parse(r#"env("MONKEY_SURELY_NOT_SET")"#).unwrap();
+        assert_eq!(evaluator.eval_program(&program, &env).to_string(), "null");
+        assert_eq!(
+            evaluator.eval_program(&parse("env(1)").unwrap(), &env).to_string(),
+            "ERROR: argument to `env` must be STRING, got INTEGER"
+        );
+    }
+
+    #[test]
+    fn it_works_on_error() {
+        assert_eq!(evaluate_input(r#"error("boom")"#), "ERROR: boom");
+        assert_eq!(evaluate_input(r#"try { error("boom"); 1 } catch (e) { "caught " + e }"#), "caught boom");
+        assert_eq!(evaluate_input("error(1)"), "ERROR: argument to `error` must be STRING, got INTEGER");
+    }
+
+    #[test]
+    fn it_works_on_is_null() {
+        assert_eq!(evaluate_input("is_null(null)"), "true");
+        assert_eq!(evaluate_input("is_null(if false { 1 })"), "true");
+        assert_eq!(evaluate_input(r#"is_null(0) || is_null("") || is_null(false)"#), "false");
+    }
+
+    #[test]
+    fn it_works_on_inspect() {
+        assert_eq!(evaluate_input(r#"inspect("a\"b")"#), r#""a\"b""#);
+        assert_eq!(evaluate_input(r#"inspect(["a", {"k": 1}])"#), r#"["a", {"k": 1}]"#);
+        assert_eq!(evaluate_input(r#"inspect(["a", {"k": 1}], 1)"#), r#"["a", {...}]"#);
+        assert_eq!(evaluate_input(r#"inspect([[["deep"]], []], 2)"#), "[[[...]], []]");
+        assert_eq!(evaluate_input("inspect(1, -1)"), "ERROR: argument to `inspect` must be a non-negative INTEGER, got -1");
+        assert_eq!(evaluate_input("inspect()"), "ERROR: wrong number of arguments: expected 1 or 2, got 0");
+    }
+
+    #[test]
+    fn it_works_on_random_with_a_seed() {
+        let program = parse("[random(), random(6), random(-3, 3), random(10, 11)]").unwrap();
+        let env = Rc::new(RefCell::new(Environment::new()));
+        let first = Evaluator::new().with_seed(1).eval_program(&program, &env).to_string();
+        let second = Evaluator::new().with_seed(1).eval_program(&program, &env).to_string();
+        assert_eq!(first, second);
+        assert!(first.ends_with(", 10]"));
+
+        assert_eq!(evaluate_input("let ok = true; for i in 0..100 { let r = random(2, 5); ok = ok && r > 1 && r < 5 }; ok"), "true");
+        assert_eq!(evaluate_input("random(0)"), "ERROR: empty range for `random`: 0..0");
+        assert_eq!(evaluate_input("random(1, true)"), "ERROR: argument to `random` must be INTEGER, got BOOLEAN");
+        assert_eq!(evaluate_input("random(1, 2, 3)"), "ERROR: wrong number of arguments: expected at most 2, got 3");
+        assert_eq!(evaluate_input("random(-2147483647 - 1, 2147483647) < 2147483647"), "true");
+    }
+
+    #[test]
+    fn it_works_on_clock() {
+        let program = parse("let start = clock(); let end = clock(); [start, end - start]").unwrap();
+        let env = Rc::new(RefCell::new(Environment::new()));
+        let mut evaluator = Evaluator::new().with_clock(Clock::Fake { now: 100.0, step: 0.25 });
+        assert_eq!(evaluator.eval_program(&program, &env).to_string(), "[100.0, 0.25]");
+        assert_eq!(evaluate_input("clock() > 0.0"), "true");
+        assert_eq!(evaluate_input("clock(1)"), "ERROR: wrong number of arguments: expected 0, got 1");
+    }
+
+    #[test]
+    fn it_works_on_args() {
+        let program = parse("[args(), len(args())]").unwrap();
+        let env = Rc::new(RefCell::new(Environment::new()));
+        let mut evaluator = Evaluator::new().with_arguments(vec![String::from("--input"), String::from("data.txt")]);
+        assert_eq!(evaluator.eval_program(&program, &env).to_string(), "[[\"--input\", \"data.txt\"], 2]");
+        assert_eq!(evaluate_input("args()"), "[]");
+        assert_eq!(evaluate_input("args(1)"), "ERROR: wrong number of arguments: expected 0, got 1");
+    }
+
+    #[test]
+    fn it_works_on_assert() {
+        assert_eq!(evaluate_input(r#"assert(1 + 1 == 2, "math"); "ok""#), "ok");
+        assert_eq!(evaluate_input("let x = 0;\n  assert(x > 0)"), "ERROR: assertion failed at 2:3");
+        assert_eq!(
+            evaluate_input(r#"let check = fn(x) { assert(x, "x must be truthy") }; check(1); check(null)"#),
+            "ERROR: assertion failed at 1:21: x must be truthy"
+        );
+        assert_eq!(
+            evaluate_input(r#"let f = fn() { [assert(true), assert(false)] }; f()"#),
+            "ERROR: assertion failed at 1:31"
+        );
+        assert_eq!(evaluate_input("assert(false, 1)"), "ERROR: argument to `assert` must be STRING, got INTEGER");
+        assert_eq!(evaluate_input("assert()"), "ERROR: wrong number of arguments: expected 1 or 2, got 0");
+    }
+}
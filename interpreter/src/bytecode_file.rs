@@ -0,0 +1,668 @@
+use std::rc::Rc;
+
+use crate::builtins::all_builtin_names;
+use crate::code::{read_operands, stack_effect, CallSite, Capture, Instructions, Opcode};
+use crate::compiler::Bytecode;
+use crate::interner::Symbol;
+use crate::lexical_analyzer::Span;
+use crate::object::{CompiledFunction, HashPairs, Object};
+
+// How a `.mbc` file starts, which no monkey source does.
+pub const MAGIC: &[u8; 4] = b"\0MBC";
+
+// Changes with every change to the layout, for a file written by another version to be refused
+// rather than misread.
+pub const VERSION: u16 = 2;
+
+pub const EXTENSION: &str = "mbc";
+
+// How deep arrays and hashes in the constants can nest, for reading a file to need a bounded stack.
+const MAX_NESTING: usize = 64;
+
+// The most locals a frame can have, as many as a one byte operand can tell apart.
+const MAX_LOCALS: usize = u8::MAX as usize + 1;
+
+const INTEGER: u8 = 0;
+const FLOAT: u8 = 1;
+const BOOLEAN: u8 = 2;
+const STRING: u8 = 3;
+const NULL: u8 = 4;
+const ARRAY: u8 = 5;
+const HASH: u8 = 6;
+const RANGE: u8 = 7;
+const FUNCTION: u8 = 8;
+
+const LOCAL: u8 = 0;
+const FREE: u8 = 1;
+
+// Writes bytecode in the layout `deserialize` reads:
+//
+//     the magic bytes and the version
+//     the names of the globals
+//     the locals, instructions, calls and statements of the program
+//     the constants
+//
+// Numbers are big-endian, and counts and lengths take four bytes. A string is its length in
+// bytes and its UTF-8. A constant is a tag byte and its value. A function is its parameters,
+// locals and captures, its source, its instructions, its calls and its statements, a call being
+// its offset, the name of what it calls and the line and column it is at, and a statement its
+// offset and the line and column it starts at.
+//
+// Only values that can be written as a literal, and compiled functions, can be saved.
+pub fn serialize(bytecode: &Bytecode) -> Result<Vec<u8>, String> {
+    let mut writer = Writer { bytes: MAGIC.to_vec() };
+    writer.bytes.extend(VERSION.to_be_bytes());
+    writer.count(bytecode.globals.len())?;
+    for name in &bytecode.globals {
+        writer.string(&name.name())?;
+    }
+    writer.count(bytecode.locals)?;
+    writer.code(&bytecode.instructions, &bytecode.calls)?;
+    writer.statements(&bytecode.statements)?;
+    writer.count(bytecode.constants.len())?;
+    for constant in &bytecode.constants {
+        writer.constant(constant)?;
+    }
+    Ok(writer.bytes)
+}
+
+// Reads what `serialize` wrote, and checks it with `validate` for the virtual machine to be able
+// to run it.
+pub fn deserialize(bytes: &[u8]) -> Result<Bytecode, String> {
+    if !bytes.starts_with(MAGIC) {
+        return Err(String::from("not a compiled monkey program"));
+    }
+    let mut reader = Reader { bytes, offset: MAGIC.len() };
+    let version = reader.u16()?;
+    if version != VERSION {
+        return Err(format!("compiled for version {} of the bytecode format, this reads version {}", version, VERSION));
+    }
+    let mut globals = vec![];
+    for _ in 0..reader.count()? {
+        globals.push(Symbol::intern(&reader.string()?));
+    }
+    let locals = reader.count()?;
+    let (instructions, calls) = reader.code()?;
+    let statements = reader.statements()?;
+    let mut constants = vec![];
+    for _ in 0..reader.count()? {
+        constants.push(reader.constant(0)?);
+    }
+    if reader.offset < bytes.len() {
+        return Err(format!("unexpected bytes after the constants, at byte {}", reader.offset));
+    }
+    let bytecode = Bytecode { instructions, constants, globals, locals, calls, statements };
+    validate(&bytecode)?;
+    Ok(bytecode)
+}
+
+// Checks that the virtual machine can run the bytecode without going wrong itself, as it can
+// any the compiler made: that every instruction is whole and its operands point at what there
+// is, that jumps land on instructions, and that every instruction finds the values it takes on
+// the stack, however it is got to.
+pub fn validate(bytecode: &Bytecode) -> Result<(), String> {
+    if bytecode.locals > MAX_LOCALS {
+        return Err(format!("in the program: too many locals (at most {})", MAX_LOCALS));
+    }
+    let program = Code {
+        instructions: &bytecode.instructions,
+        locals: bytecode.locals,
+        free: 0,
+        calls: &bytecode.calls,
+        statements: &bytecode.statements,
+        is_function: false,
+    };
+    program.validate(bytecode).map_err(|error| format!("in the program: {}", error))?;
+    for (index, constant) in bytecode.constants.iter().enumerate() {
+        if let Object::CompiledFunction(function) = constant {
+            if function.parameters > function.locals {
+                return Err(format!("in constant {}: more parameters than locals", index));
+            }
+            if function.locals > MAX_LOCALS {
+                return Err(format!("in constant {}: too many locals (at most {})", index, MAX_LOCALS));
+            }
+            let code = Code {
+                instructions: &function.instructions,
+                locals: function.locals,
+                free: function.free.len(),
+                calls: &function.calls,
+                statements: &function.statements,
+                is_function: true,
+            };
+            code.validate(bytecode).map_err(|error| format!("in constant {}: {}", index, error))?;
+        }
+    }
+    Ok(())
+}
+
+// The instructions of the program or of a function, with what they can refer to.
+struct Code<'a> {
+    instructions: &'a Instructions,
+    locals: usize,
+    free: usize,
+    calls: &'a [CallSite],
+    statements: &'a [(usize, Span)],
+    // Only the program may end without returning.
+    is_function: bool,
+}
+
+impl Code<'_> {
+    fn validate(&self, bytecode: &Bytecode) -> Result<(), String> {
+        let instructions = self.decode()?;
+        let starts: Vec<usize> = instructions.iter().map(|(offset, _, _)| *offset).collect();
+        // The end counts as an instruction, the one that ends the program.
+        let is_target = |offset: usize| offset == self.instructions.0.len() || starts.binary_search(&offset).is_ok();
+        for (offset, opcode, operands) in &instructions {
+            self.check_operands(bytecode, *opcode, operands, is_target)
+                .map_err(|error| format!("{} at {}: {}", opcode.definition().name, offset, error))?;
+        }
+        let mut previous = None;
+        for site in self.calls {
+            let index = starts.binary_search(&site.offset).ok();
+            let is_call = index.is_some_and(|index| matches!(instructions[index].1, Opcode::Call | Opcode::TailCall));
+            if !is_call || previous.is_some_and(|previous| previous >= site.offset) {
+                return Err(format!("the call at {} is not a call instruction in order", site.offset));
+            }
+            previous = Some(site.offset);
+        }
+        let mut previous = None;
+        for (offset, _) in self.statements {
+            if !is_target(*offset) || previous.is_some_and(|previous| previous >= *offset) {
+                return Err(format!("the statement at {} does not start at an instruction in order", offset));
+            }
+            previous = Some(*offset);
+        }
+        self.check_stack(&instructions, &starts)
+    }
+
+    // Every instruction in order, with where it starts.
+    fn decode(&self) -> Result<Vec<(usize, Opcode, Vec<usize>)>, String> {
+        let bytes = &self.instructions.0;
+        let mut instructions = vec![];
+        let mut offset = 0;
+        while offset < bytes.len() {
+            let opcode = Opcode::from_byte(bytes[offset])
+                .ok_or_else(|| format!("unknown opcode {} at {}", bytes[offset], offset))?;
+            let definition = opcode.definition();
+            if offset + 1 + definition.operand_widths.iter().sum::<usize>() > bytes.len() {
+                return Err(format!("{} at {} is cut off", definition.name, offset));
+            }
+            let (operands, read) = read_operands(definition, &bytes[offset + 1..]);
+            instructions.push((offset, opcode, operands));
+            offset += 1 + read;
+        }
+        Ok(instructions)
+    }
+
+    fn check_operands(
+        &self,
+        bytecode: &Bytecode,
+        opcode: Opcode,
+        operands: &[usize],
+        is_target: impl Fn(usize) -> bool,
+    ) -> Result<(), String> {
+        let within = |what: &str, index: usize, count: usize| match index < count {
+            true => Ok(()),
+            false => Err(format!("{} {} out of {}", what, index, count)),
+        };
+        let target = |offset: usize| match is_target(offset) {
+            true => Ok(()),
+            false => Err(format!("jump to {}, which is not an instruction", offset)),
+        };
+        match opcode {
+            Opcode::Constant => within("constant", operands[0], bytecode.constants.len()),
+            Opcode::Closure => {
+                let Some(Object::CompiledFunction(function)) = bytecode.constants.get(operands[0]) else {
+                    return Err(format!("constant {} is not a function", operands[0]));
+                };
+                for capture in &function.free {
+                    match *capture {
+                        Capture::Local(index) => within("local", index, self.locals)?,
+                        Capture::Free(index) => within("free variable", index, self.free)?,
+                    }
+                }
+                Ok(())
+            }
+            Opcode::GetGlobal | Opcode::SetGlobal | Opcode::AssignGlobal => {
+                within("global", operands[0], bytecode.globals.len())
+            }
+            Opcode::GetLocal | Opcode::SetLocal | Opcode::AssignLocal | Opcode::ResetLocal => {
+                within("local", operands[0], self.locals)
+            }
+            Opcode::GetFree | Opcode::AssignFree => within("free variable", operands[0], self.free),
+            Opcode::GetBuiltin => within("builtin", operands[0], all_builtin_names().len()),
+            Opcode::Jump | Opcode::JumpIfFalse | Opcode::SetupTry => target(operands[0]),
+            // It takes the place of the frame of a function, which the program does not have.
+            Opcode::TailCall if !self.is_function => Err(String::from("only a function can make tail calls")),
+            Opcode::Iterate => match operands[1] {
+                1 | 2 => target(operands[0]),
+                variables => Err(format!("{} loop variables, a loop has one or two", variables)),
+            },
+            _ => Ok(()),
+        }
+    }
+
+    // Follows every path through the instructions, counting the values on the stack as the
+    // compiler counts them. Paths that meet have to agree on the count.
+    fn check_stack(&self, instructions: &[(usize, Opcode, Vec<usize>)], starts: &[usize]) -> Result<(), String> {
+        let index_of = |offset: usize| starts.binary_search(&offset).ok();
+        let mut depths: Vec<Option<usize>> = vec![None; instructions.len()];
+        let mut pending = vec![(0, 0)];
+        while let Some((offset, depth)) = pending.pop() {
+            let Some(index) = index_of(offset) else {
+                if self.is_function {
+                    return Err(format!("the function ends at {} without returning", offset));
+                }
+                continue;
+            };
+            match depths[index] {
+                Some(known) if known == depth => continue,
+                Some(known) => {
+                    return Err(format!("{} values on the stack at {}, and {} on another path", depth, offset, known))
+                }
+                None => depths[index] = Some(depth),
+            }
+            let (_, opcode, operands) = &instructions[index];
+            let needed = match opcode {
+                Opcode::AssignGlobal | Opcode::AssignLocal | Opcode::AssignFree | Opcode::ReturnValue => 1,
+                Opcode::Iterate => 2,
+                _ => stack_effect(*opcode, operands).0,
+            };
+            if depth < needed {
+                let name = opcode.definition().name;
+                return Err(format!("{} at {} takes {} values, the stack has {}", name, offset, needed, depth));
+            }
+            let (popped, pushed) = stack_effect(*opcode, operands);
+            let after = depth - popped + pushed;
+            let next = instructions.get(index + 1).map_or(self.instructions.0.len(), |(start, _, _)| *start);
+            match opcode {
+                Opcode::ReturnValue | Opcode::TailCall => {}
+                Opcode::Jump => pending.push((operands[0], after)),
+                Opcode::JumpIfFalse => pending.extend([(operands[0], after), (next, after)]),
+                Opcode::Iterate => pending.extend([(operands[0], depth), (next, after)]),
+                // The handler starts with the message of the error on the stack.
+                Opcode::SetupTry => pending.extend([(operands[0], depth + 1), (next, after)]),
+                _ => pending.push((next, after)),
+            }
+        }
+        Ok(())
+    }
+}
+
+struct Writer {
+    bytes: Vec<u8>,
+}
+
+impl Writer {
+    fn count(&mut self, count: usize) -> Result<(), String> {
+        let count = u32::try_from(count).map_err(|_| format!("too large to save: {}", count))?;
+        self.bytes.extend(count.to_be_bytes());
+        Ok(())
+    }
+
+    fn string(&mut self, string: &str) -> Result<(), String> {
+        self.count(string.len())?;
+        self.bytes.extend(string.as_bytes());
+        Ok(())
+    }
+
+    fn code(&mut self, instructions: &Instructions, calls: &[CallSite]) -> Result<(), String> {
+        self.count(instructions.0.len())?;
+        self.bytes.extend(&instructions.0);
+        self.count(calls.len())?;
+        for site in calls {
+            self.count(site.offset)?;
+            self.string(&site.function)?;
+            self.count(site.span.line)?;
+            self.count(site.span.column)?;
+        }
+        Ok(())
+    }
+
+    fn statements(&mut self, statements: &[(usize, Span)]) -> Result<(), String> {
+        self.count(statements.len())?;
+        for (offset, span) in statements {
+            self.count(*offset)?;
+            self.count(span.line)?;
+            self.count(span.column)?;
+        }
+        Ok(())
+    }
+
+    fn constant(&mut self, constant: &Object) -> Result<(), String> {
+        match constant {
+            Object::Integer(value) => {
+                self.bytes.push(INTEGER);
+                self.bytes.extend(value.to_be_bytes());
+            }
+            Object::Float(value) => {
+                self.bytes.push(FLOAT);
+                self.bytes.extend(value.to_bits().to_be_bytes());
+            }
+            Object::Boolean(value) => self.bytes.extend([BOOLEAN, *value as u8]),
+            Object::String(value) => {
+                self.bytes.push(STRING);
+                self.string(value)?;
+            }
+            Object::Null => self.bytes.push(NULL),
+            Object::Array(elements) => {
+                self.bytes.push(ARRAY);
+                self.count(elements.len())?;
+                for element in elements.iter() {
+                    self.constant(element)?;
+                }
+            }
+            Object::Hash(pairs) => {
+                self.bytes.push(HASH);
+                self.count(pairs.len())?;
+                for (key, value) in pairs.iter() {
+                    self.constant(&key.to_object())?;
+                    self.constant(value)?;
+                }
+            }
+            Object::Range(start, end) => {
+                self.bytes.push(RANGE);
+                self.bytes.extend(start.to_be_bytes());
+                self.bytes.extend(end.to_be_bytes());
+            }
+            Object::CompiledFunction(function) => {
+                self.bytes.push(FUNCTION);
+                self.count(function.parameters)?;
+                self.count(function.locals)?;
+                self.count(function.free.len())?;
+                for capture in &function.free {
+                    let (tag, index) = match *capture {
+                        Capture::Local(index) => (LOCAL, index),
+                        Capture::Free(index) => (FREE, index),
+                    };
+                    self.bytes.push(tag);
+                    self.count(index)?;
+                }
+                self.string(&function.source)?;
+                self.code(&function.instructions, &function.calls)?;
+                self.statements(&function.statements)?;
+            }
+            other => return Err(format!("cannot be saved: {}", other.type_name())),
+        }
+        Ok(())
+    }
+}
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+    offset: usize,
+}
+
+impl Reader<'_> {
+    fn take(&mut self, count: usize) -> Result<&[u8], String> {
+        if self.bytes.len() - self.offset < count {
+            return Err(format!("cut off at byte {}", self.bytes.len()));
+        }
+        self.offset += count;
+        Ok(&self.bytes[self.offset - count..self.offset])
+    }
+
+    fn u8(&mut self) -> Result<u8, String> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn u16(&mut self) -> Result<u16, String> {
+        Ok(u16::from_be_bytes(self.take(2)?.try_into().expect("two bytes were taken")))
+    }
+
+    fn u32(&mut self) -> Result<u32, String> {
+        Ok(u32::from_be_bytes(self.take(4)?.try_into().expect("four bytes were taken")))
+    }
+
+    fn i32(&mut self) -> Result<i32, String> {
+        Ok(self.u32()? as i32)
+    }
+
+    fn count(&mut self) -> Result<usize, String> {
+        Ok(self.u32()? as usize)
+    }
+
+    fn string(&mut self) -> Result<String, String> {
+        let offset = self.offset;
+        let length = self.count()?;
+        let bytes = self.take(length)?.to_vec();
+        String::from_utf8(bytes).map_err(|_| format!("a string that is not UTF-8 at byte {}", offset))
+    }
+
+    fn code(&mut self) -> Result<(Instructions, Vec<CallSite>), String> {
+        let length = self.count()?;
+        let instructions = Instructions(self.take(length)?.to_vec());
+        let mut calls = vec![];
+        for _ in 0..self.count()? {
+            let offset = self.count()?;
+            let function = self.string()?;
+            let span = Span { line: self.count()?, column: self.count()? };
+            calls.push(CallSite { offset, function, span });
+        }
+        Ok((instructions, calls))
+    }
+
+    fn statements(&mut self) -> Result<Vec<(usize, Span)>, String> {
+        let mut statements = vec![];
+        for _ in 0..self.count()? {
+            let offset = self.count()?;
+            statements.push((offset, Span { line: self.count()?, column: self.count()? }));
+        }
+        Ok(statements)
+    }
+
+    fn constant(&mut self, nesting: usize) -> Result<Object, String> {
+        let offset = self.offset;
+        let constant = match self.u8()? {
+            INTEGER => Object::Integer(self.i32()?),
+            FLOAT => {
+                let bits = u64::from_be_bytes(self.take(8)?.try_into().expect("eight bytes were taken"));
+                Object::Float(f64::from_bits(bits))
+            }
+            BOOLEAN => Object::Boolean(self.u8()? != 0),
+            STRING => Object::String(self.string()?),
+            NULL => Object::Null,
+            ARRAY | HASH if nesting == MAX_NESTING => {
+                return Err(format!("constants nested deeper than {} at byte {}", MAX_NESTING, offset))
+            }
+            ARRAY => {
+                let mut elements = vec![];
+                for _ in 0..self.count()? {
+                    elements.push(self.constant(nesting + 1)?);
+                }
+                Object::Array(Rc::new(elements))
+            }
+            HASH => {
+                let mut pairs = HashPairs::new();
+                for _ in 0..self.count()? {
+                    let key = self.constant(nesting + 1)?;
+                    let hash_key = key.hash_key().ok_or_else(|| format!("unusable as hash key: {}", key.type_name()))?;
+                    pairs.insert(hash_key, self.constant(nesting + 1)?);
+                }
+                Object::Hash(Rc::new(pairs))
+            }
+            RANGE => Object::Range(self.i32()?, self.i32()?),
+            FUNCTION => {
+                let parameters = self.count()?;
+                let locals = self.count()?;
+                let mut free = vec![];
+                for _ in 0..self.count()? {
+                    let capture = match (self.u8()?, self.count()?) {
+                        (LOCAL, index) => Capture::Local(index),
+                        (FREE, index) => Capture::Free(index),
+                        (tag, _) => return Err(format!("unknown capture {} at byte {}", tag, self.offset - 5)),
+                    };
+                    free.push(capture);
+                }
+                let source = self.string()?;
+                let (instructions, calls) = self.code()?;
+                let statements = self.statements()?;
+                let function = CompiledFunction { instructions, locals, parameters, free, source, calls, statements };
+                Object::CompiledFunction(Rc::new(function))
+            }
+            tag => return Err(format!("unknown constant {} at byte {}", tag, offset)),
+        };
+        Ok(constant)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::code::make;
+    use crate::compiler::compile;
+    use crate::evaluator::Evaluator;
+    use crate::object::HashKey;
+    use crate::syntax_analyzer::parse;
+    use crate::vm::Vm;
+
+    const PROGRAM: &str = "let adder = fn(a) { fn(b) { a + b } }; let s = 0; \
+                           for i, x in [1.5, 2] { s = s + adder(i)(x) }; [s, \"\\\"s\\\"\", 0..2, len(\"abc\")]";
+
+    fn compiled(input: &str) -> Bytecode {
+        compile(&parse(input).unwrap()).unwrap()
+    }
+
+    fn assemble(instructions: &[Vec<u8>], constants: Vec<Object>) -> Bytecode {
+        let instructions = Instructions(instructions.concat());
+        Bytecode { instructions, constants, globals: vec![], locals: 0, calls: vec![], statements: vec![] }
+    }
+
+    fn function(instructions: &[Vec<u8>], free: Vec<Capture>) -> Object {
+        Object::CompiledFunction(Rc::new(CompiledFunction {
+            instructions: Instructions(instructions.concat()),
+            locals: 1,
+            parameters: 1,
+            free,
+            source: String::from("fn(x) { x }"),
+            calls: vec![],
+            statements: vec![],
+        }))
+    }
+
+    fn run(bytecode: Bytecode) -> String {
+        Vm::new(bytecode).run(&mut Evaluator::new().with_step_budget(10_000)).to_string()
+    }
+
+    #[test]
+    fn it_reads_back_what_it_writes() {
+        let bytecode = compiled(PROGRAM);
+        let read = deserialize(&serialize(&bytecode).unwrap()).unwrap();
+        assert_eq!(read.disassemble(), bytecode.disassemble());
+        assert_eq!(read.calls, bytecode.calls);
+        assert_eq!(read.statements, bytecode.statements);
+        assert_eq!(run(read), run(bytecode));
+
+        let mut pairs = HashPairs::new();
+        pairs.insert(HashKey::String(Rc::from("a")), Object::Array(Rc::new(vec![Object::Null])));
+        let constants = vec![
+            Object::Integer(-7),
+            Object::Float(-0.0),
+            Object::Boolean(true),
+            Object::String(String::from("é\n")),
+            Object::Hash(Rc::new(pairs)),
+            Object::Range(-1, 3),
+        ];
+        let bytecode = assemble(&[make(Opcode::Constant, &[4]), make(Opcode::Constant, &[1])], constants);
+        let read = deserialize(&serialize(&bytecode).unwrap()).unwrap();
+        assert_eq!(read.disassemble(), bytecode.disassemble());
+        assert!(matches!(read.constants[1], Object::Float(value) if value.is_sign_negative()));
+    }
+
+    #[test]
+    fn it_refuses_files_it_cannot_read() {
+        let bytes = serialize(&compiled(PROGRAM)).unwrap();
+        assert_eq!(deserialize(b"let x = 1;").err(), Some(String::from("not a compiled monkey program")));
+        let mut newer = bytes.clone();
+        newer[5] = 3;
+        assert_eq!(
+            deserialize(&newer).err(),
+            Some(String::from("compiled for version 3 of the bytecode format, this reads version 2"))
+        );
+        for length in MAGIC.len()..bytes.len() {
+            assert!(deserialize(&bytes[..length]).is_err(), "{}", length);
+        }
+        let mut longer = bytes.clone();
+        longer.push(0);
+        assert_eq!(
+            deserialize(&longer).err(),
+            Some(format!("unexpected bytes after the constants, at byte {}", bytes.len()))
+        );
+        let builtin = Evaluator::new().builtin(Symbol::intern("len")).cloned().map(Object::Builtin).unwrap();
+        assert_eq!(serialize(&assemble(&[], vec![builtin])).err(), Some(String::from("cannot be saved: BUILTIN")));
+    }
+
+    #[test]
+    fn it_survives_damaged_files() {
+        let bytes = serialize(&compiled(PROGRAM)).unwrap();
+        for offset in 0..bytes.len() {
+            for value in [0, 1, 2, 0x7f, 0xff] {
+                let mut damaged = bytes.clone();
+                damaged[offset] = value;
+                if let Ok(bytecode) = deserialize(&damaged) {
+                    run(bytecode);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn it_validates_instructions() {
+        let invalid = |instructions: &[Vec<u8>], constants: Vec<Object>| {
+            validate(&assemble(instructions, constants)).unwrap_err()
+        };
+        assert_eq!(invalid(&[vec![200]], vec![]), "in the program: unknown opcode 200 at 0");
+        assert_eq!(invalid(&[vec![Opcode::Constant as u8, 0]], vec![]), "in the program: OpConstant at 0 is cut off");
+        assert_eq!(
+            invalid(&[make(Opcode::Constant, &[1])], vec![Object::Null]),
+            "in the program: OpConstant at 0: constant 1 out of 1"
+        );
+        assert_eq!(
+            invalid(&[make(Opcode::True, &[]), make(Opcode::Jump, &[2])], vec![]),
+            "in the program: OpJump at 1: jump to 2, which is not an instruction"
+        );
+        assert_eq!(
+            invalid(&[make(Opcode::GetLocal, &[0])], vec![]),
+            "in the program: OpGetLocal at 0: local 0 out of 0"
+        );
+        assert_eq!(
+            invalid(&[make(Opcode::Closure, &[0])], vec![Object::Integer(1)]),
+            "in the program: OpClosure at 0: constant 0 is not a function"
+        );
+        assert_eq!(
+            invalid(&[make(Opcode::Closure, &[0])], vec![function(&[], vec![Capture::Free(0)])]),
+            "in the program: OpClosure at 0: free variable 0 out of 0"
+        );
+        assert_eq!(
+            invalid(&[make(Opcode::True, &[]), make(Opcode::Add, &[])], vec![]),
+            "in the program: OpAdd at 1 takes 2 values, the stack has 1"
+        );
+        let merge = [
+            make(Opcode::True, &[]),
+            make(Opcode::JumpIfFalse, &[8]),
+            make(Opcode::True, &[]),
+            make(Opcode::Jump, &[8]),
+            make(Opcode::Null, &[]),
+        ];
+        assert_eq!(invalid(&merge, vec![]), "in the program: 0 values on the stack at 8, and 1 on another path");
+        let handler = [make(Opcode::SetupTry, &[3]), make(Opcode::Null, &[])];
+        assert_eq!(invalid(&handler, vec![]), "in the program: 1 values on the stack at 3, and 0 on another path");
+        let falls_off = function(&[make(Opcode::GetLocal, &[0])], vec![]);
+        assert_eq!(invalid(&[], vec![falls_off]), "in constant 0: the function ends at 2 without returning");
+        let tail_call = [make(Opcode::GetBuiltin, &[0]), make(Opcode::TailCall, &[0])];
+        assert_eq!(invalid(&tail_call, vec![]), "in the program: OpTailCall at 2: only a function can make tail calls");
+
+        let mut bytecode = compiled("len([])");
+        bytecode.calls[0].offset = 0;
+        assert_eq!(
+            validate(&bytecode).unwrap_err(),
+            "in the program: the call at 0 is not a call instruction in order"
+        );
+        let mut bytecode = compiled("1; 2");
+        bytecode.statements[1].0 = 1;
+        assert_eq!(
+            validate(&bytecode).unwrap_err(),
+            "in the program: the statement at 1 does not start at an instruction in order"
+        );
+    }
+}
@@ -0,0 +1,568 @@
+use crate::evaluator::DEFAULT_MAX_DEPTH;
+use crate::syntax_dump::quote_json;
+
+// The command line of `monkey`: which subcommand to run, with which options, on what.
+#[derive(Debug, PartialEq)]
+pub struct Arguments {
+    pub command: Command,
+    // The options given, with the value of those that take one.
+    options: Vec<(String, Option<String>)>,
+}
+
+#[derive(Debug, PartialEq)]
+pub enum Command {
+    // The REPL, or the program on standard input when that is not a terminal.
+    Repl,
+    // `arguments` is what `args()` gives the script.
+    Run { script: Script, arguments: Vec<String> },
+    Format { files: Vec<String> },
+    Check { files: Vec<String> },
+    Lint { paths: Vec<String> },
+    Test { paths: Vec<String> },
+    Bench { paths: Vec<String> },
+    Ast { file: String },
+    Compile { file: String },
+    // A script, or a program `monkey compile` saved.
+    Disassemble { file: String },
+    Minify { file: String },
+    Highlight { file: String },
+    Doc { paths: Vec<String> },
+    // The explanation of an error code, or the list of codes without one.
+    Explain { code: Option<String> },
+    // The help of one subcommand, or of `monkey` as a whole.
+    Help(Option<&'static str>),
+    Version,
+}
+
+#[derive(Debug, PartialEq)]
+pub enum Script {
+    File(String),
+    // What `-e` was given.
+    Code(String),
+    // The program on standard input, for `-` as the file.
+    Stdin,
+    // The entry point of the project in the current directory, after the modules it depends on.
+    Project,
+}
+
+struct Subcommand {
+    name: &'static str,
+    usage: &'static str,
+    summary: &'static str,
+    options: &'static [&'static str],
+}
+
+const SUBCOMMANDS: [Subcommand; 14] = [
+    Subcommand {
+        name: "repl",
+        usage: "monkey [repl] [options]",
+        summary: "Starts the REPL, or runs the program on standard input when that is not a terminal.",
+        options: &[
+            "--allow-env", "--allow-fs", "--ast", "--color", "--error-format", "--limit-depth", "--limit-size",
+            "--limit-steps", "--limit-time", "--max-memory", "--optimize", "--quiet", "--sandbox", "--seed", "--tokens",
+        ],
+    },
+    Subcommand {
+        name: "run",
+        usage: "monkey [run] [options] [<file> | - | -e <code>] [arguments...]",
+        summary: "Runs a script, the one on standard input for `-`, or prints the value of the code `-e` is \
+                  given. What follows is left for `args()`. Without a script it runs the project that `monkey.toml` \
+                  in the current directory describes. A `.mbc` file that `monkey compile` saved runs on the virtual \
+                  machine.",
+        options: &[
+            "--allow-env", "--allow-fs", "--ast", "--color", "--error-format", "--eval", "--include", "--limit-depth",
+            "--limit-size", "--limit-steps", "--limit-time", "--max-memory", "--optimize", "--profile", "--sandbox",
+            "--seed", "--tokens", "--trace", "--watch",
+        ],
+    },
+    Subcommand {
+        name: "fmt",
+        usage: "monkey fmt [options] <file>...",
+        summary: "Prints files in the canonical layout, standard input for `-`, rewrites them with `--write`, or with \
+                  `--check` shows how the ones that are not in it differ and fails.",
+        options: &["--check", "--color", "--error-format", "--write"],
+    },
+    Subcommand {
+        name: "check",
+        usage: "monkey check [options] <file>...",
+        summary: "Reports the syntax errors in files without running them.",
+        options: &["--color", "--error-format"],
+    },
+    Subcommand {
+        name: "lint",
+        usage: "monkey lint [options] [<path>...]",
+        summary: "Warns about unused variables and parameters and unreachable code in the `*.monkey` files under the \
+                  paths, the current directory by default.",
+        options: &["--color", "--deny-warnings", "--error-format"],
+    },
+    Subcommand {
+        name: "test",
+        usage: "monkey test [options] [<path>...]",
+        summary: "Runs the `*_test.monkey` files under the paths, the current directory by default.",
+        options: &[
+            "--allow-env", "--allow-fs", "--color", "--coverage", "--error-format", "--limit-depth", "--limit-size",
+            "--limit-steps", "--limit-time", "--max-memory", "--optimize", "--seed",
+        ],
+    },
+    Subcommand {
+        name: "bench",
+        usage: "monkey bench [options] [<path>...]",
+        summary: "Times the `bench_` functions of the `*_bench.monkey` files under the paths.",
+        options: &["--allow-env", "--allow-fs", "--baseline", "--color", "--error-format", "--optimize", "--save"],
+    },
+    Subcommand {
+        name: "ast",
+        usage: "monkey ast [options] <file>",
+        summary: "Prints the syntax tree of a file, as an indented tree unless `--format` says otherwise.",
+        options: &["--color", "--error-format", "--format"],
+    },
+    Subcommand {
+        name: "compile",
+        usage: "monkey compile [options] <file>",
+        summary: "Compiles a script to bytecode, which `monkey run` runs without parsing it again, and saves it next \
+                  to the script as `.mbc` unless `--output` names another file.",
+        options: &["--color", "--error-format", "--optimize", "--output"],
+    },
+    Subcommand {
+        name: "disasm",
+        usage: "monkey disasm [options] <file>",
+        summary: "Prints the bytecode of a script, or of a `.mbc` file: the instructions, with the names and positions \
+                  of what they call, the constants and the globals.",
+        options: &["--color", "--error-format", "--optimize"],
+    },
+    Subcommand {
+        name: "minify",
+        usage: "monkey minify [options] <file>",
+        summary: "Prints a file on one line without its comments and with no more spaces than it needs.",
+        options: &["--color", "--error-format"],
+    },
+    Subcommand {
+        name: "highlight",
+        usage: "monkey highlight [options] <file>",
+        summary: "Prints a file with its syntax highlighted, as HTML for a web page unless `--format ansi` asks for \
+                  terminal colors.",
+        options: &["--color", "--format"],
+    },
+    Subcommand {
+        name: "doc",
+        usage: "monkey doc [options] [<path>...]",
+        summary: "Prints documentation for the top-level `let`s with a comment above them in the `*.monkey` files \
+                  under the paths, as Markdown or with `--format html` as a web page.",
+        options: &["--color", "--error-format", "--format"],
+    },
+    Subcommand {
+        name: "explain",
+        usage: "monkey explain [options] [<code>]",
+        summary: "Describes the error a code such as E0105 in an error message stands for, with examples, or lists \
+                  the codes.",
+        options: &["--color"],
+    },
+];
+
+// Options that ask for what the other one rules out, so that neither is dropped without a word.
+const CONFLICTING_OPTIONS: [(&str, &str); 6] = [
+    ("--tokens", "--ast"),
+    ("--check", "--write"),
+    // The evaluator takes one set of hooks.
+    ("--profile", "--trace"),
+    // The sandbox takes away filesystem and environment access.
+    ("--sandbox", "--allow-fs"),
+    ("--sandbox", "--allow-env"),
+    // Folding constants makes new statements, which are not the ones in the files.
+    ("--coverage", "--optimize"),
+];
+
+// An option followed by a placeholder takes a value, as `--save out.txt` or `--save=out.txt`. One
+// with the placeholder in brackets takes it only in the second form.
+const OPTIONS: [(&str, &str); 31] = [
+    ("--allow-env", "lets scripts read environment variables"),
+    ("--allow-fs", "lets scripts read and write files"),
+    ("--ast", "prints the syntax tree of the program instead of running it"),
+    ("--baseline <file>", "compares the results with the ones `--save` saved"),
+    ("--check", "prints a diff for the files that are not formatted and fails, without changing them"),
+    ("--color <when>", "colors the output always, never or on a terminal (auto, the default)"),
+    ("--coverage[=<file>]", "reports which statements the tests ran, and saves them to `coverage.html` or the file"),
+    ("--deny-warnings", "fails when there are warnings"),
+    ("--error-format <format>", "prints errors and warnings as text, or as a JSON object on each line with `json`"),
+    ("-e, --eval", "runs the code given on the command line"),
+    ("--format <format>", "json, sexpr or tree for ast, html or ansi for highlight, markdown or html for doc"),
+    ("-I, --include <dir>", "looks for a project's modules in the directory too, before those in MONKEY_PATH"),
+    ("--limit-depth <n>", "stops programs that nest calls deeper than this"),
+    ("--limit-size <n>", "stops programs that make a string, array or hash bigger than this"),
+    ("--limit-steps <n>", "stops programs that take more steps than this"),
+    ("--timeout, --limit-time <time>", "stops programs that run longer than this, as in 500ms, 5s or 2m"),
+    ("--max-memory <size>", "stops programs once they use more memory than this, as in 512K, 64M or 1G"),
+    ("--optimize", "folds constant expressions before running or compiling the program"),
+    ("-o, --output <file>", "saves the compiled program to the file"),
+    ("--profile[=<file>]", "times every call, saves the stacks for flame graphs to `profile.folded` or the file"),
+    ("--quiet", "leaves out the REPL's welcome banner"),
+    ("--sandbox", "limits what each input may do, for untrusted code"),
+    ("--save <file>", "saves the results for comparing later ones with"),
+    ("--seed <n>", "starts random() from the seed, so that every run gets the same numbers"),
+    ("--tokens", "prints the tokens of the program instead of running it"),
+    ("--trace[=<mode>]", "prints every expression evaluated, or every call with `=calls`, to standard error"),
+    ("--verbose", "shows the commit, features and default limits too with `--version`, as JSON"),
+    ("--watch", "runs the script, or the project, again whenever one of its files changes"),
+    ("--write", "rewrites the files instead of printing them"),
+    ("-h, --help", "shows this help, or a subcommand's with `monkey <subcommand> --help`"),
+    ("-V, --version", "shows the version"),
+];
+
+impl Arguments {
+    // Options may come before the subcommand or after it, but everything after a script is the
+    // script's. Without a subcommand `monkey` runs the script it is given, or starts the REPL.
+    pub fn parse(arguments: impl IntoIterator<Item = String>) -> Result<Arguments, String> {
+        let mut remaining = arguments.into_iter();
+        let mut subcommand = None;
+        let mut options = vec![];
+        let mut code = None;
+        let mut script = None;
+        let mut positionals = vec![];
+        let (mut wants_help, mut wants_version) = (false, false);
+        while let Some(argument) = remaining.next() {
+            let is_first = subcommand.is_none() && positionals.is_empty() && code.is_none();
+            match argument.as_str() {
+                _ if script.is_some() => positionals.push(argument),
+                "-h" | "--help" => wants_help = true,
+                "-V" | "--version" => wants_version = true,
+                "-e" | "--eval" => match remaining.next() {
+                    Some(text) => code = Some(text),
+                    None => return Err(String::from("usage: monkey -e <code>")),
+                },
+                option if option.starts_with('-') && option != "-" => {
+                    let (name, value) = match option.split_once('=') {
+                        Some((name, value)) => (name, Some(String::from(value))),
+                        None => (option, None),
+                    };
+                    let known = OPTIONS.iter().find(|(names, _)| option_names(names).any(|known| known == name));
+                    let (takes_value, may_take_value) = match known {
+                        Some((names, _)) => (names.contains(" <"), names.contains("[=")),
+                        None => return Err(format!("unknown option: {}", name)),
+                    };
+                    let value = match (takes_value || may_take_value, value) {
+                        (true, None) if may_take_value => None,
+                        (true, None) => match remaining.next() {
+                            Some(value) => Some(value),
+                            None => return Err(format!("{} needs a value", name)),
+                        },
+                        (false, Some(_)) => return Err(format!("{} does not take a value", name)),
+                        (_, value) => value,
+                    };
+                    // Options are kept under their long name, so `-I` is found as `--include`.
+                    let name = known.and_then(|(names, _)| option_names(names).last()).unwrap_or(name);
+                    options.push((String::from(name), value));
+                }
+                "help" if is_first && !wants_help => wants_help = true,
+                name if is_first && find(name).is_some() => subcommand = find(name),
+                _ if code.is_none() && subcommand.is_none_or(|subcommand| subcommand.name == "run") => {
+                    script = Some(argument)
+                }
+                _ => positionals.push(argument),
+            }
+        }
+
+        if wants_version {
+            return Ok(Arguments { command: Command::Version, options });
+        }
+        if wants_help {
+            return Ok(Arguments { command: Command::Help(subcommand.map(|subcommand| subcommand.name)), options });
+        }
+        let subcommand = match subcommand {
+            Some(subcommand) => subcommand,
+            None if script.is_some() || code.is_some() => &SUBCOMMANDS[1],
+            None => &SUBCOMMANDS[0],
+        };
+        let usage = || format!("usage: {}", subcommand.usage);
+        if let Some((option, _)) = options.iter().find(|(option, _)| !subcommand.options.contains(&option.as_str())) {
+            return Err(format!("{} does not apply to monkey {}\n{}", option, subcommand.name, usage()));
+        }
+        let has = |name: &str| options.iter().any(|(option, _)| option == name);
+        if let Some((first, second)) = CONFLICTING_OPTIONS.iter().find(|(first, second)| has(first) && has(second)) {
+            return Err(format!("{} and {} cannot be used together\n{}", first, second, usage()));
+        }
+        // A script has no modules to look for, only a project does.
+        if (code.is_some() || script.is_some()) && options.iter().any(|(option, _)| option == "--include") {
+            return Err(format!("--include only applies to running a project, without a script\n{}", usage()));
+        }
+
+        let command = match (subcommand.name, code, script) {
+            ("repl", None, None) if positionals.is_empty() => Command::Repl,
+            ("run", Some(code), None) if !options.iter().any(|(option, _)| option == "--watch") => Command::Run {
+                script: Script::Code(code),
+                arguments: positionals,
+            },
+            // Standard input cannot be watched for changes.
+            ("run", None, Some(file)) if file == "-" && !options.iter().any(|(option, _)| option == "--watch") => {
+                Command::Run { script: Script::Stdin, arguments: positionals }
+            }
+            ("run", None, Some(file)) if file != "-" => Command::Run {
+                script: Script::File(file),
+                arguments: positionals,
+            },
+            ("run", None, None) => Command::Run { script: Script::Project, arguments: positionals },
+            ("fmt", None, None) if !positionals.is_empty() => Command::Format { files: positionals },
+            ("check", None, None) if !positionals.is_empty() => Command::Check { files: positionals },
+            ("lint", None, None) if positionals.is_empty() => Command::Lint { paths: vec![String::from(".")] },
+            ("lint", None, None) => Command::Lint { paths: positionals },
+            ("test", None, None) if positionals.is_empty() => Command::Test { paths: vec![String::from(".")] },
+            ("test", None, None) => Command::Test { paths: positionals },
+            ("bench", None, None) if positionals.is_empty() => Command::Bench { paths: vec![String::from(".")] },
+            ("bench", None, None) => Command::Bench { paths: positionals },
+            ("ast", None, None) if positionals.len() == 1 => Command::Ast { file: positionals.remove(0) },
+            ("compile", None, None) if positionals.len() == 1 => Command::Compile { file: positionals.remove(0) },
+            ("disasm", None, None) if positionals.len() == 1 => Command::Disassemble { file: positionals.remove(0) },
+            ("minify", None, None) if positionals.len() == 1 => Command::Minify { file: positionals.remove(0) },
+            ("highlight", None, None) if positionals.len() == 1 => {
+                Command::Highlight { file: positionals.remove(0) }
+            }
+            ("doc", None, None) if positionals.is_empty() => Command::Doc { paths: vec![String::from(".")] },
+            ("doc", None, None) => Command::Doc { paths: positionals },
+            ("explain", None, None) if positionals.len() <= 1 => Command::Explain { code: positionals.pop() },
+            _ => return Err(usage()),
+        };
+        Ok(Arguments { command, options })
+    }
+
+    pub fn has(&self, option: &str) -> bool {
+        self.options.iter().any(|(name, _)| name == option)
+    }
+
+    // Every value an option was given, in order.
+    pub fn values(&self, option: &str) -> Vec<&str> {
+        self.options.iter().filter(|(name, _)| name == option).filter_map(|(_, value)| value.as_deref()).collect()
+    }
+
+    // The value of an option that takes one, the last one given when it was given more than once.
+    pub fn value(&self, option: &str) -> Option<&str> {
+        self.options.iter().rev().find(|(name, _)| name == option).and_then(|(_, value)| value.as_deref())
+    }
+}
+
+// The spellings of one of `OPTIONS`, without its placeholder.
+fn option_names(names: &str) -> impl Iterator<Item = &str> {
+    names.split(", ").map(|name| name.split([' ', '[']).next().unwrap_or(name))
+}
+
+fn find(name: &str) -> Option<&'static Subcommand> {
+    SUBCOMMANDS.iter().find(|subcommand| subcommand.name == name)
+}
+
+// What `monkey --help` prints, or `monkey <subcommand> --help` with a subcommand.
+pub fn help(subcommand: Option<&str>) -> String {
+    let options = |names: &[&str]| {
+        let options: Vec<&(&str, &str)> =
+            OPTIONS.iter().filter(|(name, _)| option_names(name).any(|name| names.contains(&name))).collect();
+        let width = options.iter().map(|(name, _)| name.len()).max().unwrap_or(0);
+        let lines: Vec<String> =
+            options.iter().map(|(name, text)| format!("  {:width$}  {}", name, text, width = width)).collect();
+        lines.join("\n")
+    };
+
+    match subcommand.and_then(find) {
+        Some(subcommand) => {
+            let mut names = subcommand.options.to_vec();
+            names.push("--help");
+            format!("usage: {}\n\n{}\n\noptions:\n{}", subcommand.usage, subcommand.summary, options(&names))
+        }
+        None => {
+            let width = SUBCOMMANDS.iter().map(|subcommand| subcommand.name.len()).max().unwrap_or(0);
+            let subcommands: Vec<String> = SUBCOMMANDS
+                .iter()
+                .map(|subcommand| format!("  {:width$}  {}", subcommand.name, subcommand.summary, width = width))
+                .collect();
+            let names: Vec<&str> = OPTIONS.iter().flat_map(|(names, _)| option_names(names)).collect();
+            format!(
+                "{}\n\nusage: monkey [<subcommand>] [options] [<file> [arguments...]]\n\nsubcommands:\n{}\n\n\
+                 options:\n{}",
+                version(),
+                subcommands.join("\n"),
+                options(&names)
+            )
+        }
+    }
+}
+
+pub fn version() -> String {
+    format!("monkey {}", env!("CARGO_PKG_VERSION"))
+}
+
+// What `monkey --version --verbose` prints: the version, the commit it was built from when it was
+// built from a git checkout, the cargo features it was built with, and the limits programs run
+// under when neither `monkey.toml` nor the options set them. No limit is `null`.
+pub fn verbose_version() -> String {
+    let commit = match env!("MONKEY_COMMIT") {
+        "" => String::from("null"),
+        commit => quote_json(commit),
+    };
+    let features = env!("MONKEY_FEATURES").split(',').filter(|feature| !feature.is_empty());
+    let features: Vec<String> = features.map(quote_json).collect();
+    format!(
+        "{{\n  \"version\": {},\n  \"commit\": {},\n  \"features\": [{}],\n  \"limits\": {{\"max_depth\": {}, \
+         \"max_size\": null, \"max_memory\": null, \"steps\": null, \"timeout\": null}}\n}}",
+        quote_json(env!("CARGO_PKG_VERSION")),
+        commit,
+        features.join(", "),
+        DEFAULT_MAX_DEPTH
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(line: &str) -> Result<Arguments, String> {
+        Arguments::parse(line.split_whitespace().map(String::from))
+    }
+
+    fn command(line: &str) -> Command {
+        parse(line).unwrap().command
+    }
+
+    fn strings(items: &[&str]) -> Vec<String> {
+        items.iter().map(|item| String::from(*item)).collect()
+    }
+
+    #[test]
+    fn it_parses_subcommands() {
+        assert_eq!(command(""), Command::Repl);
+        assert_eq!(command("repl --quiet"), Command::Repl);
+        assert_eq!(
+            command("a.monkey 1 --help"),
+            Command::Run { script: Script::File(String::from("a.monkey")), arguments: strings(&["1", "--help"]) }
+        );
+        assert_eq!(
+            command("--allow-fs run test -x"),
+            Command::Run { script: Script::File(String::from("test")), arguments: strings(&["-x"]) }
+        );
+        assert_eq!(
+            command("-e args() a b"),
+            Command::Run { script: Script::Code(String::from("args()")), arguments: strings(&["a", "b"]) }
+        );
+        assert_eq!(
+            command("fmt --write a.monkey b.monkey"),
+            Command::Format { files: strings(&["a.monkey", "b.monkey"]) }
+        );
+        assert_eq!(command("fmt --check -"), Command::Format { files: strings(&["-"]) });
+        assert_eq!(command("run - a"), Command::Run { script: Script::Stdin, arguments: strings(&["a"]) });
+        assert_eq!(command("run --sandbox"), Command::Run { script: Script::Project, arguments: vec![] });
+        assert_eq!(command("check a.monkey"), Command::Check { files: strings(&["a.monkey"]) });
+        assert_eq!(command("lint --deny-warnings"), Command::Lint { paths: strings(&["."]) });
+        assert_eq!(command("test"), Command::Test { paths: strings(&["."]) });
+        assert_eq!(command("test tests"), Command::Test { paths: strings(&["tests"]) });
+        assert_eq!(command("fmt --help"), Command::Help(Some("fmt")));
+        assert_eq!(command("help test"), Command::Help(Some("test")));
+        assert_eq!(command("-h"), Command::Help(None));
+        assert_eq!(command("--version"), Command::Version);
+        assert!(parse("--version --verbose").unwrap().has("--verbose"));
+        assert_eq!(command("bench"), Command::Bench { paths: strings(&["."]) });
+        assert_eq!(command("ast --format json a.monkey"), Command::Ast { file: String::from("a.monkey") });
+        assert_eq!(command("minify a.monkey"), Command::Minify { file: String::from("a.monkey") });
+        assert_eq!(command("highlight a.monkey"), Command::Highlight { file: String::from("a.monkey") });
+        assert_eq!(command("explain E0105"), Command::Explain { code: Some(String::from("E0105")) });
+        assert_eq!(command("explain"), Command::Explain { code: None });
+        assert_eq!(command("disasm a.mbc"), Command::Disassemble { file: String::from("a.mbc") });
+        let arguments = parse("compile -o b.mbc --optimize a.monkey").unwrap();
+        assert_eq!(arguments.command, Command::Compile { file: String::from("a.monkey") });
+        assert_eq!((arguments.value("--output"), arguments.has("--optimize")), (Some("b.mbc"), true));
+        assert_eq!(
+            parse("a.mbc 1").unwrap().command,
+            Command::Run { script: Script::File(String::from("a.mbc")), arguments: strings(&["1"]) }
+        );
+        assert_eq!(command("doc --format html lib"), Command::Doc { paths: strings(&["lib"]) });
+
+        let arguments = parse("--sandbox run --watch a.monkey").unwrap();
+        assert!(arguments.has("--sandbox") && arguments.has("--watch") && !arguments.has("--write"));
+        assert_eq!(parse("run --watch").unwrap().command, Command::Run { script: Script::Project, arguments: vec![] });
+        let arguments = parse("bench --save new.txt --baseline=old.txt benches").unwrap();
+        assert_eq!(arguments.command, Command::Bench { paths: strings(&["benches"]) });
+        assert_eq!((arguments.value("--save"), arguments.value("--baseline")), (Some("new.txt"), Some("old.txt")));
+        assert_eq!(parse("check --color=never a.monkey").unwrap().value("--color"), Some("never"));
+        assert_eq!(parse("test --limit-time 2s").unwrap().value("--limit-time"), Some("2s"));
+        assert_eq!(parse("test --seed 7").unwrap().value("--seed"), Some("7"));
+        assert_eq!(parse("run --timeout=5s a.monkey").unwrap().value("--limit-time"), Some("5s"));
+        let arguments = parse("run --trace a.monkey").unwrap();
+        assert_eq!((arguments.has("--trace"), arguments.value("--trace")), (true, None));
+        assert_eq!(
+            arguments.command,
+            Command::Run { script: Script::File(String::from("a.monkey")), arguments: vec![] }
+        );
+        assert_eq!(parse("run --trace=calls a.monkey").unwrap().value("--trace"), Some("calls"));
+        assert_eq!(parse("run --profile=out.folded a.monkey").unwrap().value("--profile"), Some("out.folded"));
+        assert!(parse("fmt --check a.monkey").unwrap().has("--check"));
+        assert_eq!(parse("test --coverage=out.html tests").unwrap().value("--coverage"), Some("out.html"));
+        assert_eq!(parse("check --error-format json a.monkey").unwrap().value("--error-format"), Some("json"));
+        assert_eq!(parse("run -I lib --include=vendor").unwrap().values("--include"), vec!["lib", "vendor"]);
+    }
+
+    #[test]
+    fn it_reports_usage_errors() {
+        let run_usage = "usage: monkey [run] [options] [<file> | - | -e <code>] [arguments...]";
+        let tests = vec![
+            ("--fast", "unknown option: --fast"),
+            ("-e", "usage: monkey -e <code>"),
+            ("fmt", "usage: monkey fmt [options] <file>..."),
+            ("check", "usage: monkey check [options] <file>..."),
+            ("repl a.monkey", "usage: monkey [repl] [options]"),
+            ("--watch", "--watch does not apply to monkey repl\nusage: monkey [repl] [options]"),
+            ("--watch -e 1", run_usage),
+            ("run --watch -", run_usage),
+            ("bench --save", "--save needs a value"),
+            ("ast a.monkey b.monkey", "usage: monkey ast [options] <file>"),
+            ("test --allow-fs=yes", "--allow-fs does not take a value"),
+            ("compile", "usage: monkey compile [options] <file>"),
+            (
+                "disasm --output a.mbc a.monkey",
+                "--output does not apply to monkey disasm\nusage: monkey disasm [options] <file>",
+            ),
+            (
+                "fmt --check --write a.monkey",
+                "--check and --write cannot be used together\nusage: monkey fmt [options] <file>...",
+            ),
+            (
+                "test --coverage --optimize",
+                "--coverage and --optimize cannot be used together\nusage: monkey test [options] [<path>...]",
+            ),
+        ];
+
+        for (line, expected) in tests {
+            assert_eq!(parse(line), Err(String::from(expected)), "{}", line);
+        }
+        assert_eq!(parse("--write a.monkey"), Err(format!("--write does not apply to monkey run\n{}", run_usage)));
+        for options in ["--tokens --ast", "--profile --trace", "--sandbox --allow-fs", "--sandbox --allow-env"] {
+            let (first, second) = options.split_once(' ').unwrap();
+            let conflict = format!("{} and {} cannot be used together\n{}", first, second, run_usage);
+            assert_eq!(parse(&format!("run {} a.monkey", options)), Err(conflict), "{}", options);
+        }
+        let include = "--include only applies to running a project, without a script";
+        assert_eq!(parse("-I lib a.monkey"), Err(format!("{}\n{}", include, run_usage)));
+        assert_eq!(parse("run --include lib -e 1"), Err(format!("{}\n{}", include, run_usage)));
+    }
+
+    #[test]
+    fn it_reports_the_build() {
+        let report = verbose_version();
+        assert!(report.starts_with("{\n  \"version\": \"0.1.0\",\n  \"commit\": "));
+        assert!(report.ends_with(
+            "\"features\": [],\n  \"limits\": {\"max_depth\": 1000, \"max_size\": null, \"max_memory\": null, \
+             \"steps\": null, \"timeout\": null}\n}"
+        ));
+    }
+
+    #[test]
+    fn it_describes_subcommands() {
+        let help = help(None);
+        assert!(help.starts_with("monkey 0.1.0\n\nusage: monkey [<subcommand>] [options]"));
+        assert!(help.contains("\n  fmt        Prints files in the canonical layout"));
+        assert!(help.contains("\n  -V, --version                   shows the version"));
+        assert_eq!(
+            super::help(Some("fmt")),
+            "usage: monkey fmt [options] <file>...\n\nPrints files in the canonical layout, standard input for `-`, \
+             rewrites them with `--write`, or with `--check` shows how the ones that are not in it differ and \
+             fails.\n\noptions:\n  --check                  prints a diff for the files that are not formatted and \
+             fails, without changing them\n  --color <when>           colors the output always, never or on a \
+             terminal (auto, the default)\n  --error-format <format>  prints errors and warnings as text, or as a \
+             JSON object on each line with `json`\n  --write                  rewrites the files instead of printing \
+             them\n  -h, --help               shows this help, or a subcommand's with `monkey <subcommand> --help`"
+        );
+    }
+}
@@ -0,0 +1,44 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+
+// What `clock()` reads. The fake clock starts at a given time and moves forward by a fixed
+// amount on every reading, so tests that measure durations get the same numbers every run.
+pub enum Clock {
+    System,
+    Fake { now: f64, step: f64 },
+}
+
+impl Clock {
+    // Seconds since the Unix epoch.
+    pub fn now(&mut self) -> f64 {
+        match self {
+            Clock::System => SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|duration| duration.as_secs_f64())
+                .unwrap_or_default(),
+            Clock::Fake { now, step } => {
+                let reading = *now;
+                *now += *step;
+                reading
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_advances_fake_clocks_by_their_step() {
+        let mut clock = Clock::Fake { now: 10.0, step: 0.5 };
+        assert_eq!([clock.now(), clock.now(), clock.now()], [10.0, 10.5, 11.0]);
+    }
+
+    #[test]
+    fn it_reads_the_system_clock() {
+        let mut clock = Clock::System;
+        let first = clock.now();
+        assert!(first > 0.0);
+        assert!(clock.now() >= first);
+    }
+}
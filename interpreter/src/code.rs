@@ -0,0 +1,276 @@
+use core::fmt;
+
+use crate::lexical_analyzer::Span;
+
+// The instructions the compiler emits and the virtual machine runs. Every instruction is an opcode
+// byte followed by its operands, big-endian and as wide as its definition says.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Opcode {
+    Constant,
+    Pop,
+    Add,
+    Subtract,
+    Multiply,
+    Divide,
+    Equal,
+    NotEqual,
+    LessThan,
+    GreaterThan,
+    Range,
+    Minus,
+    Bang,
+    True,
+    False,
+    Null,
+    Array,
+    Hash,
+    Index,
+    GetGlobal,
+    SetGlobal,
+    AssignGlobal,
+    GetLocal,
+    SetLocal,
+    AssignLocal,
+    GetBuiltin,
+    Call,
+    ReturnValue,
+    Jump,
+    JumpIfFalse,
+    Iterate,
+    GetFree,
+    AssignFree,
+    Closure,
+    ResetLocal,
+    TailCall,
+    Dup,
+    SetupTry,
+    PopTry,
+}
+
+pub struct Definition {
+    pub opcode: Opcode,
+    pub name: &'static str,
+    // The width in bytes of every operand.
+    pub operand_widths: &'static [usize],
+}
+
+// By opcode, in the order of `Opcode`.
+const DEFINITIONS: [Definition; 39] = [
+    Definition { opcode: Opcode::Constant, name: "OpConstant", operand_widths: &[2] },
+    Definition { opcode: Opcode::Pop, name: "OpPop", operand_widths: &[] },
+    Definition { opcode: Opcode::Add, name: "OpAdd", operand_widths: &[] },
+    Definition { opcode: Opcode::Subtract, name: "OpSub", operand_widths: &[] },
+    Definition { opcode: Opcode::Multiply, name: "OpMul", operand_widths: &[] },
+    Definition { opcode: Opcode::Divide, name: "OpDiv", operand_widths: &[] },
+    Definition { opcode: Opcode::Equal, name: "OpEqual", operand_widths: &[] },
+    Definition { opcode: Opcode::NotEqual, name: "OpNotEqual", operand_widths: &[] },
+    Definition { opcode: Opcode::LessThan, name: "OpLessThan", operand_widths: &[] },
+    Definition { opcode: Opcode::GreaterThan, name: "OpGreaterThan", operand_widths: &[] },
+    Definition { opcode: Opcode::Range, name: "OpRange", operand_widths: &[] },
+    Definition { opcode: Opcode::Minus, name: "OpMinus", operand_widths: &[] },
+    Definition { opcode: Opcode::Bang, name: "OpBang", operand_widths: &[] },
+    Definition { opcode: Opcode::True, name: "OpTrue", operand_widths: &[] },
+    Definition { opcode: Opcode::False, name: "OpFalse", operand_widths: &[] },
+    Definition { opcode: Opcode::Null, name: "OpNull", operand_widths: &[] },
+    Definition { opcode: Opcode::Array, name: "OpArray", operand_widths: &[2] },
+    Definition { opcode: Opcode::Hash, name: "OpHash", operand_widths: &[2] },
+    Definition { opcode: Opcode::Index, name: "OpIndex", operand_widths: &[] },
+    Definition { opcode: Opcode::GetGlobal, name: "OpGetGlobal", operand_widths: &[2] },
+    Definition { opcode: Opcode::SetGlobal, name: "OpSetGlobal", operand_widths: &[2] },
+    // Sets a global that has to be set already, leaving the value on the stack as `x = 1` is worth it.
+    Definition { opcode: Opcode::AssignGlobal, name: "OpAssignGlobal", operand_widths: &[2] },
+    Definition { opcode: Opcode::GetLocal, name: "OpGetLocal", operand_widths: &[1] },
+    Definition { opcode: Opcode::SetLocal, name: "OpSetLocal", operand_widths: &[1] },
+    Definition { opcode: Opcode::AssignLocal, name: "OpAssignLocal", operand_widths: &[1] },
+    Definition { opcode: Opcode::GetBuiltin, name: "OpGetBuiltin", operand_widths: &[1] },
+    // Calls the function under as many arguments as the operand says.
+    Definition { opcode: Opcode::Call, name: "OpCall", operand_widths: &[1] },
+    Definition { opcode: Opcode::ReturnValue, name: "OpReturnValue", operand_widths: &[] },
+    // Jumps go to the offset the operand says, in the instructions of the function they are in.
+    Definition { opcode: Opcode::Jump, name: "OpJump", operand_widths: &[2] },
+    Definition { opcode: Opcode::JumpIfFalse, name: "OpJumpIfFalse", operand_widths: &[2] },
+    // Takes the next item of the value under the index on top of the stack, pushing as many values
+    // as the second operand says, or jumps to the end of the loop once there is none left.
+    Definition { opcode: Opcode::Iterate, name: "OpIterate", operand_widths: &[2, 1] },
+    Definition { opcode: Opcode::GetFree, name: "OpGetFree", operand_widths: &[1] },
+    Definition { opcode: Opcode::AssignFree, name: "OpAssignFree", operand_widths: &[1] },
+    // Makes a closure of the function constant, capturing the variables the function says.
+    Definition { opcode: Opcode::Closure, name: "OpClosure", operand_widths: &[2] },
+    // Gives a local a new variable, the closures that captured the old one keeping it, as every
+    // iteration of a loop has variables of its own.
+    Definition { opcode: Opcode::ResetLocal, name: "OpResetLocal", operand_widths: &[1] },
+    // A call whose value the function returns, made in the frame of the function making it, which
+    // it takes the place of, as the evaluator makes tail calls.
+    Definition { opcode: Opcode::TailCall, name: "OpTailCall", operand_widths: &[1] },
+    // Pushes the value on top of the stack again, for `&&` and `||` to keep their left side.
+    Definition { opcode: Opcode::Dup, name: "OpDup", operand_widths: &[] },
+    // Starts the body of a `try`. An error in it, that a `try` can catch, takes the stack back to
+    // how it was here and jumps to the handler at the offset the operand says, with the error's
+    // message pushed.
+    Definition { opcode: Opcode::SetupTry, name: "OpSetupTry", operand_widths: &[2] },
+    // Ends the body of the innermost `try` without an error.
+    Definition { opcode: Opcode::PopTry, name: "OpPopTry", operand_widths: &[] },
+];
+
+// Where a closure being made finds a variable it captures: among the locals of the function
+// making it, or among the variables that function captured itself.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Capture {
+    Local(usize),
+    Free(usize),
+}
+
+// A call the function makes, at the offset of its instruction, named and placed as the evaluator
+// names and places the calls of its stack traces.
+#[derive(Clone, Debug, PartialEq)]
+pub struct CallSite {
+    pub offset: usize,
+    pub function: String,
+    pub span: Span,
+}
+
+impl Opcode {
+    pub fn from_byte(byte: u8) -> Option<Opcode> {
+        DEFINITIONS.get(byte as usize).map(|definition| definition.opcode)
+    }
+
+    pub fn definition(self) -> &'static Definition {
+        &DEFINITIONS[self as usize]
+    }
+}
+
+// Encodes one instruction. Operands that do not fit their width are cut to it, the compiler
+// checks its limits before it gets here.
+pub fn make(opcode: Opcode, operands: &[usize]) -> Vec<u8> {
+    let mut instruction = vec![opcode as u8];
+    for (operand, width) in operands.iter().zip(opcode.definition().operand_widths) {
+        instruction.extend_from_slice(&operand.to_be_bytes()[size_of::<usize>() - width..]);
+    }
+    instruction
+}
+
+// Decodes the operands of an instruction that starts with `definition`'s opcode, from the bytes
+// after it, and how many bytes they took.
+pub fn read_operands(definition: &Definition, bytes: &[u8]) -> (Vec<usize>, usize) {
+    let mut operands = vec![];
+    let mut offset = 0;
+    for width in definition.operand_widths {
+        operands.push(read_operand(&bytes[offset..], *width));
+        offset += width;
+    }
+    (operands, offset)
+}
+
+pub fn read_operand(bytes: &[u8], width: usize) -> usize {
+    bytes[..width].iter().fold(0, |operand, byte| operand << 8 | *byte as usize)
+}
+
+// How many values an instruction takes off the stack and how many it puts on it. A return takes
+// its value away from this function only, so it counts as leaving it.
+pub fn stack_effect(opcode: Opcode, operands: &[usize]) -> (usize, usize) {
+    match opcode {
+        Opcode::Constant
+        | Opcode::True
+        | Opcode::False
+        | Opcode::Null
+        | Opcode::GetGlobal
+        | Opcode::GetLocal
+        | Opcode::GetBuiltin
+        | Opcode::GetFree
+        | Opcode::Closure => (0, 1),
+        Opcode::Pop | Opcode::SetGlobal | Opcode::SetLocal | Opcode::JumpIfFalse => (1, 0),
+        Opcode::Add
+        | Opcode::Subtract
+        | Opcode::Multiply
+        | Opcode::Divide
+        | Opcode::Equal
+        | Opcode::NotEqual
+        | Opcode::LessThan
+        | Opcode::GreaterThan
+        | Opcode::Range
+        | Opcode::Index => (2, 1),
+        Opcode::Minus | Opcode::Bang => (1, 1),
+        Opcode::Dup => (1, 2),
+        Opcode::Array => (operands[0], 1),
+        Opcode::Hash => (2 * operands[0], 1),
+        Opcode::Call | Opcode::TailCall => (operands[0] + 1, 1),
+        Opcode::AssignGlobal
+        | Opcode::AssignLocal
+        | Opcode::AssignFree
+        | Opcode::ResetLocal
+        | Opcode::ReturnValue
+        | Opcode::Jump
+        | Opcode::SetupTry
+        | Opcode::PopTry => (0, 0),
+        Opcode::Iterate => (0, operands[1]),
+    }
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Instructions(pub Vec<u8>);
+
+// One instruction per line, after its offset, as in `0003 OpConstant 1`.
+impl fmt::Display for Instructions {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut offset = 0;
+        while offset < self.0.len() {
+            let definition = match Opcode::from_byte(self.0[offset]) {
+                Some(opcode) => opcode.definition(),
+                None => {
+                    writeln!(f, "{:04} unknown opcode {}", offset, self.0[offset])?;
+                    offset += 1;
+                    continue;
+                }
+            };
+            let width: usize = definition.operand_widths.iter().sum();
+            if offset + 1 + width > self.0.len() {
+                writeln!(f, "{:04} {} (cut off)", offset, definition.name)?;
+                break;
+            }
+            let (operands, read) = read_operands(definition, &self.0[offset + 1..]);
+            write!(f, "{:04} {}", offset, definition.name)?;
+            for operand in operands {
+                write!(f, " {}", operand)?;
+            }
+            writeln!(f)?;
+            offset += 1 + read;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_defines_opcodes_in_order() {
+        for (byte, definition) in DEFINITIONS.iter().enumerate() {
+            assert_eq!(definition.opcode as usize, byte, "{}", definition.name);
+        }
+        assert_eq!(Opcode::from_byte(DEFINITIONS.len() as u8), None);
+    }
+
+    #[test]
+    fn it_encodes_and_decodes_operands() {
+        assert_eq!(make(Opcode::Constant, &[65534]), vec![Opcode::Constant as u8, 255, 254]);
+        assert_eq!(make(Opcode::Add, &[]), vec![Opcode::Add as u8]);
+        let definition = Opcode::Constant.definition();
+        assert_eq!(read_operands(definition, &[255, 254]), (vec![65534], 2));
+        assert_eq!(make(Opcode::Iterate, &[258, 2]), vec![Opcode::Iterate as u8, 1, 2, 2]);
+        assert_eq!(read_operands(Opcode::Iterate.definition(), &[1, 2, 2]), (vec![258, 2], 3));
+    }
+
+    #[test]
+    fn it_disassembles_instructions() {
+        let instructions = [make(Opcode::Add, &[]), make(Opcode::Constant, &[2]), make(Opcode::Array, &[65535])];
+        assert_eq!(
+            Instructions(instructions.concat()).to_string(),
+            "0000 OpAdd\n0001 OpConstant 2\n0004 OpArray 65535\n"
+        );
+        assert_eq!(
+            Instructions(vec![255, Opcode::Constant as u8, 1]).to_string(),
+            "0000 unknown opcode 255\n0001 OpConstant (cut off)\n"
+        );
+    }
+}
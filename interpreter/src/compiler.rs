@@ -0,0 +1,1041 @@
+use std::collections::HashMap;
+use std::rc::Rc;
+
+use crate::abstract_syntax_tree::{
+    join, quote_string, BlockStatement, Expression, InfixOperator, PrefixOperator, Program, Statement,
+};
+use crate::builtins::all_builtin_names;
+use crate::code::{make, read_operand, stack_effect, CallSite, Capture, Instructions, Opcode};
+use crate::diagnostics::OUTSIDE_LOOP;
+use crate::evaluator::Frame;
+use crate::interner::Symbol;
+use crate::lexical_analyzer::Span;
+use crate::object::{CompiledFunction, Object};
+use crate::symbol_table::{Binding, Scope, SymbolTable};
+
+// The most constants, globals, or elements in an array or hash literal, a two byte operand can count.
+const MAX_OPERAND: usize = u16::MAX as usize;
+
+// The most locals of a function, or arguments of a call, a one byte operand can count.
+const MAX_SHORT_OPERAND: usize = u8::MAX as usize;
+
+// What the virtual machine runs: the instructions of the program and the values of the literals
+// and functions they refer to by index.
+#[derive(Clone)]
+pub struct Bytecode {
+    pub instructions: Instructions,
+    pub constants: Vec<Object>,
+    // The names of the globals by slot, for the errors about reading one before it is set.
+    pub globals: Vec<Symbol>,
+    // The slots of the program's frame, for the variables of its loops.
+    pub locals: usize,
+    // The calls of the program outside its functions, for stack traces.
+    pub calls: Vec<CallSite>,
+    // Where the statements of the program outside its functions start, for errors to tell where
+    // they happened.
+    pub statements: Vec<(usize, Span)>,
+}
+
+impl Bytecode {
+    // What `monkey disasm` prints: the program's instructions, then every constant, with the
+    // instructions of functions under them, then the globals. Each statement starts with the line
+    // it is on, calls are followed by the name and position of what they call and builtins by
+    // their name.
+    pub fn disassemble(&self) -> String {
+        let mut lines = vec![format!("program ({}):", plural(self.locals, "local"))];
+        lines.extend(disassemble_instructions(&self.instructions, &self.calls, &self.statements));
+        lines.push(String::from("\nconstants:"));
+        for (index, constant) in self.constants.iter().enumerate() {
+            match constant {
+                Object::String(value) => lines.push(format!("{:4}: {}", index, quote_string(value))),
+                Object::CompiledFunction(function) => {
+                    let captures: Vec<String> = function
+                        .free
+                        .iter()
+                        .map(|capture| match capture {
+                            Capture::Local(index) => format!("local {}", index),
+                            Capture::Free(index) => format!("free {}", index),
+                        })
+                        .collect();
+                    lines.push(format!(
+                        "{:4}: {} ({}, {}, captures [{}])",
+                        index,
+                        function.source,
+                        plural(function.parameters, "parameter"),
+                        plural(function.locals, "local"),
+                        captures.join(", ")
+                    ));
+                    let instructions =
+                        disassemble_instructions(&function.instructions, &function.calls, &function.statements);
+                    lines.extend(instructions.into_iter().map(|line| format!("      {}", line)));
+                }
+                constant => lines.push(format!("{:4}: {}", index, constant)),
+            }
+        }
+        lines.push(String::from("\nglobals:"));
+        lines.extend(self.globals.iter().enumerate().map(|(index, name)| format!("{:4}: {}", index, name)));
+        lines.join("\n")
+    }
+}
+
+fn disassemble_instructions(
+    instructions: &Instructions,
+    calls: &[CallSite],
+    statements: &[(usize, Span)],
+) -> Vec<String> {
+    let builtins = all_builtin_names();
+    let mut lines = vec![];
+    for line in instructions.to_string().lines() {
+        let parts: Vec<&str> = line.split(' ').collect();
+        let offset = parts[0].parse::<usize>().ok();
+        for (_, span) in statements.iter().filter(|(start, _)| Some(*start) == offset) {
+            lines.push(format!("; line {}", span.line));
+        }
+        let builtin = match parts[..] {
+            [_, "OpGetBuiltin", index] => index.parse::<usize>().ok().and_then(|index| builtins.get(index)),
+            _ => None,
+        };
+        match (calls.iter().find(|site| Some(site.offset) == offset), builtin) {
+            (Some(site), _) => lines.push(format!("{}  ; {} at {}", line, site.function, site.span)),
+            (None, Some(name)) => lines.push(format!("{}  ; {}", line, name)),
+            (None, None) => lines.push(String::from(line)),
+        }
+    }
+    lines
+}
+
+fn plural(count: usize, noun: &str) -> String {
+    format!("{} {}{}", count, noun, if count == 1 { "" } else { "s" })
+}
+
+// Why a program could not be compiled, with the code of the error when a program that runs can get
+// it too.
+#[derive(Debug, PartialEq)]
+pub struct CompileError {
+    pub message: String,
+    pub code: Option<&'static str>,
+}
+
+pub fn compile(program: &Program) -> Result<Bytecode, CompileError> {
+    let mut compiler = Compiler::new();
+    compiler.compile(program)?;
+    Ok(compiler.bytecode())
+}
+
+#[derive(PartialEq, Eq, Hash)]
+enum Literal {
+    Integer(i32),
+    // By its bits, which tell `0.0` from `-0.0`.
+    Float(u64),
+    String(String),
+}
+
+// The constants of a program. Equal literals share one, wherever they are in the program.
+#[derive(Default)]
+struct ConstantPool {
+    constants: Vec<Object>,
+    literals: HashMap<Literal, usize>,
+}
+
+impl ConstantPool {
+    fn add(&mut self, constant: Object) -> Result<usize, CompileError> {
+        let literal = match &constant {
+            Object::Integer(value) => Some(Literal::Integer(*value)),
+            Object::Float(value) => Some(Literal::Float(value.to_bits())),
+            Object::String(value) => Some(Literal::String(value.clone())),
+            _ => None,
+        };
+        if let Some(&index) = literal.as_ref().and_then(|literal| self.literals.get(literal)) {
+            return Ok(index);
+        }
+        if self.constants.len() > MAX_OPERAND {
+            let message = format!("too many constants (at most {})", MAX_OPERAND + 1);
+            return Err(CompileError { message, code: None });
+        }
+        self.constants.push(constant);
+        if let Some(literal) = literal {
+            self.literals.insert(literal, self.constants.len() - 1);
+        }
+        Ok(self.constants.len() - 1)
+    }
+}
+
+// A function being compiled, or the program.
+#[derive(Default)]
+struct FunctionScope {
+    instructions: Instructions,
+    // How many values the instructions so far leave on the stack, for `break` and `continue` to pop
+    // the ones of the expression they are in. What follows a jump is counted as if it fell through.
+    depth: usize,
+    loops: Vec<Loop>,
+    // How many bodies of `try`s the instructions so far are in, for `break` and `continue` to end
+    // the ones in their loop.
+    tries: usize,
+    calls: Vec<CallSite>,
+    statements: Vec<(usize, Span)>,
+    // The statements being compiled, innermost last, for the code after a block in one of them to
+    // be placed back in it.
+    enclosing: Vec<Span>,
+}
+
+struct Loop {
+    // Where `continue` jumps to.
+    start: usize,
+    // How many values are on the stack in the body, before every statement.
+    depth: usize,
+    // How many bodies of `try`s the loop is in.
+    tries: usize,
+    // The jumps of the `break`s, to point at the end of the loop once it is known.
+    breaks: Vec<usize>,
+}
+
+// Turns a syntax tree into bytecode in a single walk, the way the evaluator walks it. The program,
+// like every block, leaves the value of its last statement on the stack.
+//
+// A global gets its slot the first time its name is seen, where it is defined or not, so that
+// reading one before it is set fails when that runs, as it does in the evaluator, and not while
+// compiling. That is also what lets a function call another one defined after it.
+pub struct Compiler {
+    // Every function being compiled, innermost last, the program first.
+    scopes: Vec<FunctionScope>,
+    constants: ConstantPool,
+    symbols: SymbolTable,
+}
+
+impl Default for Compiler {
+    fn default() -> Self {
+        Compiler::new()
+    }
+}
+
+impl Compiler {
+    pub fn new() -> Self {
+        let mut symbols = SymbolTable::new();
+        for (index, name) in all_builtin_names().into_iter().enumerate() {
+            symbols.define_builtin(index, Symbol::intern(name));
+        }
+        Compiler { scopes: vec![FunctionScope::default()], constants: ConstantPool::default(), symbols }
+    }
+
+    pub fn compile(&mut self, program: &Program) -> Result<(), CompileError> {
+        self.compile_statements(&program.statements, &program.spans)?;
+        count("locals", self.symbols.program_locals(), MAX_SHORT_OPERAND + 1)?;
+        Ok(())
+    }
+
+    pub fn bytecode(mut self) -> Bytecode {
+        let program = self.scopes.swap_remove(0);
+        Bytecode {
+            instructions: program.instructions,
+            constants: self.constants.constants,
+            globals: self.symbols.names().to_vec(),
+            locals: self.symbols.program_locals(),
+            calls: program.calls,
+            statements: program.statements,
+        }
+    }
+
+    // Every value but the last one is popped right away. A block ending in a statement without a
+    // value is worth `null`, as it is to the evaluator.
+    fn compile_statements(&mut self, statements: &[Statement], spans: &[Span]) -> Result<(), CompileError> {
+        let mut leaves_value = false;
+        for (index, statement) in statements.iter().enumerate() {
+            if leaves_value {
+                self.emit(Opcode::Pop, &[]);
+            }
+            let span = spans.get(index).copied();
+            if let Some(span) = span {
+                self.scope_mut().enclosing.push(span);
+                self.place(span);
+            }
+            let compiled = self.compile_statement(statement);
+            if span.is_some() {
+                self.scope_mut().enclosing.pop();
+                if let Some(&outer) = self.scope().enclosing.last() {
+                    self.place(outer);
+                }
+            }
+            leaves_value = compiled?;
+        }
+        if !leaves_value {
+            self.emit(Opcode::Null, &[]);
+        }
+        Ok(())
+    }
+
+    // Whether the statement left a value on the stack.
+    fn compile_statement(&mut self, statement: &Statement) -> Result<bool, CompileError> {
+        match statement {
+            Statement::Expression(expression) => self.compile_expression(expression)?,
+            Statement::Let(name, value) => {
+                self.compile_expression(value)?;
+                let binding = self.symbols.define(*name);
+                self.emit_set(binding);
+                return Ok(false);
+            }
+            Statement::Return(value) => {
+                self.compile_expression(value)?;
+                self.emit(Opcode::ReturnValue, &[]);
+            }
+            Statement::Break | Statement::Continue => {
+                let is_break = matches!(statement, Statement::Break);
+                let scope = self.scope();
+                let Some(innermost) = scope.loops.last() else {
+                    let message = format!("{} outside loop", statement.to_string().trim_end_matches(';'));
+                    return Err(CompileError { message, code: Some(OUTSIDE_LOOP) });
+                };
+                let (depth, start) = (scope.depth, innermost.start);
+                let (values, tries) = (depth - innermost.depth, scope.tries - innermost.tries);
+                for _ in 0..tries {
+                    self.emit(Opcode::PopTry, &[]);
+                }
+                for _ in 0..values {
+                    self.emit(Opcode::Pop, &[]);
+                }
+                if is_break {
+                    let jump = self.emit(Opcode::Jump, &[0]);
+                    self.scope_mut().loops.last_mut().expect("the loop is there").breaks.push(jump);
+                } else {
+                    self.emit(Opcode::Jump, &[start]);
+                }
+                self.scope_mut().depth = depth;
+                return Ok(false);
+            }
+        }
+        Ok(true)
+    }
+
+    // The statements after `if` and `while` are in the block that the `if` or `while` is in, as
+    // they are to the evaluator.
+    fn compile_if_expression(
+        &mut self,
+        condition: &Expression,
+        consequence: &BlockStatement,
+        alternative: Option<&BlockStatement>,
+    ) -> Result<(), CompileError> {
+        self.compile_expression(condition)?;
+        let jump_if_false = self.emit(Opcode::JumpIfFalse, &[0]);
+        let depth = self.scope().depth;
+        self.compile_statements(&consequence.statements, &consequence.spans)?;
+        let jump = self.emit(Opcode::Jump, &[0]);
+        self.patch_jump(jump_if_false)?;
+        self.scope_mut().depth = depth;
+        match alternative {
+            Some(alternative) => self.compile_statements(&alternative.statements, &alternative.spans)?,
+            None => {
+                self.emit(Opcode::Null, &[]);
+            }
+        }
+        self.patch_jump(jump)
+    }
+
+    // `a && b` is worth `a` when that is falsy and `b` otherwise, and `a || b` is worth `a` when
+    // that is truthy, as they are to the evaluator. `b` only runs when it is what the value is.
+    fn compile_logical_expression(
+        &mut self,
+        operator: InfixOperator,
+        left: &Expression,
+        right: &Expression,
+    ) -> Result<(), CompileError> {
+        self.compile_expression(left)?;
+        self.emit(Opcode::Dup, &[]);
+        let jump_if_false = self.emit(Opcode::JumpIfFalse, &[0]);
+        if operator == InfixOperator::Or {
+            let jump = self.emit(Opcode::Jump, &[0]);
+            self.patch_jump(jump_if_false)?;
+            self.emit(Opcode::Pop, &[]);
+            self.compile_expression(right)?;
+            return self.patch_jump(jump);
+        }
+        self.emit(Opcode::Pop, &[]);
+        self.compile_expression(right)?;
+        self.patch_jump(jump_if_false)
+    }
+
+    fn compile_while_expression(&mut self, condition: &Expression, body: &BlockStatement) -> Result<(), CompileError> {
+        let start = self.position();
+        self.compile_expression(condition)?;
+        let jump_if_false = self.emit(Opcode::JumpIfFalse, &[0]);
+        let breaks = self.compile_loop_body(start, body)?;
+        for jump in breaks.into_iter().chain([jump_if_false]) {
+            self.patch_jump(jump)?;
+        }
+        self.emit(Opcode::Null, &[]);
+        Ok(())
+    }
+
+    // The value iterated over and the position in it stay on the stack for the whole loop. The
+    // loop variables, and the names the body defines, get slots of their own, which get new
+    // variables before every iteration, as the evaluator makes an environment for each.
+    fn compile_for_expression(
+        &mut self,
+        variables: &[Symbol],
+        iterable: &Expression,
+        body: &BlockStatement,
+    ) -> Result<(), CompileError> {
+        self.compile_expression(iterable)?;
+        self.emit_constant(Object::Integer(0))?;
+        let start = self.position();
+        let iterate = self.emit(Opcode::Iterate, &[0, variables.len()]);
+        let jump_to_reset = self.emit(Opcode::Jump, &[0]);
+        let body_start = self.position();
+        self.symbols.enter_block(declared_names(&body.statements));
+        for variable in variables.iter().rev() {
+            let binding = self.symbols.define(*variable);
+            self.emit_set(binding);
+        }
+        let breaks = self.compile_loop_body(start, body);
+        let slots = self.symbols.leave_block();
+        let breaks = breaks?;
+        self.patch_jump(jump_to_reset)?;
+        for slot in slots {
+            self.emit(Opcode::ResetLocal, &[slot]);
+        }
+        self.emit(Opcode::Jump, &[body_start]);
+        for jump in breaks.into_iter().chain([iterate]) {
+            self.patch_jump(jump)?;
+        }
+        self.emit(Opcode::Pop, &[]);
+        self.emit(Opcode::Pop, &[]);
+        self.emit(Opcode::Null, &[]);
+        Ok(())
+    }
+
+    // Runs the body and jumps back to `start`, returning the jumps of the `break`s for the loop to
+    // point at its end, where the stack has to be as it was before the body. The jumps back are to
+    // offsets before that end, which patching the jumps to it checks are in reach.
+    fn compile_loop_body(&mut self, start: usize, body: &BlockStatement) -> Result<Vec<usize>, CompileError> {
+        let (depth, tries) = (self.scope().depth, self.scope().tries);
+        self.scope_mut().loops.push(Loop { start, depth, tries, breaks: vec![] });
+        let compiled = self.compile_statements(&body.statements, &body.spans);
+        let innermost = self.scope_mut().loops.pop().expect("the loop was just pushed");
+        compiled?;
+        self.emit(Opcode::Pop, &[]);
+        self.emit(Opcode::Jump, &[start]);
+        Ok(innermost.breaks)
+    }
+
+    // The body runs in the block the `try` is in, as it does in the evaluator, and the handler in a
+    // block of its own where the variable holds the error's message, which the virtual machine
+    // pushes in place of what the body left on the stack. The handler's names get new variables
+    // every time it runs, as they do for the body of a `for`.
+    fn compile_try_expression(
+        &mut self,
+        body: &BlockStatement,
+        variable: Symbol,
+        handler: &BlockStatement,
+    ) -> Result<(), CompileError> {
+        let setup_try = self.emit(Opcode::SetupTry, &[0]);
+        let depth = self.scope().depth;
+        self.scope_mut().tries += 1;
+        let compiled = self.compile_statements(&body.statements, &body.spans);
+        self.scope_mut().tries -= 1;
+        compiled?;
+        self.emit(Opcode::PopTry, &[]);
+        let jump_over_handler = self.emit(Opcode::Jump, &[0]);
+        self.patch_jump(setup_try)?;
+        self.scope_mut().depth = depth + 1;
+        let jump_to_reset = self.emit(Opcode::Jump, &[0]);
+        let handler_start = self.position();
+        self.symbols.enter_block(declared_names(&handler.statements));
+        let binding = self.symbols.define(variable);
+        self.emit_set(binding);
+        let compiled = self.compile_statements(&handler.statements, &handler.spans);
+        let slots = self.symbols.leave_block();
+        compiled?;
+        let jump_to_end = self.emit(Opcode::Jump, &[0]);
+        self.patch_jump(jump_to_reset)?;
+        for slot in slots {
+            self.emit(Opcode::ResetLocal, &[slot]);
+        }
+        self.emit(Opcode::Jump, &[handler_start]);
+        self.patch_jump(jump_over_handler)?;
+        self.patch_jump(jump_to_end)
+    }
+
+    fn compile_expression(&mut self, expression: &Expression) -> Result<(), CompileError> {
+        match expression {
+            Expression::Integer(value) => self.emit_constant(Object::Integer(*value))?,
+            Expression::Float(value) => self.emit_constant(Object::Float(*value))?,
+            Expression::String(value) => self.emit_constant(Object::String(value.clone()))?,
+            Expression::Boolean(true) => self.emit(Opcode::True, &[]),
+            Expression::Boolean(false) => self.emit(Opcode::False, &[]),
+            Expression::Null => self.emit(Opcode::Null, &[]),
+            Expression::Array(elements) => {
+                for element in elements {
+                    self.compile_expression(element)?;
+                }
+                self.emit(Opcode::Array, &[count("array elements", elements.len(), MAX_OPERAND)?])
+            }
+            Expression::Hash(pairs) => {
+                for (key, value) in pairs {
+                    self.compile_expression(key)?;
+                    self.compile_expression(value)?;
+                }
+                self.emit(Opcode::Hash, &[count("hash pairs", pairs.len(), MAX_OPERAND)?])
+            }
+            Expression::Prefix(operator, right) => {
+                self.compile_expression(right)?;
+                match operator {
+                    PrefixOperator::Bang => self.emit(Opcode::Bang, &[]),
+                    PrefixOperator::Minus => self.emit(Opcode::Minus, &[]),
+                }
+            }
+            Expression::Infix(operator @ (InfixOperator::And | InfixOperator::Or), left, right) => {
+                return self.compile_logical_expression(*operator, left, right)
+            }
+            Expression::Infix(operator, left, right) => {
+                self.compile_expression(left)?;
+                self.compile_expression(right)?;
+                self.emit(infix_opcode(*operator), &[])
+            }
+            Expression::Index(left, index) => {
+                self.compile_expression(left)?;
+                self.compile_expression(index)?;
+                self.emit(Opcode::Index, &[])
+            }
+            Expression::Identifier(name) => {
+                let binding = match self.symbols.resolve(*name) {
+                    Some(binding) => binding,
+                    None => self.symbols.define_global(*name),
+                };
+                match binding {
+                    Binding { scope: Scope::Global, index } => self.emit(Opcode::GetGlobal, &[index]),
+                    Binding { scope: Scope::Local, index } => self.emit(Opcode::GetLocal, &[index]),
+                    Binding { scope: Scope::Builtin, index } => self.emit(Opcode::GetBuiltin, &[index]),
+                    Binding { scope: Scope::Free, index } => self.emit(Opcode::GetFree, &[index]),
+                }
+            }
+            // Builtins cannot be assigned to, their name gets a global slot that is never set for
+            // assigning to one to fail the way it does for a name that is not defined.
+            Expression::Assign(name, value) => {
+                self.compile_expression(value)?;
+                match self.symbols.resolve(*name) {
+                    Some(Binding { scope: Scope::Global, index }) => self.emit(Opcode::AssignGlobal, &[index]),
+                    Some(Binding { scope: Scope::Local, index }) => self.emit(Opcode::AssignLocal, &[index]),
+                    Some(Binding { scope: Scope::Builtin, .. }) => {
+                        let slot = self.symbols.reserve_global(*name);
+                        self.emit(Opcode::AssignGlobal, &[slot])
+                    }
+                    Some(Binding { scope: Scope::Free, index }) => self.emit(Opcode::AssignFree, &[index]),
+                    None => {
+                        let binding = self.symbols.define_global(*name);
+                        self.emit(Opcode::AssignGlobal, &[binding.index])
+                    }
+                }
+            }
+            Expression::Function { parameters, body } => {
+                self.scopes.push(FunctionScope::default());
+                self.symbols = SymbolTable::new_enclosed(std::mem::take(&mut self.symbols));
+                self.symbols.declare(declared_names(&body.statements));
+                for parameter in parameters {
+                    self.symbols.define(*parameter);
+                }
+                let compiled = self.compile_statements(&body.statements, &body.spans);
+                self.emit(Opcode::ReturnValue, &[]);
+                let mut scope = self.scopes.pop().expect("the function's scope was just pushed");
+                mark_tail_calls(&mut scope.instructions, &scope.calls, &tail_calls(body));
+                let symbols = std::mem::take(&mut self.symbols);
+                let locals = symbols.names().len();
+                let free: Vec<Capture> = symbols
+                    .free()
+                    .iter()
+                    .map(|binding| match binding.scope {
+                        Scope::Free => Capture::Free(binding.index),
+                        _ => Capture::Local(binding.index),
+                    })
+                    .collect();
+                self.symbols = symbols.into_outer().expect("the function's table encloses another one");
+                compiled?;
+                count("free variables", free.len(), MAX_SHORT_OPERAND + 1)?;
+                let function = CompiledFunction {
+                    instructions: scope.instructions,
+                    locals: count("locals", locals, MAX_SHORT_OPERAND + 1)?,
+                    parameters: parameters.len(),
+                    free,
+                    source: format!("fn({}) {}", join(parameters, ", "), body),
+                    calls: scope.calls,
+                    statements: scope.statements,
+                };
+                let index = self.constants.add(Object::CompiledFunction(Rc::new(function)))?;
+                self.emit(Opcode::Closure, &[index])
+            }
+            Expression::Call { function: callee, arguments, span } => {
+                self.compile_expression(callee)?;
+                for argument in arguments {
+                    self.compile_expression(argument)?;
+                }
+                let offset = self.emit(Opcode::Call, &[count("arguments", arguments.len(), MAX_SHORT_OPERAND)?]);
+                let Frame { function, span, .. } = Frame::new(callee, *span);
+                self.scope_mut().calls.push(CallSite { offset, function, span });
+                offset
+            }
+            Expression::If { condition, consequence, alternative } => {
+                return self.compile_if_expression(condition, consequence, alternative.as_ref())
+            }
+            Expression::While { condition, body } => return self.compile_while_expression(condition, body),
+            Expression::For { variables, iterable, body } => {
+                return self.compile_for_expression(variables, iterable, body)
+            }
+            Expression::Try { body, variable, handler } => {
+                return self.compile_try_expression(body, *variable, handler)
+            }
+        };
+        Ok(())
+    }
+
+    fn emit_set(&mut self, binding: Binding) {
+        match binding {
+            Binding { scope: Scope::Global, index } => self.emit(Opcode::SetGlobal, &[index]),
+            Binding { index, .. } => self.emit(Opcode::SetLocal, &[index]),
+        };
+    }
+
+    fn emit_constant(&mut self, constant: Object) -> Result<usize, CompileError> {
+        let index = self.constants.add(constant)?;
+        Ok(self.emit(Opcode::Constant, &[index]))
+    }
+
+    // Appends an instruction to the function being compiled and returns where it starts.
+    fn emit(&mut self, opcode: Opcode, operands: &[usize]) -> usize {
+        let scope = self.scope_mut();
+        let position = scope.instructions.0.len();
+        scope.instructions.0.extend(make(opcode, operands));
+        let (popped, pushed) = stack_effect(opcode, operands);
+        scope.depth = scope.depth - popped + pushed;
+        position
+    }
+
+    // Points the jump at `position` to the end of the instructions so far.
+    fn patch_jump(&mut self, position: usize) -> Result<(), CompileError> {
+        let target = count("instructions to jump over", self.position(), MAX_OPERAND)?;
+        let instructions = &mut self.scope_mut().instructions.0;
+        instructions[position + 1..position + 3].copy_from_slice(&(target as u16).to_be_bytes());
+        Ok(())
+    }
+
+    // Notes that the instructions from here on are of the statement at `span`.
+    fn place(&mut self, span: Span) {
+        let position = self.position();
+        let statements = &mut self.scope_mut().statements;
+        match statements.last_mut() {
+            Some(last) if last.0 == position => last.1 = span,
+            Some(last) if last.1 == span => {}
+            _ => statements.push((position, span)),
+        }
+    }
+
+    fn position(&self) -> usize {
+        self.scope().instructions.0.len()
+    }
+
+    fn scope(&self) -> &FunctionScope {
+        self.scopes.last().expect("the program's scope is never left")
+    }
+
+    fn scope_mut(&mut self) -> &mut FunctionScope {
+        self.scopes.last_mut().expect("the program's scope is never left")
+    }
+}
+
+// The spans of the calls the evaluator makes as tail calls in the body of a function: the last
+// statement's, and the last statements' of the branches of an `if` that is last.
+fn tail_calls(body: &BlockStatement) -> Vec<Span> {
+    let mut spans = vec![];
+    let mut blocks = vec![body];
+    while let Some(block) = blocks.pop() {
+        let Some(Statement::Expression(expression) | Statement::Return(expression)) = block.statements.last() else {
+            continue;
+        };
+        match expression {
+            Expression::Call { span, .. } => spans.push(*span),
+            Expression::If { consequence, alternative, .. } => {
+                blocks.push(consequence);
+                blocks.extend(alternative);
+            }
+            _ => {}
+        }
+    }
+    spans
+}
+
+// Makes those calls tail calls. The function returns their value right away, only jumps coming in
+// between, which leave the stack as it is, as they do from the end of an `if` to the return.
+fn mark_tail_calls(instructions: &mut Instructions, calls: &[CallSite], tail_calls: &[Span]) {
+    let bytes = &mut instructions.0;
+    let returns_from = |mut offset: usize| {
+        // Jumps only lead forward from the end of a branch, the bound is for ones that would not.
+        for _ in 0..bytes.len() {
+            match bytes.get(offset).copied().and_then(Opcode::from_byte) {
+                Some(Opcode::ReturnValue) => return true,
+                Some(Opcode::Jump) => offset = read_operand(&bytes[offset + 1..], 2),
+                _ => return false,
+            }
+        }
+        false
+    };
+    let width = 1 + Opcode::Call.definition().operand_widths[0];
+    let offsets: Vec<usize> = calls
+        .iter()
+        .filter(|site| tail_calls.contains(&site.span) && returns_from(site.offset + width))
+        .map(|site| site.offset)
+        .collect();
+    for offset in offsets {
+        bytes[offset] = Opcode::TailCall as u8;
+    }
+}
+
+// The names the statements define with `let` in the environment they run in, which is also the
+// one of the blocks of their `if`s, `while`s and `try`s, but not of their functions and `for`s.
+fn declared_names(statements: &[Statement]) -> Vec<Symbol> {
+    let mut names = vec![];
+    for statement in statements {
+        match statement {
+            Statement::Let(name, value) => {
+                names.push(*name);
+                declare_in_expression(value, &mut names);
+            }
+            Statement::Return(value) | Statement::Expression(value) => declare_in_expression(value, &mut names),
+            Statement::Break | Statement::Continue => {}
+        }
+    }
+    names
+}
+
+fn declare_in_expression(expression: &Expression, names: &mut Vec<Symbol>) {
+    match expression {
+        Expression::Array(elements) => elements.iter().for_each(|element| declare_in_expression(element, names)),
+        Expression::Hash(pairs) => {
+            for (key, value) in pairs {
+                declare_in_expression(key, names);
+                declare_in_expression(value, names);
+            }
+        }
+        Expression::Prefix(_, right) | Expression::Assign(_, right) => declare_in_expression(right, names),
+        Expression::Infix(_, left, right) | Expression::Index(left, right) => {
+            declare_in_expression(left, names);
+            declare_in_expression(right, names);
+        }
+        Expression::If { condition, consequence, alternative } => {
+            declare_in_expression(condition, names);
+            names.extend(declared_names(&consequence.statements));
+            if let Some(alternative) = alternative {
+                names.extend(declared_names(&alternative.statements));
+            }
+        }
+        Expression::While { condition, body } => {
+            declare_in_expression(condition, names);
+            names.extend(declared_names(&body.statements));
+        }
+        Expression::For { iterable, .. } => declare_in_expression(iterable, names),
+        Expression::Try { body, .. } => names.extend(declared_names(&body.statements)),
+        Expression::Call { function, arguments, .. } => {
+            declare_in_expression(function, names);
+            arguments.iter().for_each(|argument| declare_in_expression(argument, names));
+        }
+        _ => {}
+    }
+}
+
+fn infix_opcode(operator: InfixOperator) -> Opcode {
+    match operator {
+        InfixOperator::Plus => Opcode::Add,
+        InfixOperator::Minus => Opcode::Subtract,
+        InfixOperator::Star => Opcode::Multiply,
+        InfixOperator::Slash => Opcode::Divide,
+        InfixOperator::Equals => Opcode::Equal,
+        InfixOperator::NotEquals => Opcode::NotEqual,
+        InfixOperator::LessThan => Opcode::LessThan,
+        InfixOperator::GreaterThan => Opcode::GreaterThan,
+        InfixOperator::Range => Opcode::Range,
+        InfixOperator::And | InfixOperator::Or => unreachable!("logical operators are compiled to jumps"),
+    }
+}
+
+fn count(what: &str, count: usize, max: usize) -> Result<usize, CompileError> {
+    if count > max {
+        return Err(CompileError { message: format!("too many {} (at most {})", what, max), code: None });
+    }
+    Ok(count)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::abstract_syntax_tree::join;
+    use crate::syntax_analyzer::parse;
+
+    fn compile_input(input: &str) -> (String, String) {
+        let bytecode = compile(&parse(input).unwrap()).unwrap();
+        (bytecode.instructions.to_string(), join(&bytecode.constants, ", "))
+    }
+
+    #[test]
+    fn it_compiles_arithmetic() {
+        assert_eq!(
+            compile_input("1 + 2 * 3; -1.5"),
+            (
+                String::from(
+                    "\
+0000 OpConstant 0
+0003 OpConstant 1
+0006 OpConstant 2
+0009 OpMul
+0010 OpAdd
+0011 OpPop
+0012 OpConstant 3
+0015 OpMinus
+"
+                ),
+                String::from("1, 2, 3, 1.5")
+            )
+        );
+        assert_eq!(compile_input("!true == false").0, "0000 OpTrue\n0001 OpBang\n0002 OpFalse\n0003 OpEqual\n");
+        assert_eq!(compile_input("1 < 2").0, "0000 OpConstant 0\n0003 OpConstant 1\n0006 OpLessThan\n");
+        assert_eq!(compile_input("").0, "0000 OpNull\n");
+    }
+
+    #[test]
+    fn it_compiles_arrays_hashes_and_indexes() {
+        assert_eq!(
+            compile_input("{\"a\": [1, 2]}[\"a\"]"),
+            (
+                String::from(
+                    "\
+0000 OpConstant 0
+0003 OpConstant 1
+0006 OpConstant 2
+0009 OpArray 2
+0012 OpHash 1
+0015 OpConstant 0
+0018 OpIndex
+"
+                ),
+                String::from("a, 1, 2")
+            )
+        );
+        assert_eq!(compile_input("0..3").0, "0000 OpConstant 0\n0003 OpConstant 1\n0006 OpRange\n");
+    }
+
+    #[test]
+    fn it_compiles_globals() {
+        assert_eq!(
+            compile_input("let x = 1; x = y; x").0,
+            "\
+0000 OpConstant 0
+0003 OpSetGlobal 0
+0006 OpGetGlobal 1
+0009 OpAssignGlobal 0
+0012 OpPop
+0013 OpGetGlobal 0
+"
+        );
+        assert_eq!(compile_input("let x = 1").0, "0000 OpConstant 0\n0003 OpSetGlobal 0\n0006 OpNull\n");
+    }
+
+    #[test]
+    fn it_compiles_functions_and_calls() {
+        let bytecode = compile(&parse("let add = fn(a, b) { let c = a + b; c }; add(1, 2)").unwrap()).unwrap();
+        assert_eq!(
+            bytecode.instructions.to_string(),
+            "\
+0000 OpClosure 0
+0003 OpSetGlobal 0
+0006 OpGetGlobal 0
+0009 OpConstant 1
+0012 OpConstant 2
+0015 OpCall 2
+"
+        );
+        let Object::CompiledFunction(function) = &bytecode.constants[0] else { panic!("not a function") };
+        assert_eq!(
+            function.instructions.to_string(),
+            "\
+0000 OpGetLocal 0
+0002 OpGetLocal 1
+0004 OpAdd
+0005 OpSetLocal 2
+0007 OpGetLocal 2
+0009 OpReturnValue
+"
+        );
+        assert_eq!((function.parameters, function.locals), (2, 3));
+        assert_eq!(bytecode.constants[0].to_string(), "fn(a, b) { let c = (a + b); c }");
+        assert_eq!(
+            compile_input("len(\"ab\")"),
+            (String::from("0000 OpGetBuiltin 3\n0002 OpConstant 0\n0005 OpCall 1\n"), String::from("ab"))
+        );
+    }
+
+    #[test]
+    fn it_compiles_conditionals_to_jumps() {
+        assert_eq!(
+            compile_input("if (true) { 10 } else { 20 }; 3").0,
+            "\
+0000 OpTrue
+0001 OpJumpIfFalse 10
+0004 OpConstant 0
+0007 OpJump 13
+0010 OpConstant 1
+0013 OpPop
+0014 OpConstant 2
+"
+        );
+        assert_eq!(compile_input("if (true) { 10 }").0.lines().last(), Some("0010 OpNull"));
+    }
+
+    #[test]
+    fn it_compiles_logical_operators_to_jumps() {
+        assert_eq!(
+            compile_input("a && b").0,
+            "\
+0000 OpGetGlobal 0
+0003 OpDup
+0004 OpJumpIfFalse 11
+0007 OpPop
+0008 OpGetGlobal 1
+"
+        );
+        assert_eq!(
+            compile_input("a || b").0,
+            "\
+0000 OpGetGlobal 0
+0003 OpDup
+0004 OpJumpIfFalse 10
+0007 OpJump 14
+0010 OpPop
+0011 OpGetGlobal 1
+"
+        );
+    }
+
+    #[test]
+    fn it_compiles_loops_to_jumps() {
+        assert_eq!(
+            compile_input("while (x) { break; }").0,
+            "\
+0000 OpGetGlobal 0
+0003 OpJumpIfFalse 14
+0006 OpJump 14
+0009 OpNull
+0010 OpPop
+0011 OpJump 0
+0014 OpNull
+"
+        );
+        assert_eq!(
+            compile_input("for k, v in x { continue; }").0,
+            "\
+0000 OpGetGlobal 0
+0003 OpConstant 0
+0006 OpIterate 32 2
+0010 OpJump 25
+0013 OpSetLocal 0
+0015 OpSetLocal 1
+0017 OpJump 6
+0020 OpNull
+0021 OpPop
+0022 OpJump 6
+0025 OpResetLocal 1
+0027 OpResetLocal 0
+0029 OpJump 13
+0032 OpPop
+0033 OpPop
+0034 OpNull
+"
+        );
+        let error = |message: &str| CompileError { message: String::from(message), code: Some(OUTSIDE_LOOP) };
+        assert_eq!(compile(&parse("break").unwrap()).err(), Some(error("break outside loop")));
+        assert_eq!(
+            compile(&parse("while (true) { fn() { continue; } }").unwrap()).err(),
+            Some(error("continue outside loop"))
+        );
+    }
+
+    #[test]
+    fn it_compiles_closures() {
+        let bytecode = compile(&parse("fn(a) { let g = fn() { h(a) }; let h = fn(x) { x }; g }").unwrap()).unwrap();
+        let [Object::CompiledFunction(g), Object::CompiledFunction(h), Object::CompiledFunction(outer)] =
+            &bytecode.constants[..]
+        else {
+            panic!("not three functions")
+        };
+        assert_eq!(
+            g.instructions.to_string(),
+            "0000 OpGetFree 0\n0002 OpGetFree 1\n0004 OpTailCall 1\n0006 OpReturnValue\n"
+        );
+        assert_eq!(g.free, [Capture::Local(1), Capture::Local(0)]);
+        assert_eq!(h.free, []);
+        assert_eq!(
+            outer.instructions.to_string(),
+            "\
+0000 OpClosure 0
+0003 OpSetLocal 2
+0005 OpClosure 1
+0008 OpSetLocal 1
+0010 OpGetLocal 2
+0012 OpReturnValue
+"
+        );
+        let bytecode = compile(&parse("fn(a) { fn() { a = 1 } }").unwrap()).unwrap();
+        let Object::CompiledFunction(inner) = &bytecode.constants[1] else { panic!("not a function") };
+        assert_eq!(inner.instructions.to_string(), "0000 OpConstant 0\n0003 OpAssignFree 0\n0005 OpReturnValue\n");
+    }
+
+    #[test]
+    fn it_disassembles_bytecode() {
+        assert_eq!(
+            compile(&parse("let f = fn(x) { len(x) };\nf(\"ab\")").unwrap()).unwrap().disassemble(),
+            "\
+program (0 locals):
+; line 1
+0000 OpClosure 0
+0003 OpSetGlobal 0
+; line 2
+0006 OpGetGlobal 0
+0009 OpConstant 1
+0012 OpCall 1  ; f at 2:1
+
+constants:
+   0: fn(x) { len(x) } (1 parameter, 1 local, captures [])
+      ; line 1
+      0000 OpGetBuiltin 3  ; len
+      0002 OpGetLocal 0
+      0004 OpTailCall 1  ; len at 1:17
+      0006 OpReturnValue
+   1: \"ab\"
+
+globals:
+   0: f"
+        );
+    }
+
+    #[test]
+    fn it_shares_constants() {
+        assert_eq!(compile_input("1 + 1; 1.0; \"1\"; 1").1, "1, 1.0, 1");
+    }
+
+    #[test]
+    fn it_compiles_try_expressions() {
+        assert_eq!(
+            compile_input("try { a } catch (e) { e }").0,
+            "\
+0000 OpSetupTry 10
+0003 OpGetGlobal 0
+0006 OpPopTry
+0007 OpJump 25
+0010 OpJump 20
+0013 OpSetLocal 0
+0015 OpGetLocal 0
+0017 OpJump 25
+0020 OpResetLocal 0
+0022 OpJump 13
+"
+        );
+        // A `break` ends the `try`s it leaves.
+        assert_eq!(
+            compile_input("while (a) { try { break; } catch (e) { } }").0.lines().nth(3),
+            Some("0009 OpPopTry")
+        );
+    }
+}
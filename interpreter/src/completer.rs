@@ -0,0 +1,130 @@
+use std::cell::RefCell;
+use std::fs;
+use std::rc::Rc;
+
+use crate::environment::Environment;
+use crate::lexical_analyzer::{is_identifier_part, keywords};
+use crate::line_editor::Completion;
+
+// Completes the word before the cursor from keywords, builtins and whatever the session's
+// environment has bound at the moment Tab is pressed, or the path after `:load`.
+pub struct Completer {
+    env: Rc<RefCell<Environment>>,
+    // Keywords and builtins, which do not change during a session.
+    fixed_words: Vec<String>,
+}
+
+impl Completer {
+    pub fn new(env: Rc<RefCell<Environment>>, builtin_names: Vec<String>) -> Self {
+        let mut fixed_words = keywords();
+        fixed_words.extend(builtin_names);
+        Completer { env, fixed_words }
+    }
+
+    pub fn complete(&self, line: &str, cursor: usize) -> Completion {
+        let characters: Vec<char> = line.chars().collect();
+        let before_cursor: String = characters[..cursor].iter().collect();
+        if let Some(path) = before_cursor.trim_start().strip_prefix(":load ") {
+            let path = path.trim_start();
+            return complete_path(path, cursor - path.chars().count());
+        }
+
+        let mut start = cursor;
+        while start > 0 && is_identifier_part(characters[start - 1]) {
+            start -= 1;
+        }
+
+        let prefix: String = characters[start..cursor].iter().collect();
+        if prefix.is_empty() {
+            return Completion { start, candidates: vec![] };
+        }
+
+        let bound_names = self.env.borrow().bindings().into_iter().map(|(name, _)| name.to_string());
+        let mut candidates: Vec<String> =
+            self.fixed_words.iter().cloned().chain(bound_names).filter(|word| word.starts_with(&prefix)).collect();
+        candidates.sort();
+        candidates.dedup();
+        Completion { start, candidates }
+    }
+}
+
+// Offers the directories and `.monkey` files whose path starts with `typed`, which begins at
+// `start` in the line. Directories end in `/` so completing can go on into them.
+fn complete_path(typed: &str, start: usize) -> Completion {
+    let (directory, prefix) = match typed.rfind('/') {
+        Some(slash) => typed.split_at(slash + 1),
+        None => ("", typed),
+    };
+    let entries = match fs::read_dir(if directory.is_empty() { "." } else { directory }) {
+        Ok(entries) => entries,
+        Err(_) => return Completion { start, candidates: vec![] },
+    };
+
+    let mut candidates: Vec<String> = entries
+        .filter_map(|entry| entry.ok())
+        .filter_map(|entry| {
+            let name = entry.file_name().into_string().ok()?;
+            // Hidden files only when asked for.
+            if !name.starts_with(prefix) || (name.starts_with('.') && !prefix.starts_with('.')) {
+                return None;
+            }
+            match entry.path().is_dir() {
+                true => Some(format!("{}{}/", directory, name)),
+                false if name.ends_with(".monkey") => Some(format!("{}{}", directory, name)),
+                false => None,
+            }
+        })
+        .collect();
+    candidates.sort();
+    Completion { start, candidates }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::evaluator::evaluate;
+    use crate::syntax_analyzer::parse;
+
+    fn complete(completer: &Completer, line: &str) -> Vec<String> {
+        completer.complete(line, line.chars().count()).candidates
+    }
+
+    #[test]
+    fn it_completes_keywords_builtins_and_bindings() {
+        let env = Rc::new(RefCell::new(Environment::new()));
+        let completer = Completer::new(Rc::clone(&env), Vec::from([String::from("len"), String::from("lower")]));
+
+        assert_eq!(complete(&completer, "le"), ["len", "let"]);
+        assert_eq!(complete(&completer, "1 + lo"), ["lower"]);
+        assert_eq!(complete(&completer, "1 + "), Vec::<String>::new());
+
+        evaluate(&parse("let length = 3; let lowest_value = 1").unwrap(), &env);
+        assert_eq!(complete(&completer, "le"), ["len", "length", "let"]);
+        assert_eq!(complete(&completer, "f(lo"), ["lower", "lowest_value"]);
+        assert_eq!(completer.complete("f(lo, x)", 4).start, 2);
+    }
+
+    #[test]
+    fn it_completes_paths_to_load() {
+        let directory = std::env::temp_dir().join(format!("monkey_completion_test_{}", std::process::id()));
+        fs::create_dir_all(directory.join("examples")).unwrap();
+        for file in ["fib.monkey", "fizzbuzz.monkey", "notes.txt", ".hidden.monkey"] {
+            fs::write(directory.join(file), "").unwrap();
+        }
+        let completer = Completer::new(Rc::new(RefCell::new(Environment::new())), vec![]);
+        let directory = directory.display().to_string();
+
+        assert_eq!(
+            complete(&completer, &format!(":load {}/f", directory)),
+            [format!("{}/fib.monkey", directory), format!("{}/fizzbuzz.monkey", directory)]
+        );
+        assert_eq!(complete(&completer, &format!(":load {}/", directory)).len(), 3);
+        assert_eq!(complete(&completer, &format!(":load {}/ex", directory)), [format!("{}/examples/", directory)]);
+        assert_eq!(complete(&completer, &format!(":load {}/.h", directory)), [format!("{}/.hidden.monkey", directory)]);
+        assert_eq!(complete(&completer, &format!(":load {}/n", directory)), Vec::<String>::new());
+        assert_eq!(completer.complete(":load  dir/fi", 13).start, 7);
+        // Identifiers are not paths.
+        assert_eq!(complete(&completer, "let le"), ["let"]);
+        fs::remove_dir_all(&directory).unwrap();
+    }
+}
@@ -0,0 +1,441 @@
+use std::time::Duration;
+
+use crate::line_editor::Keybindings;
+use crate::repl::{Mode, Prompt};
+
+// The settings read from `~/.monkeyrc`, and then from a `monkey.toml` in the current directory.
+// Anything left out keeps its default, and command line flags still win over what is set here.
+//
+//     colors = false
+//     mode = "eval"
+//     keybindings = "vi"
+//     prelude = ["~/monkey/prelude.monkey"]
+//
+//     [history]
+//     size = 1000
+//
+//     [limits]
+//     max_depth = 500
+//     steps = 1000000
+//     max_size = 100000
+//     max_memory = "64M"
+//     timeout = "5s"
+//
+//     [prompt]
+//     text = "{line}> "
+//     continuation = "... "
+//     color = "green"
+//
+//     [test]
+//     seed = 42
+//     fake_clock = true
+//
+// A project's `monkey.toml` can also say what `monkey run` runs without a file, see `load_order`.
+//
+//     [project]
+//     entry = "main"
+//     sources = ["src", "lib"]
+//
+//     [dependencies]
+//     main = ["parser", "util/strings"]
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Config {
+    pub history_size: Option<usize>,
+    pub colors: Option<bool>,
+    pub mode: Option<Mode>,
+    pub keybindings: Option<Keybindings>,
+    pub max_depth: Option<usize>,
+    pub step_budget: Option<usize>,
+    // The most bytes in a string, or elements in an array or hash.
+    pub max_value_size: Option<usize>,
+    // The most bytes the heap may hold while a program runs.
+    pub max_memory: Option<usize>,
+    pub time_limit: Option<Duration>,
+    // Scripts evaluated before the first input, in order.
+    pub prelude: Vec<String>,
+    pub prompt: Prompt,
+    // The module `monkey run` runs in a project.
+    pub entry: Option<String>,
+    // Where a project's modules are, relative to the current directory.
+    pub source_dirs: Vec<String>,
+    // The modules that have to run before each module, for those that need any.
+    pub dependencies: Vec<(String, Vec<String>)>,
+    // What `random()` starts from in `monkey test`, instead of the fixed seed tests use otherwise.
+    pub test_seed: Option<u64>,
+    // Whether `clock()` in `monkey test` reads a fake clock moving forward by the same amount on
+    // every reading rather than the time.
+    pub test_fake_clock: Option<bool>,
+}
+
+#[derive(Debug, PartialEq)]
+enum Value {
+    String(String),
+    Integer(i64),
+    Boolean(bool),
+    Array(Vec<Value>),
+}
+
+impl Config {
+    // Reads the small part of TOML a settings file needs: tables, comments, and strings,
+    // integers, booleans and arrays of those as values. Errors name the line they are on.
+    pub fn parse(source: &str) -> Result<Config, String> {
+        let mut config = Config::default();
+        config.update(source)?;
+        Ok(config)
+    }
+
+    // Like `parse`, but on top of the settings so far. What came before the error is applied.
+    pub fn update(&mut self, source: &str) -> Result<(), String> {
+        let mut table = String::new();
+        for (index, line) in source.lines().enumerate() {
+            let line = strip_comment(line).trim();
+            if line.is_empty() {
+                continue;
+            }
+
+            let result = match line.strip_prefix('[').and_then(|line| line.strip_suffix(']')) {
+                Some(name) => {
+                    table = String::from(name.trim());
+                    Ok(())
+                }
+                None => match line.split_once('=') {
+                    Some((key, value)) => {
+                        // A module name like `util/strings` has to be quoted to be a key.
+                        let key = key.trim();
+                        let key = key.strip_prefix('"').and_then(|key| key.strip_suffix('"')).unwrap_or(key);
+                        let key = match table.is_empty() {
+                            true => String::from(key),
+                            false => format!("{}.{}", table, key),
+                        };
+                        parse_value(value.trim()).and_then(|value| self.set(&key, value))
+                    }
+                    None => Err(format!("expected `key = value`, got `{}`", line)),
+                },
+            };
+            result.map_err(|message| format!("line {}: {}", index + 1, message))?;
+        }
+        Ok(())
+    }
+
+    // Sets what a command line flag gives a value for, as in `--limit-steps 1000`. The value is a
+    // string unless it is a number.
+    pub fn set_from_flag(&mut self, key: &str, text: &str) -> Result<(), String> {
+        let value = match text.replace('_', "").parse() {
+            Ok(integer) => Value::Integer(integer),
+            Err(_) => Value::String(String::from(text)),
+        };
+        self.set(key, value)
+    }
+
+    fn set(&mut self, key: &str, value: Value) -> Result<(), String> {
+        match (key, value) {
+            ("colors", Value::Boolean(colors)) => self.colors = Some(colors),
+            ("mode", Value::String(name)) => match Mode::from_name(&name) {
+                Some(mode) => self.mode = Some(mode),
+                None => return Err(format!("unknown mode: {} (expected tokens, ast or eval)", name)),
+            },
+            ("keybindings", Value::String(name)) => match Keybindings::from_name(&name) {
+                Some(keybindings) => self.keybindings = Some(keybindings),
+                None => return Err(format!("unknown keybindings: {} (expected emacs or vi)", name)),
+            },
+            ("prelude", Value::Array(paths)) => {
+                for path in paths {
+                    match path {
+                        Value::String(path) => self.prelude.push(path),
+                        _ => return Err(String::from("prelude must be an array of strings")),
+                    }
+                }
+            }
+            ("history.size", Value::Integer(size)) => self.history_size = Some(to_count(key, size)?),
+            ("limits.max_depth", Value::Integer(depth)) => self.max_depth = Some(to_count(key, depth)?),
+            ("limits.steps", Value::Integer(steps)) => self.step_budget = Some(to_count(key, steps)?),
+            ("limits.max_size", Value::Integer(size)) => self.max_value_size = Some(to_count(key, size)?),
+            ("limits.max_memory", Value::Integer(bytes)) => self.max_memory = Some(to_count(key, bytes)?),
+            ("limits.max_memory", Value::String(text)) => match parse_bytes(&text) {
+                Some(bytes) => self.max_memory = Some(bytes),
+                None => return Err(format!("cannot read size `{}` (expected e.g. 512K, 64M or 1G)", text)),
+            },
+            ("limits.timeout", Value::Integer(seconds)) => {
+                self.time_limit = Some(Duration::from_secs(to_count(key, seconds)? as u64))
+            }
+            ("limits.timeout", Value::String(text)) => match parse_duration(&text) {
+                Some(duration) => self.time_limit = Some(duration),
+                None => return Err(format!("cannot read duration `{}` (expected e.g. 500ms, 5s or 2m)", text)),
+            },
+            ("prompt.text", Value::String(text)) => self.prompt.text = text,
+            ("prompt.continuation", Value::String(text)) => self.prompt.continuation = text,
+            ("prompt.color", Value::String(color)) => self.prompt.color = Some(color),
+            ("project.entry", Value::String(module)) => self.entry = Some(module),
+            ("project.sources", Value::Array(directories)) => self.source_dirs = strings(key, directories)?,
+            ("test.seed", Value::Integer(seed)) => {
+                self.test_seed = Some(u64::try_from(seed).map_err(|_| format!("{} must not be negative", key))?)
+            }
+            ("test.fake_clock", Value::Boolean(is_fake)) => self.test_fake_clock = Some(is_fake),
+            (module, Value::Array(modules)) if module.starts_with("dependencies.") => {
+                let module = String::from(&module["dependencies.".len()..]);
+                let modules = strings(key, modules)?;
+                self.dependencies.retain(|(name, _)| *name != module);
+                self.dependencies.push((module, modules));
+            }
+            (
+                "colors" | "mode" | "keybindings" | "prelude" | "history.size" | "limits.max_depth" | "limits.steps"
+                | "limits.max_size" | "limits.max_memory" | "limits.timeout" | "prompt.text" | "prompt.continuation"
+                | "prompt.color" | "project.entry" | "project.sources" | "test.seed" | "test.fake_clock",
+                _,
+            ) => return Err(format!("wrong type of value for {}", key)),
+            _ if key.starts_with("dependencies.") => return Err(format!("wrong type of value for {}", key)),
+            _ => return Err(format!("unknown setting: {}", key)),
+        }
+        Ok(())
+    }
+}
+
+// A number of milliseconds, seconds or minutes, as in `500ms`, `5s`, `1.5s` or `2m`. Without a
+// unit the number is seconds.
+pub fn parse_duration(text: &str) -> Option<Duration> {
+    let split = text.find(|character: char| !character.is_ascii_digit() && character != '.').unwrap_or(text.len());
+    let (number, unit) = text.split_at(split);
+    let number: f64 = number.parse().ok()?;
+    let seconds = match unit {
+        "ms" => number / 1000.0,
+        "" | "s" => number,
+        "m" => number * 60.0,
+        _ => return None,
+    };
+    Duration::try_from_secs_f64(seconds).ok()
+}
+
+// A number of bytes, or of kilobytes, megabytes or gigabytes, as in `512K`, `64M` or `1G`.
+pub fn parse_bytes(text: &str) -> Option<usize> {
+    let split = text.find(|character: char| !character.is_ascii_digit()).unwrap_or(text.len());
+    let (number, unit) = text.split_at(split);
+    let number: usize = number.parse().ok()?;
+    let scale: usize = match unit.to_ascii_uppercase().as_str() {
+        "" | "B" => 1,
+        "K" | "KB" => 1 << 10,
+        "M" | "MB" => 1 << 20,
+        "G" | "GB" => 1 << 30,
+        _ => return None,
+    };
+    number.checked_mul(scale)
+}
+
+fn strings(key: &str, values: Vec<Value>) -> Result<Vec<String>, String> {
+    values
+        .into_iter()
+        .map(|value| match value {
+            Value::String(text) => Ok(text),
+            _ => Err(format!("{} must be an array of strings", key)),
+        })
+        .collect()
+}
+
+fn to_count(key: &str, value: i64) -> Result<usize, String> {
+    usize::try_from(value).map_err(|_| format!("{} must not be negative", key))
+}
+
+// Drops a `#` comment, unless the `#` is inside a string.
+fn strip_comment(line: &str) -> &str {
+    let mut in_string = false;
+    let mut escaped = false;
+    for (index, character) in line.char_indices() {
+        match character {
+            _ if escaped => escaped = false,
+            '\\' if in_string => escaped = true,
+            '"' => in_string = !in_string,
+            '#' if !in_string => return &line[..index],
+            _ => {}
+        }
+    }
+    line
+}
+
+fn parse_value(text: &str) -> Result<Value, String> {
+    let (value, rest) = parse_value_prefix(text)?;
+    match rest.trim() {
+        "" => Ok(value),
+        rest => Err(format!("unexpected `{}` after the value", rest)),
+    }
+}
+
+// Parses the value at the start of `text`, returning what is left after it.
+fn parse_value_prefix(text: &str) -> Result<(Value, &str), String> {
+    if let Some(rest) = text.strip_prefix('"') {
+        let mut string = String::new();
+        let mut characters = rest.char_indices();
+        while let Some((index, character)) = characters.next() {
+            match character {
+                '"' => return Ok((Value::String(string), &rest[index + 1..])),
+                '\\' => match characters.next() {
+                    Some((_, 'n')) => string.push('\n'),
+                    Some((_, 't')) => string.push('\t'),
+                    Some((_, escaped @ ('"' | '\\'))) => string.push(escaped),
+                    _ => return Err(String::from("unknown escape in string")),
+                },
+                character => string.push(character),
+            }
+        }
+        return Err(String::from("unterminated string"));
+    }
+
+    if let Some(mut rest) = text.strip_prefix('[') {
+        let mut elements = vec![];
+        loop {
+            rest = rest.trim_start();
+            if let Some(after) = rest.strip_prefix(']') {
+                return Ok((Value::Array(elements), after));
+            }
+            let (element, after) = parse_value_prefix(rest)?;
+            elements.push(element);
+            rest = after.trim_start();
+            match rest.strip_prefix(',') {
+                Some(after) => rest = after,
+                None if rest.starts_with(']') => {}
+                None => return Err(String::from("expected `,` or `]` in array")),
+            }
+        }
+    }
+
+    let end = text.find([',', ']']).unwrap_or(text.len());
+    let (word, rest) = text.split_at(end);
+    let value = match word.trim() {
+        "true" => Value::Boolean(true),
+        "false" => Value::Boolean(false),
+        word => match word.replace('_', "").parse() {
+            Ok(integer) => Value::Integer(integer),
+            Err(_) if word.is_empty() => return Err(String::from("missing value")),
+            Err(_) => return Err(format!("cannot read value `{}`", word)),
+        },
+    };
+    Ok((value, rest))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_works_on_config() {
+        let source = "\
+# settings for the monkey REPL
+colors = false
+mode = \"ast\"  # show syntax trees
+keybindings = \"vi\"
+prelude = [\"~/a.monkey\", \"b # not a comment.monkey\"]
+
+[history]
+size = 1_000
+
+[limits]
+max_depth = 50
+steps = 100000
+max_size = 4096
+max_memory = \"1M\"
+timeout = \"1.5s\"
+
+[prompt]
+text = \"{line}> \"
+color = \"green\"
+
+[project]
+entry = \"main\"
+sources = [\"src\"]
+
+[dependencies]
+main = [\"util/strings\"]
+\"util/strings\" = []
+
+[test]
+seed = 7
+fake_clock = true
+";
+        assert_eq!(
+            Config::parse(source),
+            Ok(Config {
+                history_size: Some(1000),
+                colors: Some(false),
+                mode: Some(Mode::Ast),
+                keybindings: Some(Keybindings::Vi),
+                max_depth: Some(50),
+                step_budget: Some(100000),
+                max_value_size: Some(4096),
+                max_memory: Some(1 << 20),
+                time_limit: Some(Duration::from_millis(1500)),
+                prelude: vec![String::from("~/a.monkey"), String::from("b # not a comment.monkey")],
+                prompt: Prompt {
+                    text: String::from("{line}> "),
+                    continuation: String::from(".. "),
+                    color: Some(String::from("green")),
+                },
+                entry: Some(String::from("main")),
+                source_dirs: vec![String::from("src")],
+                dependencies: vec![
+                    (String::from("main"), vec![String::from("util/strings")]),
+                    (String::from("util/strings"), vec![]),
+                ],
+                test_seed: Some(7),
+                test_fake_clock: Some(true),
+            })
+        );
+        assert_eq!(Config::parse(""), Ok(Config::default()));
+
+        let mut config = Config::parse("[limits]\nsteps = 5\nmax_depth = 10").unwrap();
+        config.update("[limits]\nsteps = 7").unwrap();
+        config.set_from_flag("limits.timeout", "250ms").unwrap();
+        config.set_from_flag("limits.max_size", "1_000").unwrap();
+        config.set_from_flag("limits.max_memory", "64M").unwrap();
+        assert_eq!(
+            (config.step_budget, config.max_depth, config.time_limit, config.max_value_size, config.max_memory),
+            (Some(7), Some(10), Some(Duration::from_millis(250)), Some(1000), Some(64 << 20))
+        );
+        assert_eq!(
+            config.set_from_flag("limits.steps", "many"),
+            Err(String::from("wrong type of value for limits.steps"))
+        );
+    }
+
+    #[test]
+    fn it_parses_durations() {
+        assert_eq!(parse_duration("5s"), Some(Duration::from_secs(5)));
+        assert_eq!(parse_duration("5"), Some(Duration::from_secs(5)));
+        assert_eq!(parse_duration("250ms"), Some(Duration::from_millis(250)));
+        assert_eq!(parse_duration("1.5m"), Some(Duration::from_secs(90)));
+        assert_eq!(parse_duration("5h"), None);
+        assert_eq!(parse_duration("s"), None);
+    }
+
+    #[test]
+    fn it_parses_sizes() {
+        assert_eq!(parse_bytes("4096"), Some(4096));
+        assert_eq!(parse_bytes("512K"), Some(512 * 1024));
+        assert_eq!(parse_bytes("64mb"), Some(64 << 20));
+        assert_eq!(parse_bytes("1G"), Some(1 << 30));
+        assert_eq!(parse_bytes("1.5G"), None);
+        assert_eq!(parse_bytes("M"), None);
+    }
+
+    #[test]
+    fn it_reports_bad_settings() {
+        let tests = vec![
+            ("colors = yes", "line 1: cannot read value `yes`"),
+            ("\nmode = \"fast\"", "line 2: unknown mode: fast (expected tokens, ast or eval)"),
+            ("colors = 1", "line 1: wrong type of value for colors"),
+            ("keybindings = \"ed\"", "line 1: unknown keybindings: ed (expected emacs or vi)"),
+            ("[history]\nlength = 5", "line 2: unknown setting: history.length"),
+            ("[limits]\nsteps = -1", "line 2: limits.steps must not be negative"),
+            ("[limits]\ntimeout = \"soon\"", "line 2: cannot read duration `soon` (expected e.g. 500ms, 5s or 2m)"),
+            ("[limits]\nmax_memory = \"lots\"", "line 2: cannot read size `lots` (expected e.g. 512K, 64M or 1G)"),
+            ("prelude = [\"a\" \"b\"]", "line 1: expected `,` or `]` in array"),
+            ("prompt", "line 1: expected `key = value`, got `prompt`"),
+            ("mode = \"eval", "line 1: unterminated string"),
+            ("[project]\nsources = [1]", "line 2: project.sources must be an array of strings"),
+            ("[dependencies]\nmain = \"util\"", "line 2: wrong type of value for dependencies.main"),
+            ("[test]\nseed = -1", "line 2: test.seed must not be negative"),
+            ("[test]\nfake_clock = 1", "line 2: wrong type of value for test.fake_clock"),
+        ];
+
+        for (source, expected) in tests {
+            assert_eq!(Config::parse(source), Err(String::from(expected)), "{}", source);
+        }
+    }
+}
@@ -0,0 +1,250 @@
+use std::cell::RefCell;
+use std::collections::HashSet;
+use std::rc::Rc;
+
+use crate::abstract_syntax_tree::{BlockStatement, Expression, Program, Statement};
+use crate::documenter::escape_html;
+use crate::hooks::Hooks;
+use crate::lexical_analyzer::Span;
+
+// The statements that ran, by where they are in the syntax tree. Function bodies are shared with
+// the functions made from them, so a statement is the same one wherever it ran.
+pub type Executed = Rc<RefCell<HashSet<*const Statement>>>;
+
+// Notes every statement that runs, for `monkey test --coverage`.
+pub struct CoverageHook {
+    executed: Executed,
+}
+
+impl CoverageHook {
+    pub fn new(executed: Executed) -> Self {
+        CoverageHook { executed }
+    }
+}
+
+impl Hooks for CoverageHook {
+    fn on_statement(&mut self, statement: &Statement) {
+        self.executed.borrow_mut().insert(statement);
+    }
+}
+
+// Every statement of a file by the line it starts on, and whether it ran.
+#[derive(Debug, PartialEq)]
+pub struct FileCoverage {
+    pub name: String,
+    pub source: String,
+    pub statements: Vec<(usize, bool)>,
+}
+
+impl FileCoverage {
+    // `marks` are those `parse_with_marks` gave along with `program`, which is what ran.
+    pub fn new(name: &str, source: &str, program: &Program, marks: &[Span], executed: &Executed) -> Self {
+        let mut walk = Walk { marks: marks.iter(), executed: &executed.borrow(), statements: vec![] };
+        walk.statements(&program.statements);
+        FileCoverage { name: String::from(name), source: String::from(source), statements: walk.statements }
+    }
+
+    pub fn covered(&self) -> usize {
+        self.statements.iter().filter(|(_, ran)| *ran).count()
+    }
+
+    fn percent(&self) -> f64 {
+        match self.statements.len() {
+            0 => 100.0,
+            total => self.covered() as f64 / total as f64 * 100.0,
+        }
+    }
+
+    // `hit` when every statement on the line ran, `miss` when none did and `partial` otherwise.
+    // Lines without a statement starting on them have no class.
+    fn line_class(&self, line: usize) -> Option<&'static str> {
+        let statements: Vec<bool> = self.statements.iter().filter(|(at, _)| *at == line).map(|(_, ran)| *ran).collect();
+        match (statements.iter().all(|ran| *ran), statements.iter().any(|ran| *ran)) {
+            _ if statements.is_empty() => None,
+            (true, _) => Some("hit"),
+            (false, true) => Some("partial"),
+            (false, false) => Some("miss"),
+        }
+    }
+}
+
+// Walks the syntax tree in source order, taking the marks as it goes like the formatter does.
+struct Walk<'a> {
+    marks: std::slice::Iter<'a, Span>,
+    executed: &'a HashSet<*const Statement>,
+    statements: Vec<(usize, bool)>,
+}
+
+impl Walk<'_> {
+    fn mark(&mut self) -> Span {
+        self.marks.next().copied().unwrap_or_default()
+    }
+
+    fn statements(&mut self, statements: &[Statement]) {
+        for statement in statements {
+            let start = self.mark();
+            let pointer: *const Statement = statement;
+            self.statements.push((start.line, self.executed.contains(&pointer)));
+            match statement {
+                Statement::Let(_, value) | Statement::Return(value) | Statement::Expression(value) => {
+                    self.expression(value)
+                }
+                Statement::Break | Statement::Continue => {}
+            }
+            self.mark();
+        }
+    }
+
+    fn block(&mut self, block: &BlockStatement) {
+        self.mark();
+        self.statements(&block.statements);
+        self.mark();
+    }
+
+    fn expression(&mut self, expression: &Expression) {
+        match expression {
+            Expression::Identifier(_)
+            | Expression::Integer(_)
+            | Expression::Float(_)
+            | Expression::Boolean(_)
+            | Expression::String(_)
+            | Expression::Null => {}
+            Expression::Array(elements) => elements.iter().for_each(|element| self.expression(element)),
+            Expression::Hash(pairs) => {
+                for (key, value) in pairs {
+                    self.expression(key);
+                    self.expression(value);
+                }
+            }
+            Expression::Prefix(_, right) | Expression::Assign(_, right) => self.expression(right),
+            Expression::Infix(_, left, right) | Expression::Index(left, right) => {
+                self.expression(left);
+                self.expression(right);
+            }
+            Expression::If { condition, consequence, alternative } => {
+                self.expression(condition);
+                self.block(consequence);
+                if let Some(alternative) = alternative {
+                    self.block(alternative);
+                }
+            }
+            Expression::While { condition, body } => {
+                self.expression(condition);
+                self.block(body);
+            }
+            Expression::For { iterable, body, .. } => {
+                self.expression(iterable);
+                self.block(body);
+            }
+            Expression::Try { body, handler, .. } => {
+                self.block(body);
+                self.block(handler);
+            }
+            Expression::Function { body, .. } => self.block(body),
+            Expression::Call { function, arguments, .. } => {
+                self.expression(function);
+                arguments.iter().for_each(|argument| self.expression(argument));
+            }
+        }
+    }
+}
+
+// A line per file with how many of its statements ran, and a total when there is more than one.
+pub fn summary(files: &[FileCoverage]) -> String {
+    let width = files.iter().map(|file| file.name.chars().count()).chain(["total".len()]).max().unwrap_or(0);
+    let row = |name: &str, covered: usize, total: usize, percent: f64| {
+        format!("{:<width$}  {:>5}/{:<5}  {:>5.1}%\n", name, covered, total, percent)
+    };
+    let mut table = String::new();
+    for file in files {
+        table += &row(&file.name, file.covered(), file.statements.len(), file.percent());
+    }
+    if files.len() > 1 {
+        let (covered, total) = files.iter().fold((0, 0), |(covered, total), file| {
+            (covered + file.covered(), total + file.statements.len())
+        });
+        let percent = if total == 0 { 100.0 } else { covered as f64 / total as f64 * 100.0 };
+        table += &row("total", covered, total, percent);
+    }
+    table
+}
+
+// A page with every file's source, the lines colored by whether their statements ran.
+pub fn html(files: &[FileCoverage]) -> String {
+    let mut page = String::from("<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n<title>Coverage</title>\n");
+    page += "<style>\n.hit { background: #dfd; }\n.partial { background: #ffd; }\n.miss { background: #fdd; }\n";
+    page += "td { font-family: monospace; white-space: pre; padding: 0 0.5em; }\n</style>\n</head>\n<body>\n";
+    for file in files {
+        page += &format!(
+            "<h1>{}</h1>\n<p>{} of {} statements ran ({:.1}%)</p>\n<table>\n",
+            escape_html(&file.name),
+            file.covered(),
+            file.statements.len(),
+            file.percent()
+        );
+        for (index, line) in file.source.lines().enumerate() {
+            let class = match file.line_class(index + 1) {
+                Some(class) => format!(" class=\"{}\"", class),
+                None => String::new(),
+            };
+            page += &format!("<tr{}><td>{}</td><td>{}</td></tr>\n", class, index + 1, escape_html(line));
+        }
+        page += "</table>\n";
+    }
+    page + "</body>\n</html>\n"
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::environment::Environment;
+    use crate::evaluator::Evaluator;
+    use crate::syntax_analyzer::parse_with_marks;
+
+    fn coverage(name: &str, source: &str, run: &str) -> FileCoverage {
+        let executed = Executed::default();
+        let mut evaluator = Evaluator::new().with_hooks(CoverageHook::new(Rc::clone(&executed)));
+        let env = Rc::new(RefCell::new(Environment::new()));
+        let (program, marks) = parse_with_marks(source).unwrap();
+        evaluator.eval_program(&program, &env);
+        evaluator.eval_program(&parse_with_marks(run).unwrap().0, &env);
+        FileCoverage::new(name, source, &program, &marks, &executed)
+    }
+
+    const SOURCE: &str = "\
+let sign = fn(n) {
+  if (n < 0) {
+    return -1;
+  }
+  let x = 1; x
+};
+let unused = fn() { 0 };
+";
+
+    #[test]
+    fn it_records_which_statements_ran() {
+        let file = coverage("sign.monkey", SOURCE, "sign(5)");
+        let statements = vec![(1, true), (2, true), (3, false), (5, true), (5, true), (7, true), (7, false)];
+        assert_eq!(file.statements, statements);
+        assert_eq!(file.covered(), 5);
+        let classes: Vec<Option<&str>> = [2, 3, 4, 7].iter().map(|line| file.line_class(*line)).collect();
+        assert_eq!(classes, vec![Some("hit"), Some("miss"), None, Some("partial")]);
+        assert_eq!(coverage("sign.monkey", SOURCE, "sign(-5)").line_class(5), Some("miss"));
+    }
+
+    #[test]
+    fn it_reports_coverage() {
+        let files = vec![coverage("sign.monkey", SOURCE, "sign(5)"), coverage("b.monkey", "1", "")];
+        assert_eq!(
+            summary(&files),
+            "\
+sign.monkey      5/7       71.4%
+b.monkey         1/1      100.0%
+total            6/8       75.0%
+"
+        );
+        let html = html(&files);
+        assert!(html.contains("<p>5 of 7 statements ran (71.4%)</p>"));
+        assert!(html.contains("<tr class=\"miss\"><td>3</td><td>    return -1;</td></tr>\n<tr><td>4</td><td>  }"));
+    }
+}
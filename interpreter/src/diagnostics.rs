@@ -0,0 +1,754 @@
+use crate::builtins::{environment_builtins, filesystem_builtins};
+use crate::lexical_analyzer::Span;
+use crate::object::{Builtin, RuntimeError};
+use crate::syntax_dump::quote_json;
+
+// One kind of error a program can run into, or of warning `monkey lint` gives, with the code that
+// stands for it in reports. Codes never change meaning and are not reused: E00.. are for programs
+// that do not lex or parse, E01.. for programs that fail as they run and W.. for warnings.
+pub struct Diagnostic {
+    pub code: &'static str,
+    pub summary: &'static str,
+    // How the messages of this kind start.
+    pub messages: &'static [&'static str],
+    pub explanation: &'static str,
+}
+
+// The codes, which each diagnostic is given where it is made.
+pub const UNTERMINATED_STRING: &str = "E0001";
+pub const INTEGER_TOO_LARGE: &str = "E0002";
+pub const UNEXPECTED_CHARACTER: &str = "E0003";
+pub const EXPECTED_IDENTIFIER: &str = "E0004";
+pub const EXPECTED_EXPRESSION: &str = "E0005";
+pub const EXPECTED_TOKEN: &str = "E0006";
+pub const NESTED_TOO_DEEPLY: &str = "E0007";
+pub const INVALID_ASSIGNMENT_TARGET: &str = "E0008";
+pub const IDENTIFIER_NOT_FOUND: &str = "E0101";
+pub const UNDECLARED_ASSIGNMENT: &str = "E0102";
+pub const TYPE_MISMATCH: &str = "E0103";
+pub const UNKNOWN_OPERATOR: &str = "E0104";
+pub const DIVISION_BY_ZERO: &str = "E0105";
+pub const INTEGER_OVERFLOW: &str = "E0106";
+pub const NOT_A_FUNCTION: &str = "E0107";
+pub const WRONG_ARGUMENT_COUNT: &str = "E0108";
+pub const UNSUPPORTED_ARGUMENT: &str = "E0109";
+pub const UNSUPPORTED_INDEX: &str = "E0110";
+pub const UNUSABLE_HASH_KEY: &str = "E0111";
+pub const NOT_ITERABLE: &str = "E0112";
+pub const OUTSIDE_LOOP: &str = "E0113";
+pub const DEPTH_EXCEEDED: &str = "E0114";
+pub const BUDGET_EXCEEDED: &str = "E0115";
+pub const TIME_EXCEEDED: &str = "E0116";
+pub const MEMORY_EXCEEDED: &str = "E0117";
+pub const INTERRUPTED: &str = "E0118";
+pub const ASSERTION_FAILED: &str = "E0119";
+pub const CANNOT_CONVERT: &str = "E0120";
+pub const IO_FAILED: &str = "E0121";
+pub const BAD_FORMAT_OR_RANGE: &str = "E0122";
+// The code of the errors the program raises with `error(...)`.
+pub const RAISED: &str = "E0123";
+pub const UNUSED_VARIABLE: &str = "W0001";
+pub const UNUSED_PARAMETER: &str = "W0002";
+pub const UNREACHABLE_CODE: &str = "W0003";
+
+pub const DIAGNOSTICS: [Diagnostic; 34] = [
+    Diagnostic {
+        code: UNTERMINATED_STRING,
+        summary: "unterminated string",
+        messages: &["unterminated string"],
+        explanation: "\
+A string literal has no closing `\"`, so it runs to the end of the file.
+
+    let greeting = \"hello;
+
+Close the string on the line it starts on:
+
+    let greeting = \"hello\";",
+    },
+    Diagnostic {
+        code: INTEGER_TOO_LARGE,
+        summary: "integer literal too large",
+        messages: &["integer literal too large"],
+        explanation: "\
+An integer literal is bigger than the largest integer there is, 2147483647.
+
+    let big = 99999999999;
+
+Use a float when the exact value does not matter:
+
+    let big = 99999999999.0;",
+    },
+    Diagnostic {
+        code: UNEXPECTED_CHARACTER,
+        summary: "unexpected character",
+        messages: &["unexpected character"],
+        explanation: "\
+The program has a character that is not part of the language, outside of a string or comment.
+
+    let rest = 10 % 3;
+
+There is no `%` operator. Write what it does with the ones there are:
+
+    let rest = 10 - 10 / 3 * 3;",
+    },
+    Diagnostic {
+        code: EXPECTED_IDENTIFIER,
+        summary: "expected identifier",
+        messages: &["expected identifier"],
+        explanation: "\
+A name is missing where one has to be, after `let`, in the parameters of `fn` or as the variable of
+`for` or `catch`.
+
+    let = 5;
+    let 2x = 10;
+
+Names start with a letter or `_`:
+
+    let x = 5;
+    let x2 = 10;",
+    },
+    Diagnostic {
+        code: EXPECTED_EXPRESSION,
+        summary: "expected expression",
+        messages: &["expected expression"],
+        explanation: "\
+Something that is not a value starts an expression, or the program ends in the middle of one.
+
+    let x = ;
+    let y = 1 +
+
+Finish the expression:
+
+    let x = 0;
+    let y = 1 + 2;",
+    },
+    Diagnostic {
+        code: EXPECTED_TOKEN,
+        summary: "expected token",
+        messages: &["expected "],
+        explanation: "\
+A token the syntax needs to come next is missing, most often a closing bracket or the `=` of a `let`.
+
+    let add = fn(a, b { a + b };
+    if (x > 1) { x
+
+Close every bracket that is opened:
+
+    let add = fn(a, b) { a + b };
+    if (x > 1) { x }",
+    },
+    Diagnostic {
+        code: NESTED_TOO_DEEPLY,
+        summary: "expression nested too deeply",
+        messages: &["expression nested too deeply"],
+        explanation: "\
+An expression has so many brackets or operators inside each other that the parser stops rather than
+run out of stack.
+
+    let x = ((((((((((((((((((((1))))))))))))))))))));  // and so on, thousands deep
+
+Split the expression up with `let`s. Programs that make code should make it flatter.",
+    },
+    Diagnostic {
+        code: INVALID_ASSIGNMENT_TARGET,
+        summary: "invalid assignment target",
+        messages: &["invalid assignment target"],
+        explanation: "\
+The left of `=` is not a variable. Only variables can be assigned to, not values, elements of arrays
+or hashes, or the results of calls.
+
+    1 = x;
+    items[0] = 1;
+
+Assign to a variable, which may be given a new array:
+
+    x = 1;
+    items = [1];",
+    },
+    Diagnostic {
+        code: IDENTIFIER_NOT_FOUND,
+        summary: "identifier not found",
+        messages: &["identifier not found"],
+        explanation: "\
+A name is read that has no `let` in scope, was misspelled, or is a builtin function that is not
+available. `read_file` and friends need `--allow-fs` and `env` needs `--allow-env`.
+
+    let total = 1;
+    puts(totl);
+
+Define the name before it is read, or spell it the way it was defined:
+
+    puts(total);",
+    },
+    Diagnostic {
+        code: UNDECLARED_ASSIGNMENT,
+        summary: "assignment to undeclared identifier",
+        messages: &["assignment to undeclared identifier"],
+        explanation: "\
+A variable is assigned to with `=` without a `let` for it first. Assigning only changes variables that
+exist.
+
+    count = 0;
+
+Declare it with `let`:
+
+    let count = 0;
+    count = count + 1;",
+    },
+    Diagnostic {
+        code: TYPE_MISMATCH,
+        summary: "type mismatch",
+        messages: &["type mismatch"],
+        explanation: "\
+An operator was given values of two types it does not combine. Integers and floats do not mix
+either.
+
+    1 + true
+    \"total: \" + 5
+    1.5 * 2
+
+Convert one of the values first:
+
+    \"total: \" + str(5)
+    1.5 * float(2)",
+    },
+    Diagnostic {
+        code: UNKNOWN_OPERATOR,
+        summary: "unknown operator",
+        messages: &["unknown operator"],
+        explanation: "\
+An operator was used on a type it does not work on, such as `-` on strings or `..` on floats.
+
+    \"ab\" - \"b\"
+    -true
+    1.5 .. 3.5
+
+Ranges are made of integers, and `!` is the way to negate a boolean:
+
+    1..3
+    !true",
+    },
+    Diagnostic {
+        code: DIVISION_BY_ZERO,
+        summary: "division by zero",
+        messages: &["division by zero"],
+        explanation: "\
+An integer was divided by zero.
+
+    let average = total / count;  // with count == 0
+
+Check the divisor first:
+
+    let average = if (count == 0) { 0 } else { total / count };",
+    },
+    Diagnostic {
+        code: INTEGER_OVERFLOW,
+        summary: "integer overflow",
+        messages: &["integer overflow"],
+        explanation: "\
+An integer operation made a number too big or too small to hold, past 2147483647 or -2147483648.
+Integers do not wrap around.
+
+    let big = 2147483647 + 1;
+
+Use floats for numbers that grow that large:
+
+    let big = 2147483647.0 + 1.0;",
+    },
+    Diagnostic {
+        code: NOT_A_FUNCTION,
+        summary: "not a function",
+        messages: &["not a function"],
+        explanation: "\
+Something that is not a function was called. A variable that holds a function may have been given
+another value since.
+
+    let double = 2;
+    double(4)
+
+Only call functions:
+
+    let double = fn(x) { x * 2 };
+    double(4)",
+    },
+    Diagnostic {
+        code: WRONG_ARGUMENT_COUNT,
+        summary: "wrong number of arguments",
+        messages: &["wrong number of arguments"],
+        explanation: "\
+A function was called with more or fewer arguments than it has parameters. Every parameter has to be
+given a value.
+
+    let add = fn(a, b) { a + b };
+    add(1)
+
+Pass one argument for each parameter:
+
+    add(1, 2)",
+    },
+    Diagnostic {
+        code: UNSUPPORTED_ARGUMENT,
+        summary: "argument not supported",
+        messages: &["argument to `"],
+        explanation: "\
+A builtin function was given an argument of a type it does not take.
+
+    len(5)
+    join(\"abc\", \",\")
+
+The REPL tells what each builtin takes with `:doc <name>`:
+
+    len(\"5\")
+    join([\"a\", \"b\", \"c\"], \",\")",
+    },
+    Diagnostic {
+        code: UNSUPPORTED_INDEX,
+        summary: "index operator not supported",
+        messages: &["index operator not supported"],
+        explanation: "\
+A value was indexed with `[]` that cannot be, or with an index of the wrong type. Arrays and strings
+take integers, hashes take their keys.
+
+    let n = 5;
+    n[0]
+    [1, 2][true]
+
+Index arrays, strings and hashes only:
+
+    [1, 2][0]",
+    },
+    Diagnostic {
+        code: UNUSABLE_HASH_KEY,
+        summary: "unusable as hash key",
+        messages: &["unusable as hash key"],
+        explanation: "\
+A value that cannot be a key of a hash was used as one. Keys are integers, strings and booleans.
+
+    let seen = {[1, 2]: true};
+
+Turn the value into a string first:
+
+    let seen = {str([1, 2]): true};",
+    },
+    Diagnostic {
+        code: NOT_ITERABLE,
+        summary: "not iterable",
+        messages: &["not iterable", "a RANGE yields one loop variable"],
+        explanation: "\
+A `for` loop was given something that cannot be looped over, or more variables than it yields.
+Arrays, strings and ranges yield one value at a time, hashes a key and a value.
+
+    for x in 5 { puts(x) }
+    for i, x in 0..3 { puts(x) }
+
+Loop over a range to count:
+
+    for x in 0..5 { puts(x) }
+    for key, value in {\"a\": 1} { puts(key, value) }",
+    },
+    Diagnostic {
+        code: OUTSIDE_LOOP,
+        summary: "break or continue outside loop",
+        messages: &["break outside loop", "continue outside loop"],
+        explanation: "\
+`break` or `continue` was reached outside of any `while` or `for` loop. A function called from a loop
+is not inside it.
+
+    let stop = fn() { break };
+    while (true) { stop() }
+
+Return a value that tells the loop to stop:
+
+    let done = fn() { true };
+    while (true) { if (done()) { break } }",
+    },
+    Diagnostic {
+        code: DEPTH_EXCEEDED,
+        summary: "maximum recursion depth exceeded",
+        messages: &["maximum recursion depth exceeded"],
+        explanation: "\
+Calls were nested deeper than the limit, 1000 unless `--limit-depth` or `max_depth` under [limits] in
+`monkey.toml` says otherwise. This is most often recursion without a case that ends it.
+
+    let count = fn(n) { 1 + count(n - 1) };
+
+Give the recursion a case that returns without calling again, or loop instead:
+
+    let count = fn(n) { if (n == 0) { 0 } else { 1 + count(n - 1) } };",
+    },
+    Diagnostic {
+        code: BUDGET_EXCEEDED,
+        summary: "evaluation budget exceeded",
+        messages: &["evaluation budget exceeded"],
+        explanation: "\
+The program took more steps than `--limit-steps` or `steps` under [limits] in `monkey.toml` allows. It
+was stopped, which is most often because it loops forever.
+
+    while (true) { }
+
+Make sure loops end, or raise the limit for programs that need it.",
+    },
+    Diagnostic {
+        code: TIME_EXCEEDED,
+        summary: "time limit exceeded",
+        messages: &["time limit exceeded"],
+        explanation: "\
+The program ran for longer than `--timeout` or `timeout` under [limits] in `monkey.toml` allows. It
+exits with 124 then, as a command stopped by timeout(1) does, and the report has the calls it was in.
+
+    while (true) { }
+
+Make sure loops end, or allow more time with `--timeout 10s`.",
+    },
+    Diagnostic {
+        code: MEMORY_EXCEEDED,
+        summary: "memory limit exceeded",
+        messages: &["memory limit exceeded"],
+        explanation: "\
+A string, array or hash grew bigger than `--limit-size` or `max_size` under [limits] in `monkey.toml`
+allows, or the program as a whole used more memory than `--max-memory` or `max_memory` does.
+
+    let s = \"x\";
+    while (true) { s = s + s }
+
+Build smaller values, or raise the limit for programs that need big ones.",
+    },
+    Diagnostic {
+        code: INTERRUPTED,
+        summary: "interrupted",
+        messages: &["interrupted"],
+        explanation: "\
+The program was stopped with Ctrl-C before it finished. The REPL keeps the variables defined before
+the input that was stopped.",
+    },
+    Diagnostic {
+        code: ASSERTION_FAILED,
+        summary: "assertion failed",
+        messages: &["assertion failed"],
+        explanation: "\
+`assert` was called with something that is not true. The message says where, and what `assert` was
+given to say about it.
+
+    assert(1 + 1 == 3, \"arithmetic\")
+
+In a test this means the test failed. Fix the code under test or the expectation.",
+    },
+    Diagnostic {
+        code: CANNOT_CONVERT,
+        summary: "cannot convert",
+        messages: &["cannot convert"],
+        explanation: "\
+`int` or `float` was given a string that is not a number.
+
+    int(\"12px\")
+
+Convert only the digits:
+
+    int(\"12\")",
+    },
+    Diagnostic {
+        code: IO_FAILED,
+        summary: "input or output failed",
+        messages: &["cannot read", "cannot write"],
+        explanation: "\
+Reading or writing a file, standard input or standard output failed, as when a file does not exist
+or may not be written to. The message ends with what the system said.
+
+    read_file(\"missing.txt\")
+
+Check that the file exists and can be read. The builtins that use files need `--allow-fs`.",
+    },
+    Diagnostic {
+        code: BAD_FORMAT_OR_RANGE,
+        summary: "bad format or range",
+        messages: &["unmatched `", "format string has", "empty range for `random`"],
+        explanation: "\
+A builtin was given arguments that do not make sense together: a `{` or `}` in a format string
+without its partner, a format string with more or fewer `{}` than values, or a range for `random`
+that has nothing in it.
+
+    format(\"{\", 1)
+    random(5, 1)
+
+Write `{{` and `}}` for braces in the text, and give `random` a bigger upper bound than lower one:
+
+    format(\"{{}} is {}\", 1)
+    random(1, 5)",
+    },
+    Diagnostic {
+        code: RAISED,
+        summary: "error raised by the program",
+        messages: &[],
+        explanation: "\
+The program called `error` with the message, and nothing caught it. Whatever the message says, the
+program raised it itself.
+
+    let parse = fn(s) { if (len(s) == 0) { error(\"empty input\") } else { int(s) } };
+    parse(\"\")
+
+Catch the error where the program can go on without the value:
+
+    let n = try { parse(\"\") } catch (e) { 0 };",
+    },
+    Diagnostic {
+        code: UNUSED_VARIABLE,
+        summary: "unused variable",
+        messages: &["unused variable"],
+        explanation: "\
+A `let` defines a variable that nothing reads. It may be left over, or a different name may be read
+where this one was meant.
+
+    let total = 0;
+    let count = 1;
+    puts(count);
+
+Remove it, use it, or start its name with `_` to say it is unused on purpose:
+
+    let _total = 0;",
+    },
+    Diagnostic {
+        code: UNUSED_PARAMETER,
+        summary: "unused parameter",
+        messages: &["unused parameter"],
+        explanation: "\
+A function has a parameter that its body never reads.
+
+    let first = fn(a, b) { a };
+
+Start its name with `_` when the function has to take it anyway, as a callback does:
+
+    let first = fn(a, _b) { a };",
+    },
+    Diagnostic {
+        code: UNREACHABLE_CODE,
+        summary: "unreachable code",
+        messages: &["unreachable code"],
+        explanation: "\
+Statements come after a `return`, `break` or `continue` in the same block, so they never run.
+
+    let f = fn(x) {
+      return x;
+      puts(x);
+    };
+
+Remove them, or move them before the statement that leaves the block.",
+    },
+];
+
+
+// An error message with its code after it, when it has one.
+pub fn with_code(message: &str, code: Option<&str>) -> String {
+    match code {
+        Some(code) => format!("{} [{}]", message, code),
+        None => String::from(message),
+    }
+}
+
+// What `monkey explain` prints for a code, which may be written in lowercase.
+pub fn explain(code: &str) -> Option<String> {
+    let code = code.to_uppercase();
+    let diagnostic = DIAGNOSTICS.iter().find(|diagnostic| diagnostic.code == code)?;
+    Some(format!("{}: {}\n\n{}", diagnostic.code, diagnostic.summary, diagnostic.explanation))
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Severity {
+    Error,
+    Warning,
+}
+
+// An error or warning as `--error-format json` prints it.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Report {
+    pub severity: Severity,
+    pub code: Option<&'static str>,
+    pub file: String,
+    pub span: Option<Span>,
+    pub message: String,
+    // What might fix it, when there is something to say about that.
+    pub suggestions: Vec<String>,
+    // The calls a runtime error happened in, the innermost first: the function called, and the file
+    // and span of the call.
+    pub stack: Vec<(String, String, Span)>,
+}
+
+impl Report {
+    pub fn new(severity: Severity, code: Option<&'static str>, file: &str, span: Option<Span>, message: &str) -> Self {
+        Report {
+            severity,
+            code,
+            file: String::from(file),
+            span,
+            message: String::from(message),
+            suggestions: suggestions(code, message),
+            stack: vec![],
+        }
+    }
+
+    // A runtime error with the code it was raised with, at `span` and in the calls of `stack`.
+    pub fn for_error(file: &str, span: Option<Span>, error: &RuntimeError, stack: Vec<(String, String, Span)>) -> Self {
+        Report {
+            severity: Severity::Error,
+            code: error.code,
+            file: String::from(file),
+            span,
+            message: error.message.clone(),
+            suggestions: suggestions(error.code, &error.message),
+            stack,
+        }
+    }
+
+    // One line of JSON. The span and code are `null` when there is none.
+    pub fn to_json(&self) -> String {
+        let span = |span: &Span| format!("{{\"line\": {}, \"column\": {}}}", span.line, span.column);
+        let severity = match self.severity {
+            Severity::Error => "error",
+            Severity::Warning => "warning",
+        };
+        let suggestions: Vec<String> = self.suggestions.iter().map(|suggestion| quote_json(suggestion)).collect();
+        let stack: Vec<String> = self
+            .stack
+            .iter()
+            .map(|(function, file, at)| {
+                format!(
+                    "{{\"function\": {}, \"file\": {}, \"span\": {}}}",
+                    quote_json(function),
+                    quote_json(file),
+                    span(at)
+                )
+            })
+            .collect();
+        format!(
+            "{{\"severity\": \"{}\", \"code\": {}, \"file\": {}, \"span\": {}, \"message\": {}, \"suggestions\": [{}], \
+             \"stack\": [{}]}}",
+            severity,
+            self.code.map_or(String::from("null"), quote_json),
+            quote_json(&self.file),
+            self.span.as_ref().map_or(String::from("null"), span),
+            quote_json(&self.message),
+            suggestions.join(", "),
+            stack.join(", ")
+        )
+    }
+}
+
+fn suggestions(code: Option<&str>, message: &str) -> Vec<String> {
+    let quoted = message.split('`').nth(1).unwrap_or("");
+    let suggestion = match code {
+        Some(IDENTIFIER_NOT_FOUND) => {
+            let name = message.rsplit(' ').next().unwrap_or("");
+            let is_named = |builtins: Vec<Builtin>| builtins.iter().any(|builtin| builtin.name == name);
+            match (is_named(filesystem_builtins()), is_named(environment_builtins())) {
+                (true, _) => format!("run with --allow-fs to use `{}`", name),
+                (_, true) => format!("run with --allow-env to use `{}`", name),
+                _ => return vec![],
+            }
+        }
+        Some(DEPTH_EXCEEDED) => String::from("allow deeper calls with --limit-depth"),
+        Some(BUDGET_EXCEEDED) => String::from("allow more steps with --limit-steps"),
+        Some(TIME_EXCEEDED) => String::from("allow more time with --timeout"),
+        Some(MEMORY_EXCEEDED) if message.contains("bytes in use") => {
+            String::from("allow more memory with --max-memory")
+        }
+        Some(MEMORY_EXCEEDED) => String::from("allow bigger values with --limit-size"),
+        Some(UNUSED_VARIABLE | UNUSED_PARAMETER) => format!("rename it to `_{}` if it is unused on purpose", quoted),
+        Some(UNREACHABLE_CODE) => String::from("remove the statements that cannot be reached"),
+        _ => return vec![],
+    };
+    vec![suggestion]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::environment::Environment;
+    use crate::evaluator::evaluate;
+    use crate::linter::lint;
+    use crate::object::Object;
+    use crate::syntax_analyzer::{parse, parse_with_spans};
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    #[test]
+    fn it_gives_errors_the_codes_their_messages_are_listed_under() {
+        let is_listed = |code: &str, message: &str| {
+            let diagnostic = DIAGNOSTICS.iter().find(|diagnostic| diagnostic.code == code).unwrap();
+            diagnostic.messages.iter().any(|start| message.starts_with(start))
+        };
+        for source in ["\"abc", "99999999999", "1 @", "let = 1", "1 +", "(1", "1 + x = 2", &"(".repeat(300)] {
+            for error in parse_with_spans(source).unwrap_err() {
+                assert!(is_listed(error.code, &error.message), "{}: {}", error.code, error.message);
+            }
+        }
+        let programs = [
+            "x", "y = 1", "1 + true", "-true", "true + true", "1 / 0", "2147483647 + 1", "1()", "fn(x) { x }()",
+            "len(1)", "1[0]", "{[1]: 2}", "for x in 1 {}", "for a, b in 0..2 {}", "assert(false)", "int(\"a\")",
+            "format(\"{\")", "format(\"{}\")", "random(5, 1)",
+        ];
+        for program in programs {
+            let env = Rc::new(RefCell::new(Environment::new()));
+            let Object::Error(error) = evaluate(&parse(program).unwrap(), &env) else {
+                panic!("{} did not fail", program);
+            };
+            assert!(is_listed(error.code.unwrap(), &error.message), "{:?}: {}", error.code, error.message);
+        }
+        for warning in lint("let x = 1;\nlet f = fn(a) { return 1; 2 };\nf(1)").unwrap() {
+            assert!(is_listed(warning.code, &warning.message), "{}: {}", warning.code, warning.message);
+        }
+
+        let mut codes: Vec<&str> = DIAGNOSTICS.iter().map(|diagnostic| diagnostic.code).collect();
+        codes.dedup();
+        assert_eq!(codes.len(), DIAGNOSTICS.len());
+    }
+
+    #[test]
+    fn it_writes_reports_as_json() {
+        let span = Some(Span { line: 1, column: 9 });
+        let reports = [
+            Report::new(
+                Severity::Error,
+                Some(EXPECTED_EXPRESSION),
+                "a.monkey",
+                span,
+                "expected expression, got <;, ;>",
+            ),
+            Report::new(Severity::Error, Some(INTERRUPTED), "b.monkey", None, "interrupted"),
+        ];
+        assert_eq!(
+            reports.iter().map(Report::to_json).collect::<Vec<String>>(),
+            vec![
+                "{\"severity\": \"error\", \"code\": \"E0005\", \"file\": \"a.monkey\", \"span\": {\"line\": 1, \
+                 \"column\": 9}, \"message\": \"expected expression, got <;, ;>\", \"suggestions\": [], \"stack\": []}",
+                "{\"severity\": \"error\", \"code\": \"E0118\", \"file\": \"b.monkey\", \"span\": null, \"message\": \
+                 \"interrupted\", \"suggestions\": [], \"stack\": []}",
+            ]
+        );
+
+        let error = RuntimeError { message: String::from("identifier not found: read_file"), code: Some("E0101") };
+        let stack = vec![(String::from("f"), String::from("b.monkey"), Span { line: 2, column: 5 })];
+        assert_eq!(
+            Report::for_error("a.monkey", Some(Span { line: 3, column: 1 }), &error, stack.clone()),
+            Report {
+                severity: Severity::Error,
+                code: Some("E0101"),
+                file: String::from("a.monkey"),
+                span: Some(Span { line: 3, column: 1 }),
+                message: String::from("identifier not found: read_file"),
+                suggestions: vec![String::from("run with --allow-fs to use `read_file`")],
+                stack: stack.clone(),
+            }
+        );
+        let report = Report::for_error("a.monkey", None, &error, stack);
+        assert!(report.to_json().contains("\"stack\": [{\"function\": \"f\", \"file\": \"b.monkey\", \"span\": {"));
+        let warning = Report::new(Severity::Warning, Some(UNUSED_VARIABLE), "a.monkey", None, "unused variable `x`");
+        assert!(warning.to_json().contains("\"code\": \"W0001\", \"file\": \"a.monkey\", \"span\": null"));
+        assert_eq!(warning.suggestions, vec!["rename it to `_x` if it is unused on purpose"]);
+    }
+
+    #[test]
+    fn it_explains_codes() {
+        let explanation = explain("e0105").unwrap();
+        assert!(explanation.starts_with("E0105: division by zero\n\nAn integer was divided by zero.\n"));
+        assert_eq!(explain("E9999"), None);
+    }
+}
@@ -0,0 +1,121 @@
+// How many unchanged lines a hunk shows around the changed ones.
+const CONTEXT: usize = 3;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Edit {
+    Keep,
+    Remove,
+    Add,
+}
+
+// The changes that turn `before` into `after` as a unified diff, or nothing when they are the
+// same. `name` heads both sides, the new one marked `(formatted)`.
+pub fn unified_diff(before: &str, after: &str, name: &str) -> String {
+    let (old, new): (Vec<&str>, Vec<&str>) = (before.lines().collect(), after.lines().collect());
+    let edits = edits(&old, &new);
+    if edits.iter().all(|(edit, _, _)| *edit == Edit::Keep) {
+        return String::new();
+    }
+
+    let mut diff = format!("--- {}\n+++ {} (formatted)\n", name, name);
+    let mut start = 0;
+    while let Some(first) = edits[start..].iter().position(|(edit, _, _)| *edit != Edit::Keep) {
+        // A hunk goes on for as long as the next change is close enough for the context to meet.
+        let first = start + first;
+        let mut last = first;
+        while let Some(next) = edits[last + 1..].iter().position(|(edit, _, _)| *edit != Edit::Keep) {
+            if next > 2 * CONTEXT {
+                break;
+            }
+            last += next + 1;
+        }
+        let (from, to) = (first.saturating_sub(CONTEXT), (last + CONTEXT + 1).min(edits.len()));
+        let hunk = &edits[from..to];
+        let count = |side: Edit| hunk.iter().filter(|(edit, _, _)| *edit == Edit::Keep || *edit == side).count();
+        let (old_start, new_start) = (hunk[0].1, hunk[0].2);
+        diff += &format!(
+            "@@ -{} +{} @@\n",
+            range(old_start, count(Edit::Remove)),
+            range(new_start, count(Edit::Add))
+        );
+        for (edit, old_index, new_index) in hunk {
+            diff += &match edit {
+                Edit::Keep => format!(" {}\n", old[*old_index]),
+                Edit::Remove => format!("-{}\n", old[*old_index]),
+                Edit::Add => format!("+{}\n", new[*new_index]),
+            };
+        }
+        start = to;
+    }
+    diff
+}
+
+// A range in a hunk header, numbered from 1 and from the line before when it is empty, as `diff -u`
+// does.
+fn range(start: usize, count: usize) -> String {
+    match count {
+        0 => format!("{},0", start),
+        1 => format!("{}", start + 1),
+        _ => format!("{},{}", start + 1, count),
+    }
+}
+
+// Every line of both sides with the index it is at in each, kept when it is part of their longest
+// common subsequence.
+fn edits(old: &[&str], new: &[&str]) -> Vec<(Edit, usize, usize)> {
+    // `lengths[i][j]` is the length of the longest common subsequence of `old[i..]` and `new[j..]`.
+    let mut lengths = vec![vec![0; new.len() + 1]; old.len() + 1];
+    for i in (0..old.len()).rev() {
+        for j in (0..new.len()).rev() {
+            lengths[i][j] = match old[i] == new[j] {
+                true => lengths[i + 1][j + 1] + 1,
+                false => lengths[i + 1][j].max(lengths[i][j + 1]),
+            };
+        }
+    }
+
+    let (mut i, mut j) = (0, 0);
+    let mut edits = vec![];
+    while i < old.len() || j < new.len() {
+        if i < old.len() && j < new.len() && old[i] == new[j] {
+            edits.push((Edit::Keep, i, j));
+            (i, j) = (i + 1, j + 1);
+        } else if j == new.len() || (i < old.len() && lengths[i + 1][j] >= lengths[i][j + 1]) {
+            edits.push((Edit::Remove, i, j));
+            i += 1;
+        } else {
+            edits.push((Edit::Add, i, j));
+            j += 1;
+        }
+    }
+    edits
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_diffs_lines() {
+        assert_eq!(unified_diff("a\nb\n", "a\nb\n", "same.monkey"), "");
+        assert_eq!(
+            unified_diff("let x=1;\nputs(x)\n", "let x = 1;\nputs(x)\n", "a.monkey"),
+            "--- a.monkey\n+++ a.monkey (formatted)\n@@ -1,2 +1,2 @@\n-let x=1;\n+let x = 1;\n puts(x)\n"
+        );
+        assert_eq!(
+            unified_diff("", "1\n", "new.monkey"),
+            "--- new.monkey\n+++ new.monkey (formatted)\n@@ -0,0 +1 @@\n+1\n"
+        );
+    }
+
+    #[test]
+    fn it_splits_distant_changes_into_hunks() {
+        let before: String = (1..=12).map(|line| format!("{}\n", line)).collect();
+        let after = before.replacen("2\n", "two\n", 1).replace("11\n", "eleven\n");
+        assert_eq!(
+            unified_diff(&before, &after, "n.monkey"),
+            "--- n.monkey\n+++ n.monkey (formatted)\n@@ -1,5 +1,5 @@\n 1\n-2\n+two\n 3\n 4\n 5\n@@ -8,5 +8,5 @@\n 8\n \
+             9\n 10\n-11\n+eleven\n 12\n"
+        );
+    }
+}
@@ -0,0 +1,155 @@
+use crate::abstract_syntax_tree::{join, Expression, Statement};
+use crate::lexical_analyzer::{comments, tokenize_with_spans, Span, Token};
+use crate::syntax_analyzer::{parse_with_spans, SyntaxError};
+
+// A top-level `let` with the comment right above it. Lets without one are left out of the
+// documentation.
+#[derive(Debug, PartialEq)]
+pub struct Item {
+    pub name: String,
+    // `name(a, b)` when the value is a function literal, the name alone otherwise.
+    pub signature: String,
+    // The comment's lines without their `//`, so that a line of only `//` separates paragraphs.
+    pub doc: String,
+    pub span: Span,
+}
+
+// The documented items of a program, in source order.
+pub fn document(source: &str) -> Result<Vec<Item>, Vec<SyntaxError>> {
+    let program = parse_with_spans(source)?;
+    let mut comments = comments(source);
+    comments.retain(|comment| !comment.is_trailing);
+
+    // The parser keeps no spans for statements, so the `let` tokens outside of braces stand in for
+    // them. They come in the same order as the top-level `let` statements.
+    let mut depth = 0;
+    let mut lets = vec![];
+    for (token, span) in tokenize_with_spans(source) {
+        match token {
+            Token::LeftBrace => depth += 1,
+            Token::RightBrace => depth -= 1,
+            Token::Let if depth == 0 => lets.push(span),
+            _ => {}
+        }
+    }
+
+    let mut items = vec![];
+    let definitions = program.statements.iter().filter_map(|statement| match statement {
+        Statement::Let(name, value) => Some((name, value)),
+        _ => None,
+    });
+    for ((name, value), span) in definitions.zip(lets) {
+        let mut lines = vec![];
+        let mut line = span.line;
+        while let Some(comment) = comments.iter().find(|comment| comment.span.line + 1 == line) {
+            let text = comment.text.trim_start_matches('/');
+            lines.push(text.strip_prefix(' ').unwrap_or(text));
+            line -= 1;
+        }
+        if lines.is_empty() {
+            continue;
+        }
+        lines.reverse();
+        let signature = match value {
+            Expression::Function { parameters, .. } => format!("{}({})", name, join(parameters, ", ")),
+            _ => name.to_string(),
+        };
+        items.push(Item { name: name.to_string(), signature, doc: lines.join("\n"), span });
+    }
+    Ok(items)
+}
+
+// A section per file, with a heading per item. The comments are taken to be Markdown already.
+pub fn markdown(files: &[(String, Vec<Item>)]) -> String {
+    let mut sections = vec![];
+    for (name, items) in files.iter().filter(|(_, items)| !items.is_empty()) {
+        let mut section = format!("# {}\n", name);
+        for item in items {
+            section += &format!("\n## `{}`\n\n{}\n", item.signature, item.doc);
+        }
+        sections.push(section);
+    }
+    sections.join("\n")
+}
+
+// A page of its own, with the comments' paragraphs as they are, escaped.
+pub fn html(files: &[(String, Vec<Item>)]) -> String {
+    let mut page = String::from("<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n");
+    page += "<title>Documentation</title>\n</head>\n<body>\n";
+    for (name, items) in files.iter().filter(|(_, items)| !items.is_empty()) {
+        page += &format!("<h1>{}</h1>\n", escape_html(name));
+        for item in items {
+            let (name, signature) = (escape_html(&item.name), escape_html(&item.signature));
+            page += &format!("<h2 id=\"{}\"><code>{}</code></h2>\n", name, signature);
+            for paragraph in item.doc.split("\n\n").map(str::trim).filter(|paragraph| !paragraph.is_empty()) {
+                page += &format!("<p>{}</p>\n", escape_html(paragraph));
+            }
+        }
+    }
+    page + "</body>\n</html>\n"
+}
+
+pub fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;").replace('"', "&quot;")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SOURCE: &str = "\
+// Helpers for numbers.
+
+// Adds two numbers.
+//
+// Works on floats too.
+let add = fn(a, b) { a + b };
+let undocumented = 1;
+/// The largest value `clamp` returns.
+let limit = 100; // not part of it
+let f = fn() {
+  // Not at the top level.
+  let inner = 2;
+};
+";
+
+    #[test]
+    fn it_documents_top_level_lets() {
+        assert_eq!(
+            document(SOURCE),
+            Ok(vec![
+                Item {
+                    name: String::from("add"),
+                    signature: String::from("add(a, b)"),
+                    doc: String::from("Adds two numbers.\n\nWorks on floats too."),
+                    span: Span { line: 6, column: 1 },
+                },
+                Item {
+                    name: String::from("limit"),
+                    signature: String::from("limit"),
+                    doc: String::from("The largest value `clamp` returns."),
+                    span: Span { line: 9, column: 1 },
+                },
+            ])
+        );
+        assert!(document("let = 1").is_err());
+    }
+
+    #[test]
+    fn it_writes_markdown_and_html() {
+        let files =
+            vec![(String::from("math.monkey"), document(SOURCE).unwrap()), (String::from("empty.monkey"), vec![])];
+        assert_eq!(
+            markdown(&files),
+            "# math.monkey\n\n## `add(a, b)`\n\nAdds two numbers.\n\nWorks on floats too.\n\n## `limit`\n\nThe largest \
+             value `clamp` returns.\n"
+        );
+        let html = html(&files);
+        assert!(html.contains(
+            "<h1>math.monkey</h1>\n<h2 id=\"add\"><code>add(a, b)</code></h2>\n<p>Adds two numbers.</p>\n<p>Works on \
+             floats too.</p>\n"
+        ));
+        assert!(!html.contains("empty.monkey"));
+        assert_eq!(escape_html("a < \"b\" & c"), "a &lt; &quot;b&quot; &amp; c");
+    }
+}
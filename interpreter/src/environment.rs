@@ -0,0 +1,186 @@
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::rc::Rc;
+
+use crate::interner::Symbol;
+use crate::object::Object;
+
+// Environments are always shared as `Rc<RefCell<Environment>>` and never copied:
+//
+// - a function literal keeps an `Rc` to the environment it was defined in, so it sees
+//   bindings added to that environment after its definition (which is what makes
+//   `let f = fn(n) { f(n) }` recursive);
+// - every call creates one fresh environment enclosing the function's environment and drops
+//   it when the call returns, unless a closure created during the call keeps it alive;
+// - values themselves are cheap to clone: functions are behind an `Rc`, so closures handed
+//   around share their captured environment with every other copy.
+#[derive(Default)]
+pub struct Environment {
+    store: HashMap<Symbol, Object>,
+    outer: Option<Rc<RefCell<Environment>>>,
+}
+
+impl Environment {
+    pub fn new() -> Self {
+        Environment::default()
+    }
+
+    pub fn new_enclosed(outer: Rc<RefCell<Environment>>) -> Self {
+        Environment {
+            store: HashMap::new(),
+            outer: Some(outer),
+        }
+    }
+
+    pub fn get(&self, name: Symbol) -> Option<Object> {
+        match self.store.get(&name) {
+            Some(value) => Some(value.clone()),
+            None => self.outer.as_ref().and_then(|outer| outer.borrow().get(name)),
+        }
+    }
+
+    pub fn set(&mut self, name: Symbol, value: Object) {
+        self.store.insert(name, value);
+    }
+
+    // Updates an existing binding in the innermost environment that declares it, returning
+    // false when no enclosing environment does.
+    pub fn assign(&mut self, name: Symbol, value: Object) -> bool {
+        if let Some(binding) = self.store.get_mut(&name) {
+            *binding = value;
+            return true;
+        }
+
+        match &self.outer {
+            None => false,
+            Some(outer) => outer.borrow_mut().assign(name, value),
+        }
+    }
+
+    // Captures the bindings of this environment, not of the ones enclosing it. Values are
+    // shared with the environment rather than copied, which is enough since arrays, hashes and
+    // strings are immutable. Closures keep pointing at the environment itself, so after a
+    // restore they see the restored bindings.
+    pub fn snapshot(&self) -> Snapshot {
+        Snapshot {
+            store: Rc::new(self.store.clone()),
+        }
+    }
+
+    // Puts back exactly the bindings the snapshot was taken with, forgetting every binding
+    // made since.
+    pub fn restore(&mut self, snapshot: &Snapshot) {
+        self.store = (*snapshot.store).clone();
+    }
+
+    // The bindings made directly in this environment, sorted by name.
+    pub fn bindings(&self) -> Vec<(Symbol, Object)> {
+        let mut bindings: Vec<(Symbol, Object)> =
+            self.store.iter().map(|(name, value)| (*name, value.clone())).collect();
+        bindings.sort_by_key(|(name, _)| name.name());
+        bindings
+    }
+
+    pub(crate) fn values(&self) -> impl Iterator<Item = &Object> {
+        self.store.values()
+    }
+
+    pub(crate) fn outer(&self) -> Option<Rc<RefCell<Environment>>> {
+        self.outer.clone()
+    }
+
+    pub(crate) fn clear(&mut self) {
+        self.store.clear();
+        self.outer = None;
+    }
+}
+
+// An immutable copy of an environment's bindings, cheap to clone and restorable any number
+// of times.
+#[derive(Clone)]
+pub struct Snapshot {
+    store: Rc<HashMap<Symbol, Object>>,
+}
+
+impl Snapshot {
+    pub fn len(&self) -> usize {
+        self.store.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.store.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::evaluator::evaluate;
+    use crate::syntax_analyzer::parse;
+
+    fn evaluate_in(env: &Rc<RefCell<Environment>>, input: &str) -> String {
+        evaluate(&parse(input).unwrap(), env).to_string()
+    }
+
+    fn captured_env(env: &Rc<RefCell<Environment>>, name: &str) -> Rc<RefCell<Environment>> {
+        match env.borrow().get(Symbol::intern(name)) {
+            Some(Object::Function(function)) => Rc::clone(&function.env),
+            other => panic!("expected function, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn it_shares_the_defining_environment_with_functions() {
+        let env = Rc::new(RefCell::new(Environment::new()));
+        assert_eq!(evaluate_in(&env, "let f = fn() { g }; let g = 5; f()"), "5");
+        assert!(Rc::ptr_eq(&captured_env(&env, "f"), &env));
+    }
+
+    #[test]
+    fn it_does_not_keep_call_environments_alive() {
+        let env = Rc::new(RefCell::new(Environment::new()));
+        evaluate_in(&env, "let id = fn(x) { x };");
+        let strong_count = Rc::strong_count(&env);
+
+        assert_eq!(evaluate_in(&env, "id(id(id(1)))"), "1");
+        assert_eq!(Rc::strong_count(&env), strong_count);
+    }
+
+    #[test]
+    fn it_gives_each_call_its_own_environment() {
+        let env = Rc::new(RefCell::new(Environment::new()));
+        evaluate_in(&env, "let make = fn(x) { fn() { x } }; let one = make(1); let two = make(2);");
+        assert_eq!(evaluate_in(&env, "one() + two()"), "3");
+
+        let one_env = captured_env(&env, "one");
+        let two_env = captured_env(&env, "two");
+        assert!(!Rc::ptr_eq(&one_env, &two_env));
+        // Both call environments enclose the same global environment rather than a copy of it.
+        assert!(Rc::ptr_eq(one_env.borrow().outer.as_ref().unwrap(), &env));
+        assert!(Rc::ptr_eq(two_env.borrow().outer.as_ref().unwrap(), &env));
+    }
+
+    #[test]
+    fn it_shares_captured_environments_between_copies_of_a_closure() {
+        let env = Rc::new(RefCell::new(Environment::new()));
+        evaluate_in(&env, "let make = fn(x) { fn() { x } }; let one = make(1); let alias = one;");
+        assert!(Rc::ptr_eq(&captured_env(&env, "one"), &captured_env(&env, "alias")));
+    }
+
+    #[test]
+    fn it_restores_snapshots() {
+        let env = Rc::new(RefCell::new(Environment::new()));
+        evaluate_in(&env, "let x = 1; let counter = fn() { x = x + 1; x };");
+        let snapshot = env.borrow().snapshot();
+        assert_eq!(snapshot.len(), 2);
+
+        assert_eq!(evaluate_in(&env, "counter(); let y = 5; x = 10; counter()"), "11");
+        env.borrow_mut().restore(&snapshot);
+        assert_eq!(evaluate_in(&env, "y"), "ERROR: identifier not found: y");
+        assert_eq!(evaluate_in(&env, "counter()"), "2");
+
+        // The snapshot itself is not changed by restoring it and evaluating more.
+        env.borrow_mut().restore(&snapshot);
+        assert_eq!(evaluate_in(&env, "x"), "1");
+    }
+}
@@ -0,0 +1,1702 @@
+use core::fmt;
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::io::{self, Read, Write};
+use std::rc::Rc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use crate::abstract_syntax_tree::{BlockStatement, Expression, InfixOperator, PrefixOperator, Program, Statement};
+use crate::builtins::{environment_builtins, filesystem_builtins, standard_builtins};
+use crate::clock::Clock;
+use crate::diagnostics::{
+    BUDGET_EXCEEDED, DEPTH_EXCEEDED, DIVISION_BY_ZERO, IDENTIFIER_NOT_FOUND, INTEGER_OVERFLOW, INTERRUPTED,
+    MEMORY_EXCEEDED, NOT_A_FUNCTION, NOT_ITERABLE, OUTSIDE_LOOP, TIME_EXCEEDED, TYPE_MISMATCH, UNDECLARED_ASSIGNMENT,
+    UNKNOWN_OPERATOR, UNSUPPORTED_INDEX, UNUSABLE_HASH_KEY, WRONG_ARGUMENT_COUNT,
+};
+use crate::environment::Environment;
+use crate::garbage_collector::CycleCollector;
+use crate::hooks::{Call, Hooks};
+use crate::interner::Symbol;
+use crate::lexical_analyzer::Span;
+use crate::object::{Builtin, Function, HashPairs, Object};
+use crate::optimizer::fold_constants;
+use crate::random::Random;
+
+pub const DEFAULT_MAX_DEPTH: usize = 1000;
+
+// How many steps go by between looks at the clock for the time limit.
+const TIME_CHECK_INTERVAL: usize = 1024;
+
+pub fn evaluate(program: &Program, env: &Rc<RefCell<Environment>>) -> Object {
+    Evaluator::new().eval_program(program, env)
+}
+
+// Calls the functions the evaluator cannot, those of the virtual machine when a builtin it ran is
+// passed one.
+pub(crate) type FunctionCaller = Rc<dyn Fn(&mut Evaluator, Object, Vec<Object>) -> Object>;
+
+// What stops a program without a `try` being able to catch it, or a sandboxed program could ignore
+// the limits it was given.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Limit {
+    Steps,
+    Time,
+    // The heap limit, and the limit on the size of a value.
+    Memory,
+    Depth,
+}
+
+enum Evaluation {
+    Value(Object),
+    TailCall(Object, Vec<Object>, Frame),
+}
+
+// A call an error passed through on its way out, named after the expression that was called, and
+// the file the call is in when the program was told it.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Frame {
+    pub function: String,
+    pub span: Span,
+    pub file: Option<Rc<str>>,
+}
+
+impl Frame {
+    pub(crate) fn new(function: &Expression, span: Span) -> Self {
+        let function = match function {
+            Expression::Identifier(name) => name.to_string(),
+            _ => String::from("<anonymous>"),
+        };
+        Frame { function, span, file: None }
+    }
+}
+
+impl fmt::Display for Frame {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.file {
+            Some(file) => write!(f, "at {} ({}:{})", self.function, file, self.span),
+            None => write!(f, "at {} ({})", self.function, self.span),
+        }
+    }
+}
+
+pub struct Evaluator {
+    max_depth: usize,
+    depth: usize,
+    step_budget: Option<usize>,
+    // How long evaluation may take, counted from `started`.
+    time_limit: Option<Duration>,
+    started: Instant,
+    steps: usize,
+    max_value_size: Option<usize>,
+    // The most bytes the heap may hold, and how to tell how many it holds now.
+    heap_limit: Option<(usize, Box<dyn Fn() -> usize>)>,
+    cycle_collector: CycleCollector,
+    builtins: HashMap<Symbol, Builtin>,
+    output: Box<dyn Write>,
+    input: Box<dyn Read>,
+    stack_trace: Vec<Frame>,
+    // Where the last error happened, the call that failed or else the statement it happened in,
+    // and in which file.
+    error_span: Option<Span>,
+    error_file: Option<Rc<str>>,
+    // The file of the code running, that of the function called while it runs.
+    file: Option<Rc<str>>,
+    // The limit the program ran into, if it did.
+    exceeded: Option<Limit>,
+    folds_constants: bool,
+    random: Random,
+    clock: Clock,
+    arguments: Vec<String>,
+    call_site: Span,
+    hooks: Option<Box<dyn Hooks>>,
+    interrupt: Option<Arc<AtomicBool>>,
+    function_caller: Option<FunctionCaller>,
+}
+
+impl Default for Evaluator {
+    fn default() -> Self {
+        Evaluator::new()
+    }
+}
+
+impl Evaluator {
+    pub fn new() -> Self {
+        Evaluator {
+            max_depth: DEFAULT_MAX_DEPTH,
+            depth: 0,
+            step_budget: None,
+            time_limit: None,
+            started: Instant::now(),
+            steps: 0,
+            max_value_size: None,
+            heap_limit: None,
+            cycle_collector: CycleCollector::new(),
+            builtins: standard_builtins().into_iter().map(|builtin| (Symbol::intern(builtin.name), builtin)).collect(),
+            output: Box::new(io::stdout()),
+            input: Box::new(io::stdin()),
+            stack_trace: vec![],
+            error_span: None,
+            error_file: None,
+            file: None,
+            exceeded: None,
+            folds_constants: false,
+            random: Random::from_time(),
+            clock: Clock::System,
+            arguments: vec![],
+            call_site: Span { line: 1, column: 1 },
+            hooks: None,
+            interrupt: None,
+            function_caller: None,
+        }
+    }
+
+    pub fn with_max_depth(mut self, max_depth: usize) -> Self {
+        self.max_depth = max_depth;
+        self
+    }
+
+    // Caps the total number of expressions this evaluator will evaluate over its lifetime,
+    // so embedders can run untrusted programs without risking a hang.
+    pub fn with_step_budget(mut self, step_budget: usize) -> Self {
+        self.step_budget = Some(step_budget);
+        self
+    }
+
+    // Stops evaluation with an error once it has run for longer than `time_limit` since the
+    // evaluator was made, or since the last `reset_steps`.
+    pub fn with_time_limit(mut self, time_limit: Duration) -> Self {
+        self.time_limit = Some(time_limit);
+        self
+    }
+
+    // Starts the step budget and the time limit over, for hosts that give every program its own
+    // instead of this evaluator's whole lifetime.
+    pub fn reset_steps(&mut self) {
+        self.steps = 0;
+        self.started = Instant::now();
+    }
+
+    // Caps how many bytes a string, or elements an array or hash, may hold. Doubling a string a
+    // few dozen times takes hardly any steps, so the step budget alone does not bound memory.
+    pub fn with_memory_limit(mut self, max_value_size: usize) -> Self {
+        self.max_value_size = Some(max_value_size);
+        self
+    }
+
+    // Stops evaluation with an error once `usage` says more than `max_bytes` are in use. Only the
+    // host knows how much its allocator has given out, so it is asked on every step.
+    pub fn with_heap_limit(mut self, max_bytes: usize, usage: impl Fn() -> usize + 'static) -> Self {
+        self.heap_limit = Some((max_bytes, Box::new(usage)));
+        self
+    }
+
+    // Stops evaluation with an error as soon as `interrupt` is set, e.g. from a Ctrl-C handler. The flag
+    // is left set, it is up to the caller to clear it before evaluating again.
+    pub fn with_interrupt(mut self, interrupt: Arc<AtomicBool>) -> Self {
+        self.interrupt = Some(interrupt);
+        self
+    }
+
+    // Runs `fold_constants` over every program before evaluating it.
+    pub fn with_constant_folding(mut self) -> Self {
+        self.folds_constants = true;
+        self
+    }
+
+    pub fn with_hooks(mut self, hooks: impl Hooks + 'static) -> Self {
+        self.hooks = Some(Box::new(hooks));
+        self
+    }
+
+    // Makes `random` return the same sequence on every run.
+    pub fn with_seed(mut self, seed: u64) -> Self {
+        self.random = Random::new(seed);
+        self
+    }
+
+    pub fn with_clock(mut self, clock: Clock) -> Self {
+        self.clock = clock;
+        self
+    }
+
+    // What `args()` returns, the command line arguments given after the script.
+    pub fn with_arguments(mut self, arguments: Vec<String>) -> Self {
+        self.arguments = arguments;
+        self
+    }
+
+    pub(crate) fn arguments(&self) -> &[String] {
+        &self.arguments
+    }
+
+    pub(crate) fn random(&mut self) -> &mut Random {
+        &mut self.random
+    }
+
+    pub(crate) fn clock(&mut self) -> &mut Clock {
+        &mut self.clock
+    }
+
+    // Where the function being applied was called, for builtins to report.
+    pub(crate) fn call_site(&self) -> Span {
+        self.call_site
+    }
+
+    // Takes a builtin away from programs, e.g. `input` where standard input is not theirs to read.
+    pub fn without_builtin(mut self, name: &str) -> Self {
+        self.builtins.remove(&Symbol::intern(name));
+        self
+    }
+
+    // Registers `read_file` and `write_file`. Programs cannot touch the filesystem unless the
+    // host opts in.
+    pub fn with_filesystem_access(mut self) -> Self {
+        self.builtins.extend(filesystem_builtins().into_iter().map(|builtin| (Symbol::intern(builtin.name), builtin)));
+        self
+    }
+
+    // Registers `env`, the same way: environment variables often hold secrets.
+    pub fn with_environment_access(mut self) -> Self {
+        self.builtins.extend(environment_builtins().into_iter().map(|builtin| (Symbol::intern(builtin.name), builtin)));
+        self
+    }
+
+    // Where `puts` and `printf` write to, standard output unless the host redirects it.
+    pub fn with_output(mut self, output: impl Write + 'static) -> Self {
+        self.output = Box::new(output);
+        self
+    }
+
+    pub(crate) fn output(&mut self) -> &mut dyn Write {
+        &mut self.output
+    }
+
+    // Where `input` reads lines from, standard input unless the host redirects it.
+    pub fn with_input(mut self, input: impl Read + 'static) -> Self {
+        self.input = Box::new(input);
+        self
+    }
+
+    // Reads one line without its line ending, `None` at the end of the input. Bytes are taken
+    // one at a time so nothing past the line is consumed, standard input stays usable by a
+    // REPL reading from it too.
+    pub(crate) fn read_line(&mut self) -> io::Result<Option<String>> {
+        let mut line = vec![];
+        let mut byte = [0];
+        loop {
+            if self.input.read(&mut byte)? == 0 {
+                if line.is_empty() {
+                    return Ok(None);
+                }
+                break;
+            }
+            if byte[0] == b'\n' {
+                break;
+            }
+            line.push(byte[0]);
+        }
+
+        if line.last() == Some(&b'\r') {
+            line.pop();
+        }
+        Ok(Some(String::from_utf8_lossy(&line).into_owned()))
+    }
+
+    // The calls the error returned by the last `eval_program` propagated through, innermost
+    // first. A chain of tail calls only keeps its last call.
+    pub fn stack_trace(&self) -> &[Frame] {
+        &self.stack_trace
+    }
+
+    // Where the error the last program stopped with happened, the innermost call or statement it
+    // happened in.
+    pub fn error_span(&self) -> Option<Span> {
+        self.error_span
+    }
+
+    // The file `error_span` is in, if the program was told the files of its code.
+    pub fn error_file(&self) -> Option<&str> {
+        self.error_file.as_deref()
+    }
+
+    // Tells the programs evaluated from now on which file they are in. The functions they define
+    // keep it, so that their errors are placed in it wherever they are called from.
+    pub fn set_file(&mut self, file: Option<&str>) {
+        self.file = file.map(Rc::from);
+    }
+
+    // Notes where an error of the virtual machine happened, as `locate_error` does.
+    pub(crate) fn set_error_span(&mut self, span: Span) {
+        if self.error_span.is_none() {
+            self.error_span = Some(span);
+            self.error_file = self.file.clone();
+        }
+    }
+
+    // Whether a `try` can catch the error the program is stopping with. Running into a limit cannot
+    // be caught, and neither can an interrupt.
+    pub(crate) fn can_catch(&self) -> bool {
+        !self.is_limit_exceeded() && !self.is_interrupted()
+    }
+
+    // Forgets the error a `try` caught: where it happened, and the calls it passed through after
+    // the `stack_depth` there were when the `try` started.
+    pub(crate) fn catch_error(&mut self, stack_depth: usize) {
+        self.stack_trace.truncate(stack_depth);
+        self.error_span = None;
+        self.error_file = None;
+    }
+
+    pub(crate) fn builtin(&self, name: Symbol) -> Option<&Builtin> {
+        self.builtins.get(&name)
+    }
+
+    pub(crate) fn replace_function_caller(&mut self, caller: Option<FunctionCaller>) -> Option<FunctionCaller> {
+        std::mem::replace(&mut self.function_caller, caller)
+    }
+
+    // Counts a call the virtual machine makes against the depth limit, until `leave_call`.
+    pub(crate) fn enter_call(&mut self) -> Option<Object> {
+        if self.depth >= self.max_depth {
+            return Some(self.exceed(Limit::Depth, String::from("maximum recursion depth exceeded")));
+        }
+        self.depth += 1;
+        None
+    }
+
+    pub(crate) fn leave_call(&mut self) {
+        self.depth -= 1;
+    }
+
+    // Adds a call an error of the virtual machine passed through, after the ones inside it.
+    pub(crate) fn push_frame(&mut self, frame: Frame) {
+        self.stack_trace.push(Frame { file: frame.file.or_else(|| self.file.clone()), ..frame });
+    }
+
+    // A program only runs at the top level, so nothing can be left on the stack from before, not
+    // even after a panic the REPL caught.
+    pub(crate) fn start_program(&mut self) {
+        self.stack_trace.clear();
+        self.error_span = None;
+        self.error_file = None;
+        self.exceeded = None;
+        self.depth = 0;
+    }
+
+    // The names of the builtins programs can call, sorted.
+    pub fn builtin_names(&self) -> Vec<String> {
+        let mut names: Vec<String> = self.builtins.keys().map(|name| name.to_string()).collect();
+        names.sort();
+        names
+    }
+
+    // The builtins programs can call, sorted by name.
+    pub fn builtins(&self) -> Vec<&Builtin> {
+        let mut builtins: Vec<&Builtin> = self.builtins.values().collect();
+        builtins.sort_by_key(|builtin| builtin.name);
+        builtins
+    }
+
+    pub fn steps(&self) -> usize {
+        self.steps
+    }
+
+    pub fn cycle_collector(&mut self) -> &mut CycleCollector {
+        &mut self.cycle_collector
+    }
+
+    pub fn eval_program(&mut self, program: &Program, env: &Rc<RefCell<Environment>>) -> Object {
+        if !self.cycle_collector.is_tracked(env) {
+            self.cycle_collector.track(env);
+        }
+        self.start_program();
+
+        let folded;
+        let program = if self.folds_constants {
+            folded = fold_constants(program);
+            &folded
+        } else {
+            program
+        };
+
+        let mut result = Object::Null;
+        for (index, statement) in program.statements.iter().enumerate() {
+            result = self.eval_statement(statement, env);
+            if let Object::Break | Object::Continue = result {
+                result = outside_loop_error(&result);
+            }
+            self.locate_error(&result, program.spans.get(index));
+            match result {
+                Object::ReturnValue(value) => return *value,
+                Object::Error(_) => return result,
+                _ => {}
+            }
+        }
+
+        result
+    }
+
+    fn eval_block_statement(&mut self, block: &BlockStatement, env: &Rc<RefCell<Environment>>) -> Object {
+        let mut result = Object::Null;
+        for (index, statement) in block.statements.iter().enumerate() {
+            result = self.eval_statement(statement, env);
+            self.locate_error(&result, block.spans.get(index));
+            // Return values and loop signals stay wrapped here so that enclosing blocks stop as
+            // well, until a function call or a loop unwraps them.
+            if result.is_abrupt() {
+                return result;
+            }
+        }
+
+        result
+    }
+
+    // Notes that an error happened in the statement at `span`, unless it happened in one inside it.
+    fn locate_error(&mut self, result: &Object, span: Option<&Span>) {
+        if result.is_error() && self.error_span.is_none() {
+            self.error_span = span.copied();
+            self.error_file = self.file.clone();
+        }
+    }
+
+    // The call at `span` of the code running, for the stack trace.
+    fn frame(&self, callee: &Expression, span: Span) -> Frame {
+        Frame { file: self.file.clone(), ..Frame::new(callee, span) }
+    }
+
+    fn eval_statement(&mut self, statement: &Statement, env: &Rc<RefCell<Environment>>) -> Object {
+        if let Some(hooks) = &mut self.hooks {
+            hooks.on_statement(statement);
+        }
+        match statement {
+            Statement::Let(name, expression) => {
+                let value = self.eval_expression(expression, env);
+                if value.is_abrupt() {
+                    return value;
+                }
+                env.borrow_mut().set(*name, value);
+                Object::Null
+            }
+            Statement::Return(expression) => {
+                let value = self.eval_expression(expression, env);
+                if value.is_abrupt() {
+                    return value;
+                }
+                Object::ReturnValue(Box::new(value))
+            }
+            Statement::Break => Object::Break,
+            Statement::Continue => Object::Continue,
+            Statement::Expression(expression) => self.eval_expression(expression, env),
+        }
+    }
+
+    fn track_environment(&mut self, env: &Rc<RefCell<Environment>>) {
+        self.cycle_collector.track(env);
+        if self.cycle_collector.should_collect() {
+            self.cycle_collector.collect();
+        }
+    }
+
+    // Has the cycle collector look after a cell the virtual machine moved a captured variable to.
+    pub(crate) fn track_cell(&mut self, cell: &Rc<RefCell<Object>>) {
+        self.cycle_collector.track_cell(cell);
+        if self.cycle_collector.should_collect() {
+            self.cycle_collector.collect();
+        }
+    }
+
+    pub(crate) fn take_step(&mut self) -> Option<Object> {
+        self.steps += 1;
+        if self.is_out_of_steps() {
+            return Some(self.exceed(Limit::Steps, String::from("evaluation budget exceeded")));
+        }
+        if self.is_interrupted() {
+            return Some(Object::error(INTERRUPTED, String::from("interrupted")));
+        }
+        if self.is_out_of_time() {
+            return Some(self.exceed(Limit::Time, String::from("time limit exceeded")));
+        }
+        if let Some((max_bytes, usage)) = &self.heap_limit {
+            let (max_bytes, in_use) = (*max_bytes, usage());
+            if in_use > max_bytes {
+                let message = format!("memory limit exceeded: {} bytes in use (at most {})", in_use, max_bytes);
+                return Some(self.exceed(Limit::Memory, message));
+            }
+        }
+
+        None
+    }
+
+    fn exceed(&mut self, limit: Limit, message: String) -> Object {
+        self.exceeded = Some(limit);
+        let code = match limit {
+            Limit::Steps => BUDGET_EXCEEDED,
+            Limit::Time => TIME_EXCEEDED,
+            Limit::Memory => MEMORY_EXCEEDED,
+            Limit::Depth => DEPTH_EXCEEDED,
+        };
+        Object::error(code, message)
+    }
+
+    // Whether the error the program stopped with is from running into one of its limits.
+    pub fn is_limit_exceeded(&self) -> bool {
+        self.exceeded.is_some()
+    }
+
+    pub fn exceeded_limit(&self) -> Option<Limit> {
+        self.exceeded
+    }
+
+    // Reading the clock on every step would slow everything down for a limit that is never close.
+    fn is_out_of_time(&self) -> bool {
+        self.steps.is_multiple_of(TIME_CHECK_INTERVAL)
+            && self.time_limit.is_some_and(|time_limit| self.started.elapsed() > time_limit)
+    }
+
+    fn is_out_of_steps(&self) -> bool {
+        self.step_budget.is_some_and(|step_budget| self.steps > step_budget)
+    }
+
+    fn is_interrupted(&self) -> bool {
+        self.interrupt.as_ref().is_some_and(|interrupt| interrupt.load(Ordering::Relaxed))
+    }
+
+    fn eval_expression(&mut self, expression: &Expression, env: &Rc<RefCell<Environment>>) -> Object {
+        if self.hooks.is_none() {
+            let result = self.eval_unhooked_expression(expression, env);
+            return self.check_size(result);
+        }
+
+        if let Some(hooks) = &mut self.hooks {
+            hooks.on_eval_enter(expression, env);
+        }
+        let result = self.eval_unhooked_expression(expression, env);
+        if let Some(hooks) = &mut self.hooks {
+            hooks.on_eval_exit(expression, env, &result);
+        }
+        self.check_size(result)
+    }
+
+    pub(crate) fn check_size(&mut self, value: Object) -> Object {
+        let max_value_size = match self.max_value_size {
+            Some(max_value_size) => max_value_size,
+            None => return value,
+        };
+        let (size, unit) = match &value {
+            Object::String(value) => (value.len(), "bytes"),
+            Object::Array(elements) => (elements.len(), "elements"),
+            Object::Hash(pairs) => (pairs.len(), "pairs"),
+            _ => return value,
+        };
+        if size <= max_value_size {
+            return value;
+        }
+        let message =
+            format!("memory limit exceeded: {} of {} {} (at most {})", value.type_name(), size, unit, max_value_size);
+        self.exceed(Limit::Memory, message)
+    }
+
+    fn eval_unhooked_expression(&mut self, expression: &Expression, env: &Rc<RefCell<Environment>>) -> Object {
+        if let Some(error) = self.take_step() {
+            return error;
+        }
+
+        match expression {
+            Expression::Identifier(name) => self.eval_identifier(*name, env),
+            Expression::Integer(value) => Object::Integer(*value),
+            Expression::Float(value) => Object::Float(*value),
+            Expression::Null => Object::Null,
+            Expression::Boolean(value) => Object::Boolean(*value),
+            Expression::String(value) => Object::String(value.clone()),
+            Expression::Array(elements) => match self.eval_expressions(elements, env) {
+                Ok(elements) => Object::Array(Rc::new(elements)),
+                Err(error) => error,
+            },
+            Expression::Hash(pairs) => self.eval_hash_literal(pairs, env),
+            Expression::Prefix(operator, right) => {
+                let right = self.eval_expression(right, env);
+                if right.is_error() {
+                    return right;
+                }
+                eval_prefix_expression(*operator, right)
+            }
+            Expression::Infix(InfixOperator::And, left, right) => self.eval_logical_expression(false, left, right, env),
+            Expression::Infix(InfixOperator::Or, left, right) => self.eval_logical_expression(true, left, right, env),
+            Expression::Infix(operator, left, right) => {
+                let left = self.eval_expression(left, env);
+                if left.is_error() {
+                    return left;
+                }
+                let right = self.eval_expression(right, env);
+                if right.is_error() {
+                    return right;
+                }
+                eval_infix_expression(*operator, left, right)
+            }
+            Expression::Assign(name, value) => {
+                let value = self.eval_expression(value, env);
+                if value.is_error() {
+                    return value;
+                }
+
+                if !env.borrow_mut().assign(*name, value.clone()) {
+                    let message = format!("assignment to undeclared identifier: {}", name);
+                    return Object::error(UNDECLARED_ASSIGNMENT, message);
+                }
+                value
+            }
+            Expression::If { condition, consequence, alternative } => {
+                let condition = self.eval_expression(condition, env);
+                if condition.is_error() {
+                    return condition;
+                }
+
+                if condition.is_truthy() {
+                    self.eval_block_statement(consequence, env)
+                } else if let Some(alternative) = alternative {
+                    self.eval_block_statement(alternative, env)
+                } else {
+                    Object::Null
+                }
+            }
+            Expression::While { condition, body } => self.eval_while_expression(condition, body, env),
+            Expression::For { variables, iterable, body } => self.eval_for_expression(variables, iterable, body, env),
+            Expression::Try { body, variable, handler } => self.eval_try_expression(body, *variable, handler, env),
+            Expression::Function { parameters, body } => Object::Function(Rc::new(Function {
+                parameters: parameters.clone(),
+                body: Rc::clone(body),
+                env: Rc::clone(env),
+                file: self.file.clone(),
+            })),
+            Expression::Call { function: callee, arguments, span } => match self.eval_call(callee, arguments, env) {
+                Ok((function, arguments)) => {
+                    self.call_site = *span;
+                    let result = self.apply_call(function, arguments, Some((expression, env)));
+                    if result.is_error() {
+                        let frame = self.frame(callee, *span);
+                        self.stack_trace.push(frame);
+                        // Unless it happened in a statement of the function, the error is the call's.
+                        self.locate_error(&result, Some(span));
+                    }
+                    result
+                }
+                Err(error) => error,
+            },
+            Expression::Index(left, index) => {
+                let left = self.eval_expression(left, env);
+                if left.is_error() {
+                    return left;
+                }
+                let index = self.eval_expression(index, env);
+                if index.is_error() {
+                    return index;
+                }
+                eval_index_expression(left, index)
+            }
+        }
+    }
+
+    // Bindings shadow builtins, so scripts can still define their own `map`.
+    fn eval_identifier(&self, name: Symbol, env: &Rc<RefCell<Environment>>) -> Object {
+        if let Some(value) = env.borrow().get(name) {
+            return value;
+        }
+
+        match self.builtins.get(&name) {
+            Some(builtin) => Object::Builtin(builtin.clone()),
+            None => Object::error(IDENTIFIER_NOT_FOUND, format!("identifier not found: {}", name)),
+        }
+    }
+
+    fn eval_expressions(
+        &mut self,
+        expressions: &[Expression],
+        env: &Rc<RefCell<Environment>>,
+    ) -> Result<Vec<Object>, Object> {
+        let mut values = vec![];
+        for expression in expressions {
+            let value = self.eval_expression(expression, env);
+            if value.is_error() {
+                return Err(value);
+            }
+            values.push(value);
+        }
+
+        Ok(values)
+    }
+
+    fn eval_hash_literal(&mut self, pairs: &[(Expression, Expression)], env: &Rc<RefCell<Environment>>) -> Object {
+        let mut hash = HashPairs::new();
+        for (key, value) in pairs {
+            let key = self.eval_expression(key, env);
+            if key.is_error() {
+                return key;
+            }
+            let hash_key = match key.hash_key() {
+                Some(hash_key) => hash_key,
+                None => return Object::error(UNUSABLE_HASH_KEY, format!("unusable as hash key: {}", key.type_name())),
+            };
+
+            let value = self.eval_expression(value, env);
+            if value.is_error() {
+                return value;
+            }
+            hash.insert(hash_key, value);
+        }
+
+        Object::Hash(Rc::new(hash))
+    }
+
+    fn eval_while_expression(
+        &mut self,
+        condition: &Expression,
+        body: &BlockStatement,
+        env: &Rc<RefCell<Environment>>,
+    ) -> Object {
+        loop {
+            // Re-evaluating the condition takes a step every iteration, so even `while (true) {}`
+            // stops once the step budget runs out.
+            let condition = self.eval_expression(condition, env);
+            if condition.is_error() {
+                return condition;
+            }
+            if !condition.is_truthy() {
+                return Object::Null;
+            }
+
+            match self.eval_block_statement(body, env) {
+                Object::Break => return Object::Null,
+                result @ (Object::ReturnValue(_) | Object::Error(_)) => return result,
+                _ => {}
+            }
+        }
+    }
+
+    // Every iteration runs the body in a fresh environment enclosing the loop's one, holding
+    // the loop variables. Bindings made with `let` in the body stay local to their iteration and
+    // closures created in the body capture that iteration's values, while assignments still
+    // reach variables declared outside the loop.
+    fn eval_for_expression(
+        &mut self,
+        variables: &[Symbol],
+        iterable: &Expression,
+        body: &BlockStatement,
+        env: &Rc<RefCell<Environment>>,
+    ) -> Object {
+        let iterable = self.eval_expression(iterable, env);
+        if iterable.is_error() {
+            return iterable;
+        }
+
+        let items: Box<dyn Iterator<Item = Vec<Object>>> = match (&iterable, variables.len()) {
+            (Object::Array(elements), 1) => Box::new(elements.iter().map(|element| vec![element.clone()])),
+            (Object::Array(elements), _) => Box::new(
+                elements
+                    .iter()
+                    .enumerate()
+                    .map(|(index, element)| vec![Object::Integer(index as i32), element.clone()]),
+            ),
+            (Object::Hash(pairs), 1) => Box::new(pairs.keys().map(|key| vec![key.to_object()])),
+            (Object::Hash(pairs), _) => Box::new(pairs.iter().map(|(key, value)| vec![key.to_object(), value.clone()])),
+            (Object::Range(start, end), 1) => Box::new((*start..*end).map(|value| vec![Object::Integer(value)])),
+            (Object::Range(_, _), _) => {
+                return Object::error(NOT_ITERABLE, String::from("a RANGE yields one loop variable, got 2"));
+            }
+            (iterable, _) => return Object::error(NOT_ITERABLE, format!("not iterable: {}", iterable.type_name())),
+        };
+
+        for values in items {
+            if let Some(error) = self.take_step() {
+                return error;
+            }
+
+            let mut iteration_env = Environment::new_enclosed(Rc::clone(env));
+            for (variable, value) in variables.iter().zip(values) {
+                iteration_env.set(*variable, value);
+            }
+            let iteration_env = Rc::new(RefCell::new(iteration_env));
+            self.track_environment(&iteration_env);
+
+            match self.eval_block_statement(body, &iteration_env) {
+                Object::Break => break,
+                result @ (Object::ReturnValue(_) | Object::Error(_)) => return result,
+                _ => {}
+            }
+        }
+
+        Object::Null
+    }
+
+    // The handler runs in a fresh environment enclosing the current one, holding the error's
+    // message.
+    fn eval_try_expression(
+        &mut self,
+        body: &BlockStatement,
+        variable: Symbol,
+        handler: &BlockStatement,
+        env: &Rc<RefCell<Environment>>,
+    ) -> Object {
+        let stack_depth = self.stack_trace.len();
+        let message = match self.eval_block_statement(body, env) {
+            Object::Error(error) if self.can_catch() => error.message,
+            result => return result,
+        };
+        self.catch_error(stack_depth);
+
+        let mut handler_env = Environment::new_enclosed(Rc::clone(env));
+        handler_env.set(variable, Object::String(message));
+        let handler_env = Rc::new(RefCell::new(handler_env));
+        self.track_environment(&handler_env);
+
+        self.eval_block_statement(handler, &handler_env)
+    }
+
+    fn eval_call(
+        &mut self,
+        function: &Expression,
+        arguments: &[Expression],
+        env: &Rc<RefCell<Environment>>,
+    ) -> Result<(Object, Vec<Object>), Object> {
+        let function = self.eval_expression(function, env);
+        if function.is_error() {
+            return Err(function);
+        }
+
+        Ok((function, self.eval_expressions(arguments, env)?))
+    }
+
+    // Evaluates a function body like `eval_block_statement`, except that a call whose result
+    // would be returned straight away is handed back to `apply_function` instead of being
+    // made, so tail calls run in constant stack space.
+    fn eval_tail_block_statement(&mut self, block: &BlockStatement, env: &Rc<RefCell<Environment>>) -> Evaluation {
+        let (last, rest) = match block.statements.split_last() {
+            None => return Evaluation::Value(Object::Null),
+            Some(split) => split,
+        };
+
+        for (index, statement) in rest.iter().enumerate() {
+            let result = self.eval_statement(statement, env);
+            self.locate_error(&result, block.spans.get(index));
+            if result.is_abrupt() {
+                return Evaluation::Value(result);
+            }
+        }
+
+        let span = block.spans.get(rest.len());
+        match last {
+            Statement::Expression(expression) | Statement::Return(expression) => {
+                if let Some(hooks) = &mut self.hooks {
+                    hooks.on_statement(last);
+                }
+                match self.eval_tail_expression(expression, env) {
+                    Evaluation::Value(result) => {
+                        self.locate_error(&result, span);
+                        Evaluation::Value(result)
+                    }
+                    tail_call => tail_call,
+                }
+            }
+            statement => {
+                let result = self.eval_statement(statement, env);
+                self.locate_error(&result, span);
+                Evaluation::Value(result)
+            }
+        }
+    }
+
+    fn eval_tail_expression(&mut self, expression: &Expression, env: &Rc<RefCell<Environment>>) -> Evaluation {
+        if !matches!(expression, Expression::Call { .. } | Expression::If { .. }) {
+            return Evaluation::Value(self.eval_expression(expression, env));
+        }
+
+        if let Some(hooks) = &mut self.hooks {
+            hooks.on_eval_enter(expression, env);
+        }
+        let result = self.eval_unhooked_tail_expression(expression, env);
+        if let (Some(hooks), Evaluation::Value(result)) = (&mut self.hooks, &result) {
+            hooks.on_eval_exit(expression, env, result);
+        }
+        result
+    }
+
+    fn eval_unhooked_tail_expression(&mut self, expression: &Expression, env: &Rc<RefCell<Environment>>) -> Evaluation {
+        if let Some(error) = self.take_step() {
+            return Evaluation::Value(error);
+        }
+
+        match expression {
+            Expression::Call { function: callee, arguments, span } => match self.eval_call(callee, arguments, env) {
+                Ok((function, arguments)) => {
+                    if let Some(hooks) = &mut self.hooks {
+                        hooks.on_call(Some((expression, env)), &function, &arguments, *span);
+                    }
+                    Evaluation::TailCall(function, arguments, self.frame(callee, *span))
+                }
+                Err(error) => Evaluation::Value(error),
+            },
+            Expression::If { condition, consequence, alternative } => {
+                let condition = self.eval_expression(condition, env);
+                if condition.is_error() {
+                    return Evaluation::Value(condition);
+                }
+
+                if condition.is_truthy() {
+                    self.eval_tail_block_statement(consequence, env)
+                } else if let Some(alternative) = alternative {
+                    self.eval_tail_block_statement(alternative, env)
+                } else {
+                    Evaluation::Value(Object::Null)
+                }
+            }
+            _ => unreachable!("only calls and if expressions reach this point"),
+        }
+    }
+
+    // `&&` and `||` are control flow rather than strict operators: the right operand is only
+    // evaluated when the left one does not already decide the result, and the last evaluated
+    // operand is returned as is.
+    fn eval_logical_expression(
+        &mut self,
+        short_circuits_on: bool,
+        left: &Expression,
+        right: &Expression,
+        env: &Rc<RefCell<Environment>>,
+    ) -> Object {
+        let left = self.eval_expression(left, env);
+        if left.is_error() || left.is_truthy() == short_circuits_on {
+            return left;
+        }
+
+        self.eval_expression(right, env)
+    }
+
+    pub(crate) fn apply_function(&mut self, function: Object, arguments: Vec<Object>) -> Object {
+        self.apply_call(function, arguments, None)
+    }
+
+    // Applies the function for `call`, when a call expression makes the call rather than a builtin.
+    fn apply_call(&mut self, function: Object, arguments: Vec<Object>, call: Option<Call>) -> Object {
+        let mut function = function;
+        let mut arguments = arguments;
+        let mut tail_call_site = None;
+        let mut calls = 0;
+        if let Some(hooks) = &mut self.hooks {
+            hooks.on_call(call, &function, &arguments, self.call_site);
+        }
+
+        // Tail calls replace the current call instead of nesting inside it, so the depth limit
+        // only applies to calls that actually need to come back. Their hooks are called where
+        // they are made, while their call expressions are still at hand.
+        loop {
+            calls += 1;
+            match self.call_function(function, arguments) {
+                Evaluation::TailCall(next_function, next_arguments, site) => {
+                    function = next_function;
+                    arguments = next_arguments;
+                    self.call_site = site.span;
+                    tail_call_site = Some(site);
+                }
+                Evaluation::Value(result) => {
+                    if let Some(hooks) = &mut self.hooks {
+                        for _ in 0..calls {
+                            hooks.on_return(&result);
+                        }
+                    }
+                    if let (true, Some(site)) = (result.is_error(), tail_call_site) {
+                        // The last tail call was made in the code of the function that made it.
+                        if self.error_span.is_none() {
+                            self.error_span = Some(site.span);
+                            self.error_file = site.file.clone();
+                        }
+                        self.stack_trace.push(site);
+                    }
+                    return result;
+                }
+            }
+        }
+    }
+
+    // Functions the compiler made are only called back while the virtual machine runs the program.
+    fn call_compiled_function(&mut self, function: Object, arguments: Vec<Object>) -> Object {
+        match self.function_caller.clone() {
+            Some(caller) => caller(self, function, arguments),
+            None => Object::error(NOT_A_FUNCTION, String::from("not a function: FUNCTION")),
+        }
+    }
+
+    fn call_function(&mut self, function: Object, arguments: Vec<Object>) -> Evaluation {
+        let callee = match function {
+            Object::Function(function) => function,
+            Object::Closure(_) => return Evaluation::Value(self.call_compiled_function(function, arguments)),
+            Object::Builtin(builtin) => {
+                return Evaluation::Value((builtin.function)(self, arguments).unwrap_or_else(|error| error))
+            }
+            other => {
+                let message = format!("not a function: {}", other.type_name());
+                return Evaluation::Value(Object::error(NOT_A_FUNCTION, message));
+            }
+        };
+
+        if callee.parameters.len() != arguments.len() {
+            return Evaluation::Value(Object::error(WRONG_ARGUMENT_COUNT, format!(
+                "wrong number of arguments: expected {}, got {}",
+                callee.parameters.len(),
+                arguments.len()
+            )));
+        }
+
+        let mut function_env = Environment::new_enclosed(Rc::clone(&callee.env));
+        for (parameter, argument) in callee.parameters.iter().zip(arguments) {
+            function_env.set(*parameter, argument);
+        }
+
+        if self.depth >= self.max_depth {
+            return Evaluation::Value(self.exceed(Limit::Depth, String::from("maximum recursion depth exceeded")));
+        }
+
+        let function_env = Rc::new(RefCell::new(function_env));
+        self.track_environment(&function_env);
+
+        self.depth += 1;
+        let file = std::mem::replace(&mut self.file, callee.file.clone());
+        let result = self.eval_tail_block_statement(&callee.body, &function_env);
+        self.file = file;
+        self.depth -= 1;
+
+        match result {
+            Evaluation::Value(Object::ReturnValue(value)) => Evaluation::Value(*value),
+            Evaluation::Value(result @ (Object::Break | Object::Continue)) => {
+                Evaluation::Value(outside_loop_error(&result))
+            }
+            result => result,
+        }
+    }
+}
+
+pub(crate) fn eval_prefix_expression(operator: PrefixOperator, right: Object) -> Object {
+    match (operator, right) {
+        (PrefixOperator::Bang, right) => Object::Boolean(!right.is_truthy()),
+        (PrefixOperator::Minus, Object::Integer(value)) => match value.checked_neg() {
+            Some(result) => Object::Integer(result),
+            None => Object::error(INTEGER_OVERFLOW, String::from("integer overflow")),
+        },
+        (PrefixOperator::Minus, Object::Float(value)) => Object::Float(-value),
+        (operator, right) => {
+            let message = format!("unknown operator: {}{}", operator, right.type_name());
+            Object::error(UNKNOWN_OPERATOR, message)
+        }
+    }
+}
+
+pub(crate) fn eval_infix_expression(operator: InfixOperator, left: Object, right: Object) -> Object {
+    if matches!(operator, InfixOperator::Equals | InfixOperator::NotEquals) {
+        return match left.equals(&right) {
+            Some(is_equal) => Object::Boolean(is_equal == (operator == InfixOperator::Equals)),
+            None => Object::error(TYPE_MISMATCH, format!(
+                "type mismatch: {} {} {}",
+                left.type_name(),
+                operator,
+                right.type_name()
+            )),
+        };
+    }
+
+    match (left, right) {
+        (Object::Integer(left), Object::Integer(right)) => eval_integer_infix_expression(operator, left, right),
+        (Object::Float(left), Object::Float(right)) => eval_float_infix_expression(operator, left, right),
+        (Object::String(left), Object::String(right)) if operator == InfixOperator::Plus => {
+            Object::String(left + &right)
+        }
+        (left, right) if left.type_name() != right.type_name() => Object::error(TYPE_MISMATCH, format!(
+            "type mismatch: {} {} {}",
+            left.type_name(),
+            operator,
+            right.type_name()
+        )),
+        (left, right) => Object::error(UNKNOWN_OPERATOR, format!(
+            "unknown operator: {} {} {}",
+            left.type_name(),
+            operator,
+            right.type_name()
+        )),
+    }
+}
+
+fn eval_integer_infix_expression(operator: InfixOperator, left: i32, right: i32) -> Object {
+    let result = match operator {
+        InfixOperator::Plus => left.checked_add(right),
+        InfixOperator::Minus => left.checked_sub(right),
+        InfixOperator::Star => left.checked_mul(right),
+        InfixOperator::Slash => {
+            if right == 0 {
+                return Object::error(DIVISION_BY_ZERO, String::from("division by zero"));
+            }
+            left.checked_div(right)
+        }
+        InfixOperator::Equals => return Object::Boolean(left == right),
+        InfixOperator::NotEquals => return Object::Boolean(left != right),
+        InfixOperator::LessThan => return Object::Boolean(left < right),
+        InfixOperator::GreaterThan => return Object::Boolean(left > right),
+        InfixOperator::Range => return Object::Range(left, right),
+        InfixOperator::And | InfixOperator::Or => unreachable!("logical operators are evaluated lazily"),
+    };
+
+    match result {
+        Some(value) => Object::Integer(value),
+        None => Object::error(INTEGER_OVERFLOW, String::from("integer overflow")),
+    }
+}
+
+// Integers and floats never mix implicitly, `1 + 1.0` is a type mismatch.
+fn eval_float_infix_expression(operator: InfixOperator, left: f64, right: f64) -> Object {
+    match operator {
+        InfixOperator::Plus => Object::Float(left + right),
+        InfixOperator::Minus => Object::Float(left - right),
+        InfixOperator::Star => Object::Float(left * right),
+        InfixOperator::Slash if right == 0.0 => Object::error(DIVISION_BY_ZERO, String::from("division by zero")),
+        InfixOperator::Slash => Object::Float(left / right),
+        InfixOperator::Equals => Object::Boolean(left == right),
+        InfixOperator::NotEquals => Object::Boolean(left != right),
+        InfixOperator::LessThan => Object::Boolean(left < right),
+        InfixOperator::GreaterThan => Object::Boolean(left > right),
+        InfixOperator::And | InfixOperator::Or => unreachable!("logical operators are evaluated lazily"),
+        InfixOperator::Range => Object::error(UNKNOWN_OPERATOR, format!("unknown operator: FLOAT {} FLOAT", operator)),
+    }
+}
+
+fn outside_loop_error(signal: &Object) -> Object {
+    Object::error(OUTSIDE_LOOP, format!("{} outside loop", signal))
+}
+
+pub(crate) fn eval_index_expression(left: Object, index: Object) -> Object {
+    match (left, index) {
+        (Object::Array(elements), Object::Integer(index)) => {
+            let element = usize::try_from(index).ok().and_then(|index| elements.get(index));
+            element.cloned().unwrap_or(Object::Null)
+        }
+        (Object::Hash(pairs), index) => match index.hash_key() {
+            Some(hash_key) => pairs.get(&hash_key).cloned().unwrap_or(Object::Null),
+            None => Object::error(UNUSABLE_HASH_KEY, format!("unusable as hash key: {}", index.type_name())),
+        },
+        (left, _) => Object::error(UNSUPPORTED_INDEX, format!("index operator not supported: {}", left.type_name())),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::syntax_analyzer::parse;
+    use std::cell::Cell;
+
+    fn evaluate_input(input: &str) -> String {
+        let program = parse(input).unwrap();
+        let env = Rc::new(RefCell::new(Environment::new()));
+        evaluate(&program, &env).to_string()
+    }
+
+    fn evaluate_input_with(evaluator: &mut Evaluator, input: &str) -> String {
+        let program = parse(input).unwrap();
+        let env = Rc::new(RefCell::new(Environment::new()));
+        evaluator.eval_program(&program, &env).to_string()
+    }
+
+    #[test]
+    fn it_works_on_arithmetic_expression() {
+        assert_eq!(evaluate_input("(5 + 10 * 2 + 15 / 3) * 2 + -10"), "50");
+        assert_eq!(evaluate_input("1 < 2 == !false"), "true");
+        assert_eq!(evaluate_input("1 / 0"), "ERROR: division by zero");
+        assert_eq!(evaluate_input("2147483647 + 1"), "ERROR: integer overflow");
+        assert_eq!(evaluate_input("true + 1"), "ERROR: type mismatch: BOOLEAN + INTEGER");
+        assert_eq!(evaluate_input("true + false"), "ERROR: unknown operator: BOOLEAN + BOOLEAN");
+    }
+
+    #[test]
+    fn it_works_on_string_expression() {
+        assert_eq!(evaluate_input(r#""hello" + " " + "world""#), "hello world");
+        assert_eq!(evaluate_input(r#""a" == "a""#), "true");
+        assert_eq!(evaluate_input(r#""a" != "a""#), "false");
+        assert_eq!(evaluate_input(r#""a" - "a""#), "ERROR: unknown operator: STRING - STRING");
+        assert_eq!(evaluate_input(r#""a" + 1"#), "ERROR: type mismatch: STRING + INTEGER");
+        assert_eq!(evaluate_input(r#"{"one": 1, "two": 2}["t" + "wo"]"#), "2");
+    }
+
+    #[test]
+    fn it_works_on_float_expression() {
+        assert_eq!(evaluate_input("1.5 + 2.25"), "3.75");
+        assert_eq!(evaluate_input("-1.5 * 2.0"), "-3.0");
+        assert_eq!(evaluate_input("1.0 / 4.0"), "0.25");
+        assert_eq!(evaluate_input("0.5 < 1.5"), "true");
+        assert_eq!(evaluate_input("1.0 / 0.0"), "ERROR: division by zero");
+        assert_eq!(evaluate_input("1 + 1.0"), "ERROR: type mismatch: INTEGER + FLOAT");
+        assert_eq!(evaluate_input("{1.5: 1}"), "ERROR: unusable as hash key: FLOAT");
+    }
+
+    #[test]
+    fn it_works_on_null() {
+        assert_eq!(evaluate_input("null"), "null");
+        assert_eq!(evaluate_input("null == null"), "true");
+        assert_eq!(evaluate_input("null != null"), "false");
+        assert_eq!(evaluate_input("null == 0"), "false");
+        assert_eq!(evaluate_input(r#""" != null"#), "true");
+        assert_eq!(evaluate_input("if false { 1 } == null"), "true");
+        assert_eq!(evaluate_input("!null"), "true");
+        assert_eq!(evaluate_input("if null { 1 } else { 2 }"), "2");
+        assert_eq!(evaluate_input("null || 3"), "3");
+        assert_eq!(evaluate_input("null + 1"), "ERROR: type mismatch: NULL + INTEGER");
+        assert_eq!(evaluate_input("null < null"), "ERROR: unknown operator: NULL < NULL");
+        assert_eq!(evaluate_input("-null"), "ERROR: unknown operator: -NULL");
+        assert_eq!(evaluate_input("{null: 1}"), "ERROR: unusable as hash key: NULL");
+    }
+
+    #[test]
+    fn it_works_on_if_else_and_return() {
+        assert_eq!(evaluate_input("if (1 > 2) { 10 } else { 20 }"), "20");
+        assert_eq!(evaluate_input("if (false) { 10 }"), "null");
+        assert_eq!(evaluate_input("if (true) { if (true) { return 10; } return 1; }"), "10");
+    }
+
+    #[test]
+    fn it_works_on_functions_and_closures() {
+        assert_eq!(evaluate_input("let add = fn(a, b) { a + b }; add(2, add(3, 4))"), "9");
+        assert_eq!(
+            evaluate_input("let adder = fn(x) { fn(y) { x + y } }; let addTwo = adder(2); addTwo(3)"),
+            "5",
+        );
+        assert_eq!(evaluate_input("fn(x) { x }(1, 2)"), "ERROR: wrong number of arguments: expected 1, got 2");
+        assert_eq!(evaluate_input("let x = 1; x()"), "ERROR: not a function: INTEGER");
+        assert_eq!(evaluate_input("y"), "ERROR: identifier not found: y");
+    }
+
+    #[test]
+    fn it_short_circuits_logical_operators() {
+        assert_eq!(evaluate_input("false && crash()"), "false");
+        assert_eq!(evaluate_input("true || crash()"), "true");
+        assert_eq!(evaluate_input("true && crash()"), "ERROR: identifier not found: crash");
+        assert_eq!(evaluate_input("false || crash()"), "ERROR: identifier not found: crash");
+    }
+
+    #[test]
+    fn it_returns_last_evaluated_operand_of_logical_operators() {
+        assert_eq!(evaluate_input("1 && 2"), "2");
+        assert_eq!(evaluate_input("0 || 2"), "0");
+        assert_eq!(evaluate_input("if (false) { 1 } || 3"), "3");
+        assert_eq!(evaluate_input("if (false) { 1 } && 3"), "null");
+        assert_eq!(evaluate_input("let f = fn() { false }; f() || f() && crash()"), "false");
+    }
+
+    #[test]
+    fn it_limits_recursion_depth() {
+        // The default limit needs more stack than the test harness hands its threads.
+        let unbounded = std::thread::Builder::new()
+            .stack_size(64 * 1024 * 1024)
+            .spawn(|| evaluate_input("let f = fn(n) { 1 + f(n + 1) }; f(0)"))
+            .unwrap();
+        assert_eq!(unbounded.join().unwrap(), "ERROR: maximum recursion depth exceeded");
+
+        let mut evaluator = Evaluator::new().with_max_depth(10);
+        let count_down = "let f = fn(n) { if (n > 0) { 0 + f(n - 1) } else { n } };";
+        assert_eq!(evaluate_input_with(&mut evaluator, &format!("{} f(9)", count_down)), "0");
+        assert_eq!(
+            evaluate_input_with(&mut evaluator, &format!("{} f(10)", count_down)),
+            "ERROR: maximum recursion depth exceeded",
+        );
+        // The depth is unwound after an error so the evaluator stays usable.
+        assert_eq!(evaluate_input_with(&mut evaluator, &format!("{} f(9)", count_down)), "0");
+    }
+
+    #[test]
+    fn it_stops_when_step_budget_is_exceeded() {
+        let mut evaluator = Evaluator::new().with_max_depth(usize::MAX).with_step_budget(1000);
+        assert_eq!(
+            evaluate_input_with(&mut evaluator, "let f = fn(n) { f(n + 1) }; f(0)"),
+            "ERROR: evaluation budget exceeded",
+        );
+        // The budget is spent for good, later programs fail straight away.
+        assert_eq!(evaluate_input_with(&mut evaluator, "1 + 2"), "ERROR: evaluation budget exceeded");
+
+        let mut evaluator = Evaluator::new().with_step_budget(3);
+        assert_eq!(evaluate_input_with(&mut evaluator, "1 + 2"), "3");
+        assert_eq!(evaluator.steps(), 3);
+    }
+
+    #[test]
+    fn it_runs_tail_calls_in_constant_stack_space() {
+        assert_eq!(
+            evaluate_input(
+                "let count = fn(n, acc) { if (n == 0) { acc } else { count(n - 1, acc + 1) } }; count(100000, 0)"
+            ),
+            "100000",
+        );
+        assert_eq!(
+            evaluate_input(
+                "let isEven = fn(n) { if (n == 0) { return true; } isOdd(n - 1) };
+                 let isOdd = fn(n) { if (n == 0) { return false; } return isEven(n - 1); };
+                 isEven(100001)"
+            ),
+            "false",
+        );
+        // Calls that are not in tail position still count towards the limit.
+        let mut evaluator = Evaluator::new().with_max_depth(10);
+        assert_eq!(
+            evaluate_input_with(&mut evaluator, "let f = fn(n) { if (n == 0) { 0 } else { 1 + f(n - 1) } }; f(10)"),
+            "ERROR: maximum recursion depth exceeded",
+        );
+    }
+
+    #[test]
+    fn it_works_on_assignment_expression() {
+        assert_eq!(evaluate_input("let x = 1; x = x + 1; x"), "2");
+        assert_eq!(evaluate_input("let x = 1; let y = 2; x = y = 3; x + y"), "6");
+        assert_eq!(evaluate_input("x = 1"), "ERROR: assignment to undeclared identifier: x");
+    }
+
+    #[test]
+    fn it_assigns_in_the_declaring_scope() {
+        assert_eq!(evaluate_input("let x = 1; let f = fn() { x = 10 }; f(); x"), "10");
+        assert_eq!(evaluate_input("let x = 1; let f = fn() { let x = 2; x = 3; x }; f() + x"), "4");
+        assert_eq!(evaluate_input("let f = fn(x) { x = x * 2; x }; let x = 5; f(x) + x"), "15");
+    }
+
+    #[test]
+    fn it_lets_closures_mutate_captured_variables() {
+        assert_eq!(
+            evaluate_input(
+                "let counter = fn() { let count = 0; fn() { count = count + 1 } };
+                 let a = counter(); let b = counter();
+                 a(); a(); b();
+                 a() * 10 + b()"
+            ),
+            "32",
+        );
+        assert_eq!(
+            evaluate_input(
+                "let total = 0;
+                 let pair = fn() { let add = fn(n) { total = total + n }; let twice = fn(n) { add(n); add(n) }; twice };
+                 pair()(5); pair()(1); total"
+            ),
+            "12",
+        );
+    }
+
+    #[test]
+    fn it_works_on_while_expression() {
+        assert_eq!(evaluate_input("let i = 0; let sum = 0; while (i < 5) { i = i + 1; sum = sum + i; } sum"), "15");
+        assert_eq!(evaluate_input("while (false) { crash() }"), "null");
+        assert_eq!(evaluate_input("let i = 0; while (i < 3) { i = i + 1 }"), "null");
+        assert_eq!(
+            evaluate_input("let find = fn(n) { let i = 0; while (true) { if (i * i > n) { return i; } i = i + 1; } }; find(50)"),
+            "8",
+        );
+        assert_eq!(evaluate_input("while (true) { crash() }"), "ERROR: identifier not found: crash");
+        assert_eq!(evaluate_input("while (crash) { 1 }"), "ERROR: identifier not found: crash");
+    }
+
+    #[test]
+    fn it_interrupts_infinite_while_loops_with_step_budget() {
+        let mut evaluator = Evaluator::new().with_step_budget(10000);
+        assert_eq!(evaluate_input_with(&mut evaluator, "while (true) { }"), "ERROR: evaluation budget exceeded");
+    }
+
+    #[test]
+    fn it_works_on_array_and_hash_literals() {
+        assert_eq!(evaluate_input("[1, 2 * 2, [true]]"), "[1, 4, [true]]");
+        assert_eq!(evaluate_input("[1, 2, 3][1]"), "2");
+        assert_eq!(evaluate_input("[1, 2, 3][3]"), "null");
+        assert_eq!(evaluate_input("[1, 2, 3][-1]"), "null");
+        assert_eq!(evaluate_input("{1: 10, true: 20}[1 > 0]"), "20");
+        assert_eq!(evaluate_input("{1: 10}[2]"), "null");
+        assert_eq!(evaluate_input("{fn() { 1 }: 2}"), "ERROR: unusable as hash key: FUNCTION");
+        assert_eq!(evaluate_input("{1: 2}[[]]"), "ERROR: unusable as hash key: ARRAY");
+        assert_eq!(evaluate_input("1[0]"), "ERROR: index operator not supported: INTEGER");
+        assert_eq!(evaluate_input("2..5"), "2..5");
+    }
+
+    #[test]
+    fn it_compares_every_pair_of_types() {
+        let values = [
+            "1", "1.5", "true", r#""s""#, "null", "[1]", "{1: 1}", "0..1", "f", "len",
+        ];
+        for (i, left) in values.iter().enumerate() {
+            for (j, right) in values.iter().enumerate() {
+                let input = format!("let f = fn() {{ }}; [{0} == {1}, {0} != {1}]", left, right);
+                let expected = match (i, j) {
+                    (0, 1) | (1, 0) => format!(
+                        "ERROR: type mismatch: {} == {}",
+                        ["INTEGER", "FLOAT"][i],
+                        ["INTEGER", "FLOAT"][j]
+                    ),
+                    _ if i == j => String::from("[true, false]"),
+                    _ => String::from("[false, true]"),
+                };
+                assert_eq!(evaluate_input(&input), expected, "{} == {}", left, right);
+            }
+        }
+    }
+
+    #[test]
+    fn it_compares_arrays_hashes_and_functions_by_their_kind() {
+        assert_eq!(evaluate_input(r#"[1, ["a", null]] == [1, ["a", null]]"#), "true");
+        assert_eq!(evaluate_input("[1, 2] == [2, 1]"), "false");
+        assert_eq!(evaluate_input("[1] == [1, 1]"), "false");
+        assert_eq!(evaluate_input("[1] == [1.0]"), "ERROR: type mismatch: ARRAY == ARRAY");
+        assert_eq!(evaluate_input(r#"{"a": [1], "b": 2} == {"b": 2, "a": [1]}"#), "true");
+        assert_eq!(evaluate_input(r#"{"a": 1} == {"b": 1}"#), "false");
+        assert_eq!(evaluate_input("0..2 == 0..2"), "true");
+        assert_eq!(evaluate_input("fn() { 1 } == fn() { 1 }"), "false");
+        assert_eq!(evaluate_input("let f = fn() { 1 }; let g = f; [f == g, [f] == [g]]"), "[true, true]");
+        assert_eq!(evaluate_input("len == len"), "true");
+    }
+
+    #[test]
+    fn it_keeps_hashes_in_insertion_order() {
+        let keys = (0..20).map(|key| format!("\"k{}\": {}", 19 - key, key)).collect::<Vec<String>>();
+        let hash = format!("{{{}}}", keys.join(", "));
+        assert_eq!(evaluate_input(r#"{"z": 1, "a": 2, 3: 3, true: 4}"#), r#"{"z": 1, "a": 2, 3: 3, true: 4}"#);
+        assert_eq!(
+            evaluate_input(&format!("let order = \"\"; for k in {} {{ order = order + k }}; order", hash)),
+            (0..20).map(|key| format!("k{}", 19 - key)).collect::<String>(),
+        );
+        assert_eq!(evaluate_input(r#"{"b": 1, "a": 2, "b": 3}"#), r#"{"b": 3, "a": 2}"#);
+    }
+
+    #[test]
+    fn it_works_on_for_expression() {
+        assert_eq!(evaluate_input("let sum = 0; for x in [1, 2, 3] { sum = sum + x; } sum"), "6");
+        assert_eq!(evaluate_input("let sum = 0; for i, x in [5, 6, 7] { sum = sum + i * x; } sum"), "20");
+        assert_eq!(evaluate_input("let sum = 0; for k in {1: 10, 2: 20} { sum = sum + k; } sum"), "3");
+        assert_eq!(evaluate_input("let sum = 0; for k, v in {1: 10, 2: 20} { sum = sum + k * v; } sum"), "50");
+        assert_eq!(evaluate_input("let sum = 0; for i in 0..5 { sum = sum + i; } sum"), "10");
+        assert_eq!(evaluate_input("for i in 5..0 { crash() }"), "null");
+        assert_eq!(evaluate_input("for i, x in 0..5 { }"), "ERROR: a RANGE yields one loop variable, got 2");
+        assert_eq!(evaluate_input("for x in 5 { }"), "ERROR: not iterable: INTEGER");
+        assert_eq!(
+            evaluate_input("let first = fn(xs) { for x in xs { if (x > 1) { return x; } } }; first([1, 2, 3])"),
+            "2",
+        );
+    }
+
+    #[test]
+    fn it_scopes_for_loop_variables_to_each_iteration() {
+        assert_eq!(evaluate_input("for x in [1] { let y = x; } y"), "ERROR: identifier not found: y");
+        assert_eq!(evaluate_input("for x in [1] { } x"), "ERROR: identifier not found: x");
+        assert_eq!(evaluate_input("let x = 0; for x in [1, 2] { } x"), "0");
+        assert_eq!(
+            evaluate_input(
+                "let first = 0; let last = 0;
+                 for i in 0..3 { let get = fn() { i }; if (i == 0) { first = get; } last = get; }
+                 first() * 10 + last()"
+            ),
+            "2",
+        );
+    }
+
+    #[test]
+    fn it_interrupts_long_for_loops_with_step_budget() {
+        let mut evaluator = Evaluator::new().with_step_budget(10000);
+        assert_eq!(evaluate_input_with(&mut evaluator, "for i in 0..2147483647 { }"), "ERROR: evaluation budget exceeded");
+    }
+
+    #[test]
+    fn it_works_on_break_and_continue() {
+        assert_eq!(
+            evaluate_input("let i = 0; while (true) { i = i + 1; if (i == 5) { break; } } i"),
+            "5",
+        );
+        assert_eq!(
+            evaluate_input("let sum = 0; for i in 0..10 { if (i > 3) { continue; } sum = sum + i; } sum"),
+            "6",
+        );
+        assert_eq!(
+            evaluate_input("let sum = 0; let i = 0; while (i < 6) { i = i + 1; if (i == 2) { continue } sum = sum + i; } sum"),
+            "19",
+        );
+        assert_eq!(evaluate_input("let last = 0; for x in [1, 2, 3] { last = x; break; } last"), "1");
+        assert_eq!(evaluate_input("let x = 0; while (true) { let y = if (true) { break; }; x = 1; } x"), "0");
+    }
+
+    #[test]
+    fn it_breaks_only_the_innermost_loop() {
+        assert_eq!(
+            evaluate_input(
+                "let count = 0;
+                 for i in 0..3 { for j in 0..3 { if (j == 1) { break; } count = count + 1; } }
+                 count"
+            ),
+            "3",
+        );
+    }
+
+    #[test]
+    fn it_rejects_break_and_continue_outside_loops() {
+        assert_eq!(evaluate_input("break"), "ERROR: break outside loop");
+        assert_eq!(evaluate_input("if (true) { continue; }"), "ERROR: continue outside loop");
+        assert_eq!(
+            evaluate_input("let f = fn() { break; }; for x in [1] { f() }"),
+            "ERROR: break outside loop",
+        );
+        assert_eq!(
+            evaluate_input("let f = fn() { if (true) { continue } 1 }; while (true) { f(); }"),
+            "ERROR: continue outside loop",
+        );
+    }
+
+    #[test]
+    fn it_works_on_stack_traces() {
+        let mut evaluator = Evaluator::new();
+        let input = [
+            "let add = fn(a, b) { a + b };",
+            "let twice = fn(x) { add(x) + 1 };",
+            "let run = fn() { twice(1) };",
+            "1 + run()",
+        ];
+        assert_eq!(
+            evaluate_input_with(&mut evaluator, &input.join("\n")),
+            "ERROR: wrong number of arguments: expected 2, got 1"
+        );
+        assert_eq!(
+            evaluator.stack_trace().iter().map(|frame| frame.to_string()).collect::<Vec<String>>(),
+            Vec::from(["at add (2:21)", "at twice (3:18)", "at run (4:5)"]),
+        );
+
+        assert_eq!(evaluate_input_with(&mut evaluator, "fn(x) { x / 0 }(1)"), "ERROR: division by zero");
+        assert_eq!(
+            evaluator.stack_trace(),
+            [Frame { function: String::from("<anonymous>"), span: Span { line: 1, column: 1 }, file: None }]
+        );
+
+        assert_eq!(evaluate_input_with(&mut evaluator, "let f = fn() { 1 }; f()"), "1");
+        assert!(evaluator.stack_trace().is_empty());
+    }
+
+    #[test]
+    fn it_works_on_try_expression() {
+        assert_eq!(evaluate_input("try { 1 / 0 } catch (e) { e }"), "division by zero");
+        assert_eq!(evaluate_input("try { 1 } catch (e) { 2 }"), "1");
+        assert_eq!(evaluate_input("let f = fn() { try { return 1; } catch (e) { 2 }; 3 }; f()"), "1");
+        assert_eq!(
+            evaluate_input("let x = 0; try { x = 1; x + true; x = 2 } catch (e) { x = x + 10 }; x"),
+            "11"
+        );
+        assert_eq!(evaluate_input("try { 1 / 0 } catch (e) { e + 1 }"), "ERROR: type mismatch: STRING + INTEGER");
+        assert_eq!(evaluate_input("try { 1 / 0 } catch (e) { 1 }; e"), "ERROR: identifier not found: e");
+
+        let mut evaluator = Evaluator::new();
+        let input = "let f = fn() { 1 / 0 }; try { f() } catch (e) { 0 }; let g = fn() { f() + 1 }; g()";
+        assert_eq!(evaluate_input_with(&mut evaluator, input), "ERROR: division by zero");
+        assert_eq!(
+            evaluator.stack_trace().iter().map(|frame| frame.to_string()).collect::<Vec<String>>(),
+            Vec::from(["at f (1:69)", "at g (1:80)"]),
+        );
+    }
+
+    #[test]
+    fn it_does_not_catch_running_into_limits() {
+        let mut evaluator = Evaluator::new().with_step_budget(100);
+        assert_eq!(
+            evaluate_input_with(&mut evaluator, "while (true) { try { while (true) { } } catch (e) { } }"),
+            "ERROR: evaluation budget exceeded"
+        );
+        assert_eq!(evaluator.exceeded_limit(), Some(Limit::Steps));
+
+        let mut evaluator = Evaluator::new().with_time_limit(Duration::from_millis(20));
+        assert_eq!(
+            evaluate_input_with(&mut evaluator, "let n = 0; while (true) { try { while (true) { } } catch (e) { n } }"),
+            "ERROR: time limit exceeded"
+        );
+        assert_eq!(evaluator.exceeded_limit(), Some(Limit::Time));
+
+        let mut evaluator = Evaluator::new().with_max_depth(20);
+        let input = "let f = fn(n) { 1 + f(n) }; let n = 0; while (n < 3) { try { f(0) } catch (e) { n = n + 1 } }";
+        assert_eq!(evaluate_input_with(&mut evaluator, input), "ERROR: maximum recursion depth exceeded");
+        assert_eq!(evaluator.exceeded_limit(), Some(Limit::Depth));
+
+        assert_eq!(evaluate_input_with(&mut evaluator, "try { 1 / 0 } catch (e) { e }"), "division by zero");
+        assert!(!evaluator.is_limit_exceeded());
+    }
+
+    #[test]
+    fn it_limits_the_size_of_values() {
+        let mut evaluator = Evaluator::new().with_memory_limit(1000);
+        assert_eq!(
+            evaluate_input_with(&mut evaluator, "let s = \"ab\"; while (true) { s = s + s }"),
+            "ERROR: memory limit exceeded: STRING of 1024 bytes (at most 1000)"
+        );
+        assert_eq!(
+            evaluate_input_with(&mut evaluator, "map(0..1001, fn(x) { x })"),
+            "ERROR: memory limit exceeded: ARRAY of 1001 elements (at most 1000)"
+        );
+        assert_eq!(evaluate_input_with(&mut evaluator, "len(map(0..1000, fn(x) { x }))"), "1000");
+        let input = "let s = \"ab\"; while (true) { s = try { s + s } catch (e) { \"ab\" } }";
+        assert_eq!(
+            evaluate_input_with(&mut evaluator, input),
+            "ERROR: memory limit exceeded: STRING of 1024 bytes (at most 1000)"
+        );
+        assert_eq!(evaluator.exceeded_limit(), Some(Limit::Memory));
+    }
+
+    #[test]
+    fn it_limits_the_heap() {
+        let in_use = Rc::new(Cell::new(0));
+        let usage = Rc::clone(&in_use);
+        let mut evaluator = Evaluator::new().with_heap_limit(1000, move || usage.get());
+        assert_eq!(evaluate_input_with(&mut evaluator, "1 + 2"), "3");
+        in_use.set(1001);
+        assert_eq!(
+            evaluate_input_with(&mut evaluator, "1 + 2"),
+            "ERROR: memory limit exceeded: 1001 bytes in use (at most 1000)"
+        );
+
+        // The heap is looked at on every step, and grows by a little each time, as it does while the
+        // string doubles. Catching the error must not let the program go on.
+        let in_use = Rc::new(Cell::new(0));
+        let mut evaluator = Evaluator::new().with_heap_limit(1000, move || {
+            in_use.set(in_use.get() + 16);
+            in_use.get()
+        });
+        assert_eq!(
+            evaluate_input_with(&mut evaluator, "let s = \"a\"; while (true) { s = try { s + s } catch (e) { s } }"),
+            "ERROR: memory limit exceeded: 1008 bytes in use (at most 1000)"
+        );
+        assert_eq!(evaluator.exceeded_limit(), Some(Limit::Memory));
+    }
+
+    #[test]
+    fn it_stops_when_the_time_limit_is_exceeded() {
+        let mut evaluator = Evaluator::new().with_time_limit(Duration::from_millis(20));
+        assert_eq!(evaluate_input_with(&mut evaluator, "1 + 2"), "3");
+        assert_eq!(evaluate_input_with(&mut evaluator, "while (true) { }"), "ERROR: time limit exceeded");
+        evaluator.reset_steps();
+        assert_eq!(evaluate_input_with(&mut evaluator, "len(\"abc\")"), "3");
+    }
+
+    #[test]
+    fn it_takes_builtins_away_and_resets_steps() {
+        let mut evaluator = Evaluator::new().with_step_budget(5).without_builtin("input");
+        assert_eq!(evaluate_input_with(&mut evaluator, "input()"), "ERROR: identifier not found: input");
+        assert_eq!(evaluate_input_with(&mut evaluator, "1 + 2 + 3"), "ERROR: evaluation budget exceeded");
+        evaluator.reset_steps();
+        assert_eq!(evaluate_input_with(&mut evaluator, "1 + 2"), "3");
+    }
+
+    #[test]
+    fn it_stops_when_interrupted() {
+        let interrupt = Arc::new(AtomicBool::new(false));
+        let mut evaluator = Evaluator::new().with_interrupt(Arc::clone(&interrupt));
+        assert_eq!(evaluate_input_with(&mut evaluator, "1 + 2"), "3");
+
+        let setter = Arc::clone(&interrupt);
+        std::thread::spawn(move || {
+            std::thread::sleep(std::time::Duration::from_millis(50));
+            setter.store(true, Ordering::Relaxed);
+        });
+        assert_eq!(
+            evaluate_input_with(&mut evaluator, "while (true) { try { while (true) { } } catch (e) { } }"),
+            "ERROR: interrupted"
+        );
+
+        interrupt.store(false, Ordering::Relaxed);
+        assert_eq!(evaluate_input_with(&mut evaluator, "1 + 2"), "3");
+    }
+}
@@ -0,0 +1,609 @@
+use crate::abstract_syntax_tree::{join, BlockStatement, Expression, InfixOperator, PrefixOperator, Program, Statement};
+use crate::lexical_analyzer::{comments, Comment, Span};
+use crate::repl::skip_shebang;
+use crate::syntax_analyzer::{parse_with_marks, SyntaxError};
+
+const INDENT: usize = 2;
+// Lines are broken where they would get longer than this, as far as the syntax allows.
+const MAX_WIDTH: usize = 100;
+
+// How tightly each kind of expression binds, following the parser. An operand that binds less
+// tightly than its place needs needs parentheses.
+const PREFIX: u8 = 9;
+const CALL: u8 = 10;
+
+// Reprints a program the way `monkey fmt` lays it out: two spaces of indentation, one statement
+// per line, spaces around infix operators, and lists, calls and blocks broken over lines when they
+// do not fit. Comments keep their place among the statements, and one blank line is kept wherever
+// there were some.
+pub fn format_source(source: &str) -> Result<String, Vec<SyntaxError>> {
+    let body = skip_shebang(source);
+    let shebang = &source[..source.len() - body.len()];
+    let (program, marks) = parse_with_marks(body)?;
+
+    let mut formatter = Formatter {
+        marks,
+        next_mark: 0,
+        comments: comments(body),
+        next_comment: 0,
+    };
+    let mut lines = formatter.program(&program);
+    if !shebang.is_empty() {
+        lines.insert(0, String::from(shebang));
+    }
+    Ok(lines.iter().map(|line| format!("{}\n", line)).collect())
+}
+
+struct Formatter {
+    // Where statements and blocks start and end, in the order the formatter walks the syntax tree.
+    marks: Vec<Span>,
+    next_mark: usize,
+    comments: Vec<Comment>,
+    // Comments are written out in order, this is the first one still to go.
+    next_comment: usize,
+}
+
+impl Formatter {
+    fn program(&mut self, program: &Program) -> Vec<String> {
+        self.statements(&program.statements, 0, false)
+    }
+
+    fn mark(&mut self) -> Span {
+        let mark = self.marks[self.next_mark];
+        self.next_mark += 1;
+        mark
+    }
+
+    // Takes the comments that come before `span`.
+    fn comments_before(&mut self, span: Span) -> Vec<Comment> {
+        let first = self.next_comment;
+        while self.comments.get(self.next_comment).is_some_and(|comment| is_before(comment.span, span)) {
+            self.next_comment += 1;
+        }
+        self.comments[first..self.next_comment].to_vec()
+    }
+
+    // Takes the comment after the end of a statement on its last line, unless something else
+    // comes between them.
+    fn trailing_comment(&mut self, end: Span) -> Option<Comment> {
+        let comment = self.comments.get(self.next_comment)?;
+        let is_next = self.marks.get(self.next_mark).is_none_or(|&next| is_before(comment.span, next));
+        if !comment.is_trailing || comment.span.line != end.line || !is_next {
+            return None;
+        }
+        self.next_comment += 1;
+        Some(comment.clone())
+    }
+
+    // The lines of a list of statements and the comments among them. Inside a block this also
+    // takes the block's closing brace, and the comments before it.
+    fn statements(&mut self, statements: &[Statement], depth: usize, closes_block: bool) -> Vec<String> {
+        let indent = " ".repeat(depth * INDENT);
+        let mut lines = vec![];
+        let mut last_line = None;
+        for (index, statement) in statements.iter().enumerate() {
+            let start = self.mark();
+            for comment in self.comments_before(start) {
+                separate(&mut lines, last_line, comment.span.line);
+                lines.push(format!("{}{}", indent, comment.text));
+                last_line = Some(comment.span.line);
+            }
+            separate(&mut lines, last_line, start.line);
+
+            let mut text = self.statement(statement, depth);
+            let end = self.mark();
+            // Comments in the middle of a statement have nowhere to go but before it.
+            for comment in self.comments_before(end) {
+                lines.push(format!("{}{}", indent, comment.text));
+            }
+            // Without a semicolon the next statement would be read as more of this one.
+            let is_continued = statements.get(index + 1).is_some_and(starts_with_bracket);
+            if matches!(statement, Statement::Expression(_)) && is_continued {
+                text.push(';');
+            }
+            if let Some(comment) = self.trailing_comment(end) {
+                text = format!("{} {}", text, comment.text);
+            }
+            lines.push(format!("{}{}", indent, text));
+            last_line = Some(end.line);
+        }
+
+        let rest = match closes_block {
+            true => {
+                let close = self.mark();
+                self.comments_before(close)
+            }
+            false => self.comments_before(Span { line: usize::MAX, column: usize::MAX }),
+        };
+        for comment in rest {
+            separate(&mut lines, last_line, comment.span.line);
+            lines.push(format!("{}{}", indent, comment.text));
+            last_line = Some(comment.span.line);
+        }
+        lines
+    }
+
+    fn statement(&mut self, statement: &Statement, depth: usize) -> String {
+        let column = depth * INDENT;
+        match statement {
+            Statement::Let(name, value) => {
+                let prefix = format!("let {} = ", name);
+                let value = self.expression(value, depth, column + width(&prefix));
+                format!("{}{};", prefix, value)
+            }
+            Statement::Return(value) => format!("return {};", self.expression(value, depth, column + 7)),
+            Statement::Break => String::from("break;"),
+            Statement::Continue => String::from("continue;"),
+            Statement::Expression(expression) => self.expression(expression, depth, column),
+        }
+    }
+
+    // An expression starting at `column` of a line indented to `depth`, on that line when it fits.
+    // Lines after the first carry their own indentation.
+    fn expression(&mut self, expression: &Expression, depth: usize, column: usize) -> String {
+        let next_mark = self.next_mark;
+        if let Some(text) = self.flat(expression) {
+            if column + width(&text) <= MAX_WIDTH {
+                return text;
+            }
+        }
+        self.next_mark = next_mark;
+        self.broken(expression, depth, column)
+    }
+
+    fn operand(&mut self, expression: &Expression, minimum: u8, depth: usize, column: usize) -> String {
+        match precedence(expression) < minimum {
+            true => format!("({})", self.expression(expression, depth, column + 1)),
+            false => self.expression(expression, depth, column),
+        }
+    }
+
+    // The expression on one line, or None when it has a block that does not fit on one.
+    fn flat(&mut self, expression: &Expression) -> Option<String> {
+        let text = match expression {
+            Expression::Array(elements) => format!("[{}]", self.flat_list(elements)?),
+            Expression::Hash(pairs) => {
+                let mut entries = vec![];
+                for (key, value) in pairs {
+                    entries.push(format!("{}: {}", self.flat(key)?, self.flat(value)?));
+                }
+                format!("{{{}}}", entries.join(", "))
+            }
+            Expression::Prefix(operator, right) => format!("{}{}", operator, self.flat_operand(right, PREFIX)?),
+            Expression::Infix(operator, left, right) => {
+                let minimum = infix_precedence(operator);
+                let left = self.flat_operand(left, minimum)?;
+                let right = self.flat_operand(right, minimum + 1)?;
+                format!("{}{}{}", left, separator(operator), right)
+            }
+            Expression::Assign(name, value) => format!("{} = {}", name, self.flat(value)?),
+            Expression::If { condition, consequence, alternative } => {
+                let mut text = format!("if ({}) {}", self.flat(condition)?, self.flat_block(consequence)?);
+                if let Some(alternative) = alternative {
+                    text = format!("{} else {}", text, self.flat_block(alternative)?);
+                }
+                text
+            }
+            Expression::While { condition, body } => {
+                format!("while ({}) {}", self.flat(condition)?, self.flat_block(body)?)
+            }
+            Expression::For { variables, iterable, body } => {
+                format!("for {} in {} {}", join(variables, ", "), self.flat(iterable)?, self.flat_block(body)?)
+            }
+            Expression::Try { body, variable, handler } => {
+                let body = self.flat_block(body)?;
+                format!("try {} catch ({}) {}", body, variable, self.flat_block(handler)?)
+            }
+            Expression::Function { parameters, body } => {
+                format!("fn({}) {}", join(parameters, ", "), self.flat_block(body)?)
+            }
+            Expression::Call { function, arguments, .. } => {
+                format!("{}({})", self.flat_operand(function, CALL)?, self.flat_list(arguments)?)
+            }
+            Expression::Index(left, index) => format!("{}[{}]", self.flat_operand(left, CALL)?, self.flat(index)?),
+            primary => primary_text(primary),
+        };
+        Some(text)
+    }
+
+    fn flat_operand(&mut self, expression: &Expression, minimum: u8) -> Option<String> {
+        match precedence(expression) < minimum {
+            true => Some(format!("({})", self.flat(expression)?)),
+            false => self.flat(expression),
+        }
+    }
+
+    fn flat_list(&mut self, expressions: &[Expression]) -> Option<String> {
+        let mut texts = vec![];
+        for expression in expressions {
+            texts.push(self.flat(expression)?);
+        }
+        Some(texts.join(", "))
+    }
+
+    // Only a block of at most one statement, with no comments in it, goes on one line.
+    fn flat_block(&mut self, block: &BlockStatement) -> Option<String> {
+        let open = self.mark();
+        let text = match block.statements.as_slice() {
+            [] => String::from("{}"),
+            [statement] => {
+                self.mark();
+                let text = self.flat_statement(statement)?;
+                self.mark();
+                format!("{{ {} }}", text)
+            }
+            _ => return None,
+        };
+        let close = self.mark();
+        let comments = &self.comments[self.next_comment..];
+        match comments.iter().any(|comment| is_before(open, comment.span) && is_before(comment.span, close)) {
+            true => None,
+            false => Some(text),
+        }
+    }
+
+    fn flat_statement(&mut self, statement: &Statement) -> Option<String> {
+        let text = match statement {
+            Statement::Let(name, value) => format!("let {} = {};", name, self.flat(value)?),
+            Statement::Return(value) => format!("return {};", self.flat(value)?),
+            Statement::Break => String::from("break;"),
+            Statement::Continue => String::from("continue;"),
+            Statement::Expression(expression) => self.flat(expression)?,
+        };
+        Some(text)
+    }
+
+    // The expression over several lines: list elements one per line, a function passed last to
+    // a call hugging the call's parentheses, and blocks broken open. Infix operators are never
+    // broken at, only their operands are.
+    fn broken(&mut self, expression: &Expression, depth: usize, column: usize) -> String {
+        match expression {
+            Expression::Array(elements) => format!("[{}]", self.broken_list(elements, depth)),
+            Expression::Hash(pairs) => {
+                if pairs.is_empty() {
+                    return String::from("{}");
+                }
+                let indent = " ".repeat((depth + 1) * INDENT);
+                let mut entries = vec![];
+                for (key, value) in pairs {
+                    let key = self.expression(key, depth + 1, width(&indent));
+                    let value = self.expression(value, depth + 1, column_after(&key, width(&indent)) + 2);
+                    entries.push(format!("{}{}: {}", indent, key, value));
+                }
+                format!("{{\n{}\n{}}}", entries.join(",\n"), " ".repeat(depth * INDENT))
+            }
+            Expression::Prefix(operator, right) => {
+                format!("{}{}", operator, self.operand(right, PREFIX, depth, column + 1))
+            }
+            Expression::Infix(operator, left, right) => {
+                let minimum = infix_precedence(operator);
+                let left = self.operand(left, minimum, depth, column);
+                let separator = separator(operator);
+                let column = column_after(&left, column) + separator.len();
+                format!("{}{}{}", left, separator, self.operand(right, minimum + 1, depth, column))
+            }
+            Expression::Assign(name, value) => {
+                let prefix = format!("{} = ", name);
+                format!("{}{}", prefix, self.expression(value, depth, column + width(&prefix)))
+            }
+            Expression::If { condition, consequence, alternative } => {
+                let condition = self.expression(condition, depth, column + 4);
+                let mut text = format!("if ({}) {}", condition, self.block(consequence, depth));
+                if let Some(alternative) = alternative {
+                    text = format!("{} else {}", text, self.block(alternative, depth));
+                }
+                text
+            }
+            Expression::While { condition, body } => {
+                let condition = self.expression(condition, depth, column + 7);
+                format!("while ({}) {}", condition, self.block(body, depth))
+            }
+            Expression::For { variables, iterable, body } => {
+                let prefix = format!("for {} in ", join(variables, ", "));
+                let iterable = self.expression(iterable, depth, column + width(&prefix));
+                format!("{}{} {}", prefix, iterable, self.block(body, depth))
+            }
+            Expression::Try { body, variable, handler } => {
+                let body = self.block(body, depth);
+                format!("try {} catch ({}) {}", body, variable, self.block(handler, depth))
+            }
+            Expression::Function { parameters, body } => {
+                format!("fn({}) {}", join(parameters, ", "), self.block(body, depth))
+            }
+            Expression::Call { function, arguments, .. } => {
+                let callee = self.operand(function, CALL, depth, column);
+                let column = column_after(&callee, column) + 1;
+                if let Some(text) = self.hugged_arguments(arguments, depth, column) {
+                    return format!("{}({})", callee, text);
+                }
+                format!("{}({})", callee, self.broken_list(arguments, depth))
+            }
+            Expression::Index(left, index) => {
+                let left = self.operand(left, CALL, depth, column);
+                let column = column_after(&left, column) + 1;
+                format!("{}[{}]", left, self.expression(index, depth, column))
+            }
+            primary => primary_text(primary),
+        }
+    }
+
+    // `map(xs, fn(x) {` on one line when everything before the function's body fits there.
+    fn hugged_arguments(&mut self, arguments: &[Expression], depth: usize, column: usize) -> Option<String> {
+        let (last, rest) = arguments.split_last()?;
+        if !matches!(last, Expression::Function { .. }) {
+            return None;
+        }
+        let next_mark = self.next_mark;
+        let mut text = String::new();
+        for argument in rest {
+            match self.flat(argument) {
+                Some(argument) => text = format!("{}{}, ", text, argument),
+                None => {
+                    self.next_mark = next_mark;
+                    return None;
+                }
+            }
+        }
+        if column + width(&text) > MAX_WIDTH {
+            self.next_mark = next_mark;
+            return None;
+        }
+        let column = column + width(&text);
+        Some(format!("{}{}", text, self.broken(last, depth, column)))
+    }
+
+    fn broken_list(&mut self, expressions: &[Expression], depth: usize) -> String {
+        if expressions.is_empty() {
+            return String::new();
+        }
+        let indent = " ".repeat((depth + 1) * INDENT);
+        let mut lines = vec![];
+        for expression in expressions {
+            lines.push(format!("{}{}", indent, self.expression(expression, depth + 1, width(&indent))));
+        }
+        format!("\n{}\n{}", lines.join(",\n"), " ".repeat(depth * INDENT))
+    }
+
+    fn block(&mut self, block: &BlockStatement, depth: usize) -> String {
+        self.mark();
+        let lines = self.statements(&block.statements, depth + 1, true);
+        if lines.is_empty() {
+            return String::from("{}");
+        }
+        format!("{{\n{}\n{}}}", lines.join("\n"), " ".repeat(depth * INDENT))
+    }
+}
+
+fn is_before(span: Span, other: Span) -> bool {
+    (span.line, span.column) < (other.line, other.column)
+}
+
+// Keeps one blank line where there were any between two things on the same level.
+fn separate(lines: &mut Vec<String>, last_line: Option<usize>, line: usize) {
+    if last_line.is_some_and(|last_line| line > last_line + 1) {
+        lines.push(String::new());
+    }
+}
+
+fn width(text: &str) -> usize {
+    text.chars().count()
+}
+
+// The column a line is at after `text` was written starting at `column`.
+fn column_after(text: &str, column: usize) -> usize {
+    match text.rsplit_once('\n') {
+        Some((_, last_line)) => width(last_line),
+        None => column + width(text),
+    }
+}
+
+fn precedence(expression: &Expression) -> u8 {
+    match expression {
+        Expression::Assign(..) => 1,
+        Expression::Infix(operator, ..) => infix_precedence(operator),
+        Expression::Prefix(..) => PREFIX,
+        Expression::Call { .. } => CALL,
+        Expression::Index(..) => 11,
+        _ => 12,
+    }
+}
+
+fn infix_precedence(operator: &InfixOperator) -> u8 {
+    match operator {
+        InfixOperator::Or => 2,
+        InfixOperator::And => 3,
+        InfixOperator::Equals | InfixOperator::NotEquals => 4,
+        InfixOperator::LessThan | InfixOperator::GreaterThan => 5,
+        InfixOperator::Range => 6,
+        InfixOperator::Plus | InfixOperator::Minus => 7,
+        InfixOperator::Star | InfixOperator::Slash => 8,
+    }
+}
+
+// Ranges read best without spaces, `0..n`.
+fn separator(operator: &InfixOperator) -> String {
+    match operator {
+        InfixOperator::Range => operator.to_string(),
+        operator => format!(" {} ", operator),
+    }
+}
+
+fn primary_text(expression: &Expression) -> String {
+    match expression {
+        // `{:?}` would write large floats with an exponent the lexer does not read.
+        Expression::Float(value) if !value.to_string().contains('.') => format!("{}.0", value),
+        Expression::Float(value) => value.to_string(),
+        primary => primary.to_string(),
+    }
+}
+
+// Whether a statement starts with a token that would continue the expression statement before it.
+fn starts_with_bracket(statement: &Statement) -> bool {
+    fn starts_with_bracket(expression: &Expression) -> bool {
+        match expression {
+            Expression::Array(_) | Expression::Prefix(PrefixOperator::Minus, _) => true,
+            Expression::Infix(operator, left, _) => {
+                precedence(left) < infix_precedence(operator) || starts_with_bracket(left)
+            }
+            Expression::Call { function: left, .. } | Expression::Index(left, _) => {
+                precedence(left) < CALL || starts_with_bracket(left)
+            }
+            _ => false,
+        }
+    }
+    match statement {
+        Statement::Expression(expression) => starts_with_bracket(expression),
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::syntax_analyzer::parse;
+
+    // Formatting must not change what a program means, and formatting twice changes nothing more.
+    fn assert_formats(source: &str, expected: &str) {
+        let formatted = format_source(source).unwrap();
+        assert_eq!(formatted, expected);
+        let reparsed = parse(skip_shebang(&formatted)).unwrap();
+        assert_eq!(reparsed.to_string(), parse(skip_shebang(source)).unwrap().to_string());
+        assert_eq!(format_source(&formatted).unwrap(), formatted);
+    }
+
+    #[test]
+    fn it_works_on_spacing_and_indentation() {
+        assert_formats(
+            "let   add=fn(a,b){a+b};let x=add(1,2)*3;if(x>5){puts(\"big\");x=x-1}else{puts(\"small\")}",
+            "\
+let add = fn(a, b) { a + b };
+let x = add(1, 2) * 3;
+if (x > 5) {
+  puts(\"big\")
+  x = x - 1
+} else {
+  puts(\"small\")
+}
+",
+        );
+        assert_formats(
+            "for i,x in [1.5,2.0,-3]{while(i<x){i=i+1;continue}}",
+            "\
+for i, x in [1.5, 2.0, -3] {
+  while (i < x) {
+    i = i + 1
+    continue;
+  }
+}
+",
+        );
+        assert_formats(
+            "try{f()}catch(e){null};{\"a\":[],true:{}}[\"a\"]",
+            "try { f() } catch (e) { null }\n{\"a\": [], true: {}}[\"a\"]\n",
+        );
+        assert_formats("", "");
+    }
+
+    #[test]
+    fn it_keeps_needed_parentheses() {
+        assert_formats("(a+b)*(c-d)-(e-f)", "(a + b) * (c - d) - (e - f)\n");
+        assert_formats(
+            "-(-x) + !(a==b) + (-y)[0] + (f)(1) + (g(1))(2)",
+            "--x + !(a == b) + (-y)[0] + f(1) + g(1)(2)\n",
+        );
+        assert_formats("(x = 1) + 0..(n+1)", "(x = 1) + 0..n + 1\n");
+        assert_formats("a - (b - c) == (d == e)", "a - (b - c) == (d == e)\n");
+        // Without the semicolons these would be calls, indexes and subtractions.
+        assert_formats("f;(x=1)+2;g;[2];h;-3;i", "f;\n(x = 1) + 2\ng;\n[2]\nh;\n-3\ni\n");
+        assert_formats("100000000000000000000.0 + 0.5", "100000000000000000000.0 + 0.5\n");
+    }
+
+    #[test]
+    fn it_breaks_long_lines() {
+        assert_formats(
+            "let numbers = [111111111, 222222222, 333333333, 444444444, 555555555, 666666666, 777777777, \
+             888888888, 999999999];",
+            "\
+let numbers = [
+  111111111,
+  222222222,
+  333333333,
+  444444444,
+  555555555,
+  666666666,
+  777777777,
+  888888888,
+  999999999
+];
+",
+        );
+        assert_formats(
+            "map(items, fn(item) { item_with_a_long_name + another_long_name + yet_another_long_name + \
+             the_last_name })",
+            "\
+map(items, fn(item) {
+  item_with_a_long_name + another_long_name + yet_another_long_name + the_last_name
+})
+",
+        );
+        assert_formats(
+            "puts(\"a string long enough to need a line of its own, really\", \
+             \"and another string that is just as long\")",
+            "\
+puts(
+  \"a string long enough to need a line of its own, really\",
+  \"and another string that is just as long\"
+)
+",
+        );
+    }
+
+    #[test]
+    fn it_keeps_comments() {
+        assert_formats(
+            "\
+#!/usr/bin/env monkey
+// Counts down.
+let n = 3;   // start
+
+
+// loop
+while (n > 0) { // not trailing the statement
+  puts(n)
+
+  n = n - 1 // step
+  // done with this round
+}
+let x = [1, // one
+  2];
+// the end
+",
+            "\
+#!/usr/bin/env monkey
+// Counts down.
+let n = 3; // start
+
+// loop
+while (n > 0) {
+  // not trailing the statement
+  puts(n)
+
+  n = n - 1 // step
+  // done with this round
+}
+// one
+let x = [1, 2];
+// the end
+",
+        );
+        assert_formats("if (x) { // why\n}\n", "if (x) {\n  // why\n}\n");
+        assert_formats("a // one\nb // two\n", "a // one\nb // two\n");
+    }
+
+    #[test]
+    fn it_reports_syntax_errors() {
+        let errors = format_source("let x = ;\nlet = 2").unwrap_err();
+        assert_eq!(errors.len(), 2);
+        assert_eq!(errors[0].message, "expected expression, got <;, ;>");
+    }
+}
@@ -0,0 +1,304 @@
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::rc::{Rc, Weak};
+
+use crate::environment::Environment;
+use crate::object::{Closure, Function, HashPairs, Object};
+
+const INITIAL_THRESHOLD: usize = 1024;
+
+// A closure stored in the environment it captured (`let f = fn() { f }` being the simplest
+// case) forms an `Rc` cycle that reference counting alone never frees. The collector keeps a
+// weak list of every environment the evaluator creates and periodically finds the groups of
+// objects that are only kept alive by each other. The virtual machine's closures do the same
+// through the cells of the variables they capture, which it has the collector track as well.
+//
+// It uses trial deletion over everything reachable from the tracked environments and cells:
+// environments, cells, functions, closures, arrays and hashes. References found between them are
+// subtracted from their strong counts. Whatever is left over is held from outside, by the host,
+// the Rust stack of a running evaluation, the virtual machine's stack or a value being passed
+// around, and everything reachable from there is live. The remaining environments and cells are
+// garbage and get emptied, which breaks their cycles and lets reference counting free the rest.
+pub struct CycleCollector {
+    environments: Vec<Weak<RefCell<Environment>>>,
+    cells: Vec<Weak<RefCell<Object>>>,
+    threshold: usize,
+}
+
+enum Node {
+    Environment(Rc<RefCell<Environment>>),
+    Cell(Rc<RefCell<Object>>),
+    Function(Rc<Function>),
+    Closure(Rc<Closure>),
+    Array(Rc<Vec<Object>>),
+    Hash(Rc<HashPairs>),
+}
+
+impl Node {
+    fn of(value: &Object) -> Option<Node> {
+        match value {
+            Object::Function(function) => Some(Node::Function(Rc::clone(function))),
+            Object::Closure(closure) => Some(Node::Closure(Rc::clone(closure))),
+            Object::Array(elements) => Some(Node::Array(Rc::clone(elements))),
+            Object::Hash(pairs) => Some(Node::Hash(Rc::clone(pairs))),
+            _ => None,
+        }
+    }
+
+    fn address(&self) -> *const () {
+        match self {
+            Node::Environment(env) => Rc::as_ptr(env) as *const (),
+            Node::Cell(cell) => Rc::as_ptr(cell) as *const (),
+            Node::Function(function) => Rc::as_ptr(function) as *const (),
+            Node::Closure(closure) => Rc::as_ptr(closure) as *const (),
+            Node::Array(elements) => Rc::as_ptr(elements) as *const (),
+            Node::Hash(pairs) => Rc::as_ptr(pairs) as *const (),
+        }
+    }
+
+    fn strong_count(&self) -> usize {
+        match self {
+            Node::Environment(env) => Rc::strong_count(env),
+            Node::Cell(cell) => Rc::strong_count(cell),
+            Node::Function(function) => Rc::strong_count(function),
+            Node::Closure(closure) => Rc::strong_count(closure),
+            Node::Array(elements) => Rc::strong_count(elements),
+            Node::Hash(pairs) => Rc::strong_count(pairs),
+        }
+    }
+
+    // Returns `None` for an environment or cell that is being modified right now, it cannot be
+    // inspected and keeping it alive is the only safe choice.
+    fn references(&self) -> Option<Vec<Node>> {
+        match self {
+            Node::Environment(env) => {
+                let env = env.try_borrow().ok()?;
+                let mut references: Vec<Node> = env.values().filter_map(Node::of).collect();
+                references.extend(env.outer().map(Node::Environment));
+                Some(references)
+            }
+            Node::Cell(cell) => Some(Node::of(&*cell.try_borrow().ok()?).into_iter().collect()),
+            Node::Function(function) => Some(vec![Node::Environment(Rc::clone(&function.env))]),
+            Node::Closure(closure) => Some(closure.free.iter().map(|cell| Node::Cell(Rc::clone(cell))).collect()),
+            Node::Array(elements) => Some(elements.iter().filter_map(Node::of).collect()),
+            Node::Hash(pairs) => Some(pairs.values().filter_map(Node::of).collect()),
+        }
+    }
+}
+
+// Holds exactly one clone of every node, so each strong count is off by one at most.
+#[derive(Default)]
+struct Graph {
+    nodes: Vec<Node>,
+    indices: HashMap<*const (), usize>,
+    references: Vec<Vec<usize>>,
+    is_opaque: Vec<bool>,
+}
+
+impl Graph {
+    fn add(&mut self, node: Node) -> usize {
+        if let Some(&index) = self.indices.get(&node.address()) {
+            return index;
+        }
+
+        self.indices.insert(node.address(), self.nodes.len());
+        self.nodes.push(node);
+        self.references.push(vec![]);
+        self.is_opaque.push(false);
+        self.nodes.len() - 1
+    }
+}
+
+impl Default for CycleCollector {
+    fn default() -> Self {
+        CycleCollector::new()
+    }
+}
+
+impl CycleCollector {
+    pub fn new() -> Self {
+        CycleCollector {
+            environments: vec![],
+            cells: vec![],
+            threshold: INITIAL_THRESHOLD,
+        }
+    }
+
+    pub fn track(&mut self, env: &Rc<RefCell<Environment>>) {
+        self.environments.push(Rc::downgrade(env));
+    }
+
+    pub fn track_cell(&mut self, cell: &Rc<RefCell<Object>>) {
+        self.cells.push(Rc::downgrade(cell));
+    }
+
+    pub fn is_tracked(&self, env: &Rc<RefCell<Environment>>) -> bool {
+        let env = Rc::downgrade(env);
+        self.environments.iter().any(|tracked| tracked.ptr_eq(&env))
+    }
+
+    pub fn should_collect(&self) -> bool {
+        self.environments.len() + self.cells.len() >= self.threshold
+    }
+
+    pub fn tracked_environments(&self) -> usize {
+        self.environments.iter().filter(|env| env.strong_count() > 0).count()
+    }
+
+    pub fn tracked_cells(&self) -> usize {
+        self.cells.iter().filter(|cell| cell.strong_count() > 0).count()
+    }
+
+    // Frees every environment and cell that is only reachable through cycles and returns how many
+    // there were.
+    pub fn collect(&mut self) -> usize {
+        let mut graph = Graph::default();
+        for env in self.environments.iter().filter_map(Weak::upgrade) {
+            graph.add(Node::Environment(env));
+        }
+        for cell in self.cells.iter().filter_map(Weak::upgrade) {
+            graph.add(Node::Cell(cell));
+        }
+
+        let mut next = 0;
+        while next < graph.nodes.len() {
+            match graph.nodes[next].references() {
+                None => graph.is_opaque[next] = true,
+                Some(references) => {
+                    for reference in references {
+                        let index = graph.add(reference);
+                        graph.references[next].push(index);
+                    }
+                }
+            }
+            next += 1;
+        }
+
+        let mut external_references: Vec<isize> =
+            graph.nodes.iter().map(|node| node.strong_count() as isize - 1).collect();
+        for references in &graph.references {
+            for &index in references {
+                external_references[index] -= 1;
+            }
+        }
+
+        let mut is_live = vec![false; graph.nodes.len()];
+        let mut pending: Vec<usize> = (0..graph.nodes.len())
+            .filter(|&index| external_references[index] > 0 || graph.is_opaque[index])
+            .collect();
+        while let Some(index) = pending.pop() {
+            if is_live[index] {
+                continue;
+            }
+            is_live[index] = true;
+            pending.extend(graph.references[index].iter().filter(|&&reference| !is_live[reference]));
+        }
+
+        let mut collected = 0;
+        for (index, node) in graph.nodes.iter().enumerate() {
+            match (node, is_live[index]) {
+                (Node::Environment(env), false) => env.borrow_mut().clear(),
+                (Node::Cell(cell), false) => *cell.borrow_mut() = Object::Null,
+                _ => continue,
+            }
+            collected += 1;
+        }
+        drop(graph);
+
+        self.environments.retain(|env| env.strong_count() > 0);
+        self.cells.retain(|cell| cell.strong_count() > 0);
+        self.threshold = INITIAL_THRESHOLD.max((self.environments.len() + self.cells.len()) * 2);
+
+        collected
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::evaluator::Evaluator;
+    use crate::syntax_analyzer::parse;
+
+    fn evaluate_in(evaluator: &mut Evaluator, env: &Rc<RefCell<Environment>>, input: &str) -> String {
+        evaluator.eval_program(&parse(input).unwrap(), env).to_string()
+    }
+
+    #[test]
+    fn it_collects_self_referencing_closures() {
+        let mut evaluator = Evaluator::new();
+        let env = Rc::new(RefCell::new(Environment::new()));
+        evaluate_in(&mut evaluator, &env, "let f = fn() { let g = fn() { g }; 1 };");
+        for _ in 0..10 {
+            evaluate_in(&mut evaluator, &env, "f()");
+        }
+
+        // The global environment and the ten call environments holding `g`.
+        assert_eq!(evaluator.cycle_collector().tracked_environments(), 11);
+        assert_eq!(evaluator.cycle_collector().collect(), 10);
+        assert_eq!(evaluator.cycle_collector().tracked_environments(), 1);
+        assert_eq!(evaluate_in(&mut evaluator, &env, "f()"), "1");
+    }
+
+    #[test]
+    fn it_keeps_environments_that_are_still_reachable() {
+        let mut evaluator = Evaluator::new();
+        let env = Rc::new(RefCell::new(Environment::new()));
+        evaluate_in(
+            &mut evaluator,
+            &env,
+            "let make = fn(x) { let g = fn() { x + h() }; let h = fn() { 1 }; g }; let g = make(41);",
+        );
+        let held_outside = evaluator.eval_program(&parse("make(1)").unwrap(), &env);
+
+        assert_eq!(evaluator.cycle_collector().collect(), 0);
+        assert_eq!(evaluate_in(&mut evaluator, &env, "g()"), "42");
+        assert_eq!(held_outside.to_string(), "fn() { (x + h()) }");
+
+        drop(held_outside);
+        assert_eq!(evaluator.cycle_collector().collect(), 1);
+    }
+
+    #[test]
+    fn it_follows_references_through_arrays_and_hashes() {
+        let mut evaluator = Evaluator::new();
+        let env = Rc::new(RefCell::new(Environment::new()));
+        evaluate_in(
+            &mut evaluator,
+            &env,
+            "let make = fn() { let self = [{1: fn() { self }}]; self }; make(); let kept = make();",
+        );
+        let held_outside = evaluator.eval_program(&parse("make()").unwrap(), &env);
+
+        assert_eq!(evaluator.cycle_collector().collect(), 1);
+        assert_eq!(evaluate_in(&mut evaluator, &env, "kept[0][1]()[0][1]()"), "[{1: fn() { self }}]");
+        assert_eq!(held_outside.to_string(), "[{1: fn() { self }}]");
+
+        drop(held_outside);
+        assert_eq!(evaluator.cycle_collector().collect(), 1);
+    }
+
+    #[test]
+    fn it_collects_automatically_while_evaluating() {
+        let mut evaluator = Evaluator::new();
+        let env = Rc::new(RefCell::new(Environment::new()));
+        evaluate_in(&mut evaluator, &env, "let f = fn() { let g = fn() { g }; 1 };");
+        for _ in 0..(INITIAL_THRESHOLD * 3) {
+            evaluate_in(&mut evaluator, &env, "f()");
+        }
+
+        assert!(evaluator.cycle_collector().tracked_environments() <= INITIAL_THRESHOLD);
+    }
+
+    #[test]
+    fn it_collects_a_dropped_global_environment() {
+        let mut evaluator = Evaluator::new();
+        let env = Rc::new(RefCell::new(Environment::new()));
+        evaluate_in(&mut evaluator, &env, "let f = fn() { f };");
+        let weak_env = Rc::downgrade(&env);
+        drop(env);
+
+        assert!(weak_env.upgrade().is_some());
+        assert_eq!(evaluator.cycle_collector().collect(), 1);
+        assert!(weak_env.upgrade().is_none());
+    }
+}
@@ -0,0 +1,180 @@
+use std::ops::Range;
+
+use crate::documenter::escape_html;
+use crate::lexical_analyzer::{comments, tokenize_with_ranges, Token};
+use crate::repl::skip_shebang;
+
+const RESET: &str = "\x1b[0m";
+const KEYWORD: &str = "\x1b[35m";
+const LITERAL: &str = "\x1b[33m";
+const STRING: &str = "\x1b[32m";
+const COMMENT: &str = "\x1b[90m";
+const MATCHING_BRACKET: &str = "\x1b[1;4m";
+
+// The names the HTML gives each kind of token for styling, and the colors they have in a terminal.
+const CLASSES: [(&str, &str); 4] =
+    [("keyword", KEYWORD), ("literal", LITERAL), ("string", STRING), ("comment", COMMENT)];
+
+fn class_of(token: &Token) -> Option<&'static str> {
+    match token {
+        Token::Let
+        | Token::Fn
+        | Token::If
+        | Token::Else
+        | Token::Return
+        | Token::While
+        | Token::For
+        | Token::In
+        | Token::Break
+        | Token::Continue
+        | Token::Try
+        | Token::Catch => Some("keyword"),
+        Token::Integer(_) | Token::Float(_) | Token::Boolean(_) | Token::Null => Some("literal"),
+        Token::String(_) => Some("string"),
+        _ => None,
+    }
+}
+
+fn color_of(class: &str) -> &'static str {
+    CLASSES.iter().find(|(name, _)| *name == class).map_or(RESET, |(_, color)| color)
+}
+
+// The class of every character of the source, by the tokens and comments it is part of.
+fn classes(source: &str, tokens: &[(Token, Range<usize>)]) -> Vec<Option<&'static str>> {
+    let mut classes = vec![None; source.chars().count()];
+    for (token, range) in tokens {
+        if let Some(class) = class_of(token) {
+            classes[range.clone()].fill(Some(class));
+        }
+    }
+    let mut line_starts = vec![0];
+    line_starts.extend(source.chars().enumerate().filter(|(_, character)| *character == '\n').map(|(at, _)| at + 1));
+    for comment in comments(source) {
+        let start = line_starts[comment.span.line - 1] + comment.span.column - 1;
+        classes[start..start + comment.text.chars().count()].fill(Some("comment"));
+    }
+    classes
+}
+
+// Like `classes` for a whole file, which may start with a shebang line. That is shown as a comment.
+fn file_classes(source: &str) -> Vec<Option<&'static str>> {
+    let body = skip_shebang(source);
+    let mut shebang = vec![Some("comment"); source[..source.len() - body.len()].chars().count()];
+    shebang.extend(classes(body, &tokenize_with_ranges(body)));
+    shebang
+}
+
+// The source cut where the style changes, every piece with its style.
+fn runs<'a>(source: &str, styles: &[Option<&'a str>]) -> Vec<(Option<&'a str>, String)> {
+    let mut runs: Vec<(Option<&str>, String)> = vec![];
+    for (character, style) in source.chars().zip(styles) {
+        match runs.last_mut() {
+            Some((current, text)) if current == style => text.push(character),
+            _ => runs.push((*style, String::from(character))),
+        }
+    }
+    runs
+}
+
+fn paint(runs: Vec<(Option<&str>, String)>) -> String {
+    let mut painted = String::new();
+    for (style, text) in runs {
+        match style {
+            Some(style) => painted += &format!("{}{}{}", style, text, RESET),
+            None => painted += &text,
+        }
+    }
+    painted
+}
+
+// Colors a line of REPL input with ANSI escapes. The styling comes from the same tokenizer the
+// parser reads, so what is highlighted is exactly what gets lexed, and where the lexer gives up,
+// at an unterminated string for example, the rest of the line stays plain. When the cursor is on
+// or just after a bracket, that bracket and its partner are emphasized.
+pub fn highlight(line: &str, cursor: usize) -> String {
+    let tokens = tokenize_with_ranges(line);
+    let mut styles: Vec<Option<&str>> = classes(line, &tokens).into_iter().map(|class| class.map(color_of)).collect();
+    if let Some((open, close)) = brackets_at(&tokens, cursor) {
+        styles[open] = Some(MATCHING_BRACKET);
+        styles[close] = Some(MATCHING_BRACKET);
+    }
+    paint(runs(line, &styles))
+}
+
+// What `monkey highlight --format ansi` prints for a file: the source colored as the REPL colors
+// its input.
+pub fn highlight_ansi(source: &str) -> String {
+    let styles: Vec<Option<&str>> = file_classes(source).into_iter().map(|class| class.map(color_of)).collect();
+    paint(runs(source, &styles))
+}
+
+// What `monkey highlight` prints for a file: the source as a `<pre>` block for a web page, with
+// every keyword, literal, string and comment in a `<span>` whose class says which it is.
+pub fn highlight_html(source: &str) -> String {
+    let mut html = String::from("<pre class=\"monkey\"><code>");
+    for (class, text) in runs(source, &file_classes(source)) {
+        match class {
+            Some(class) => html += &format!("<span class=\"{}\">{}</span>", class, escape_html(&text)),
+            None => html += &escape_html(&text),
+        }
+    }
+    html + "</code></pre>\n"
+}
+
+// The positions of the bracket pair whose opening or closing bracket is under the cursor or
+// right before it.
+fn brackets_at(tokens: &[(Token, Range<usize>)], cursor: usize) -> Option<(usize, usize)> {
+    let mut open_brackets = vec![];
+    let mut pairs = vec![];
+    for (token, range) in tokens {
+        match token {
+            Token::LeftParen | Token::LeftBrace | Token::LeftBracket => open_brackets.push(range.start),
+            Token::RightParen | Token::RightBrace | Token::RightBracket => {
+                if let Some(open) = open_brackets.pop() {
+                    pairs.push((open, range.start));
+                }
+            }
+            _ => {}
+        }
+    }
+
+    [cursor, cursor.wrapping_sub(1)]
+        .into_iter()
+        .find_map(|position| pairs.iter().copied().find(|&(open, close)| position == open || position == close))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_highlights_keywords_and_literals() {
+        assert_eq!(
+            highlight("let x = 1.5 + len(\"ab\") || null", 100),
+            "\x1b[35mlet\x1b[0m x = \x1b[33m1.5\x1b[0m + len(\x1b[32m\"ab\"\x1b[0m) || \x1b[33mnull\x1b[0m"
+        );
+        assert_eq!(highlight("if \"unterminated", 100), "\x1b[35mif\x1b[0m \"unterminated");
+        assert_eq!(highlight("letter", 100), "letter");
+        assert_eq!(highlight("x // note", 100), "x \x1b[90m// note\x1b[0m");
+    }
+
+    #[test]
+    fn it_highlights_files_as_html() {
+        let source = "// Greets.\nlet greet = fn(name) { \"<\" + name + \">\" }; // done\ngreet(null)\n";
+        assert_eq!(
+            highlight_html(source),
+            "<pre class=\"monkey\"><code><span class=\"comment\">// Greets.</span>\n<span class=\"keyword\">\
+             let</span> greet = <span class=\"keyword\">fn</span>(name) { <span class=\"string\">&quot;&lt;&quot;\
+             </span> + name + <span class=\"string\">&quot;&gt;&quot;</span> }; <span class=\"comment\">// done\
+             </span>\ngreet(<span class=\"literal\">null</span>)\n</code></pre>\n"
+        );
+        assert_eq!(highlight_ansi("#!/bin/monkey\n1"), "\x1b[90m#!/bin/monkey\x1b[0m\n\x1b[33m1\x1b[0m");
+    }
+
+    #[test]
+    fn it_highlights_matching_brackets() {
+        assert_eq!(highlight("f([1], 2)", 1), "f\x1b[1;4m(\x1b[0m[\x1b[33m1\x1b[0m], \x1b[33m2\x1b[0m\x1b[1;4m)\x1b[0m");
+        assert_eq!(highlight("[1]", 3), "\x1b[1;4m[\x1b[0m\x1b[33m1\x1b[0m\x1b[1;4m]\x1b[0m");
+        assert_eq!(highlight("(1", 0), "(\x1b[33m1\x1b[0m");
+    }
+}
@@ -0,0 +1,130 @@
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use crate::abstract_syntax_tree::{Expression, Statement};
+use crate::environment::Environment;
+use crate::lexical_analyzer::Span;
+use crate::object::Object;
+
+// A call expression and the environment it is evaluated in.
+pub type Call<'a> = (&'a Expression, &'a Rc<RefCell<Environment>>);
+
+// Callbacks an embedder can install with `Evaluator::with_hooks` to watch evaluation, for a
+// tracer, a profiler or a step debugger. Every method does nothing by default.
+//
+// A call or `if` in tail position reports entering but never exiting: the tail call replaces
+// the function body it ends, and its result is reported as the exit of whatever called that
+// function in the first place.
+pub trait Hooks {
+    // Called before every statement runs, in a block, a function body or the program.
+    fn on_statement(&mut self, _statement: &Statement) {}
+
+    fn on_eval_enter(&mut self, _expression: &Expression, _env: &Rc<RefCell<Environment>>) {}
+
+    fn on_eval_exit(&mut self, _expression: &Expression, _env: &Rc<RefCell<Environment>>, _result: &Object) {}
+
+    // Called before every function or builtin is applied, `span` being where it was called. `call` is
+    // the call expression with the environment it is evaluated in, which the functions that builtins
+    // such as `map` call back do not have.
+    fn on_call(&mut self, _call: Option<Call>, _function: &Object, _arguments: &[Object], _span: Span) {}
+
+    // Called once for every `on_call`, with what the call returned. A function ending in a tail
+    // call returns when the call that replaced it does, so the returns of both come together.
+    fn on_return(&mut self, _result: &Object) {}
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::evaluator::Evaluator;
+    use crate::syntax_analyzer::parse;
+
+    #[derive(Clone, Default)]
+    struct Recorder(Rc<RefCell<Vec<String>>>);
+
+    impl Hooks for Recorder {
+        fn on_eval_enter(&mut self, expression: &Expression, _env: &Rc<RefCell<Environment>>) {
+            self.0.borrow_mut().push(format!("enter {}", expression));
+        }
+
+        fn on_eval_exit(&mut self, expression: &Expression, _env: &Rc<RefCell<Environment>>, result: &Object) {
+            self.0.borrow_mut().push(format!("exit {} = {}", expression, result));
+        }
+
+        fn on_call(&mut self, call: Option<Call>, function: &Object, arguments: &[Object], span: Span) {
+            let call = match call {
+                Some((expression, env)) => format!("{} in {} bindings", expression, env.borrow().bindings().len()),
+                None => String::from("a builtin"),
+            };
+            let event = format!("call {} with {} at {} from {}", function.type_name(), arguments.len(), span, call);
+            self.0.borrow_mut().push(event);
+        }
+
+        fn on_return(&mut self, result: &Object) {
+            self.0.borrow_mut().push(format!("return {}", result));
+        }
+    }
+
+    fn record(input: &str) -> Vec<String> {
+        let recorder = Recorder::default();
+        let mut evaluator = Evaluator::new().with_hooks(recorder.clone());
+        let env = Rc::new(RefCell::new(Environment::new()));
+        evaluator.eval_program(&parse(input).unwrap(), &env);
+        let events = recorder.0.borrow().clone();
+        events
+    }
+
+    #[test]
+    fn it_reports_entering_and_exiting_expressions() {
+        assert_eq!(
+            record("1 + 2"),
+            Vec::from([
+                "enter (1 + 2)",
+                "enter 1",
+                "exit 1 = 1",
+                "enter 2",
+                "exit 2 = 2",
+                "exit (1 + 2) = 3",
+            ])
+        );
+    }
+
+    #[test]
+    fn it_reports_calls_and_their_environments() {
+        let events = record("let f = fn(x) { len(x) }; f([1]) + 1");
+        let calls: Vec<&String> = events.iter().filter(|event| event.starts_with("call")).collect();
+        assert_eq!(
+            calls,
+            [
+                "call FUNCTION with 1 at 1:27 from f([1]) in 1 bindings",
+                "call BUILTIN with 1 at 1:17 from len(x) in 1 bindings"
+            ]
+        );
+        let returns: Vec<&String> = events.iter().filter(|event| event.starts_with("return")).collect();
+        assert_eq!(returns, ["return 1", "return 1"]);
+        assert!(events.contains(&String::from("enter len(x)")));
+        assert!(!events.iter().any(|event| event.starts_with("exit len(x)")));
+        assert_eq!(events.last().unwrap(), "exit (f([1]) + 1) = 2");
+
+        let events = record("let y = 1; map([1], fn(x) { x + y })");
+        let calls: Vec<&String> = events.iter().filter(|event| event.starts_with("call")).collect();
+        assert_eq!(
+            calls,
+            [
+                "call BUILTIN with 2 at 1:12 from map([1], fn(x) { (x + y) }) in 1 bindings",
+                "call FUNCTION with 1 at 1:12 from a builtin"
+            ]
+        );
+    }
+
+    #[test]
+    fn it_reports_a_return_for_every_call() {
+        let events = record("let count = fn(n) { if (n > 0) { count(n - 1) } else { n } }; count(2)");
+        let calls: Vec<&String> = events.iter().filter(|event| event.starts_with("call")).collect();
+        let returns: Vec<&String> = events.iter().filter(|event| event.starts_with("return")).collect();
+        assert_eq!(calls.len(), 3);
+        // The tail calls are made by their call expressions too.
+        assert!(calls[1..].iter().all(|call| call.ends_with("from count((n - 1)) in 1 bindings")), "{:?}", calls);
+        assert_eq!(returns, ["return 0", "return 0", "return 0"]);
+    }
+}
@@ -0,0 +1,99 @@
+use core::fmt;
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::rc::Rc;
+
+// Identifiers are interned once, when they are parsed, so that looking them up in an environment
+// compares and hashes a `u32` instead of a whole string. Interned strings live as long as the
+// thread, which is fine for names in the source, so strings made at runtime are never interned.
+#[derive(Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Symbol(u32);
+
+#[derive(Default)]
+pub struct Interner {
+    symbols: HashMap<Rc<str>, Symbol>,
+    names: Vec<Rc<str>>,
+}
+
+impl Interner {
+    pub fn new() -> Self {
+        Interner::default()
+    }
+
+    pub fn intern(&mut self, name: &str) -> Symbol {
+        if let Some(&symbol) = self.symbols.get(name) {
+            return symbol;
+        }
+
+        let symbol = Symbol(self.names.len() as u32);
+        let name: Rc<str> = Rc::from(name);
+        self.symbols.insert(Rc::clone(&name), symbol);
+        self.names.push(name);
+        symbol
+    }
+
+    pub fn resolve(&self, symbol: Symbol) -> Rc<str> {
+        Rc::clone(&self.names[symbol.0 as usize])
+    }
+
+    pub fn len(&self) -> usize {
+        self.names.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.names.is_empty()
+    }
+}
+
+thread_local! {
+    static INTERNER: RefCell<Interner> = RefCell::new(Interner::new());
+}
+
+impl Symbol {
+    pub fn intern(name: &str) -> Symbol {
+        INTERNER.with(|interner| interner.borrow_mut().intern(name))
+    }
+
+    pub fn name(self) -> Rc<str> {
+        INTERNER.with(|interner| interner.borrow().resolve(self))
+    }
+
+    // How many names the thread has interned so far.
+    pub fn interned() -> usize {
+        INTERNER.with(|interner| interner.borrow().len())
+    }
+}
+
+impl fmt::Display for Symbol {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
+
+impl fmt::Debug for Symbol {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_works_on_interning() {
+        let mut interner = Interner::new();
+        let x = interner.intern("x");
+        let y = interner.intern("y");
+
+        assert_eq!(interner.intern("x"), x);
+        assert_ne!(x, y);
+        assert_eq!(&*interner.resolve(y), "y");
+    }
+
+    #[test]
+    fn it_shares_symbols_within_a_thread() {
+        assert_eq!(Symbol::intern("shared"), Symbol::intern(&String::from("shared")));
+        assert_eq!(Symbol::intern("shared").to_string(), "shared");
+    }
+}
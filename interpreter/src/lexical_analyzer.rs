@@ -1,44 +1,160 @@
 use core::fmt;
 use std::collections::HashMap;
+use std::ops::Range;
+
+use crate::diagnostics::{INTEGER_TOO_LARGE, UNEXPECTED_CHARACTER, UNTERMINATED_STRING};
 
 pub fn tokenize(input: &str) -> Vec<Token> {
+    tokenize_with_spans(input).into_iter().map(|(token, _)| token).collect()
+}
+
+// Like `tokenize`, pairing every token with where it starts in the input.
+pub fn tokenize_with_spans(input: &str) -> Vec<(Token, Span)> {
+    tokenize_checked(input).0
+}
+
+// Like `tokenize_with_spans`, also saying why lexing stopped when it did not reach the end of
+// the input.
+pub fn tokenize_checked(input: &str) -> (Vec<(Token, Span)>, Option<LexicalError>) {
     let mut tokenizer = Tokenizer::new(input);
 
     let mut tokens = vec![];
-    while let Some(token) = tokenizer.get_next_token()  {
-        tokens.push(token);
+    loop {
+        tokenizer.skip_whitespace();
+        let span = tokenizer.span();
+        match tokenizer.get_next_token() {
+            None => break,
+            Some(token) => tokens.push((token, span)),
+        }
+    }
+
+    let error = tokenizer.remaining_input.first().map(|&character| {
+        let (code, message) = match character {
+            '"' => (UNTERMINATED_STRING, String::from("unterminated string")),
+            '0'..='9' => {
+                let digits: String = tokenizer.remaining_input.iter().take_while(|c| c.is_ascii_digit()).collect();
+                (INTEGER_TOO_LARGE, format!("integer literal too large: {}", digits))
+            }
+            character => (UNEXPECTED_CHARACTER, format!("unexpected character `{}`", character)),
+        };
+        LexicalError { message, code, span: tokenizer.span() }
+    });
+    (tokens, error)
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct LexicalError {
+    pub message: String,
+    pub code: &'static str,
+    pub span: Span,
+}
+
+// Like `tokenize`, pairing every token with the characters of the input it was read from, for
+// tools such as the REPL's highlighter that need to know exactly where tokens end.
+pub fn tokenize_with_ranges(input: &str) -> Vec<(Token, Range<usize>)> {
+    let mut tokenizer = Tokenizer::new(input);
+
+    let mut tokens = vec![];
+    loop {
+        tokenizer.skip_whitespace();
+        let start = tokenizer.position;
+        match tokenizer.get_next_token() {
+            None => break,
+            Some(token) => tokens.push((token, start..tokenizer.position)),
+        }
     }
 
     tokens
 }
 
-#[derive(Clone)]
+// A `// ...` comment, which runs to the end of its line.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Comment {
+    // The comment's text from its `//` on, without trailing whitespace.
+    pub text: String,
+    pub span: Span,
+    // Whether code comes before the comment on its line.
+    pub is_trailing: bool,
+}
+
+// The comments in the input, in order. Lexing stops where `tokenize_checked` stops, so comments
+// past a lexical error are left out.
+pub fn comments(input: &str) -> Vec<Comment> {
+    let mut tokenizer = Tokenizer::new(input);
+    while tokenizer.get_next_token().is_some() {}
+    tokenizer.skip_whitespace();
+    tokenizer.comments
+}
+
+// Every word the lexer reads as a keyword or literal rather than an identifier, sorted.
+pub fn keywords() -> Vec<String> {
+    let mut keywords: Vec<String> = Tokenizer::new("").keyword_to_token.into_keys().collect();
+    keywords.sort();
+    keywords
+}
+
+// A position in the source, both counted from 1.
+#[derive(Clone, Copy, Debug, Default, Eq, Hash, PartialEq)]
+pub struct Span {
+    pub line: usize,
+    pub column: usize,
+}
+
+impl fmt::Display for Span {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}", self.line, self.column)
+    }
+}
+
+#[derive(Clone, PartialEq)]
 pub enum Token {
     // Identifier
     Identifier(String),
 
     // Fundamental data types
     Integer(i32),
+    Float(f64),
     Boolean(bool),
+    String(String),
+    Null,
 
     // Keywords
     Let,
     Fn,
     If,
     Else,
+    Return,
+    While,
+    For,
+    In,
+    Break,
+    Continue,
+    Try,
+    Catch,
 
     // Punctuation
     Plus,
     Minus,
     Star,
     Slash,
+    Bang,
     Assignment,
     Equals,
+    NotEquals,
+    LessThan,
+    GreaterThan,
+    And,
+    Or,
+    Comma,
+    Colon,
+    Range,
     Semicolon,
     LeftParen,
     RightParen,
     LeftBrace,
     RightBrace,
+    LeftBracket,
+    RightBracket,
 }
 
 impl fmt::Debug for Token {
@@ -46,28 +162,65 @@ impl fmt::Debug for Token {
         match self {
             Self::Identifier(data) => write!(f, "<identifier, {}>", data),
             Self::Integer(data) => write!(f, "<integer, {}>", data),
+            Self::Float(data) => write!(f, "<float, {:?}>", data),
             Self::Boolean(data) => write!(f, "<boolean, {}>", data),
+            Self::String(data) => write!(f, "<string, {}>", data),
+            Self::Null => write!(f, "<null, null>"),
             Self::Let => write!(f, "<let, let>"),
             Self::Fn => write!(f, "<fn, fn>"),
             Self::If => write!(f, "<if, if>"),
             Self::Else => write!(f, "<else, else>"),
+            Self::Return => write!(f, "<return, return>"),
+            Self::While => write!(f, "<while, while>"),
+            Self::For => write!(f, "<for, for>"),
+            Self::In => write!(f, "<in, in>"),
+            Self::Break => write!(f, "<break, break>"),
+            Self::Continue => write!(f, "<continue, continue>"),
+            Self::Try => write!(f, "<try, try>"),
+            Self::Catch => write!(f, "<catch, catch>"),
             Self::Plus => write!(f, "<+, +>"),
             Self::Minus => write!(f, "<-, ->"),
             Self::Star => write!(f, "<*, *>"),
             Self::Slash => write!(f, "</, />"),
+            Self::Bang => write!(f, "<!, !>"),
             Self::Assignment => write!(f, "<=, =>"),
             Self::Equals => write!(f, "<==, ==>"),
+            Self::NotEquals => write!(f, "<!=, !=>"),
+            Self::LessThan => write!(f, "<<, <>"),
+            Self::GreaterThan => write!(f, "<>, >>"),
+            Self::And => write!(f, "<&&, &&>"),
+            Self::Or => write!(f, "<||, ||>"),
+            Self::Comma => write!(f, "<,, ,>"),
+            Self::Colon => write!(f, "<:, :>"),
+            Self::Range => write!(f, "<.., ..>"),
             Self::Semicolon => write!(f, "<;, ;>"),
             Self::LeftParen => write!(f, "<(, (>"),
             Self::RightParen => write!(f, "<), )>"),
             Self::LeftBrace => write!(f, "<{{, {{>"),
             Self::RightBrace => write!(f, "<}}, }}>"),
+            Self::LeftBracket => write!(f, "<[, [>"),
+            Self::RightBracket => write!(f, "<], ]>"),
         }
     }
 }
 
+pub fn is_identifier_start(character: char) -> bool {
+    character.is_ascii_alphabetic() || character == '_'
+}
+
+pub fn is_identifier_part(character: char) -> bool {
+    character.is_ascii_alphanumeric() || character == '_'
+}
+
 struct Tokenizer {
     remaining_input: Vec<char>,
+    line: usize,
+    column: usize,
+    // How many characters of the input have been consumed.
+    position: usize,
+    comments: Vec<Comment>,
+    // Whether anything but whitespace came before the position on its line.
+    line_has_token: bool,
     punctuation_to_token: HashMap<String, Token>,
     keyword_to_token: HashMap<String, Token>,
 }
@@ -76,26 +229,51 @@ impl Tokenizer {
     fn new(input: &str) -> Self {
         Tokenizer {
             remaining_input: input.chars().collect(),
+            line: 1,
+            column: 1,
+            position: 0,
+            comments: vec![],
+            line_has_token: false,
             punctuation_to_token: HashMap::from([
                 (String::from("+"), Token::Plus),
                 (String::from("-"), Token::Minus),
                 (String::from("*"), Token::Star),
                 (String::from("/"), Token::Slash),
+                (String::from("!"), Token::Bang),
                 (String::from("="), Token::Assignment),
                 (String::from("=="), Token::Equals),
+                (String::from("!="), Token::NotEquals),
+                (String::from("<"), Token::LessThan),
+                (String::from(">"), Token::GreaterThan),
+                (String::from("&&"), Token::And),
+                (String::from("||"), Token::Or),
+                (String::from(","), Token::Comma),
+                (String::from(":"), Token::Colon),
+                (String::from(".."), Token::Range),
                 (String::from(";"), Token::Semicolon),
                 (String::from("("), Token::LeftParen),
                 (String::from(")"), Token::RightParen),
                 (String::from("{"), Token::LeftBrace),
                 (String::from("}"), Token::RightBrace),
+                (String::from("["), Token::LeftBracket),
+                (String::from("]"), Token::RightBracket),
             ]),
             keyword_to_token: HashMap::from([
                 (String::from("true"), Token::Boolean(true)),
                 (String::from("false"), Token::Boolean(false)),
+                (String::from("null"), Token::Null),
                 (String::from("let"), Token::Let),
                 (String::from("fn"), Token::Fn),
                 (String::from("if"), Token::If),
                 (String::from("else"), Token::Else),
+                (String::from("return"), Token::Return),
+                (String::from("while"), Token::While),
+                (String::from("for"), Token::For),
+                (String::from("in"), Token::In),
+                (String::from("break"), Token::Break),
+                (String::from("continue"), Token::Continue),
+                (String::from("try"), Token::Try),
+                (String::from("catch"), Token::Catch),
             ]),
         }
     }
@@ -103,41 +281,73 @@ impl Tokenizer {
     fn get_next_token(&mut self) -> Option<Token> {
         self.skip_whitespace();
 
-        if self.remaining_input.len() == 0 {
+        if self.remaining_input.is_empty() {
             return None;
         }
 
-        let token;
-        if self.remaining_input[0].is_ascii_alphabetic() {
-            token = self.chop_identifer_or_keyword_token();
-        } else if self.remaining_input[0].is_numeric() {
-            token = self.chop_integer_token();
-        } else if self.is_current_character_punctuation() {
-            token = self.chop_punctuation_token();
+        if is_identifier_start(self.remaining_input[0]) {
+            Some(self.chop_identifer_or_keyword_token())
+        } else if self.remaining_input[0].is_ascii_digit() {
+            self.chop_number_token()
+        } else if self.remaining_input[0] == '"' {
+            self.chop_string_token()
         } else {
-            return None;
+            self.chop_punctuation_token()
         }
+    }
 
-        Some(token)
+    fn span(&self) -> Span {
+        Span { line: self.line, column: self.column }
     }
 
+    fn consume(&mut self, length: usize) {
+        for &character in &self.remaining_input[..length] {
+            if character == '\n' {
+                self.line += 1;
+                self.column = 1;
+                self.line_has_token = false;
+            } else {
+                self.line_has_token = self.line_has_token || !character.is_ascii_whitespace();
+                self.column += 1;
+            }
+        }
+        self.position += length;
+        self.remaining_input = self.remaining_input[length..].to_vec();
+    }
+
+    // Comments count as whitespace, they are only kept aside for tools such as the formatter.
     fn skip_whitespace(&mut self) {
-        let mut idx = 0;
-        while idx < self.remaining_input.len() && self.remaining_input[idx].is_ascii_whitespace() {
-            idx += 1;
+        loop {
+            let mut idx = 0;
+            while idx < self.remaining_input.len() && self.remaining_input[idx].is_ascii_whitespace() {
+                idx += 1;
+            }
+            self.consume(idx);
+
+            if !self.remaining_input.starts_with(&['/', '/']) {
+                return;
+            }
+            let length = self.remaining_input.iter().position(|&character| character == '\n');
+            let length = length.unwrap_or(self.remaining_input.len());
+            let text: String = self.remaining_input[..length].iter().collect();
+            self.comments.push(Comment {
+                text: String::from(text.trim_end()),
+                span: self.span(),
+                is_trailing: self.line_has_token,
+            });
+            self.consume(length);
         }
-        self.remaining_input = self.remaining_input[idx..].to_vec();
     }
 
     fn chop_identifer_or_keyword_token(&mut self) -> Token {
         let mut idx = 0;
-        assert!(self.remaining_input[0].is_ascii_alphabetic());
-        while idx < self.remaining_input.len() && self.remaining_input[idx].is_ascii_alphanumeric() {
+        assert!(is_identifier_start(self.remaining_input[0]));
+        while idx < self.remaining_input.len() && is_identifier_part(self.remaining_input[idx]) {
             idx += 1;
         }
 
         let data_vector = self.remaining_input[..idx].to_vec();
-        self.remaining_input = self.remaining_input[idx..].to_vec();
+        self.consume(idx);
         let data: String = data_vector.into_iter().collect();
 
         match self.keyword_to_token.get(&data) {
@@ -146,39 +356,78 @@ impl Tokenizer {
         }
     }
 
-    fn chop_integer_token(&mut self) -> Token {
+    // Returns None for an integer literal too large for an INTEGER.
+    fn chop_number_token(&mut self) -> Option<Token> {
         let mut idx = 0;
-        while idx < self.remaining_input.len() && self.remaining_input[idx].is_numeric() {
+        while idx < self.remaining_input.len() && self.remaining_input[idx].is_ascii_digit() {
             idx += 1;
         }
 
-        let integer_data_vector = self.remaining_input[..idx].to_vec();
-        let integer_data_string: String = integer_data_vector.into_iter().collect();
-        let integer_data = integer_data_string.parse::<i32>().unwrap();
+        // A dot only starts a fraction when a digit follows, so `0..10` stays a range.
+        let is_float = idx + 1 < self.remaining_input.len()
+            && self.remaining_input[idx] == '.'
+            && self.remaining_input[idx + 1].is_ascii_digit();
+        if is_float {
+            idx += 1;
+            while idx < self.remaining_input.len() && self.remaining_input[idx].is_ascii_digit() {
+                idx += 1;
+            }
+        }
 
-        self.remaining_input = self.remaining_input[idx..].to_vec();
+        let number_data_vector = self.remaining_input[..idx].to_vec();
+        let number_data_string: String = number_data_vector.into_iter().collect();
 
-        Token::Integer(integer_data)
+        let token = match is_float {
+            true => Token::Float(number_data_string.parse::<f64>().ok()?),
+            false => Token::Integer(number_data_string.parse::<i32>().ok()?),
+        };
+        self.consume(idx);
+        Some(token)
     }
 
-    fn is_current_character_punctuation(&self) -> bool {
-        match self.punctuation_to_token.get(&self.remaining_input[0].to_string()) {
-            None => false,
-            _ => true,
+    // Returns None for an unterminated string literal.
+    fn chop_string_token(&mut self) -> Option<Token> {
+        assert!(self.remaining_input[0] == '"');
+        let mut data = String::new();
+        let mut idx = 1;
+        while idx < self.remaining_input.len() && self.remaining_input[idx] != '"' {
+            if self.remaining_input[idx] == '\\' && idx + 1 < self.remaining_input.len() {
+                idx += 1;
+                data.push(match self.remaining_input[idx] {
+                    'n' => '\n',
+                    't' => '\t',
+                    escaped => escaped,
+                });
+            } else {
+                data.push(self.remaining_input[idx]);
+            }
+            idx += 1;
+        }
+
+        if idx == self.remaining_input.len() {
+            return None;
         }
+        self.consume(idx + 1);
+
+        Some(Token::String(data))
     }
 
-    fn chop_punctuation_token(&mut self) -> Token {
-        let keyword_data;
-        if self.remaining_input.len() > 1 && self.remaining_input[0] == '=' && self.remaining_input[1] == '=' {
-            keyword_data = String::from("==");
-            self.remaining_input = self.remaining_input[2..].to_vec();
-        } else {
-            keyword_data = self.remaining_input[0].to_string();
-            self.remaining_input = self.remaining_input[1..].to_vec();
+    fn chop_punctuation_token(&mut self) -> Option<Token> {
+        // Two character punctuation such as `==` has to win over its one character prefix.
+        for length in [2, 1] {
+            if self.remaining_input.len() < length {
+                continue;
+            }
+
+            let punctuation_data: String = self.remaining_input[..length].iter().collect();
+            if let Some(punctuation_token) = self.punctuation_to_token.get(&punctuation_data) {
+                let punctuation_token = punctuation_token.clone();
+                self.consume(length);
+                return Some(punctuation_token);
+            }
         }
-        let punctuation_token = self.punctuation_to_token.get(&keyword_data).unwrap();
-        return (*punctuation_token).clone();
+
+        None
     }
 
 }
@@ -257,4 +506,216 @@ mod tests {
             ])
         )
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn it_works_on_logical_expression() {
+        let tokens = tokenize("!a && b != 1 || c < 2, d > 3");
+        assert_eq!(
+            tokens.iter().map(|token| format!("{:?}", token)).collect::<Vec<String>>(),
+            Vec::from([
+                "<!, !>",
+                "<identifier, a>",
+                "<&&, &&>",
+                "<identifier, b>",
+                "<!=, !=>",
+                "<integer, 1>",
+                "<||, ||>",
+                "<identifier, c>",
+                "<<, <>",
+                "<integer, 2>",
+                "<,, ,>",
+                "<identifier, d>",
+                "<>, >>",
+                "<integer, 3>",
+            ])
+        )
+    }
+
+    #[test]
+    fn it_works_on_while_statement() {
+        let tokens = tokenize("while (x) { x = false; }");
+        assert_eq!(
+            tokens.iter().map(|token| format!("{:?}", token)).collect::<Vec<String>>(),
+            Vec::from([
+                "<while, while>",
+                "<(, (>",
+                "<identifier, x>",
+                "<), )>",
+                "<{, {>",
+                "<identifier, x>",
+                "<=, =>",
+                "<boolean, false>",
+                "<;, ;>",
+                "<}, }>",
+            ])
+        )
+    }
+
+    #[test]
+    fn it_works_on_for_statement() {
+        let tokens = tokenize("for k, v in {1: [2]} { 0..10 }");
+        assert_eq!(
+            tokens.iter().map(|token| format!("{:?}", token)).collect::<Vec<String>>(),
+            Vec::from([
+                "<for, for>",
+                "<identifier, k>",
+                "<,, ,>",
+                "<identifier, v>",
+                "<in, in>",
+                "<{, {>",
+                "<integer, 1>",
+                "<:, :>",
+                "<[, [>",
+                "<integer, 2>",
+                "<], ]>",
+                "<}, }>",
+                "<{, {>",
+                "<integer, 0>",
+                "<.., ..>",
+                "<integer, 10>",
+                "<}, }>",
+            ])
+        )
+    }
+
+    #[test]
+    fn it_works_on_string_literals() {
+        let tokens = tokenize(r#"let s = "hello world" + "a \"b\"\n";"#);
+        assert_eq!(
+            tokens.iter().map(|token| format!("{:?}", token)).collect::<Vec<String>>(),
+            Vec::from([
+                "<let, let>",
+                "<identifier, s>",
+                "<=, =>",
+                "<string, hello world>",
+                "<+, +>",
+                "<string, a \"b\"\n>",
+                "<;, ;>",
+            ])
+        )
+    }
+
+    #[test]
+    fn it_works_on_float_literals() {
+        let tokens = tokenize("1.5 * 2.0 + 0..3");
+        assert_eq!(
+            tokens.iter().map(|token| format!("{:?}", token)).collect::<Vec<String>>(),
+            Vec::from([
+                "<float, 1.5>",
+                "<*, *>",
+                "<float, 2.0>",
+                "<+, +>",
+                "<integer, 0>",
+                "<.., ..>",
+                "<integer, 3>",
+            ])
+        )
+    }
+
+    #[test]
+    fn it_works_on_identifiers_with_underscores() {
+        let tokens = tokenize("let _read_file2 = read_file;");
+        assert_eq!(
+            tokens.iter().map(|token| format!("{:?}", token)).collect::<Vec<String>>(),
+            Vec::from([
+                "<let, let>",
+                "<identifier, _read_file2>",
+                "<=, =>",
+                "<identifier, read_file>",
+                "<;, ;>",
+            ])
+        )
+    }
+
+    #[test]
+    fn it_works_on_token_spans() {
+        let tokens = tokenize_with_spans("let s = \"a\nb\";\n  f(s)");
+        assert_eq!(
+            tokens.iter().map(|(token, span)| format!("{:?} {}", token, span)).collect::<Vec<String>>(),
+            Vec::from([
+                "<let, let> 1:1",
+                "<identifier, s> 1:5",
+                "<=, => 1:7",
+                "<string, a\nb> 1:9",
+                "<;, ;> 2:3",
+                "<identifier, f> 3:3",
+                "<(, (> 3:4",
+                "<identifier, s> 3:5",
+                "<), )> 3:6",
+            ])
+        )
+    }
+
+    #[test]
+    fn it_works_on_token_ranges() {
+        assert_eq!(
+            tokenize_with_ranges("let s = \"é\\\"\";  s=="),
+            Vec::from([
+                (Token::Let, 0..3),
+                (Token::Identifier(String::from("s")), 4..5),
+                (Token::Assignment, 6..7),
+                (Token::String(String::from("é\"")), 8..13),
+                (Token::Semicolon, 13..14),
+                (Token::Identifier(String::from("s")), 16..17),
+                (Token::Equals, 17..19),
+            ])
+        )
+    }
+
+    #[test]
+    fn it_reports_where_lexing_stopped() {
+        let (tokens, error) = tokenize_checked("let x = 1;\nx @ 2");
+        assert_eq!(tokens.len(), 6);
+        assert_eq!(
+            error,
+            Some(LexicalError {
+                message: String::from("unexpected character `@`"),
+                code: UNEXPECTED_CHARACTER,
+                span: Span { line: 2, column: 3 }
+            })
+        );
+        let error = tokenize_checked("puts(\"hi)").1.unwrap();
+        assert_eq!((error.message.as_str(), error.code), ("unterminated string", UNTERMINATED_STRING));
+        assert_eq!(
+            tokenize_checked("1 + 99999999999").1,
+            Some(LexicalError {
+                message: String::from("integer literal too large: 99999999999"),
+                code: INTEGER_TOO_LARGE,
+                span: Span { line: 1, column: 5 }
+            })
+        );
+        assert_eq!(tokenize_checked("2147483647").0.len(), 1);
+        assert_eq!(tokenize_checked("x = ٣").1.unwrap().message, "unexpected character `٣`");
+        assert_eq!(tokenize_checked("1 + 2 ").1, None);
+    }
+
+    #[test]
+    fn it_works_on_comments() {
+        let source = "// header\nlet x = 8 / 2; // halve  \n  // indented\nputs(\"// not a comment\")//end";
+        assert_eq!(
+            tokenize(source).iter().map(|token| format!("{:?}", token)).collect::<Vec<String>>(),
+            Vec::from([
+                "<let, let>",
+                "<identifier, x>",
+                "<=, =>",
+                "<integer, 8>",
+                "</, />",
+                "<integer, 2>",
+                "<;, ;>",
+                "<identifier, puts>",
+                "<(, (>",
+                "<string, // not a comment>",
+                "<), )>",
+            ]),
+        );
+        assert_eq!(
+            comments(source),
+            Vec::from([
+                Comment { text: String::from("// header"), span: Span { line: 1, column: 1 }, is_trailing: false },
+                Comment { text: String::from("// halve"), span: Span { line: 2, column: 16 }, is_trailing: true },
+                Comment { text: String::from("// indented"), span: Span { line: 3, column: 3 }, is_trailing: false },
+                Comment { text: String::from("//end"), span: Span { line: 4, column: 25 }, is_trailing: true },
+            ]),
+        );
+    }
+}
@@ -1 +1,38 @@
-mod lexical_analyzer;
+pub mod abstract_syntax_tree;
+pub mod bench;
+pub mod builtins;
+pub mod bytecode_file;
+pub mod cli;
+pub mod clock;
+pub mod code;
+pub mod compiler;
+pub mod completer;
+pub mod config;
+pub mod coverage;
+pub mod diagnostics;
+pub mod diff;
+pub mod documenter;
+pub mod environment;
+pub mod evaluator;
+pub mod formatter;
+pub mod garbage_collector;
+pub mod highlighter;
+pub mod hooks;
+pub mod interner;
+pub mod lexical_analyzer;
+pub mod line_editor;
+pub mod linter;
+pub mod minifier;
+pub mod object;
+pub mod optimizer;
+pub mod profiler;
+pub mod project;
+pub mod random;
+pub mod repl;
+pub mod symbol_table;
+pub mod syntax_analyzer;
+pub mod syntax_dump;
+pub mod terminal;
+pub mod tracer;
+pub mod vm;
+pub mod watcher;
@@ -0,0 +1,669 @@
+use std::fs::{self, OpenOptions};
+use std::io::{self, BufRead, Read, Write};
+use std::path::{Path, PathBuf};
+
+use crate::terminal::{Key, KeyReader, RawMode};
+
+// Previous lines, walked with Up and Down. Repeating the last line does not add it again.
+#[derive(Default)]
+pub struct History {
+    entries: Vec<String>,
+    // Where entries are appended as they are added, so they outlive the session.
+    file: Option<PathBuf>,
+    // How many entries to keep, dropping the oldest first.
+    limit: Option<usize>,
+}
+
+impl History {
+    pub fn new() -> Self {
+        History::default()
+    }
+
+    // Starts with the entries saved in `file`, one per line, if it exists yet.
+    pub fn load(file: &Path) -> Self {
+        let mut history = History::new();
+        if let Ok(contents) = fs::read_to_string(file) {
+            for line in contents.lines() {
+                history.add(line);
+            }
+        }
+        history.file = Some(file.to_path_buf());
+        history
+    }
+
+    // Keeps only the latest `limit` entries, in the file as well, so it does not grow forever.
+    pub fn with_limit(mut self, limit: usize) -> Self {
+        self.limit = Some(limit);
+        if self.entries.len() > limit {
+            self.entries.drain(..self.entries.len() - limit);
+            if let Some(file) = &self.file {
+                let contents: String = self.entries.iter().map(|entry| format!("{}\n", entry)).collect();
+                let _ = fs::write(file, contents);
+            }
+        }
+        self
+    }
+
+    pub fn add(&mut self, line: &str) {
+        if line.trim().is_empty() || self.entries.last().is_some_and(|last| last == line) {
+            return;
+        }
+        self.entries.push(String::from(line));
+        if self.limit.is_some_and(|limit| self.entries.len() > limit) {
+            self.entries.remove(0);
+        }
+
+        // Losing history is not worth interrupting the session over, so failing writes are ignored.
+        if let Some(file) = &self.file {
+            if let Ok(mut file) = OpenOptions::new().create(true).append(true).open(file) {
+                let _ = writeln!(file, "{}", line);
+            }
+        }
+    }
+
+    pub fn entries(&self) -> &[String] {
+        &self.entries
+    }
+}
+
+// The line being edited and where the cursor is in it, counted in characters.
+#[derive(Default)]
+struct LineBuffer {
+    characters: Vec<char>,
+    cursor: usize,
+}
+
+impl LineBuffer {
+    fn text(&self) -> String {
+        self.characters.iter().collect()
+    }
+
+    fn is_blank_before_cursor(&self) -> bool {
+        self.characters[..self.cursor].iter().all(|character| *character == ' ')
+    }
+
+    fn replace(&mut self, text: &str) {
+        self.characters = text.chars().collect();
+        self.cursor = self.characters.len();
+    }
+
+    fn insert(&mut self, text: &str) {
+        for character in text.chars() {
+            self.characters.insert(self.cursor, character);
+            self.cursor += 1;
+        }
+    }
+
+    // Removes and returns the characters between the two positions.
+    fn cut(&mut self, start: usize, end: usize) -> String {
+        self.cursor = start;
+        self.characters.drain(start..end).collect()
+    }
+
+    fn start_of_next_word(&self) -> usize {
+        let mut position = self.cursor;
+        while position < self.characters.len() && !self.characters[position].is_whitespace() {
+            position += 1;
+        }
+        while position < self.characters.len() && self.characters[position].is_whitespace() {
+            position += 1;
+        }
+        position
+    }
+
+    fn start_of_previous_word(&self) -> usize {
+        let mut position = self.cursor;
+        while position > 0 && self.characters[position - 1].is_whitespace() {
+            position -= 1;
+        }
+        while position > 0 && !self.characters[position - 1].is_whitespace() {
+            position -= 1;
+        }
+        position
+    }
+}
+
+// Where the REPL gets its lines from, the terminal when run for real.
+pub trait LineReader {
+    fn read_line(&mut self, prompt: &str) -> io::Result<ReadLine>;
+
+    // Reads a line that starts out holding `indent`, for continuing a block. Readers that cannot
+    // pre-fill a line read it as it comes.
+    fn read_indented_line(&mut self, prompt: &str, indent: &str) -> io::Result<ReadLine> {
+        let _ = indent;
+        self.read_line(prompt)
+    }
+
+    // Switches how keys edit the line, for readers that edit lines at all.
+    fn set_keybindings(&mut self, keybindings: Keybindings) {
+        let _ = keybindings;
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Keybindings {
+    Emacs,
+    // Lines start in insert mode, Escape switches to normal mode for moving and deleting with
+    // h, l, w, b, 0, $, x, D, C, dd, cc, p and P, recalling history with j and k, and back to
+    // inserting with i, a, I, A and s.
+    Vi,
+}
+
+impl Keybindings {
+    pub fn from_name(name: &str) -> Option<Keybindings> {
+        match name {
+            "emacs" => Some(Keybindings::Emacs),
+            "vi" => Some(Keybindings::Vi),
+            _ => None,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Keybindings::Emacs => "emacs",
+            Keybindings::Vi => "vi",
+        }
+    }
+}
+
+// Where a line edited with vi keybindings is at.
+#[derive(Default)]
+struct ViState {
+    is_normal: bool,
+    // The `d` or `c` waiting for a second one to act on the whole line.
+    operator: Option<char>,
+}
+
+// One level of indentation inside a block.
+pub const INDENT: &str = "  ";
+
+pub enum ReadLine {
+    Line(String),
+    // Ctrl-C, the line is thrown away.
+    Interrupted,
+    Eof,
+}
+
+// Decorates the line for display given the cursor position, it must not change the visible
+// characters.
+pub type Highlighter = dyn Fn(&str, usize) -> String;
+
+// What Tab offers: the candidates that could replace the line from `start` up to the cursor.
+pub struct Completion {
+    pub start: usize,
+    pub candidates: Vec<String>,
+}
+
+pub type CompletionSource = dyn Fn(&str, usize) -> Completion;
+
+// A small readline: moving with the arrows, Home/End and Ctrl-A/E/B/F, recalling history
+// with Up/Down, and Emacs style kill and yank with Ctrl-K/U/W/Y. Vi keybindings add a normal
+// mode on top.
+pub struct LineEditor {
+    history: History,
+    keybindings: Keybindings,
+    // The text removed by the last kill command, put back by Ctrl-Y.
+    kill_buffer: String,
+    highlighter: Option<Box<Highlighter>>,
+    completer: Option<Box<CompletionSource>>,
+}
+
+impl Default for LineEditor {
+    fn default() -> Self {
+        LineEditor::new()
+    }
+}
+
+impl LineEditor {
+    pub fn new() -> Self {
+        LineEditor {
+            history: History::new(),
+            keybindings: Keybindings::Emacs,
+            kill_buffer: String::new(),
+            highlighter: None,
+            completer: None,
+        }
+    }
+
+    pub fn with_history(mut self, history: History) -> Self {
+        self.history = history;
+        self
+    }
+
+    pub fn with_keybindings(mut self, keybindings: Keybindings) -> Self {
+        self.keybindings = keybindings;
+        self
+    }
+
+    pub fn with_completer(mut self, completer: impl Fn(&str, usize) -> Completion + 'static) -> Self {
+        self.completer = Some(Box::new(completer));
+        self
+    }
+
+    pub fn with_highlighter(mut self, highlighter: impl Fn(&str, usize) -> String + 'static) -> Self {
+        self.highlighter = Some(Box::new(highlighter));
+        self
+    }
+
+    pub fn history(&mut self) -> &mut History {
+        &mut self.history
+    }
+
+    // The editing loop itself, reading keys from `keys` and redrawing the line on `output`,
+    // which the prompt has already been written to.
+    pub fn edit_line<R: Read>(
+        &mut self,
+        prompt: &str,
+        keys: &mut KeyReader<R>,
+        output: &mut impl Write,
+    ) -> io::Result<ReadLine> {
+        self.edit_indented_line(prompt, "", keys, output)
+    }
+
+    // Like `edit_line`, with the line starting out as `indent`. Typing `}` right after the
+    // indentation takes one level of it back off.
+    pub fn edit_indented_line<R: Read>(
+        &mut self,
+        prompt: &str,
+        indent: &str,
+        keys: &mut KeyReader<R>,
+        output: &mut impl Write,
+    ) -> io::Result<ReadLine> {
+        let mut line = LineBuffer::default();
+        if !indent.is_empty() {
+            line.insert(indent);
+            self.redraw(prompt, &line, output)?;
+        }
+        // Up and Down move through the history, the line being typed is kept as the entry
+        // past the newest one.
+        let mut history_position = self.history.entries.len();
+        let mut draft = String::new();
+        let mut vi = ViState::default();
+
+        loop {
+            let key = match keys.read_key()? {
+                None if line.characters.is_empty() => return Ok(ReadLine::Eof),
+                None => Key::Enter,
+                Some(key) => key,
+            };
+            let key = match self.keybindings {
+                Keybindings::Emacs => key,
+                Keybindings::Vi => match self.vi_command(key, &mut line, &mut vi) {
+                    Some(key) => key,
+                    None => {
+                        self.redraw(prompt, &line, output)?;
+                        continue;
+                    }
+                },
+            };
+
+            match key {
+                Key::Enter => {
+                    write!(output, "\r\n")?;
+                    output.flush()?;
+                    let text = line.text();
+                    self.history.add(&text);
+                    return Ok(ReadLine::Line(text));
+                }
+                Key::Ctrl('c') => {
+                    write!(output, "^C\r\n")?;
+                    return Ok(ReadLine::Interrupted);
+                }
+                Key::Ctrl('d') if line.characters.is_empty() => {
+                    write!(output, "\r\n")?;
+                    return Ok(ReadLine::Eof);
+                }
+                Key::Char('}') if line.cursor >= INDENT.len() && line.is_blank_before_cursor() => {
+                    let cursor = line.cursor;
+                    line.cut(cursor - INDENT.len(), cursor);
+                    line.insert("}");
+                }
+                Key::Char(character) => line.insert(&character.to_string()),
+                Key::Backspace | Key::Ctrl('h') if line.cursor > 0 => {
+                    line.cut(line.cursor - 1, line.cursor);
+                }
+                Key::Delete | Key::Ctrl('d') if line.cursor < line.characters.len() => {
+                    let cursor = line.cursor;
+                    line.cut(cursor, cursor + 1);
+                }
+                Key::Left | Key::Ctrl('b') if line.cursor > 0 => line.cursor -= 1,
+                Key::Right | Key::Ctrl('f') if line.cursor < line.characters.len() => line.cursor += 1,
+                Key::Home | Key::Ctrl('a') => line.cursor = 0,
+                Key::End | Key::Ctrl('e') => line.cursor = line.characters.len(),
+                Key::Ctrl('k') => {
+                    let (cursor, end) = (line.cursor, line.characters.len());
+                    self.kill_buffer = line.cut(cursor, end);
+                }
+                Key::Ctrl('u') => {
+                    let cursor = line.cursor;
+                    self.kill_buffer = line.cut(0, cursor);
+                }
+                Key::Ctrl('w') => {
+                    let (start, cursor) = (line.start_of_previous_word(), line.cursor);
+                    self.kill_buffer = line.cut(start, cursor);
+                }
+                Key::Ctrl('y') => line.insert(&self.kill_buffer.clone()),
+                Key::Tab => self.complete(prompt, &mut line, output)?,
+                Key::Up | Key::Ctrl('p') if history_position > 0 => {
+                    if history_position == self.history.entries.len() {
+                        draft = line.text();
+                    }
+                    history_position -= 1;
+                    line.replace(&self.history.entries[history_position]);
+                }
+                Key::Down | Key::Ctrl('n') if history_position < self.history.entries.len() => {
+                    history_position += 1;
+                    match self.history.entries.get(history_position) {
+                        Some(entry) => line.replace(entry),
+                        None => line.replace(&draft),
+                    }
+                }
+                _ => continue,
+            }
+
+            self.redraw(prompt, &line, output)?;
+        }
+    }
+
+    // Handles the keys vi keybindings give their own meaning, and returns the key the rest of the
+    // editing loop should handle in their place, if any. Most normal mode commands stand for an
+    // Emacs style key.
+    fn vi_command(&mut self, key: Key, line: &mut LineBuffer, vi: &mut ViState) -> Option<Key> {
+        if !vi.is_normal {
+            if key != Key::Escape {
+                return Some(key);
+            }
+            vi.is_normal = true;
+            line.cursor = line.cursor.saturating_sub(1);
+            return None;
+        }
+
+        if let Some(operator) = vi.operator.take() {
+            if key == Key::Char(operator) {
+                let end = line.characters.len();
+                self.kill_buffer = line.cut(0, end);
+                vi.is_normal = operator == 'd';
+            }
+            return None;
+        }
+
+        let key = match key {
+            Key::Char('h') => Key::Left,
+            Key::Char('l' | ' ') => Key::Right,
+            Key::Char('0') => Key::Home,
+            Key::Char('$') => Key::End,
+            Key::Char('k') => Key::Up,
+            Key::Char('j') => Key::Down,
+            Key::Char('x') => Key::Delete,
+            Key::Char('D') => Key::Ctrl('k'),
+            Key::Char('P') => Key::Ctrl('y'),
+            Key::Char('p') => {
+                line.cursor = (line.cursor + 1).min(line.characters.len());
+                Key::Ctrl('y')
+            }
+            Key::Char('w') => {
+                line.cursor = line.start_of_next_word();
+                return None;
+            }
+            Key::Char('b') => {
+                line.cursor = line.start_of_previous_word();
+                return None;
+            }
+            Key::Char(operator @ ('d' | 'c')) => {
+                vi.operator = Some(operator);
+                return None;
+            }
+            Key::Char(command @ ('i' | 'a' | 'I' | 'A' | 'C' | 's')) => {
+                vi.is_normal = false;
+                match command {
+                    'a' => Key::Right,
+                    'I' => Key::Home,
+                    'A' => Key::End,
+                    'C' => Key::Ctrl('k'),
+                    's' => Key::Delete,
+                    _ => return None,
+                }
+            }
+            // Anything else typed in normal mode is not inserted.
+            Key::Char(_) | Key::Tab => return None,
+            key => key,
+        };
+        Some(key)
+    }
+
+    // Fills in as much as all the candidates have in common, and lists them when that adds
+    // nothing.
+    fn complete(&self, prompt: &str, line: &mut LineBuffer, output: &mut impl Write) -> io::Result<()> {
+        let completion = match &self.completer {
+            Some(completer) => completer(&line.text(), line.cursor),
+            None => return Ok(()),
+        };
+        let typed: String = line.characters[completion.start..line.cursor].iter().collect();
+
+        let mut common = match completion.candidates.first() {
+            Some(candidate) => candidate.clone(),
+            None => return Ok(()),
+        };
+        for candidate in &completion.candidates[1..] {
+            while !candidate.starts_with(&common) {
+                common.pop();
+            }
+        }
+
+        if common.chars().count() > typed.chars().count() {
+            let cursor = line.cursor;
+            line.cut(completion.start, cursor);
+            line.insert(&common);
+        } else if completion.candidates.len() > 1 {
+            write!(output, "\r\n{}\r\n", completion.candidates.join("  "))?;
+        }
+        self.redraw(prompt, line, output)
+    }
+
+    fn redraw(&self, prompt: &str, line: &LineBuffer, output: &mut impl Write) -> io::Result<()> {
+        let text = match &self.highlighter {
+            Some(highlighter) => highlighter(&line.text(), line.cursor),
+            None => line.text(),
+        };
+
+        // Back to the start of the row, the whole line again, clear whatever the previous
+        // version left behind, then step back to the cursor.
+        write!(output, "\r{}{}\x1b[K", prompt, text)?;
+        let after_cursor = line.characters.len() - line.cursor;
+        if after_cursor > 0 {
+            write!(output, "\x1b[{}D", after_cursor)?;
+        }
+        output.flush()
+    }
+}
+
+impl LineReader for LineEditor {
+    // Edits a line on the terminal, or reads a plain line when standard input is not one or
+    // it cannot be switched to reading single keys.
+    fn read_line(&mut self, prompt: &str) -> io::Result<ReadLine> {
+        self.read_indented_line(prompt, "")
+    }
+
+    fn set_keybindings(&mut self, keybindings: Keybindings) {
+        self.keybindings = keybindings;
+    }
+
+    // Piped input brings its own indentation, so only the terminal gets it pre-filled.
+    fn read_indented_line(&mut self, prompt: &str, indent: &str) -> io::Result<ReadLine> {
+        let mut stdout = io::stdout();
+        write!(stdout, "{}", prompt)?;
+        stdout.flush()?;
+
+        let raw_mode = match crate::terminal::is_interactive() {
+            true => RawMode::enable(),
+            false => None,
+        };
+        if raw_mode.is_none() {
+            let mut line = String::new();
+            if io::stdin().lock().read_line(&mut line)? == 0 {
+                return Ok(ReadLine::Eof);
+            }
+            let line = line.trim_end_matches(['\n', '\r']);
+            self.history.add(line);
+            return Ok(ReadLine::Line(String::from(line)));
+        }
+
+        let result = self.edit_indented_line(prompt, indent, &mut KeyReader::new(io::stdin()), &mut stdout);
+        drop(raw_mode);
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn edit(editor: &mut LineEditor, input: &str) -> String {
+        let mut keys = KeyReader::new(input.as_bytes());
+        let mut output = vec![];
+        match editor.edit_line(">> ", &mut keys, &mut output).unwrap() {
+            ReadLine::Line(line) => line,
+            ReadLine::Interrupted => String::from("<interrupted>"),
+            ReadLine::Eof => String::from("<eof>"),
+        }
+    }
+
+    #[test]
+    fn it_inserts_and_deletes_at_the_cursor() {
+        let mut editor = LineEditor::new();
+        assert_eq!(edit(&mut editor, "let x = 5\r"), "let x = 5");
+        assert_eq!(edit(&mut editor, "lt\x1b[Dex\x1b[C = 1\r"), "lext = 1");
+        assert_eq!(edit(&mut editor, "abc\x7f\x7fd\r"), "ad");
+        assert_eq!(edit(&mut editor, "abc\x1b[H\x1b[3~\x1b[Fd\r"), "bcd");
+        assert_eq!(edit(&mut editor, "bc\x01a\x05d\r"), "abcd");
+    }
+
+    #[test]
+    fn it_kills_and_yanks() {
+        let mut editor = LineEditor::new();
+        assert_eq!(edit(&mut editor, "hello world\x01\x06\x06\x06\x06\x06\x0b\x19\x19\r"), "hello world world");
+        assert_eq!(edit(&mut editor, "let x = 1\x15puts(x)\r"), "puts(x)");
+        assert_eq!(edit(&mut editor, "fn(a, b) \x17{\x19\r"), "fn(a, {b) ");
+    }
+
+    #[test]
+    fn it_starts_indented_lines_with_their_indentation() {
+        let mut editor = LineEditor::new();
+        let mut indented = |input: &str, indent: &str| {
+            let mut output = vec![];
+            match editor.edit_indented_line(".. ", indent, &mut KeyReader::new(input.as_bytes()), &mut output) {
+                Ok(ReadLine::Line(line)) => line,
+                _ => String::from("<no line>"),
+            }
+        };
+        assert_eq!(indented("x * 2\r", "    "), "    x * 2");
+        assert_eq!(indented("}\r", "    "), "  }");
+        assert_eq!(indented("}\r", ""), "}");
+        assert_eq!(indented("a}\r", "  "), "  a}");
+        assert_eq!(indented("\x01}\r", "  "), "}  ");
+        assert_eq!(indented("\r", "  "), "  ");
+    }
+
+    #[test]
+    fn it_edits_with_vi_keybindings() {
+        let mut editor = LineEditor::new().with_keybindings(Keybindings::Vi);
+        assert_eq!(edit(&mut editor, "let x = 5\r"), "let x = 5");
+        assert_eq!(edit(&mut editor, "let x = 5\x1bhhhhxiy\r"), "let y = 5");
+        assert_eq!(edit(&mut editor, "one two three\x1b0wD\r"), "one ");
+        assert_eq!(edit(&mut editor, "one two\x1bbbsO\x1bwa!\r"), "One t!wo");
+        assert_eq!(edit(&mut editor, "abc\x1bddihello\x1b0P\r"), "abchello");
+        assert_eq!(edit(&mut editor, "puts(1)\x1bccputs(2)\r"), "puts(2)");
+        assert_eq!(edit(&mut editor, "\x1bkk\r"), "abchello");
+        assert_eq!(edit(&mut editor, "x\x1bIlet \x1bAy\r"), "let xy");
+    }
+
+    #[test]
+    fn it_walks_the_history() {
+        let mut editor = LineEditor::new();
+        edit(&mut editor, "first\r");
+        edit(&mut editor, "second\r");
+        edit(&mut editor, "   \r");
+
+        assert_eq!(edit(&mut editor, "\x1b[A\r"), "second");
+        assert_eq!(edit(&mut editor, "\x1b[A\x1b[A\x1b[A\x1b[A!\r"), "first!");
+        assert_eq!(edit(&mut editor, "draft\x1b[A\x1b[B\x1b[B\r"), "draft");
+        assert_eq!(editor.history().entries(), ["first", "second", "first!", "draft"]);
+    }
+
+    #[test]
+    fn it_keeps_the_history_in_a_file() {
+        let file = std::env::temp_dir().join(format!("monkey_history_test_{}", std::process::id()));
+        let _ = fs::remove_file(&file);
+
+        let mut editor = LineEditor::new().with_history(History::load(&file));
+        edit(&mut editor, "let x = 1\r");
+        edit(&mut editor, "let x = 1\r");
+        edit(&mut editor, "x\r");
+        assert_eq!(fs::read_to_string(&file).unwrap(), "let x = 1\nx\n");
+
+        let mut editor = LineEditor::new().with_history(History::load(&file));
+        assert_eq!(editor.history().entries(), ["let x = 1", "x"]);
+        assert_eq!(edit(&mut editor, "\x1b[A\x1b[A\r"), "let x = 1");
+        fs::remove_file(&file).unwrap();
+    }
+
+    #[test]
+    fn it_keeps_only_the_latest_entries() {
+        let file = std::env::temp_dir().join(format!("monkey_history_limit_test_{}", std::process::id()));
+        fs::write(&file, "a\nb\nc\n").unwrap();
+
+        let mut history = History::load(&file).with_limit(2);
+        assert_eq!(history.entries(), ["b", "c"]);
+        assert_eq!(fs::read_to_string(&file).unwrap(), "b\nc\n");
+        history.add("d");
+        assert_eq!(history.entries(), ["c", "d"]);
+        fs::remove_file(&file).unwrap();
+    }
+
+    #[test]
+    fn it_stops_at_the_end_of_the_input() {
+        let mut editor = LineEditor::new();
+        assert_eq!(edit(&mut editor, ""), "<eof>");
+        assert_eq!(edit(&mut editor, "\x04"), "<eof>");
+        assert_eq!(edit(&mut editor, "ab\x02\x04\r"), "a");
+        assert_eq!(edit(&mut editor, "unfinished"), "unfinished");
+    }
+
+    #[test]
+    fn it_cancels_the_line_on_ctrl_c() {
+        let mut editor = LineEditor::new();
+        assert_eq!(edit(&mut editor, "let x = \x03"), "<interrupted>");
+        assert_eq!(edit(&mut editor, "\x1b[A\r"), "");
+        assert!(editor.history().entries().is_empty());
+    }
+
+    #[test]
+    fn it_completes_the_word_before_the_cursor() {
+        let words = ["let", "len", "length", "puts"];
+        let mut editor = LineEditor::new().with_completer(move |line, cursor| {
+            let start = line[..cursor].rfind(' ').map_or(0, |space| space + 1);
+            let candidates = words.iter().filter(|word| word.starts_with(&line[start..cursor]));
+            Completion { start, candidates: candidates.map(|word| word.to_string()).collect() }
+        });
+        assert_eq!(edit(&mut editor, "pu\t(1)\r"), "puts(1)");
+        assert_eq!(edit(&mut editor, "x le\tng\t\r"), "x length");
+        assert_eq!(edit(&mut editor, "len\t\r"), "len");
+        assert_eq!(edit(&mut editor, "z\t\r"), "z");
+
+        let mut keys = KeyReader::new("le\t\r".as_bytes());
+        let mut output = vec![];
+        editor.edit_line(">> ", &mut keys, &mut output).unwrap();
+        assert!(String::from_utf8(output).unwrap().contains("\r\nlet  len  length\r\n"));
+    }
+
+    #[test]
+    fn it_redraws_through_the_highlighter() {
+        let mut editor = LineEditor::new().with_highlighter(|line, cursor| format!("<{}|{}>", line, cursor));
+        let mut output = vec![];
+        editor.edit_line(">> ", &mut KeyReader::new("ab\x1b[D".as_bytes()), &mut output).unwrap();
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            "\r>> <a|1>\x1b[K\r>> <ab|2>\x1b[K\r>> <ab|1>\x1b[K\x1b[1D\r\n"
+        );
+    }
+}
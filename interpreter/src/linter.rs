@@ -0,0 +1,280 @@
+use std::collections::HashSet;
+use std::vec;
+
+use crate::abstract_syntax_tree::{BlockStatement, Expression, Program, Statement};
+use crate::diagnostics::{UNREACHABLE_CODE, UNUSED_PARAMETER, UNUSED_VARIABLE};
+use crate::interner::Symbol;
+use crate::lexical_analyzer::{tokenize_with_spans, Span, Token};
+use crate::syntax_analyzer::{parse_with_marks, SyntaxError};
+
+// Something in a program that is likely a mistake, though the program runs.
+#[derive(Debug, PartialEq)]
+pub struct Warning {
+    pub message: String,
+    pub code: &'static str,
+    pub span: Span,
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum Kind {
+    Let,
+    Parameter,
+    // The variables of `for` and `catch`, which there is no way to leave out.
+    Loop,
+}
+
+struct Binding {
+    name: Symbol,
+    span: Span,
+    kind: Kind,
+}
+
+// A program, function body, loop body or `catch` block, which each have variables of their own.
+#[derive(Default)]
+struct Scope {
+    bindings: Vec<Binding>,
+    reads: HashSet<Symbol>,
+}
+
+// The lets and parameters that are never read, and the statements that come after a `return`,
+// `break` or `continue` in the same block. Names starting with `_` are taken to be unused on
+// purpose, and so are the top-level `test_` and `bench_` functions, which the runners call.
+pub fn lint(source: &str) -> Result<Vec<Warning>, Vec<SyntaxError>> {
+    let (program, marks) = parse_with_marks(source)?;
+
+    // The syntax tree has no spans for names, so they are taken from the tokens. Every `let` is a
+    // `let` statement and every `fn` a function literal, and they come in the same order as in the
+    // tree.
+    let tokens = tokenize_with_spans(source);
+    let (mut names, mut parameters) = (vec![], vec![]);
+    for (index, (token, _)) in tokens.iter().enumerate() {
+        match token {
+            Token::Let => names.push(tokens[index + 1].1),
+            Token::Fn => parameters.push(
+                tokens[index + 1..]
+                    .iter()
+                    .take_while(|(token, _)| *token != Token::RightParen)
+                    .filter(|(token, _)| matches!(token, Token::Identifier(_)))
+                    .map(|(_, span)| *span)
+                    .collect(),
+            ),
+            _ => {}
+        }
+    }
+
+    let mut linter = Linter {
+        marks: marks.into_iter(),
+        names: names.into_iter(),
+        parameters: parameters.into_iter(),
+        scopes: vec![],
+        warnings: vec![],
+    };
+    linter.program(&program);
+    let mut warnings = linter.warnings;
+    warnings.sort_by_key(|warning| (warning.span.line, warning.span.column));
+    Ok(warnings)
+}
+
+// Walks the syntax tree in source order, taking the spans as it goes.
+struct Linter {
+    // Where statements and blocks start and end, see `parse_with_marks`.
+    marks: vec::IntoIter<Span>,
+    names: vec::IntoIter<Span>,
+    parameters: vec::IntoIter<Vec<Span>>,
+    scopes: Vec<Scope>,
+    warnings: Vec<Warning>,
+}
+
+impl Linter {
+    fn program(&mut self, program: &Program) {
+        self.scopes.push(Scope::default());
+        self.statements(&program.statements);
+        self.close_scope(true);
+    }
+
+    fn mark(&mut self) -> Span {
+        self.marks.next().unwrap_or_default()
+    }
+
+    fn statements(&mut self, statements: &[Statement]) {
+        // Only the first statement that cannot be reached is worth a warning.
+        let (mut exit, mut is_reported) = (None, false);
+        for statement in statements {
+            let start = self.mark();
+            if let Some(keyword) = exit.filter(|_| !is_reported) {
+                self.warn(UNREACHABLE_CODE, format!("unreachable code after `{}`", keyword), start);
+                is_reported = true;
+            }
+            match statement {
+                Statement::Let(name, value) => {
+                    let span = self.names.next().unwrap_or(start);
+                    self.expression(value);
+                    self.declare(*name, span, Kind::Let);
+                }
+                Statement::Return(value) => {
+                    self.expression(value);
+                    exit = exit.or(Some("return"));
+                }
+                Statement::Break => exit = exit.or(Some("break")),
+                Statement::Continue => exit = exit.or(Some("continue")),
+                Statement::Expression(value) => self.expression(value),
+            }
+            self.mark();
+        }
+    }
+
+    fn block(&mut self, block: &BlockStatement) {
+        self.mark();
+        self.statements(&block.statements);
+        self.mark();
+    }
+
+    fn expression(&mut self, expression: &Expression) {
+        match expression {
+            Expression::Identifier(name) => {
+                if let Some(scope) = self.scopes.last_mut() {
+                    scope.reads.insert(*name);
+                }
+            }
+            Expression::Integer(_)
+            | Expression::Float(_)
+            | Expression::Boolean(_)
+            | Expression::String(_)
+            | Expression::Null => {}
+            Expression::Array(elements) => elements.iter().for_each(|element| self.expression(element)),
+            Expression::Hash(pairs) => {
+                for (key, value) in pairs {
+                    self.expression(key);
+                    self.expression(value);
+                }
+            }
+            // Assigning to a variable is not reading it.
+            Expression::Prefix(_, right) | Expression::Assign(_, right) => self.expression(right),
+            Expression::Infix(_, left, right) | Expression::Index(left, right) => {
+                self.expression(left);
+                self.expression(right);
+            }
+            Expression::If { condition, consequence, alternative } => {
+                self.expression(condition);
+                self.block(consequence);
+                if let Some(alternative) = alternative {
+                    self.block(alternative);
+                }
+            }
+            Expression::While { condition, body } => {
+                self.expression(condition);
+                self.block(body);
+            }
+            Expression::For { variables, iterable, body } => {
+                self.expression(iterable);
+                self.scopes.push(Scope::default());
+                variables.iter().for_each(|variable| self.declare(*variable, Span::default(), Kind::Loop));
+                self.block(body);
+                self.close_scope(false);
+            }
+            Expression::Try { body, variable, handler } => {
+                self.block(body);
+                self.scopes.push(Scope::default());
+                self.declare(*variable, Span::default(), Kind::Loop);
+                self.block(handler);
+                self.close_scope(false);
+            }
+            Expression::Function { parameters, body } => {
+                let spans = self.parameters.next().unwrap_or_default();
+                self.scopes.push(Scope::default());
+                for (index, parameter) in parameters.iter().enumerate() {
+                    let span = spans.get(index).copied().unwrap_or_default();
+                    self.declare(*parameter, span, Kind::Parameter);
+                }
+                self.block(body);
+                self.close_scope(false);
+            }
+            Expression::Call { function, arguments, .. } => {
+                self.expression(function);
+                arguments.iter().for_each(|argument| self.expression(argument));
+            }
+        }
+    }
+
+    fn declare(&mut self, name: Symbol, span: Span, kind: Kind) {
+        if let Some(scope) = self.scopes.last_mut() {
+            scope.bindings.push(Binding { name, span, kind });
+        }
+    }
+
+    // Only now that all of the scope's variables are known can the names read in it be told apart
+    // from those of the scopes around it, which a function may read before they are defined. A
+    // variable defined twice counts as read when either is.
+    fn close_scope(&mut self, is_top_level: bool) {
+        let Some(scope) = self.scopes.pop() else {
+            return;
+        };
+        for binding in &scope.bindings {
+            let name = binding.name.name();
+            let is_runner_function = is_top_level && (name.starts_with("test_") || name.starts_with("bench_"));
+            if scope.reads.contains(&binding.name) || name.starts_with('_') || is_runner_function {
+                continue;
+            }
+            match binding.kind {
+                Kind::Let => self.warn(UNUSED_VARIABLE, format!("unused variable `{}`", name), binding.span),
+                Kind::Parameter => self.warn(UNUSED_PARAMETER, format!("unused parameter `{}`", name), binding.span),
+                Kind::Loop => {}
+            }
+        }
+        if let Some(outer) = self.scopes.last_mut() {
+            let declared: HashSet<Symbol> = scope.bindings.iter().map(|binding| binding.name).collect();
+            outer.reads.extend(scope.reads.difference(&declared));
+        }
+    }
+
+    fn warn(&mut self, code: &'static str, message: String, span: Span) {
+        self.warnings.push(Warning { message, code, span });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn messages(source: &str) -> Vec<String> {
+        lint(source).unwrap().iter().map(|warning| format!("{}: {}", warning.span, warning.message)).collect()
+    }
+
+    #[test]
+    fn it_reports_unused_variables_and_parameters() {
+        let source = "\
+let unused = 1;
+let add = fn(a, b, _c) { let sum = a; sum };
+let later = fn() { helper() };
+let helper = fn() { 2 };
+for k, v in {1: 2} { puts(v) }
+let test_add = fn() { add(1, 2) };
+let _ignored = later();
+";
+        assert_eq!(messages(source), vec!["1:5: unused variable `unused`", "2:17: unused parameter `b`"]);
+        assert_eq!(messages("let f = fn(x) { let x = 1; x };"), vec!["1:5: unused variable `f`"]);
+        assert_eq!(messages("let n = 0; n = 1;"), vec!["1:5: unused variable `n`"]);
+    }
+
+    #[test]
+    fn it_reports_unreachable_code() {
+        let source = "\
+let f = fn(x) {
+  while (true) { break; puts(1) }
+  return x;
+  puts(2);
+  puts(3);
+};
+f(1)
+";
+        assert_eq!(
+            messages(source),
+            vec!["2:25: unreachable code after `break`", "4:3: unreachable code after `return`"]
+        );
+    }
+
+    #[test]
+    fn it_reports_syntax_errors() {
+        assert!(lint("let = 1").is_err());
+    }
+}
@@ -1,21 +1,1098 @@
-mod lexical_analyzer;
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::cell::RefCell;
+use std::io::{self, IsTerminal, Read, Write};
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+use std::rc::Rc;
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::OnceLock;
+use std::time::Duration;
 
-use std::io;
-use std::io::Write;
+use interpreter::bench::{change, format_baseline, measure, parse_baseline, Baseline};
+use interpreter::bytecode_file::{deserialize, serialize, EXTENSION};
+use interpreter::cli::{help, verbose_version, version, Arguments, Command, Script};
+use interpreter::clock::Clock;
+use interpreter::compiler::Bytecode;
+use interpreter::config::Config;
+use interpreter::coverage::{html as coverage_html, summary as coverage_summary, CoverageHook, Executed, FileCoverage};
+use interpreter::diagnostics::{explain, with_code, Report, Severity, DIAGNOSTICS};
+use interpreter::diff::unified_diff;
+use interpreter::documenter::{html, markdown};
+use interpreter::evaluator::{Evaluator, Limit};
+use interpreter::highlighter::{highlight, highlight_ansi, highlight_html};
+use interpreter::line_editor::{History, LineEditor};
+use interpreter::object::Object;
+use interpreter::profiler::{Profile, Profiler};
+use interpreter::project::load_order;
+use interpreter::repl::{
+    banner, check_program, compile_program, document_program, dump_syntax, format_program, lint_program,
+    list_statements, list_tokens, minify_program, parse_marked_program, skip_shebang, Mode, Repl, ScriptError,
+    TestOutcome,
+};
+use interpreter::syntax_dump::DumpFormat;
+use interpreter::terminal::{self, color_code, ColorChoice, CLEAR_SCREEN, RESET};
+use interpreter::tracer::{TraceMode, Tracer};
+use interpreter::watcher::Watcher;
 
-fn main() {
-    println!("Welcome to monkey interpreter!\n");
+// Deep recursion in a script has to reach the evaluator's depth limit and come back as an error,
+// the main thread's stack is too small for that without optimizations.
+const STACK_SIZE: usize = 64 * 1024 * 1024;
 
+// What the exit code says went wrong. A program that does not parse is bad input data in the
+// sense of sysexits.h, which is where 65 comes from.
+const RUNTIME_ERROR: u8 = 1;
+const USAGE_ERROR: u8 = 2;
+const PARSE_ERROR: u8 = 65;
+// A program stopped by `--timeout` exits the way one stopped by timeout(1) does.
+const TIMED_OUT: u8 = 124;
+
+// The settings file of a project, next to its scripts.
+const PROJECT_CONFIG: &str = "monkey.toml";
+
+// The flags that override a `[limits]` setting, and which one.
+const LIMIT_FLAGS: [(&str, &str); 5] = [
+    ("--limit-depth", "limits.max_depth"),
+    ("--limit-size", "limits.max_size"),
+    ("--limit-steps", "limits.steps"),
+    ("--limit-time", "limits.timeout"),
+    ("--max-memory", "limits.max_memory"),
+];
+
+// How often `--watch` looks at whether the script changed.
+const WATCH_INTERVAL: Duration = Duration::from_millis(250);
+
+// Where `--profile` saves the stacks unless it is given a file, and how many functions it lists.
+const PROFILE_FILE: &str = "profile.folded";
+const PROFILE_ROWS: usize = 10;
+
+// What `random()` starts from in `monkey test` unless `[test] seed` or `--seed` says otherwise, and
+// how many seconds the clock of `[test] fake_clock` moves on each reading.
+const TEST_SEED: u64 = 42;
+const FAKE_CLOCK_STEP: f64 = 0.001;
+
+// Where `monkey test --coverage` saves its report unless it is given a file.
+const COVERAGE_FILE: &str = "coverage.html";
+
+// How long `monkey bench` runs each benchmark for, after a first call to warm up.
+const BENCH_SECONDS: f64 = 1.0;
+
+// Counts allocations for `monkey bench` and otherwise leaves them to the system allocator.
+struct CountingAllocator;
+
+static ALLOCATIONS: AtomicU64 = AtomicU64::new(0);
+
+// The bytes allocated and not yet freed, for `--max-memory`.
+static HEAP_IN_USE: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        HEAP_IN_USE.fetch_add(layout.size(), Ordering::Relaxed);
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, pointer: *mut u8, layout: Layout) {
+        HEAP_IN_USE.fetch_sub(layout.size(), Ordering::Relaxed);
+        System.dealloc(pointer, layout)
+    }
+
+    unsafe fn realloc(&self, pointer: *mut u8, layout: Layout, size: usize) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        HEAP_IN_USE.fetch_add(size, Ordering::Relaxed);
+        HEAP_IN_USE.fetch_sub(layout.size(), Ordering::Relaxed);
+        System.realloc(pointer, layout, size)
+    }
+}
+
+#[global_allocator]
+static ALLOCATOR: CountingAllocator = CountingAllocator;
+
+// What `--color` asked for, which everything that colors its output goes by.
+static COLOR_CHOICE: OnceLock<ColorChoice> = OnceLock::new();
+
+// Whether `--error-format json` asked for errors and warnings as JSON.
+static JSON_ERRORS: OnceLock<bool> = OnceLock::new();
+
+fn main() -> ExitCode {
+    let interpreter = std::thread::Builder::new().stack_size(STACK_SIZE).spawn(run).unwrap();
+    interpreter.join().unwrap_or(ExitCode::FAILURE)
+}
+
+fn run() -> ExitCode {
+    let arguments = match Arguments::parse(std::env::args().skip(1)) {
+        Ok(arguments) => arguments,
+        Err(usage) => {
+            eprintln!("{}", usage);
+            return ExitCode::from(USAGE_ERROR);
+        }
+    };
+    let color_choice = arguments.value("--color").unwrap_or("auto");
+    match ColorChoice::from_name(color_choice) {
+        Some(choice) => COLOR_CHOICE.get_or_init(|| choice),
+        None => {
+            eprintln!("unknown --color: {} (expected auto, always or never)", color_choice);
+            return ExitCode::from(USAGE_ERROR);
+        }
+    };
+    match arguments.value("--error-format").unwrap_or("text") {
+        "text" => JSON_ERRORS.get_or_init(|| false),
+        "json" => JSON_ERRORS.get_or_init(|| true),
+        format => {
+            eprintln!("unknown --error-format: {} (expected text or json)", format);
+            return ExitCode::from(USAGE_ERROR);
+        }
+    };
+    let config = match configure(&arguments) {
+        Ok(config) => config,
+        Err(error) => {
+            eprintln!("{}", error);
+            return ExitCode::from(USAGE_ERROR);
+        }
+    };
+    match &arguments.command {
+        Command::Help(subcommand) => {
+            print_line(&help(*subcommand));
+            ExitCode::SUCCESS
+        }
+        Command::Version => {
+            let description = match arguments.has("--verbose") {
+                true => verbose_version(),
+                false => version(),
+            };
+            print_line(&description);
+            ExitCode::SUCCESS
+        }
+        Command::Format { files } => format_files(files, arguments.has("--write"), arguments.has("--check")),
+        Command::Check { files } => check_files(files),
+        Command::Lint { paths } => lint_files(paths, arguments.has("--deny-warnings")),
+        Command::Test { paths } => run_tests(paths, &arguments, &config),
+        Command::Bench { paths } => run_benchmarks(paths, &arguments, &config),
+        Command::Ast { file } => print_syntax_tree(file, arguments.value("--format").unwrap_or("tree")),
+        Command::Compile { file } => compile_file(file, arguments.value("--output"), arguments.has("--optimize")),
+        Command::Disassemble { file } => print_disassembly(file, arguments.has("--optimize")),
+        Command::Minify { file } => print_minified(file),
+        Command::Highlight { file } => print_highlighted(file, arguments.value("--format").unwrap_or("html")),
+        Command::Explain { code } => print_explanation(code.as_deref()),
+        Command::Doc { paths } => print_documentation(paths, arguments.value("--format").unwrap_or("markdown")),
+        Command::Run { script: Script::File(path), .. } if is_compiled(path) => run_compiled(path, &arguments, &config),
+        Command::Run { script, .. } if arguments.has("--watch") => watch(script, &arguments, &config),
+        Command::Run { script, .. } => interpret(&arguments, config, Some(script)),
+        Command::Repl => interpret(&arguments, config, None),
+    }
+}
+
+// What `load_config` reads, with the limits the flags set in place of the ones it says.
+fn configure(arguments: &Arguments) -> Result<Config, String> {
+    let mut config = load_config()?;
+    for (flag, key) in LIMIT_FLAGS {
+        if let Some(Err(error)) = arguments.value(flag).map(|value| config.set_from_flag(key, value)) {
+            return Err(format!("{}: {}", flag, error));
+        }
+    }
+    if let Some(seed) = arguments.value("--seed").filter(|_| seed(arguments).is_none()) {
+        return Err(format!("--seed: cannot read seed `{}` (expected a whole number)", seed));
+    }
+    Ok(config)
+}
+
+// What `--seed` was given, once `configure` made sure it is a number.
+fn seed(arguments: &Arguments) -> Option<u64> {
+    arguments.value("--seed").and_then(|seed| seed.replace('_', "").parse().ok())
+}
+
+// Where a project's modules are looked for besides its source directories: the `--include`
+// directories, then those in `MONKEY_PATH`.
+fn search_path(arguments: &Arguments) -> Vec<PathBuf> {
+    let mut directories: Vec<PathBuf> = arguments.values("--include").into_iter().map(PathBuf::from).collect();
+    if let Some(path) = std::env::var_os("MONKEY_PATH") {
+        directories.extend(std::env::split_paths(&path).filter(|directory| !directory.as_os_str().is_empty()));
+    }
+    directories
+}
+
+// Runs the script, or the REPL when there is none.
+fn interpret(arguments: &Arguments, config: Config, script: Option<&Script>) -> ExitCode {
+    // A project runs as its entry point, with the modules it depends on run before it.
+    let (entry, modules);
+    let script = match script {
+        Some(Script::Project) => match load_order(&config, Path::new(""), &search_path(arguments)) {
+            Ok(mut files) => {
+                entry = files.pop().map(|file| Script::File(file.display().to_string()));
+                modules = files;
+                entry.as_ref()
+            }
+            Err(_) if !Path::new(PROJECT_CONFIG).is_file() => {
+                print_error(&format!("no script to run and no {} in the current directory", PROJECT_CONFIG));
+                return ExitCode::from(USAGE_ERROR);
+            }
+            Err(error) => {
+                print_error(&format!("{}: {}", PROJECT_CONFIG, error));
+                return ExitCode::from(USAGE_ERROR);
+            }
+        },
+        script => {
+            modules = vec![];
+            script
+        }
+    };
+    let mut features = vec![];
+    let mut evaluator = new_evaluator(arguments, &config);
+    if arguments.has("--allow-fs") {
+        features.push("filesystem access");
+    }
+    if arguments.has("--allow-env") {
+        features.push("environment access");
+    }
+
+    // With a program to run, `--tokens` and `--ast` show what the lexer or the parser make of it
+    // instead. Without one they pick what the REPL shows.
+    let dump = match (arguments.has("--tokens"), arguments.has("--ast")) {
+        (true, _) => Some(Mode::Tokens),
+        (_, true) => Some(Mode::Ast),
+        _ => None,
+    };
+    let mode = dump.or(config.mode).unwrap_or(Mode::Eval);
+    let mut prompt = config.prompt;
+    if let Ok(text) = std::env::var("MONKEY_PROMPT") {
+        prompt.text = text;
+    }
+    // `echo 'puts(1 + 2)' | monkey` runs the input as one program, and the exit code says whether it
+    // failed. `monkey run -` does so even when the input is a terminal.
+    let reads_input = match script {
+        Some(Script::Stdin) => true,
+        None => terminal::is_input_piped(),
+        Some(_) => false,
+    };
+    let program = match script {
+        _ if reads_input => {
+            let mut source = String::new();
+            if let Err(error) = io::stdin().read_to_string(&mut source) {
+                print_error(&format!("cannot read standard input: {}", error));
+                return ExitCode::from(RUNTIME_ERROR);
+            }
+            Some((String::from(skip_shebang(&source)), String::from("<stdin>")))
+        }
+        Some(Script::Code(code)) => Some((code.clone(), String::from("<eval>"))),
+        Some(Script::File(path)) => match std::fs::read_to_string(path) {
+            Ok(source) => Some((String::from(skip_shebang(&source)), path.clone())),
+            Err(error) => {
+                print_error(&format!("cannot read {}: {}", path, error));
+                return ExitCode::from(USAGE_ERROR);
+            }
+        },
+        _ => None,
+    };
+    if arguments.has("--trace") {
+        let mode = match arguments.value("--trace") {
+            None => TraceMode::Nodes,
+            Some(name) => match TraceMode::from_name(name) {
+                Some(mode) => mode,
+                None => {
+                    print_error(&format!("unknown --trace: {} (expected nodes or calls)", name));
+                    return ExitCode::from(USAGE_ERROR);
+                }
+            },
+        };
+        let source = program.as_ref().map_or("", |(source, _)| source.as_str());
+        evaluator = evaluator.with_hooks(Tracer::new(mode, source, io::stderr()));
+    }
+    // The evaluator takes one set of hooks, `Arguments` does not let `--trace` come with `--profile`.
+    let profile = Rc::new(RefCell::new(Profile::default()));
+    if arguments.has("--profile") {
+        let source = program.as_ref().map_or("", |(source, _)| source.as_str());
+        evaluator = evaluator.with_hooks(Profiler::new(source, Clock::System, Rc::clone(&profile)));
+    }
+    let is_sandboxed = arguments.has("--sandbox");
+    let mut repl = Repl::new(evaluator).with_mode(mode).with_prompt(prompt);
+    if is_sandboxed {
+        repl = repl.with_sandbox();
+        features.push("sandbox");
+    }
+    if let Some((source, name)) = program {
+        for module in modules.iter().filter(|_| dump.is_none()) {
+            let name = module.display().to_string();
+            let result = match std::fs::read_to_string(module) {
+                Ok(source) => repl.run_script(skip_shebang(&source), &name),
+                Err(error) => {
+                    print_error(&format!("cannot read {}: {}", name, error));
+                    return ExitCode::from(USAGE_ERROR);
+                }
+            };
+            if let Err(error) = result {
+                return report(error);
+            }
+        }
+        let exit_code = match dump {
+            Some(Mode::Tokens) => print_listing(list_tokens(&source, &name)),
+            Some(_) => print_listing(list_statements(&source, &name)),
+            None if matches!(script, Some(Script::Code(_))) => evaluate_code(&mut repl, &source),
+            None => run_program(&mut repl, &source, &name),
+        };
+        if arguments.has("--profile") && dump.is_none() {
+            let path = arguments.value("--profile").unwrap_or(PROFILE_FILE);
+            let profile = profile.borrow();
+            if let Err(error) = std::fs::write(path, profile.folded()) {
+                print_error(&format!("cannot write {}: {}", path, error));
+                return ExitCode::from(RUNTIME_ERROR);
+            }
+            eprint!("{}", profile.summary(PROFILE_ROWS));
+        }
+        return exit_code;
+    }
+
+    // The settings file can turn the REPL's colors on or off, unless `--color` says otherwise.
+    let color_choice = match (arguments.has("--color"), config.colors) {
+        (false, Some(true)) => ColorChoice::Always,
+        (false, Some(false)) => ColorChoice::Never,
+        _ => color_choice(),
+    };
+    let uses_colors = color_choice.applies_to(&io::stdout());
+    if uses_colors {
+        repl = repl.with_colors();
+        features.push("colors");
+    }
+    // Ctrl-C only needs catching when there is someone at a terminal to press it.
+    if terminal::is_interactive() {
+        repl = repl.with_interrupt(terminal::catch_interrupts());
+        if let Some((rows, _)) = terminal::size() {
+            // Leave the prompt the result came from on the screen.
+            repl = repl.with_page_height(rows - 1);
+        }
+    }
+
+    let completer = repl.completer();
+    let mut editor = LineEditor::new();
+    if let Some(keybindings) = config.keybindings {
+        editor = editor.with_keybindings(keybindings);
+    }
+    // Piped input is not worth remembering, and what strangers type into a sandbox is not ours to
+    // keep.
+    if let Some(history_file) = history_file().filter(|_| terminal::is_interactive() && !is_sandboxed) {
+        let mut history = History::load(&history_file);
+        if let Some(size) = config.history_size {
+            history = history.with_limit(size);
+        }
+        editor = editor.with_history(history);
+        features.push("history");
+    }
+    if uses_colors {
+        editor = editor.with_highlighter(highlight);
+    }
+    let mut editor = editor.with_completer(move |line, cursor| completer.complete(line, cursor));
+
+    if terminal::is_interactive() && !arguments.has("--quiet") {
+        print_line(&banner(&features));
+    }
+    // What fails here is reading the terminal or writing to it, which the session cannot go on without.
+    for path in &config.prelude {
+        if let Err(error) = repl.load_prelude(&expand_home(path)) {
+            print_error(&format!("cannot load {}: {}", path, error));
+            return ExitCode::from(RUNTIME_ERROR);
+        }
+    }
+    match repl.run(&mut editor) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(error) => {
+            print_error(&format!("the session stopped: {}", error));
+            ExitCode::from(RUNTIME_ERROR)
+        }
+    }
+}
+
+// Runs the script, or the project, from scratch every time one of its files changes, on a cleared
+// screen, until interrupted. A project's files are the ones `load_order` finds, again before every
+// run, and `monkey.toml`, which says what they are and is read again too.
+fn watch(script: &Script, arguments: &Arguments, config: &Config) -> ExitCode {
+    if matches!(script, Script::Project) && !Path::new(PROJECT_CONFIG).is_file() {
+        print_error(&format!("no script to watch and no {} in the current directory", PROJECT_CONFIG));
+        return ExitCode::from(USAGE_ERROR);
+    }
     loop {
-        let mut input = String::new();
-        print!(">> ");
-        io::stdout().flush().unwrap();
-
-        io::stdin().read_line(&mut input).unwrap();
-        let tokens = lexical_analyzer::tokenize(&input);
-        println!(
-            "{:?}",
-            tokens.iter().map(|token| format!("{:?}", token)).collect::<Vec<String>>()
-        );
-    }
-}
\ No newline at end of file
+        // The screen is cleared before the run's own output, which may go to stderr.
+        print_text(CLEAR_SCREEN);
+        let _ = io::stdout().flush();
+        let (config, files) = match script {
+            Script::File(path) => (config.clone(), vec![PathBuf::from(path)]),
+            _ => {
+                // Nothing runs until a broken monkey.toml is fixed.
+                let configured = configure(arguments).and_then(|config| {
+                    let files = load_order(&config, Path::new(""), &search_path(arguments))
+                        .map_err(|error| format!("{}: {}", PROJECT_CONFIG, error))?;
+                    Ok((config, files))
+                });
+                configured.unwrap_or_else(|error| {
+                    print_error(&error);
+                    (config.clone(), vec![])
+                })
+            }
+        };
+        let mut watched = files.clone();
+        if matches!(script, Script::Project) {
+            watched.push(PathBuf::from(PROJECT_CONFIG));
+        }
+        let watcher = Watcher::new(watched);
+        let mut repl = Repl::new(new_evaluator(arguments, &config));
+        for file in &files {
+            let name = file.display().to_string();
+            let result = match std::fs::read_to_string(file) {
+                Ok(source) => repl.run_script(skip_shebang(&source), &name),
+                Err(error) => {
+                    print_error(&format!("cannot read {}: {}", name, error));
+                    break;
+                }
+            };
+            if let Err(error) = result {
+                report(error);
+                break;
+            }
+        }
+        let names: Vec<String> = watcher.files().iter().map(|file| file.display().to_string()).collect();
+        eprintln!("[watching {} for changes]", names.join(", "));
+        watcher.wait(WATCH_INTERVAL);
+    }
+}
+
+// Only programs that do not lex or parse fail to list.
+fn print_listing(listing: Result<String, ScriptError>) -> ExitCode {
+    match listing {
+        Ok(listing) => {
+            print_line(&listing);
+            ExitCode::SUCCESS
+        }
+        Err(error) => report(error),
+    }
+}
+
+fn print_syntax_tree(path: &str, format: &str) -> ExitCode {
+    let format = match DumpFormat::from_name(format) {
+        Some(format) => format,
+        None => {
+            eprintln!("unknown format: {} (expected json, sexpr or tree)", format);
+            return ExitCode::from(USAGE_ERROR);
+        }
+    };
+    match std::fs::read_to_string(path) {
+        Ok(source) => print_listing(dump_syntax(skip_shebang(&source), path, format)),
+        Err(error) => {
+            print_error(&format!("cannot read {}: {}", path, error));
+            ExitCode::from(USAGE_ERROR)
+        }
+    }
+}
+
+// Runs a program `monkey compile` saved, on the virtual machine. There is no source to show the
+// tokens or the syntax tree of, or to trace and profile the expressions of.
+fn run_compiled(path: &str, arguments: &Arguments, config: &Config) -> ExitCode {
+    let options = ["--ast", "--tokens", "--trace", "--profile", "--watch"];
+    if let Some(option) = options.into_iter().find(|option| arguments.has(option)) {
+        print_error(&format!("{} does not apply to a compiled program", option));
+        return ExitCode::from(USAGE_ERROR);
+    }
+    let bytecode = match load_bytecode(path) {
+        Ok(bytecode) => bytecode,
+        Err(exit_code) => return exit_code,
+    };
+    let mut repl = Repl::new(new_evaluator(arguments, config));
+    if arguments.has("--sandbox") {
+        repl = repl.with_sandbox();
+    }
+    match repl.run_bytecode(bytecode, path) {
+        Ok(_) => ExitCode::SUCCESS,
+        Err(error) => report(error),
+    }
+}
+
+// Saves the script compiled, as `a.mbc` next to `a.monkey` unless `output` names another file. A
+// program the compiler cannot compile, as one with too many constants, fails the way one that does
+// not parse does.
+fn compile_file(path: &str, output: Option<&str>, optimizes: bool) -> ExitCode {
+    let source = match std::fs::read_to_string(path) {
+        Ok(source) => source,
+        Err(error) => {
+            print_error(&format!("cannot read {}: {}", path, error));
+            return ExitCode::from(USAGE_ERROR);
+        }
+    };
+    let compiled = compile_program(skip_shebang(&source), path, optimizes)
+        .and_then(|bytecode| {
+            let file = String::from(path);
+            serialize(&bytecode).map_err(|message| ScriptError::Compile { file, message, code: None })
+        });
+    let bytes = match compiled {
+        Ok(bytes) => bytes,
+        Err(error) => return report(error),
+    };
+    let output = output.map_or_else(|| Path::new(path).with_extension(EXTENSION), PathBuf::from);
+    if output == Path::new(path) {
+        print_error(&format!("{} would be overwritten, --output can name another file", path));
+        return ExitCode::from(USAGE_ERROR);
+    }
+    if let Err(error) = std::fs::write(&output, bytes) {
+        print_error(&format!("cannot write {}: {}", output.display(), error));
+        return ExitCode::from(RUNTIME_ERROR);
+    }
+    ExitCode::SUCCESS
+}
+
+fn print_disassembly(path: &str, optimizes: bool) -> ExitCode {
+    let bytecode = if is_compiled(path) {
+        load_bytecode(path)
+    } else {
+        match std::fs::read_to_string(path) {
+            Ok(source) => compile_program(skip_shebang(&source), path, optimizes)
+                .map_err(report),
+            Err(error) => {
+                print_error(&format!("cannot read {}: {}", path, error));
+                Err(ExitCode::from(USAGE_ERROR))
+            }
+        }
+    };
+    match bytecode {
+        Ok(bytecode) => {
+            print_line(&bytecode.disassemble());
+            ExitCode::SUCCESS
+        }
+        Err(exit_code) => exit_code,
+    }
+}
+
+fn is_compiled(path: &str) -> bool {
+    Path::new(path).extension().is_some_and(|extension| extension == EXTENSION)
+}
+
+// A file that is not bytecode this version can run is bad input data, as a script that does not
+// parse is.
+fn load_bytecode(path: &str) -> Result<Bytecode, ExitCode> {
+    let bytes = std::fs::read(path).map_err(|error| {
+        print_error(&format!("cannot read {}: {}", path, error));
+        ExitCode::from(USAGE_ERROR)
+    })?;
+    deserialize(&bytes)
+        .map_err(|message| report(ScriptError::Compile { file: String::from(path), message, code: None }))
+}
+
+fn print_minified(path: &str) -> ExitCode {
+    let source = match std::fs::read_to_string(path) {
+        Ok(source) => source,
+        Err(error) => {
+            print_error(&format!("cannot read {}: {}", path, error));
+            return ExitCode::from(USAGE_ERROR);
+        }
+    };
+    match minify_program(&source, path) {
+        Ok(minified) => {
+            print_text(&minified);
+            ExitCode::SUCCESS
+        }
+        Err(error) => report(error),
+    }
+}
+
+// Highlighting goes by the tokens, so a file that does not parse is highlighted as far as it lexes.
+fn print_highlighted(path: &str, format: &str) -> ExitCode {
+    let highlight = match format {
+        "html" => highlight_html,
+        "ansi" => highlight_ansi,
+        _ => {
+            eprintln!("unknown format: {} (expected html or ansi)", format);
+            return ExitCode::from(USAGE_ERROR);
+        }
+    };
+    match std::fs::read_to_string(path) {
+        Ok(source) => {
+            print_text(&highlight(&source));
+            ExitCode::SUCCESS
+        }
+        Err(error) => {
+            print_error(&format!("cannot read {}: {}", path, error));
+            ExitCode::from(USAGE_ERROR)
+        }
+    }
+}
+
+fn print_explanation(code: Option<&str>) -> ExitCode {
+    let Some(code) = code else {
+        for diagnostic in &DIAGNOSTICS {
+            print_line(&format!("{}  {}", diagnostic.code, diagnostic.summary));
+        }
+        return ExitCode::SUCCESS;
+    };
+    match explain(code) {
+        Some(explanation) => {
+            print_line(&explanation);
+            ExitCode::SUCCESS
+        }
+        None => {
+            print_error(&format!("unknown error code: {} (`monkey explain` lists them)", code));
+            ExitCode::from(USAGE_ERROR)
+        }
+    }
+}
+
+// Documents all the `*.monkey` files under the paths together, leaving out the ones that do not
+// parse after reporting them.
+fn print_documentation(paths: &[String], format: &str) -> ExitCode {
+    let write = match format {
+        "markdown" => markdown,
+        "html" => html,
+        _ => {
+            eprintln!("unknown format: {} (expected markdown or html)", format);
+            return ExitCode::from(USAGE_ERROR);
+        }
+    };
+    let mut exit_code = ExitCode::SUCCESS;
+    let mut files = vec![];
+    for (name, source) in read_files(paths, ".monkey", &mut exit_code) {
+        match document_program(skip_shebang(&source), &name) {
+            Ok(items) => files.push((name, items)),
+            Err(error) => exit_code = report(error),
+        }
+    }
+    print_text(&write(&files));
+    exit_code
+}
+
+// Prints every file formatted, one after the other, or with `--write` rewrites the ones that are
+// not formatted yet. With `--check` it prints how those differ instead, and fails when there were
+// any. A file that cannot be formatted does not stop the others from being.
+//
+// `-` is standard input, which is always printed, so that editors can format through `monkey fmt -`.
+fn format_files(paths: &[String], writes: bool, checks: bool) -> ExitCode {
+    let mut exit_code = ExitCode::SUCCESS;
+    for path in paths {
+        let is_stdin = path == "-";
+        let read = if is_stdin {
+            let mut source = String::new();
+            io::stdin().read_to_string(&mut source).map(|_| source)
+        } else {
+            std::fs::read_to_string(path)
+        };
+        let path = if is_stdin { "<stdin>" } else { path.as_str() };
+        let source = match read {
+            Ok(source) => source,
+            Err(error) => {
+                print_error(&format!("cannot read {}: {}", path, error));
+                exit_code = ExitCode::from(USAGE_ERROR);
+                continue;
+            }
+        };
+        let formatted = match format_program(&source, path) {
+            Ok(formatted) => formatted,
+            Err(error) => {
+                exit_code = report(error);
+                continue;
+            }
+        };
+        if checks {
+            // The diff is by lines, so it has nothing to show for a missing last newline or `\r\n`s.
+            let diff = match unified_diff(&source, &formatted, path) {
+                diff if diff.is_empty() => format!("{}: differs in line endings only\n", path),
+                diff => paint_diff(&diff),
+            };
+            if formatted != source {
+                print_text(&diff);
+                exit_code = ExitCode::from(RUNTIME_ERROR);
+            }
+        } else if !writes || is_stdin {
+            print_text(&formatted);
+        } else if formatted != source {
+            if let Err(error) = std::fs::write(path, formatted) {
+                print_error(&format!("cannot write {}: {}", path, error));
+                exit_code = ExitCode::from(RUNTIME_ERROR);
+            }
+        }
+    }
+    exit_code
+}
+
+// Removed lines in red, added ones in green and hunk headers in cyan, when standard output is colored.
+fn paint_diff(diff: &str) -> String {
+    let stdout = io::stdout();
+    let paint_line = |line: &str| match line.as_bytes().first() {
+        _ if line.starts_with("---") || line.starts_with("+++") => String::from(line),
+        Some(b'-') => paint(line, "red", &stdout),
+        Some(b'+') => paint(line, "green", &stdout),
+        Some(b'@') => paint(line, "cyan", &stdout),
+        _ => String::from(line),
+    };
+    diff.lines().map(|line| paint_line(line) + "\n").collect()
+}
+
+// Reports every lexical and syntax error in the files without running any of them, and fails when
+// there were any.
+fn check_files(paths: &[String]) -> ExitCode {
+    let mut exit_code = ExitCode::SUCCESS;
+    for path in paths {
+        let result = match std::fs::read_to_string(path) {
+            Ok(source) => check_program(skip_shebang(&source), path),
+            Err(error) => {
+                print_error(&format!("cannot read {}: {}", path, error));
+                exit_code = ExitCode::from(USAGE_ERROR);
+                continue;
+            }
+        };
+        if let Err(errors) = result {
+            exit_code = report(errors);
+        }
+    }
+    exit_code
+}
+
+// Prints the warnings for every `*.monkey` file under the paths. With `--deny-warnings` it fails
+// when there were any, as it does for a file that does not parse.
+fn lint_files(paths: &[String], denies_warnings: bool) -> ExitCode {
+    let mut exit_code = ExitCode::SUCCESS;
+    let mut count = 0;
+    for (name, source) in read_files(paths, ".monkey", &mut exit_code) {
+        match lint_program(skip_shebang(&source), &name) {
+            Ok(warnings) => {
+                for warning in &warnings {
+                    if json_errors() {
+                        let (code, span) = (Some(warning.code), Some(warning.span));
+                        let report = Report::new(Severity::Warning, code, &name, span, &warning.message);
+                        eprintln!("{}", report.to_json());
+                        continue;
+                    }
+                    let label = paint("warning", "yellow", &io::stderr());
+                    let message = with_code(&warning.message, Some(warning.code));
+                    eprintln!("{}:{}: {}: {}", name, warning.span, label, message);
+                }
+                count += warnings.len();
+            }
+            Err(errors) => exit_code = report(errors),
+        }
+    }
+    if denies_warnings && count > 0 && exit_code == ExitCode::SUCCESS {
+        exit_code = ExitCode::from(RUNTIME_ERROR);
+    }
+    exit_code
+}
+
+// Runs every `*_test.monkey` file under the paths, and the files named directly whatever they are
+// called, each in an evaluator of its own. Fails when a test did or a file would not parse. With
+// `--coverage` it also tells how many of each file's statements ran, and saves the files with the
+// lines that did and did not run marked as a web page.
+fn run_tests(paths: &[String], arguments: &Arguments, config: &Config) -> ExitCode {
+    let is_covered = arguments.has("--coverage");
+    let mut exit_code = ExitCode::SUCCESS;
+    let (mut passed, mut failed) = (0, 0);
+    let mut coverage = vec![];
+    for (name, source) in read_files(paths, "_test.monkey", &mut exit_code) {
+        let source = skip_shebang(&source);
+        let executed = Executed::default();
+        // Every file gets the same random numbers on every run, unless `--seed` asks for others.
+        let mut evaluator = new_evaluator(arguments, config);
+        if seed(arguments).is_none() {
+            evaluator = evaluator.with_seed(config.test_seed.unwrap_or(TEST_SEED));
+        }
+        if config.test_fake_clock == Some(true) {
+            evaluator = evaluator.with_clock(Clock::Fake { now: 0.0, step: FAKE_CLOCK_STEP });
+        }
+        if is_covered {
+            evaluator = evaluator.with_hooks(CoverageHook::new(Rc::clone(&executed)));
+        }
+        let mut repl = Repl::new(evaluator);
+        let result = match is_covered {
+            true => parse_marked_program(source, &name).map(|(program, marks)| {
+                let outcomes = repl.run_parsed_tests(&program, source, &name);
+                coverage.push(FileCoverage::new(&name, source, &program, &marks, &executed));
+                outcomes
+            }),
+            false => repl.run_tests(source, &name),
+        };
+        let outcomes = match result {
+            Ok(outcomes) => outcomes,
+            Err(error) => {
+                exit_code = report(error);
+                continue;
+            }
+        };
+        for TestOutcome { name, failure } in outcomes {
+            match failure {
+                None => {
+                    print_line(&format!("test {} ... {}", name, paint("ok", "green", &io::stdout())));
+                    passed += 1;
+                }
+                Some(failure) if json_errors() => {
+                    print_line(&format!("test {} ... {}", name, paint("FAILED", "red", &io::stdout())));
+                    failure.reports().iter().for_each(|report| eprintln!("{}", report.to_json()));
+                    failed += 1;
+                }
+                Some(failure) => {
+                    let label = paint("FAILED", "red", &io::stdout());
+                    let failure = failure.to_string().replace('\n', "\n    ");
+                    print_line(&format!("test {} ... {}\n    {}", name, label, failure));
+                    failed += 1;
+                }
+            }
+        }
+    }
+
+    print_line(&format!("\n{} passed, {} failed", passed, failed));
+    if is_covered {
+        print_line(&format!("\n{}", coverage_summary(&coverage).trim_end()));
+        let path = arguments.value("--coverage").unwrap_or(COVERAGE_FILE);
+        if let Err(error) = std::fs::write(path, coverage_html(&coverage)) {
+            print_error(&format!("cannot write {}: {}", path, error));
+            return ExitCode::from(RUNTIME_ERROR);
+        }
+    }
+    match failed {
+        0 => exit_code,
+        _ => ExitCode::from(RUNTIME_ERROR),
+    }
+}
+
+// Runs every `*_bench.monkey` file under the paths, and the files named directly, then times each
+// of their `bench_...` functions. `--save` writes the results to a file which `--baseline` reads
+// to compare later runs against.
+fn run_benchmarks(paths: &[String], arguments: &Arguments, config: &Config) -> ExitCode {
+    let mut exit_code = ExitCode::SUCCESS;
+    let baselines = match arguments.value("--baseline") {
+        None => vec![],
+        Some(path) => match std::fs::read_to_string(path).map_err(|error| error.to_string()).and_then(|source| {
+            parse_baseline(&source)
+        }) {
+            Ok(baselines) => baselines,
+            Err(error) => {
+                print_error(&format!("cannot read the baseline {}: {}", path, error));
+                return ExitCode::from(USAGE_ERROR);
+            }
+        },
+    };
+
+    let mut measurements = vec![];
+    for (name, source) in read_files(paths, "_bench.monkey", &mut exit_code) {
+        let source = skip_shebang(&source);
+        let mut repl = Repl::new(new_evaluator(arguments, config));
+        if let Err(error) = repl.run_script(source, &name) {
+            exit_code = report(error);
+            continue;
+        }
+        for (function, span) in repl.find_functions(source, "bench_") {
+            let bench = format!("{}::{}", name, function);
+            let mut clock = Clock::System;
+            let allocations = || ALLOCATIONS.load(Ordering::Relaxed);
+            let measurement = measure(&bench, &mut clock, BENCH_SECONDS, allocations, || {
+                repl.call_function(function, span, &name).map(|_| ())
+            });
+            let measurement = match measurement {
+                Ok(measurement) => measurement,
+                Err(error) => {
+                    let label = paint("FAILED", "red", &io::stdout());
+                    let error = error.to_string().replace('\n', "\n    ");
+                    print_line(&format!("bench {} ... {}\n    {}", bench, label, error));
+                    exit_code = ExitCode::from(RUNTIME_ERROR);
+                    continue;
+                }
+            };
+            let mut line = format!(
+                "bench {} ... {:.0} iterations/s, {:.1} allocations/iteration",
+                bench,
+                measurement.per_second(),
+                measurement.allocations_per_iteration()
+            );
+            if let Some(baseline) = baselines.iter().find(|baseline: &&Baseline| baseline.name == bench) {
+                line += &format!(
+                    " ({} speed, {} allocations)",
+                    change(measurement.per_second(), baseline.per_second),
+                    change(measurement.allocations_per_iteration(), baseline.allocations_per_iteration)
+                );
+            }
+            print_line(&line);
+            measurements.push(measurement);
+        }
+    }
+
+    if let Some(path) = arguments.value("--save") {
+        if let Err(error) = std::fs::write(path, format_baseline(&measurements)) {
+            print_error(&format!("cannot write {}: {}", path, error));
+            exit_code = ExitCode::from(USAGE_ERROR);
+        }
+    }
+    exit_code
+}
+
+// Reads the files ending in `suffix` under the directories among the paths, and the other paths as
+// they are, with the names to report them by. Files that cannot be read are reported and set the
+// exit code.
+fn read_files(paths: &[String], suffix: &str, exit_code: &mut ExitCode) -> Vec<(String, String)> {
+    let mut files = vec![];
+    for path in paths {
+        let path = Path::new(path);
+        match path.is_dir() {
+            true => find_files(path, suffix, &mut files),
+            false => files.push(path.to_path_buf()),
+        }
+    }
+
+    let mut sources = vec![];
+    for path in files {
+        let name = path.strip_prefix(".").unwrap_or(&path).display().to_string();
+        match std::fs::read_to_string(&path) {
+            Ok(source) => sources.push((name, source)),
+            Err(error) => {
+                print_error(&format!("cannot read {}: {}", name, error));
+                *exit_code = ExitCode::from(USAGE_ERROR);
+            }
+        }
+    }
+    sources
+}
+
+// Collects the files ending in `suffix` in a directory and the ones below it, sorted so that they
+// run in the same order everywhere. Hidden directories are left out.
+fn find_files(directory: &Path, suffix: &str, paths: &mut Vec<PathBuf>) {
+    let mut entries: Vec<PathBuf> = match std::fs::read_dir(directory) {
+        Ok(entries) => entries.filter_map(|entry| entry.ok()).map(|entry| entry.path()).collect(),
+        Err(_) => return,
+    };
+    entries.sort();
+    for path in entries {
+        let file_name = path.file_name().map(|name| name.to_string_lossy().into_owned()).unwrap_or_default();
+        if path.is_dir() && !file_name.starts_with('.') {
+            find_files(&path, suffix, paths);
+        } else if file_name.ends_with(suffix) {
+            paths.push(path);
+        }
+    }
+}
+
+fn new_evaluator(arguments: &Arguments, config: &Config) -> Evaluator {
+    let script_arguments = match &arguments.command {
+        Command::Run { arguments, .. } => arguments.clone(),
+        _ => vec![],
+    };
+    let mut evaluator = Evaluator::new().with_arguments(script_arguments);
+    if let Some(max_depth) = config.max_depth {
+        evaluator = evaluator.with_max_depth(max_depth);
+    }
+    if let Some(step_budget) = config.step_budget {
+        evaluator = evaluator.with_step_budget(step_budget);
+    }
+    if let Some(max_value_size) = config.max_value_size {
+        evaluator = evaluator.with_memory_limit(max_value_size);
+    }
+    if let Some(time_limit) = config.time_limit {
+        evaluator = evaluator.with_time_limit(time_limit);
+    }
+    if let Some(max_memory) = config.max_memory {
+        evaluator = evaluator.with_heap_limit(max_memory, || HEAP_IN_USE.load(Ordering::Relaxed));
+    }
+    if arguments.has("--allow-fs") {
+        evaluator = evaluator.with_filesystem_access();
+    }
+    if arguments.has("--allow-env") {
+        evaluator = evaluator.with_environment_access();
+    }
+    if arguments.has("--optimize") {
+        evaluator = evaluator.with_constant_folding();
+    }
+    if let Some(seed) = seed(arguments) {
+        evaluator = evaluator.with_seed(seed);
+    }
+    evaluator
+}
+
+fn json_errors() -> bool {
+    JSON_ERRORS.get().copied().unwrap_or(false)
+}
+
+fn color_choice() -> ColorChoice {
+    COLOR_CHOICE.get().copied().unwrap_or(ColorChoice::Auto)
+}
+
+fn paint(text: &str, color: &str, stream: &impl IsTerminal) -> String {
+    match color_code(color).filter(|_| color_choice().applies_to(stream)) {
+        Some(code) => format!("{}{}{}", code, text, RESET),
+        None => String::from(text),
+    }
+}
+
+// Errors go to standard error, in red when that is colored.
+fn print_error(message: &str) {
+    eprintln!("{}", paint(message, "red", &io::stderr()));
+}
+
+// Unlike `print!` and `println!` these do not panic when the reader went away, as `head` does once
+// it has read enough. Everything the interpreter prints to standard output goes through them, but
+// for the REPL's session, which stops on what fails to print.
+fn print_text(text: &str) {
+    let _ = write!(io::stdout(), "{}", text);
+}
+
+fn print_line(text: &str) {
+    let _ = writeln!(io::stdout(), "{}", text);
+}
+
+fn run_program(repl: &mut Repl, source: &str, name: &str) -> ExitCode {
+    match repl.run_script(source, name) {
+        Ok(_) => ExitCode::SUCCESS,
+        Err(error) => report(error),
+    }
+}
+
+fn report(error: ScriptError) -> ExitCode {
+    let code = match &error {
+        ScriptError::Syntax { .. } | ScriptError::Compile { .. } => PARSE_ERROR,
+        ScriptError::Runtime { limit: Some(Limit::Time), .. } => TIMED_OUT,
+        ScriptError::Runtime { .. } => RUNTIME_ERROR,
+    };
+    match json_errors() {
+        true => error.reports().iter().for_each(|report| eprintln!("{}", report.to_json())),
+        false => print_error(&error.to_string()),
+    }
+    ExitCode::from(code)
+}
+
+// Prints the value of a one-liner unless there is none, and fails when it is an error or `false` so
+// that `monkey -e 'len(x) > 3'` works as a check in a shell script.
+fn evaluate_code(repl: &mut Repl, code: &str) -> ExitCode {
+    match repl.run_script(code, "<eval>") {
+        Ok(Object::Null) => ExitCode::SUCCESS,
+        Ok(Object::Boolean(false)) => {
+            print_line("false");
+            ExitCode::from(RUNTIME_ERROR)
+        }
+        Ok(value) => {
+            print_line(&value.to_string());
+            ExitCode::SUCCESS
+        }
+        Err(error) => report(error),
+    }
+}
+
+// `$MONKEY_HISTORY` when set, `~/.monkey_history` otherwise.
+fn history_file() -> Option<PathBuf> {
+    match std::env::var_os("MONKEY_HISTORY") {
+        Some(path) => Some(PathBuf::from(path)),
+        None => std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".monkey_history")),
+    }
+}
+
+// Reads `$MONKEYRC`, or `~/.monkeyrc` when that is not set, and then the project's `monkey.toml`
+// in the current directory. Missing files mean the defaults. A broken one is an error, rather than
+// running without the limits it may set.
+fn load_config() -> Result<Config, String> {
+    let user_config = match std::env::var_os("MONKEYRC") {
+        Some(path) => Some(PathBuf::from(path)),
+        None => std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".monkeyrc")),
+    };
+    let mut config = Config::default();
+    for path in user_config.into_iter().chain([PathBuf::from(PROJECT_CONFIG)]) {
+        let source = match std::fs::read_to_string(&path) {
+            Ok(source) => source,
+            Err(_) => continue,
+        };
+        config.update(&source).map_err(|error| format!("{}: {}", path.display(), error))?;
+    }
+    Ok(config)
+}
+
+// Lets prelude paths start with `~/` like they would in a shell.
+fn expand_home(path: &str) -> String {
+    match (path.strip_prefix("~/"), std::env::var("HOME")) {
+        (Some(rest), Ok(home)) => format!("{}/{}", home, rest),
+        _ => String::from(path),
+    }
+}
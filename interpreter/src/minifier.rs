@@ -0,0 +1,68 @@
+use crate::lexical_analyzer::{tokenize, tokenize_with_ranges};
+use crate::repl::skip_shebang;
+use crate::syntax_analyzer::{parse_with_spans, SyntaxError};
+
+// The program on one line, its tokens as they were written with a space only between those that
+// would otherwise run together. Comments are left out, a shebang line is kept.
+pub fn minify(source: &str) -> Result<String, Vec<SyntaxError>> {
+    let body = skip_shebang(source);
+    let shebang = &source[..source.len() - body.len()];
+    parse_with_spans(body)?;
+
+    let mut minified = String::from(shebang.trim_end());
+    if !minified.is_empty() {
+        minified.push('\n');
+    }
+    let mut previous: Option<&str> = None;
+    for (_, range) in tokenize_with_ranges(body) {
+        let text = &body[range];
+        if previous.is_some_and(|previous| runs_together(previous, text)) {
+            minified.push(' ');
+        }
+        minified += text;
+        previous = Some(text);
+    }
+    Ok(minified + "\n")
+}
+
+// Whether two tokens written next to each other would be read as something else, as `let x` would
+// as `letx` and `=` `=` as `==`.
+fn runs_together(first: &str, second: &str) -> bool {
+    let (apart, together) = (tokenize(&format!("{} {}", first, second)), tokenize(&format!("{}{}", first, second)));
+    apart != together
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::syntax_analyzer::parse;
+
+    fn assert_minifies(source: &str, expected: &str) {
+        let minified = minify(source).unwrap();
+        assert_eq!(minified, expected);
+        let (before, after) = (parse(skip_shebang(source)).unwrap(), parse(skip_shebang(&minified)).unwrap());
+        assert_eq!(after.to_string(), before.to_string());
+    }
+
+    #[test]
+    fn it_minifies_programs() {
+        assert_minifies(
+            "\
+// Adds two numbers.
+let add = fn(a, b) {
+  a + b // the sum
+};
+let x = add(1, -2) - -3;
+puts(\"a  b\", x == 2, 1.5, !true)
+",
+            "let add=fn(a,b){a+b};let x=add(1,-2)--3;puts(\"a  b\",x==2,1.5,!true)\n",
+        );
+        assert_minifies("#!/usr/bin/env monkey\nlet y = [1, 2]\n[0]", "#!/usr/bin/env monkey\nlet y=[1,2][0]\n");
+        assert_minifies("for k, v in {1: 2} { puts(k) }", "for k,v in{1:2}{puts(k)}\n");
+    }
+
+    #[test]
+    fn it_reports_syntax_errors() {
+        assert!(minify("let = 1").is_err());
+    }
+}
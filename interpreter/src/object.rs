@@ -0,0 +1,493 @@
+use core::fmt;
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::rc::Rc;
+
+use crate::abstract_syntax_tree::{join, quote_string, BlockStatement};
+use crate::code::{CallSite, Capture, Instructions};
+use crate::diagnostics::RAISED;
+use crate::environment::Environment;
+use crate::evaluator::Evaluator;
+use crate::interner::Symbol;
+use crate::lexical_analyzer::Span;
+
+#[derive(Clone)]
+pub enum Object {
+    Integer(i32),
+    Float(f64),
+    Boolean(bool),
+    String(String),
+    Null,
+    Array(Rc<Vec<Object>>),
+    Hash(Rc<HashPairs>),
+    Range(i32, i32),
+    ReturnValue(Box<Object>),
+    Break,
+    Continue,
+    Error(Box<RuntimeError>),
+    Function(Rc<Function>),
+    CompiledFunction(Rc<CompiledFunction>),
+    Closure(Rc<Closure>),
+    Builtin(Builtin),
+}
+
+// What stopped a program, with the code of the diagnostic that explains it, see `DIAGNOSTICS`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct RuntimeError {
+    pub message: String,
+    pub code: Option<&'static str>,
+}
+
+// The subset of objects that can be used as hash keys, with value based equality and hashing.
+// String keys are not interned like identifiers, so they are freed with the last hash using them.
+#[derive(Clone, PartialEq, Eq, Hash)]
+pub enum HashKey {
+    Integer(i32),
+    Boolean(bool),
+    String(Rc<str>),
+}
+
+impl HashKey {
+    pub fn to_object(&self) -> Object {
+        match self {
+            Self::Integer(value) => Object::Integer(*value),
+            Self::Boolean(value) => Object::Boolean(*value),
+            Self::String(value) => Object::String(value.to_string()),
+        }
+    }
+}
+
+// The pairs of a hash in the order their keys were first inserted, so printing and iterating
+// over a hash gives the same result on every run.
+#[derive(Clone, Default)]
+pub struct HashPairs {
+    pairs: Vec<(HashKey, Object)>,
+    indices: HashMap<HashKey, usize>,
+}
+
+impl HashPairs {
+    pub fn new() -> Self {
+        HashPairs::default()
+    }
+
+    // Replacing the value of an existing key keeps the key where it was.
+    pub fn insert(&mut self, key: HashKey, value: Object) {
+        match self.indices.get(&key) {
+            Some(&index) => self.pairs[index].1 = value,
+            None => {
+                self.indices.insert(key.clone(), self.pairs.len());
+                self.pairs.push((key, value));
+            }
+        }
+    }
+
+    pub fn get(&self, key: &HashKey) -> Option<&Object> {
+        self.indices.get(key).map(|&index| &self.pairs[index].1)
+    }
+
+    pub fn len(&self) -> usize {
+        self.pairs.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.pairs.is_empty()
+    }
+
+    // The pair inserted `index`th, for iterating with nothing but a position.
+    pub fn get_index(&self, index: usize) -> Option<(&HashKey, &Object)> {
+        self.pairs.get(index).map(|(key, value)| (key, value))
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&HashKey, &Object)> {
+        self.pairs.iter().map(|(key, value)| (key, value))
+    }
+
+    pub fn keys(&self) -> impl Iterator<Item = &HashKey> {
+        self.pairs.iter().map(|(key, _)| key)
+    }
+
+    pub fn values(&self) -> impl Iterator<Item = &Object> {
+        self.pairs.iter().map(|(_, value)| value)
+    }
+}
+
+impl FromIterator<(HashKey, Object)> for HashPairs {
+    fn from_iter<I: IntoIterator<Item = (HashKey, Object)>>(pairs: I) -> Self {
+        let mut hash = HashPairs::new();
+        for (key, value) in pairs {
+            hash.insert(key, value);
+        }
+        hash
+    }
+}
+
+// Builtins get the evaluator so they can call back into Monkey functions they are passed, and
+// report failures as an `Err` holding an error object.
+pub type BuiltinFunction = fn(&mut Evaluator, Vec<Object>) -> Result<Object, Object>;
+
+#[derive(Clone)]
+pub struct Builtin {
+    pub name: &'static str,
+    // How the arguments are written in `:doc`, optional ones in brackets.
+    pub parameters: &'static str,
+    pub description: &'static str,
+    pub function: BuiltinFunction,
+}
+
+impl Builtin {
+    pub fn signature(&self) -> String {
+        format!("{}({})", self.name, self.parameters)
+    }
+}
+
+pub struct Function {
+    pub parameters: Vec<Symbol>,
+    pub body: Rc<BlockStatement>,
+    pub env: Rc<RefCell<Environment>>,
+    // The file that defined it, which the errors in its body are placed in.
+    pub file: Option<Rc<str>>,
+}
+
+// A function the compiler made, for the virtual machine to call.
+pub struct CompiledFunction {
+    pub instructions: Instructions,
+    // The slots of its frame, the parameters first and then its `let`s.
+    pub locals: usize,
+    pub parameters: usize,
+    // Where the variables it uses from the functions around it are, in the function making it.
+    pub free: Vec<Capture>,
+    // How the function was written, which is how it displays.
+    pub source: String,
+    // Sorted by offset.
+    pub calls: Vec<CallSite>,
+    // Where its statements start, sorted by offset.
+    pub statements: Vec<(usize, Span)>,
+}
+
+// A compiled function as the program makes it, with the variables it captured. They are shared
+// with the function they were captured from, as the environment of a function is in the evaluator.
+pub struct Closure {
+    pub function: Rc<CompiledFunction>,
+    pub free: Vec<Rc<RefCell<Object>>>,
+}
+
+impl Object {
+    pub fn type_name(&self) -> &'static str {
+        match self {
+            Self::Integer(_) => "INTEGER",
+            Self::Float(_) => "FLOAT",
+            Self::Boolean(_) => "BOOLEAN",
+            Self::String(_) => "STRING",
+            Self::Null => "NULL",
+            Self::Array(_) => "ARRAY",
+            Self::Hash(_) => "HASH",
+            Self::Range(_, _) => "RANGE",
+            Self::ReturnValue(_) => "RETURN_VALUE",
+            Self::Break => "BREAK",
+            Self::Continue => "CONTINUE",
+            Self::Error(_) => "ERROR",
+            Self::Function(_) | Self::CompiledFunction(_) | Self::Closure(_) => "FUNCTION",
+            Self::Builtin(_) => "BUILTIN",
+        }
+    }
+
+    // An error the interpreter ran into, with the code for its kind.
+    pub fn error(code: &'static str, message: String) -> Object {
+        Object::Error(Box::new(RuntimeError { message, code: Some(code) }))
+    }
+
+    // An error in bytecode the compiler does not make, which has no code as no program can cause it.
+    pub fn malformed(message: String) -> Object {
+        Object::Error(Box::new(RuntimeError { message, code: None }))
+    }
+
+    // An error the program raised itself, which has a code of its own whatever it says.
+    pub fn raised(message: String) -> Object {
+        Object::Error(Box::new(RuntimeError { message, code: Some(RAISED) }))
+    }
+
+    pub fn is_truthy(&self) -> bool {
+        !matches!(self, Self::Null | Self::Boolean(false))
+    }
+
+    pub fn is_error(&self) -> bool {
+        matches!(self, Self::Error(_))
+    }
+
+    // Whether the object stops the evaluation of the enclosing block: errors and the signals
+    // of `return`, `break` and `continue` on their way to whoever handles them.
+    pub fn is_abrupt(&self) -> bool {
+        matches!(self, Self::ReturnValue(_) | Self::Break | Self::Continue | Self::Error(_))
+    }
+
+    // What `==` means: values of different types are never equal, arrays and hashes are
+    // compared element by element, hashes regardless of their order, and functions are only
+    // equal to themselves. Integers and floats are the one pair that cannot be compared, as
+    // nothing converts between them implicitly, and `None` reports it, from nested elements too.
+    pub fn equals(&self, other: &Object) -> Option<bool> {
+        match (self, other) {
+            (Self::Integer(_), Self::Float(_)) | (Self::Float(_), Self::Integer(_)) => None,
+            (Self::Integer(left), Self::Integer(right)) => Some(left == right),
+            (Self::Float(left), Self::Float(right)) => Some(left == right),
+            (Self::Boolean(left), Self::Boolean(right)) => Some(left == right),
+            (Self::String(left), Self::String(right)) => Some(left == right),
+            (Self::Null, Self::Null) | (Self::Break, Self::Break) | (Self::Continue, Self::Continue) => Some(true),
+            (Self::Array(left), Self::Array(right)) => {
+                if left.len() != right.len() {
+                    return Some(false);
+                }
+                let mut is_equal = true;
+                for (left, right) in left.iter().zip(right.iter()) {
+                    is_equal &= left.equals(right)?;
+                }
+                Some(is_equal)
+            }
+            (Self::Hash(left), Self::Hash(right)) => {
+                if left.len() != right.len() {
+                    return Some(false);
+                }
+                let mut is_equal = true;
+                for (key, left) in left.iter() {
+                    match right.get(key) {
+                        None => is_equal = false,
+                        Some(right) => is_equal &= left.equals(right)?,
+                    }
+                }
+                Some(is_equal)
+            }
+            (Self::Range(left_start, left_end), Self::Range(right_start, right_end)) => {
+                Some(left_start == right_start && left_end == right_end)
+            }
+            (Self::ReturnValue(left), Self::ReturnValue(right)) => left.equals(right),
+            (Self::Error(left), Self::Error(right)) => Some(left == right),
+            (Self::Function(left), Self::Function(right)) => Some(Rc::ptr_eq(left, right)),
+            (Self::CompiledFunction(left), Self::CompiledFunction(right)) => Some(Rc::ptr_eq(left, right)),
+            (Self::Closure(left), Self::Closure(right)) => Some(Rc::ptr_eq(left, right)),
+            (Self::Builtin(left), Self::Builtin(right)) => Some(left.name == right.name),
+            _ => Some(false),
+        }
+    }
+
+    pub fn hash_key(&self) -> Option<HashKey> {
+        match self {
+            Self::Integer(value) => Some(HashKey::Integer(*value)),
+            Self::Boolean(value) => Some(HashKey::Boolean(*value)),
+            Self::String(value) => Some(HashKey::String(Rc::from(value.as_str()))),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for Object {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Integer(value) => write!(f, "{}", value),
+            // Debug formatting keeps the fraction of whole numbers, `1.0` rather than `1`.
+            Self::Float(value) => write!(f, "{:?}", value),
+            Self::Boolean(value) => write!(f, "{}", value),
+            Self::String(value) => write!(f, "{}", value),
+            Self::Null => write!(f, "null"),
+            Self::Array(_) | Self::Hash(_) => write!(f, "{}", self.inspect(InspectOptions::default())),
+            Self::Range(start, end) => write!(f, "{}..{}", start, end),
+            Self::ReturnValue(value) => write!(f, "{}", value),
+            Self::Break => write!(f, "break"),
+            Self::Continue => write!(f, "continue"),
+            Self::Error(error) => write!(f, "ERROR: {}", error.message),
+            Self::Function(function) => {
+                write!(f, "fn({}) {}", join(&function.parameters, ", "), function.body)
+            }
+            Self::CompiledFunction(function) => write!(f, "{}", function.source),
+            Self::Closure(closure) => write!(f, "{}", closure.function.source),
+            Self::Builtin(builtin) => write!(f, "builtin function {}", builtin.name),
+        }
+    }
+}
+
+#[derive(Clone, Copy)]
+pub struct InspectOptions {
+    // Arrays and hashes nested deeper than this are shown as `[...]` and `{...}`.
+    pub max_depth: usize,
+    // Arrays and hashes that do not fit on a line this long get one element per line.
+    pub width: usize,
+    // Arrays and hashes with more elements than this show the first ones and how many are left.
+    pub max_elements: usize,
+}
+
+impl Default for InspectOptions {
+    fn default() -> Self {
+        InspectOptions {
+            max_depth: 16,
+            width: 80,
+            max_elements: usize::MAX,
+        }
+    }
+}
+
+impl Object {
+    // Shows the object the way it would be written in source, strings quoted. This is what
+    // `Display` uses for the contents of arrays and hashes, while a plain string displays as
+    // its contents so `puts("a")` prints `a`.
+    pub fn inspect(&self, options: InspectOptions) -> String {
+        Inspector { options, path: vec![] }.inspect(self, 0, 0)
+    }
+}
+
+struct Inspector {
+    options: InspectOptions,
+    // The arrays and hashes being inspected, outermost first, to spot cycles.
+    path: Vec<*const ()>,
+}
+
+impl Inspector {
+    fn inspect(&mut self, value: &Object, depth: usize, indent: usize) -> String {
+        match value {
+            Object::String(value) => quote_string(value),
+            Object::Array(elements) => {
+                let entries = elements.iter().map(|element| (None, element)).collect();
+                self.inspect_container(("[", "]"), Rc::as_ptr(elements) as *const (), entries, depth, indent)
+            }
+            Object::Hash(pairs) => {
+                let entries = pairs.iter().map(|(key, value)| (Some(key.to_object()), value)).collect();
+                self.inspect_container(("{", "}"), Rc::as_ptr(pairs) as *const (), entries, depth, indent)
+            }
+            other => other.to_string(),
+        }
+    }
+
+    fn inspect_container(
+        &mut self,
+        (open, close): (&str, &str),
+        address: *const (),
+        entries: Vec<(Option<Object>, &Object)>,
+        depth: usize,
+        indent: usize,
+    ) -> String {
+        if entries.is_empty() {
+            return format!("{}{}", open, close);
+        }
+        if self.path.contains(&address) {
+            return format!("{}<cycle>{}", open, close);
+        }
+        if depth >= self.options.max_depth {
+            return format!("{}...{}", open, close);
+        }
+
+        let (noun, count) = (if open == "[" { "element" } else { "pair" }, entries.len());
+        self.path.push(address);
+        let mut items: Vec<String> = entries
+            .into_iter()
+            .take(self.options.max_elements)
+            .map(|(key, value)| match key {
+                None => self.inspect(value, depth + 1, indent + 2),
+                Some(key) => {
+                    let key = self.inspect(&key, depth + 1, indent + 2);
+                    format!("{}: {}", key, self.inspect(value, depth + 1, indent + 2))
+                }
+            })
+            .collect();
+        self.path.pop();
+        if count > items.len() {
+            let left = count - items.len();
+            items.push(format!("… {} more {}{}", group_thousands(left), noun, if left == 1 { "" } else { "s" }));
+        }
+
+        let compact = format!("{}{}{}", open, items.join(", "), close);
+        if !compact.contains('\n') && indent + compact.len() <= self.options.width {
+            return compact;
+        }
+
+        let item_indent = " ".repeat(indent + 2);
+        let items: Vec<String> = items.iter().map(|item| format!("{}{}", item_indent, item)).collect();
+        format!("{}\n{}\n{}{}", open, items.join(",\n"), " ".repeat(indent), close)
+    }
+}
+
+// Writes `9950` as `9,950`.
+fn group_thousands(number: usize) -> String {
+    let digits = number.to_string();
+    let mut grouped = String::new();
+    for (index, digit) in digits.chars().enumerate() {
+        if index > 0 && (digits.len() - index).is_multiple_of(3) {
+            grouped.push(',');
+        }
+        grouped.push(digit);
+    }
+    grouped
+}
+
+impl fmt::Debug for Object {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::RefCell;
+
+    use super::*;
+    use crate::evaluator::evaluate;
+    use crate::syntax_analyzer::parse;
+
+    fn evaluate_input(input: &str) -> Object {
+        let env = Rc::new(RefCell::new(Environment::new()));
+        evaluate(&parse(input).unwrap(), &env)
+    }
+
+    #[test]
+    fn it_quotes_strings_inside_arrays_and_hashes() {
+        assert_eq!(evaluate_input(r#""top level""#).to_string(), "top level");
+        assert_eq!(evaluate_input(r#"["a", {"k": "v\n"}, 1.5, null]"#).to_string(), r#"["a", {"k": "v\n"}, 1.5, null]"#);
+    }
+
+    #[test]
+    fn it_breaks_long_arrays_and_hashes_into_lines() {
+        let value = evaluate_input(r#"["a long string element", {"key": [1, 2, 3], "other": "another long string"}]"#);
+        let options = InspectOptions {
+            width: 40,
+            ..InspectOptions::default()
+        };
+        assert_eq!(
+            value.inspect(options).lines().collect::<Vec<&str>>()[0..2],
+            ["[", "  \"a long string element\","],
+        );
+        assert!(value.inspect(options).ends_with("\n  }\n]"));
+        assert!(!value.inspect(InspectOptions { width: 120, ..InspectOptions::default() }).contains('\n'));
+    }
+
+    #[test]
+    fn it_leaves_out_elements_past_the_limit() {
+        let options = InspectOptions {
+            max_elements: 3,
+            ..InspectOptions::default()
+        };
+        assert_eq!(evaluate_input("[1, 2, 3]").inspect(options), "[1, 2, 3]");
+        assert_eq!(
+            evaluate_input("[[1, 2, 3, 4], 2, 3, 4]").inspect(options),
+            "[[1, 2, 3, … 1 more element], 2, 3, … 1 more element]"
+        );
+        assert_eq!(
+            evaluate_input("map(0..10000, fn(x) { x })").inspect(options),
+            "[0, 1, 2, … 9,997 more elements]"
+        );
+        assert_eq!(evaluate_input("{1: 1, 2: 2, 3: 3, 4: 4, 5: 5}").inspect(options).matches("… 2 more pairs").count(), 1);
+        assert_eq!(group_thousands(1234567), "1,234,567");
+        assert_eq!(group_thousands(999), "999");
+    }
+
+    #[test]
+    fn it_does_not_intern_string_hash_keys() {
+        let program = parse("map(0..1000, fn(i) { {\"key \" + str(i): i} })[999][\"key 999\"]").unwrap();
+        // The names of the builtins are interned by the first evaluator on the thread.
+        let env = Rc::new(RefCell::new(Environment::new()));
+        evaluate(&parse("1").unwrap(), &env);
+        let interned = Symbol::interned();
+        assert_eq!(evaluate(&program, &env).to_string(), "999");
+        assert_eq!(Symbol::interned(), interned);
+    }
+
+    #[test]
+    fn it_only_marks_cycles_and_not_shared_values() {
+        assert_eq!(evaluate_input("let a = [1]; [a, {1: a}]").to_string(), "[[1], {1: [1]}]");
+    }
+}
@@ -0,0 +1,195 @@
+use std::rc::Rc;
+
+use crate::abstract_syntax_tree::{BlockStatement, Expression, InfixOperator, Program, Statement};
+use crate::evaluator::{eval_infix_expression, eval_prefix_expression};
+use crate::object::Object;
+
+// Replaces operators applied to literals with their result before evaluation, `2 * 3 + 4`
+// becomes `10`. Folding goes through the evaluator's own operator functions so results are
+// the same either way, and an operation that fails is left alone to fail at runtime, where
+// the error can be caught and shows up in stack traces.
+pub fn fold_constants(program: &Program) -> Program {
+    Program {
+        statements: program.statements.iter().map(fold_statement).collect(),
+        spans: program.spans.clone(),
+    }
+}
+
+fn fold_block_statement(block: &BlockStatement) -> BlockStatement {
+    BlockStatement {
+        statements: block.statements.iter().map(fold_statement).collect(),
+        spans: block.spans.clone(),
+    }
+}
+
+fn fold_statement(statement: &Statement) -> Statement {
+    match statement {
+        Statement::Let(name, value) => Statement::Let(*name, fold_expression(value)),
+        Statement::Return(value) => Statement::Return(fold_expression(value)),
+        Statement::Expression(expression) => Statement::Expression(fold_expression(expression)),
+        Statement::Break | Statement::Continue => statement.clone(),
+    }
+}
+
+fn fold_expressions(expressions: &[Expression]) -> Vec<Expression> {
+    expressions.iter().map(fold_expression).collect()
+}
+
+fn fold_expression(expression: &Expression) -> Expression {
+    match expression {
+        Expression::Identifier(_)
+        | Expression::Integer(_)
+        | Expression::Float(_)
+        | Expression::Boolean(_)
+        | Expression::String(_)
+        | Expression::Null => expression.clone(),
+        Expression::Array(elements) => Expression::Array(fold_expressions(elements)),
+        Expression::Hash(pairs) => Expression::Hash(
+            pairs
+                .iter()
+                .map(|(key, value)| (fold_expression(key), fold_expression(value)))
+                .collect(),
+        ),
+        Expression::Prefix(operator, right) => {
+            let right = fold_expression(right);
+            literal_value(&right)
+                .and_then(|value| literal_expression(eval_prefix_expression(*operator, value)))
+                .unwrap_or_else(|| Expression::Prefix(*operator, Box::new(right)))
+        }
+        Expression::Infix(operator, left, right) => fold_infix_expression(*operator, left, right),
+        Expression::Assign(name, value) => Expression::Assign(*name, Box::new(fold_expression(value))),
+        Expression::If { condition, consequence, alternative } => Expression::If {
+            condition: Box::new(fold_expression(condition)),
+            consequence: fold_block_statement(consequence),
+            alternative: alternative.as_ref().map(fold_block_statement),
+        },
+        Expression::While { condition, body } => Expression::While {
+            condition: Box::new(fold_expression(condition)),
+            body: fold_block_statement(body),
+        },
+        Expression::For { variables, iterable, body } => Expression::For {
+            variables: variables.clone(),
+            iterable: Box::new(fold_expression(iterable)),
+            body: fold_block_statement(body),
+        },
+        Expression::Try { body, variable, handler } => Expression::Try {
+            body: fold_block_statement(body),
+            variable: *variable,
+            handler: fold_block_statement(handler),
+        },
+        Expression::Function { parameters, body } => Expression::Function {
+            parameters: parameters.clone(),
+            body: Rc::new(fold_block_statement(body)),
+        },
+        Expression::Call { function, arguments, span } => Expression::Call {
+            function: Box::new(fold_expression(function)),
+            arguments: fold_expressions(arguments),
+            span: *span,
+        },
+        Expression::Index(left, index) => {
+            Expression::Index(Box::new(fold_expression(left)), Box::new(fold_expression(index)))
+        }
+    }
+}
+
+fn fold_infix_expression(operator: InfixOperator, left: &Expression, right: &Expression) -> Expression {
+    let left = fold_expression(left);
+    let right = fold_expression(right);
+
+    let folded = match (operator, literal_value(&left), literal_value(&right)) {
+        // A literal left operand decides whether the right one is evaluated, and the operand
+        // that would be evaluated last is the result.
+        (InfixOperator::And, Some(value), _) if value.is_truthy() => Some(right.clone()),
+        (InfixOperator::Or, Some(value), _) if !value.is_truthy() => Some(right.clone()),
+        (InfixOperator::And | InfixOperator::Or, Some(_), _) => Some(left.clone()),
+        (InfixOperator::And | InfixOperator::Or, None, _) => None,
+        (operator, Some(left), Some(right)) => literal_expression(eval_infix_expression(operator, left, right)),
+        _ => None,
+    };
+
+    folded.unwrap_or_else(|| Expression::Infix(operator, Box::new(left), Box::new(right)))
+}
+
+fn literal_value(expression: &Expression) -> Option<Object> {
+    match expression {
+        Expression::Integer(value) => Some(Object::Integer(*value)),
+        Expression::Float(value) => Some(Object::Float(*value)),
+        Expression::Boolean(value) => Some(Object::Boolean(*value)),
+        Expression::String(value) => Some(Object::String(value.clone())),
+        Expression::Null => Some(Object::Null),
+        _ => None,
+    }
+}
+
+// Only objects that can be written as a literal, a range or an error stays unfolded.
+fn literal_expression(value: Object) -> Option<Expression> {
+    match value {
+        Object::Integer(value) => Some(Expression::Integer(value)),
+        Object::Float(value) => Some(Expression::Float(value)),
+        Object::Boolean(value) => Some(Expression::Boolean(value)),
+        Object::String(value) => Some(Expression::String(value)),
+        Object::Null => Some(Expression::Null),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    use super::*;
+    use crate::environment::Environment;
+    use crate::evaluator::Evaluator;
+    use crate::syntax_analyzer::parse;
+
+    fn fold_input(input: &str) -> String {
+        fold_constants(&parse(input).unwrap()).to_string()
+    }
+
+    #[test]
+    fn it_works_on_arithmetic_and_boolean_expressions() {
+        assert_eq!(fold_input("2 * 3 + 4"), "10");
+        assert_eq!(fold_input("-(1 + 2) * x"), "(-3 * x)");
+        assert_eq!(fold_input("1.5 * 2.0 < 4.0 == !false"), "true");
+        assert_eq!(fold_input(r#""mon" + "key""#), r#""monkey""#);
+        assert_eq!(fold_input("null == null"), "true");
+    }
+
+    #[test]
+    fn it_works_on_nested_expressions() {
+        assert_eq!(
+            fold_input("let f = fn(x) { if x > 1 + 1 { [x * (2 + 2)] } else { {1 + 1: x} } }; f(3 - 1)"),
+            "let f = fn(x) { if (x > 2) { [(x * 4)] } else { {2: x} } }; f(2)"
+        );
+        assert_eq!(fold_input("for i in 0..2 * 5 { i }"), "for i in (0 .. 10) { i }");
+    }
+
+    #[test]
+    fn it_works_on_logical_expressions() {
+        assert_eq!(fold_input("true && x"), "x");
+        assert_eq!(fold_input("false && x"), "false");
+        assert_eq!(fold_input("0 || x"), "0");
+        assert_eq!(fold_input("null || x"), "x");
+        assert_eq!(fold_input("x && true"), "(x && true)");
+    }
+
+    #[test]
+    fn it_leaves_failing_operations_to_runtime() {
+        assert_eq!(fold_input("1 / 0"), "(1 / 0)");
+        assert_eq!(fold_input("2147483647 + 1"), "(2147483647 + 1)");
+        assert_eq!(fold_input("true + 1"), "(true + 1)");
+        assert_eq!(fold_input("try { 1 / (2 - 2) } catch (e) { e }"), "try { (1 / 0) } catch (e) { e }");
+    }
+
+    #[test]
+    fn it_works_on_evaluator_flag() {
+        let program = parse("try { 1 / (1 - 1) } catch (e) { e + \" at \" + str(2 * 21) }").unwrap();
+        let env = Rc::new(RefCell::new(Environment::new()));
+        let mut evaluator = Evaluator::new();
+        let mut folding_evaluator = Evaluator::new().with_constant_folding();
+        assert_eq!(evaluator.eval_program(&program, &env).to_string(), "division by zero at 42");
+        assert_eq!(folding_evaluator.eval_program(&program, &env).to_string(), "division by zero at 42");
+        assert!(folding_evaluator.steps() < evaluator.steps());
+    }
+}
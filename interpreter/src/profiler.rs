@@ -0,0 +1,167 @@
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::rc::Rc;
+
+use crate::clock::Clock;
+use crate::hooks::{Call, Hooks};
+use crate::lexical_analyzer::Span;
+use crate::object::Object;
+use crate::tracer::{call_names, callee_name};
+
+// How long was spent in each function, and under which calls. Self time is what a call took
+// without the calls it made.
+#[derive(Debug, Default, PartialEq)]
+pub struct Profile {
+    // The self time of every chain of calls, by the names in it joined with `;`.
+    stacks: HashMap<String, f64>,
+    functions: HashMap<String, FunctionProfile>,
+}
+
+#[derive(Debug, Default, PartialEq)]
+struct FunctionProfile {
+    calls: u64,
+    // A recursive call's time is already part of the call it was made from.
+    total: f64,
+    self_time: f64,
+}
+
+impl Profile {
+    // The stacks in the folded format flamegraph tools read, one `a;b;c <microseconds>` per line.
+    pub fn folded(&self) -> String {
+        let mut stacks: Vec<(&String, &f64)> = self.stacks.iter().collect();
+        stacks.sort_by_key(|(stack, _)| *stack);
+        stacks.iter().map(|(stack, seconds)| format!("{} {}\n", stack, (*seconds * 1e6).round() as u64)).collect()
+    }
+
+    // The functions that took the most time of their own, as a table.
+    pub fn summary(&self, rows: usize) -> String {
+        let mut functions: Vec<(&String, &FunctionProfile)> = self.functions.iter().collect();
+        functions.sort_by(|(a, first), (b, second)| second.self_time.total_cmp(&first.self_time).then(a.cmp(b)));
+        functions.truncate(rows);
+        let width = functions.iter().map(|(name, _)| name.chars().count()).chain(["function".len()]).max().unwrap_or(0);
+        let mut table = format!("{:<width$}  {:>8}  {:>12}  {:>12}\n", "function", "calls", "total", "self");
+        for (name, function) in functions {
+            table += &format!(
+                "{:<width$}  {:>8}  {:>12}  {:>12}\n",
+                name,
+                function.calls,
+                milliseconds(function.total),
+                milliseconds(function.self_time)
+            );
+        }
+        table
+    }
+}
+
+fn milliseconds(seconds: f64) -> String {
+    format!("{:.3}ms", seconds * 1000.0)
+}
+
+struct Frame {
+    name: String,
+    start: f64,
+    // The time spent in the calls made from this one so far.
+    children: f64,
+    is_builtin: bool,
+}
+
+// Times every function call, for `--profile`. What it finds goes into a profile shared with
+// whoever reads it after the run.
+pub struct Profiler {
+    clock: Clock,
+    names: HashMap<Span, String>,
+    frames: Vec<Frame>,
+    profile: Rc<RefCell<Profile>>,
+}
+
+impl Profiler {
+    // `source` is the program being profiled, for the names of the functions it calls.
+    pub fn new(source: &str, clock: Clock, profile: Rc<RefCell<Profile>>) -> Self {
+        Profiler { clock, names: call_names(source), frames: vec![], profile }
+    }
+}
+
+impl Hooks for Profiler {
+    fn on_call(&mut self, _call: Option<Call>, function: &Object, _arguments: &[Object], span: Span) {
+        let is_from_builtin = self.frames.last().is_some_and(|frame| frame.is_builtin);
+        let name = callee_name(&self.names, function, span, is_from_builtin);
+        let is_builtin = matches!(function, Object::Builtin(_));
+        self.frames.push(Frame { name, start: self.clock.now(), children: 0.0, is_builtin });
+    }
+
+    fn on_return(&mut self, _result: &Object) {
+        let Some(frame) = self.frames.pop() else {
+            return;
+        };
+        let elapsed = self.clock.now() - frame.start;
+        let self_time = elapsed - frame.children;
+        if let Some(caller) = self.frames.last_mut() {
+            caller.children += elapsed;
+        }
+
+        let mut profile = self.profile.borrow_mut();
+        let mut stack: Vec<&str> = self.frames.iter().map(|caller| caller.name.as_str()).collect();
+        stack.push(&frame.name);
+        *profile.stacks.entry(stack.join(";")).or_default() += self_time;
+        let is_recursive = self.frames.iter().any(|caller| caller.name == frame.name);
+        let function = profile.functions.entry(frame.name).or_default();
+        function.calls += 1;
+        function.self_time += self_time;
+        if !is_recursive {
+            function.total += elapsed;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::environment::Environment;
+    use crate::evaluator::Evaluator;
+    use crate::syntax_analyzer::parse_with_spans;
+
+    // Every reading of the clock is a millisecond after the one before.
+    fn profile(source: &str) -> Profile {
+        let profile = Rc::new(RefCell::new(Profile::default()));
+        let clock = Clock::Fake { now: 0.0, step: 0.001 };
+        let mut evaluator = Evaluator::new().with_hooks(Profiler::new(source, clock, Rc::clone(&profile)));
+        let env = Rc::new(RefCell::new(Environment::new()));
+        evaluator.eval_program(&parse_with_spans(source).unwrap(), &env);
+        drop(evaluator);
+        Rc::try_unwrap(profile).unwrap().into_inner()
+    }
+
+    #[test]
+    fn it_folds_stacks() {
+        let profile = profile(
+            "\
+let count = fn(n) { if (n > 0) { count(n - 1) } else { len(\"\") } };
+let main = fn() { count(1); map([1], fn(x) { x }) };
+main()",
+        );
+        assert_eq!(
+            profile.folded(),
+            "\
+main 3000
+main;count 2000
+main;count;count 2000
+main;count;count;len 1000
+main;map 2000
+main;map;<anonymous> 1000
+"
+        );
+        assert_eq!(profile.functions["count"], FunctionProfile { calls: 2, total: 0.005, self_time: 0.004 });
+    }
+
+    #[test]
+    fn it_summarizes_the_hottest_functions() {
+        let summary = profile("let f = fn() { len(\"a\") }; f(); f()").summary(1);
+        assert_eq!(
+            summary,
+            "\
+function     calls         total          self
+f                2       6.000ms       4.000ms
+"
+        );
+    }
+}
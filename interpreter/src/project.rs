@@ -0,0 +1,122 @@
+use std::path::{Path, PathBuf};
+
+use crate::config::Config;
+
+// The files of a project in the order they run, every module after the ones it depends on and the
+// entry point last. Modules that nothing the entry point depends on needs are left out. A module
+// `util/strings` is the file `util/strings.monkey` in the first source directory that has one, or
+// failing that in the first directory of `search_path` that does.
+pub fn load_order(config: &Config, root: &Path, search_path: &[PathBuf]) -> Result<Vec<PathBuf>, String> {
+    let Some(entry) = &config.entry else {
+        return Err(String::from("no entry point, set `entry` under [project]"));
+    };
+    let mut order = vec![];
+    visit(entry, config, &mut vec![], &mut order)?;
+    order.iter().map(|module| find_module(module, config, root, search_path)).collect()
+}
+
+// Adds a module to the order after its dependencies. `visiting` is the chain of modules that led to
+// it, for telling when modules depend on each other.
+fn visit(module: &str, config: &Config, visiting: &mut Vec<String>, order: &mut Vec<String>) -> Result<(), String> {
+    if order.iter().any(|done| done == module) {
+        return Ok(());
+    }
+    if let Some(start) = visiting.iter().position(|other| other == module) {
+        let cycle: Vec<&str> = visiting[start..].iter().map(String::as_str).chain([module]).collect();
+        return Err(format!("modules depend on each other: {}", cycle.join(" -> ")));
+    }
+    visiting.push(String::from(module));
+    let dependencies = config.dependencies.iter().filter(|(name, _)| name == module).flat_map(|(_, needs)| needs);
+    for dependency in dependencies {
+        visit(dependency, config, visiting, order)?;
+    }
+    visiting.pop();
+    order.push(String::from(module));
+    Ok(())
+}
+
+fn find_module(module: &str, config: &Config, root: &Path, search_path: &[PathBuf]) -> Result<PathBuf, String> {
+    let sources = match config.source_dirs.is_empty() {
+        true => vec![String::from(".")],
+        false => config.source_dirs.clone(),
+    };
+    let mut directories: Vec<PathBuf> = sources
+        .iter()
+        .map(|directory| match directory.as_str() {
+            "." => root.to_path_buf(),
+            directory => root.join(directory),
+        })
+        .collect();
+    directories.extend(search_path.iter().cloned());
+    let file = format!("{}.monkey", module);
+    if let Some(path) = directories.iter().map(|directory| directory.join(&file)).find(|path| path.is_file()) {
+        return Ok(path);
+    }
+    let mut names = sources;
+    names.extend(search_path.iter().map(|directory| directory.display().to_string()));
+    Err(format!("cannot find module {} in {}", module, names.join(", ")))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    #[test]
+    fn it_orders_modules_after_their_dependencies() {
+        let root = std::env::temp_dir().join(format!("monkey_project_test_{}", std::process::id()));
+        for directory in ["src/util", "lib"] {
+            fs::create_dir_all(root.join(directory)).unwrap();
+        }
+        for file in ["src/main.monkey", "src/parser.monkey", "src/util/strings.monkey", "lib/parser.monkey"] {
+            fs::write(root.join(file), "").unwrap();
+        }
+        fs::write(root.join("lib/math.monkey"), "").unwrap();
+        let include = root.join("include");
+        fs::create_dir_all(include.join("vendor")).unwrap();
+        fs::write(include.join("vendor/json.monkey"), "").unwrap();
+
+        let config = Config::parse(
+            "\
+[project]
+entry = \"main\"
+sources = [\"src\", \"lib\"]
+
+[dependencies]
+main = [\"parser\", \"math\", \"vendor/json\"]
+parser = [\"util/strings\"]
+math = [\"util/strings\"]
+unused = [\"missing\"]
+",
+        )
+        .unwrap();
+        assert_eq!(
+            load_order(&config, &root, &[root.join("missing"), include.clone()]),
+            Ok(vec![
+                root.join("src/util/strings.monkey"),
+                root.join("src/parser.monkey"),
+                root.join("lib/math.monkey"),
+                include.join("vendor/json.monkey"),
+                root.join("src/main.monkey"),
+            ])
+        );
+        assert_eq!(
+            load_order(&config, &root, &[]),
+            Err(String::from("cannot find module vendor/json in src, lib"))
+        );
+
+        let config = Config::parse("[project]\nentry = \"main\"\n[dependencies]\nmain = [\"missing\"]").unwrap();
+        assert_eq!(
+            load_order(&config, &root, &[PathBuf::from("/opt/monkey")]),
+            Err(String::from("cannot find module missing in ., /opt/monkey"))
+        );
+        let config = Config::parse("[project]\nentry = \"a\"\n[dependencies]\na = [\"b\"]\nb = [\"a\"]").unwrap();
+        assert_eq!(load_order(&config, &root, &[]), Err(String::from("modules depend on each other: a -> b -> a")));
+        assert_eq!(
+            load_order(&Config::default(), &root, &[]),
+            Err(String::from("no entry point, set `entry` under [project]"))
+        );
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+}
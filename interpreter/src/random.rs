@@ -0,0 +1,73 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+
+// SplitMix64: tiny, fast and good enough for scripts. Not suitable for anything that needs
+// unpredictable numbers.
+pub struct Random {
+    state: u64,
+}
+
+impl Random {
+    pub fn new(seed: u64) -> Self {
+        Random { state: seed }
+    }
+
+    // Seeded from the current time, so every run is different.
+    pub fn from_time() -> Self {
+        let nanos = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|duration| duration.as_nanos() as u64)
+            .unwrap_or_default();
+        Random::new(nanos)
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        self.state = self.state.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut value = self.state;
+        value = (value ^ (value >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        value = (value ^ (value >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        value ^ (value >> 31)
+    }
+
+    // Uniform in `[0, 1)`.
+    pub fn next_float(&mut self) -> f64 {
+        (self.next_u64() >> 11) as f64 / (1u64 << 53) as f64
+    }
+
+    // Uniform in `[0, bound)`, `bound` must not be zero.
+    pub fn below(&mut self, bound: u64) -> u64 {
+        // Rejecting the top partial range of values avoids favouring small results.
+        let zone = u64::MAX - u64::MAX % bound;
+        loop {
+            let value = self.next_u64();
+            if value < zone {
+                return value % bound;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_repeats_sequences_for_a_seed() {
+        let mut first = Random::new(42);
+        let mut second = Random::new(42);
+        let mut other = Random::new(43);
+
+        let sequence: Vec<u64> = (0..5).map(|_| first.next_u64()).collect();
+        assert_eq!(sequence, (0..5).map(|_| second.next_u64()).collect::<Vec<u64>>());
+        assert_ne!(sequence, (0..5).map(|_| other.next_u64()).collect::<Vec<u64>>());
+    }
+
+    #[test]
+    fn it_stays_in_range() {
+        let mut random = Random::new(7);
+        for _ in 0..1000 {
+            assert!(random.below(6) < 6);
+            let value = random.next_float();
+            assert!((0.0..1.0).contains(&value));
+        }
+    }
+}
@@ -0,0 +1,1831 @@
+use std::any::Any;
+use std::cell::RefCell;
+use std::fmt;
+use std::fs;
+use std::io::{self, Write};
+use std::panic::{self, AssertUnwindSafe};
+use std::rc::Rc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::Instant;
+
+use crate::abstract_syntax_tree::{join, Expression, Program, Statement};
+use crate::compiler::{compile, Bytecode, CompileError};
+use crate::completer::Completer;
+use crate::diagnostics::{with_code, Report, Severity};
+use crate::documenter::{document, Item};
+use crate::environment::{Environment, Snapshot};
+use crate::evaluator::{Evaluator, Frame, Limit};
+use crate::formatter::format_source;
+use crate::highlighter::highlight;
+use crate::lexical_analyzer::{tokenize, tokenize_checked, tokenize_with_ranges, tokenize_with_spans, Span, Token};
+use crate::interner::Symbol;
+use crate::line_editor::{Keybindings, LineReader, ReadLine, INDENT};
+use crate::linter::{lint, Warning};
+use crate::minifier::minify;
+use crate::object::{InspectOptions, Object};
+use crate::optimizer::fold_constants;
+use crate::syntax_analyzer::{parse, parse_with_marks, parse_with_spans, SyntaxError};
+use crate::syntax_dump::{dump, DumpFormat};
+use crate::terminal::{color_code, CLEAR_SCREEN};
+use crate::vm::Vm;
+
+const HELP: &str = "\
+Enter monkey code to evaluate it, or one of these commands:
+  :help     show this help
+  :quit     leave the REPL (Ctrl-D does too)
+  :env      list the bindings made so far
+  :bindings [pattern]
+            list bindings with their types and the start of their values, those matching a prefix
+            or a glob such as `*_count` only
+The last result is bound to `_`, and the last nine to `_1` (the same as `_`) through `_9`.
+  :clear    clear the screen
+  :load <file>
+            evaluate a file in this session
+  :save <file>
+            write the session's inputs and loaded files to a script that redoes what ran
+  :reset    start over with no bindings, after asking
+  :doc [builtin]
+            show a builtin's arguments and what it does, or list them all
+  :type <code>
+            evaluate code and describe the result instead of showing it
+  :show full
+            show the last result without leaving anything out
+  :time on|off
+            show how long each evaluation took and how many steps it needed
+  :paste    read lines until one holding only `.` (or Ctrl-D), then run them all at once
+  :set keybindings emacs|vi
+            switch how keys edit the line
+  :mode     show or switch what inputs display: tokens, ast or eval
+  :tokens, :ast, :eval <code>
+            show one input as that mode would";
+
+// Why a script did not run to the end, in the words the user should see.
+#[derive(Debug, PartialEq)]
+pub enum ScriptError {
+    // The file did not lex or parse, so none of it ran.
+    Syntax { file: String, errors: Vec<SyntaxError> },
+    // The file parsed but could not be compiled, or what was compiled could not be saved or read
+    // back.
+    Compile { file: String, message: String, code: Option<&'static str> },
+    // The report is the error as `--error-format json` gives it, and the limit the one the program
+    // ran into, if that is what stopped it.
+    Runtime { message: String, report: Box<Report>, limit: Option<Limit> },
+}
+
+impl ScriptError {
+    // One for each syntax error, or the runtime error.
+    pub fn reports(&self) -> Vec<Report> {
+        match self {
+            ScriptError::Syntax { file, errors } => errors
+                .iter()
+                .map(|error| Report::new(Severity::Error, Some(error.code), file, Some(error.span), &error.message))
+                .collect(),
+            ScriptError::Compile { file, message, code } => {
+                vec![Report::new(Severity::Error, *code, file, None, message)]
+            }
+            ScriptError::Runtime { report, .. } => vec![Report::clone(report)],
+        }
+    }
+}
+
+impl fmt::Display for ScriptError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ScriptError::Syntax { file, errors } => {
+                let describe = |error: &SyntaxError| {
+                    format!("{}:{}: {}", file, error.span, with_code(&error.message, Some(error.code)))
+                };
+                let lines: Vec<String> = errors.iter().map(describe).collect();
+                write!(f, "{}", lines.join("\n"))
+            }
+            ScriptError::Compile { file, message, code } => write!(f, "{}: {}", file, with_code(message, *code)),
+            ScriptError::Runtime { message, .. } => write!(f, "{}", message),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Mode {
+    // Show the tokens the lexer produces for each input.
+    Tokens,
+    // Show the syntax tree the parser builds, fully parenthesized.
+    Ast,
+    Eval,
+}
+
+impl Mode {
+    pub fn from_name(name: &str) -> Option<Mode> {
+        match name {
+            "tokens" => Some(Mode::Tokens),
+            "ast" => Some(Mode::Ast),
+            "eval" => Some(Mode::Eval),
+            _ => None,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Mode::Tokens => "tokens",
+            Mode::Ast => "ast",
+            Mode::Eval => "eval",
+        }
+    }
+}
+
+// How the prompts look. `text` starts an input and `continuation` asks for more of it, and both
+// may mention `{mode}` and `{line}`, the number of the input being read.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Prompt {
+    pub text: String,
+    pub continuation: String,
+    // One of the names `terminal::color_code` knows, used when colors are on.
+    pub color: Option<String>,
+}
+
+impl Default for Prompt {
+    fn default() -> Self {
+        Prompt {
+            text: String::from(">> "),
+            continuation: String::from(".. "),
+            color: None,
+        }
+    }
+}
+
+// A test that `monkey test` ran, named `file::test_name`, or after the file when it is one test
+// as a whole. Failing means raising an error, which is what a failed `assert()` does.
+#[derive(Debug, PartialEq)]
+pub struct TestOutcome {
+    pub name: String,
+    // The error with where it happened, as a script run would report it.
+    pub failure: Option<ScriptError>,
+}
+
+// What `:save` writes for an input or a loaded file: the code that ran, and when the input made a
+// result, the same code binding it to `_` and moving the earlier results along to `_1` through
+// `_9`, for sessions whose inputs use them.
+struct SavedInput {
+    source: String,
+    binding_result: Option<String>,
+}
+
+// One interactive session: every input is evaluated in the same environment, so bindings
+// from earlier lines stay visible to later ones.
+pub struct Repl {
+    evaluator: Evaluator,
+    env: Rc<RefCell<Environment>>,
+    // What the environment held when the session started, for `:reset` to go back to.
+    initial_bindings: Snapshot,
+    mode: Mode,
+    output: Box<dyn Write>,
+    interrupt: Option<Arc<AtomicBool>>,
+    is_running: bool,
+    uses_colors: bool,
+    // Results of earlier inputs, the latest first, bound to `_1` through `_9`.
+    recent_results: Vec<Object>,
+    // The inputs and loaded files, in order, for `:save`.
+    saved_inputs: Vec<SavedInput>,
+    shows_timing: bool,
+    prompt: Prompt,
+    // How many inputs have been read so far.
+    input_count: usize,
+    // How many lines a result may take before the rest of its elements are left out.
+    page_height: Option<usize>,
+    is_sandboxed: bool,
+}
+
+impl Repl {
+    pub fn new(evaluator: Evaluator) -> Self {
+        let env = Environment::new();
+        Repl {
+            evaluator,
+            initial_bindings: env.snapshot(),
+            env: Rc::new(RefCell::new(env)),
+            mode: Mode::Eval,
+            output: Box::new(io::stdout()),
+            interrupt: None,
+            is_running: true,
+            uses_colors: false,
+            recent_results: vec![],
+            saved_inputs: vec![],
+            shows_timing: false,
+            prompt: Prompt::default(),
+            input_count: 0,
+            page_height: None,
+            is_sandboxed: false,
+        }
+    }
+
+    pub fn with_mode(mut self, mode: Mode) -> Self {
+        self.mode = mode;
+        self
+    }
+
+    // Shows results highlighted like input and errors in red, with the call that failed
+    // underlined.
+    pub fn with_colors(mut self) -> Self {
+        self.uses_colors = true;
+        self
+    }
+
+    pub fn with_prompt(mut self, prompt: Prompt) -> Self {
+        self.prompt = prompt;
+        self
+    }
+
+    // Shortens results that would take more than `page_height` lines, so one large array does not
+    // scroll everything else away. `:show full` still prints the whole of the last one.
+    pub fn with_page_height(mut self, page_height: usize) -> Self {
+        self.page_height = Some(page_height);
+        self
+    }
+
+    // Makes the session safe to hand to strangers: programs cannot read input or touch files, each
+    // input gets its own step budget, recursion and value sizes are capped, and the commands
+    // reading and writing files are turned off.
+    pub fn with_sandbox(mut self) -> Self {
+        self.evaluator = std::mem::take(&mut self.evaluator)
+            .with_step_budget(SANDBOX_STEPS)
+            .with_max_depth(SANDBOX_DEPTH)
+            .with_memory_limit(SANDBOX_VALUE_SIZE)
+            .without_builtin("input")
+            .without_builtin("read_file")
+            .without_builtin("write_file")
+            .without_builtin("env");
+        self.is_sandboxed = true;
+        self
+    }
+
+    pub fn with_output(mut self, output: impl Write + 'static) -> Self {
+        self.output = Box::new(output);
+        self
+    }
+
+    // Lets setting `interrupt` stop the evaluation in progress. It is cleared before each input.
+    pub fn with_interrupt(mut self, interrupt: Arc<AtomicBool>) -> Self {
+        self.evaluator = std::mem::take(&mut self.evaluator).with_interrupt(Arc::clone(&interrupt));
+        self.interrupt = Some(interrupt);
+        self
+    }
+
+    // Evaluates a script before the session starts, printing only what goes wrong. What it binds
+    // counts as part of the initial bindings, so `:reset` keeps it.
+    pub fn load_prelude(&mut self, path: &str) -> io::Result<()> {
+        let output = self.load(path);
+        if !output.is_empty() {
+            writeln!(self.output, "{}", output)?;
+        }
+        self.initial_bindings = self.env.borrow().snapshot();
+        Ok(())
+    }
+
+    // A completer for the line editor, following the bindings of this session as they change.
+    pub fn completer(&self) -> Completer {
+        Completer::new(Rc::clone(&self.env), self.evaluator.builtin_names())
+    }
+
+    // Reads and handles inputs until the lines run out or the user quits.
+    pub fn run(&mut self, lines: &mut impl LineReader) -> io::Result<()> {
+        while self.is_running {
+            let input = match self.read_input(lines)? {
+                Some(input) => input,
+                None => break,
+            };
+            self.handle(&input, lines)?;
+        }
+        Ok(())
+    }
+
+    // Reads lines until they make up a complete input. A blank continuation line submits what
+    // there is, so a stray bracket can't keep the prompt waiting forever, and Ctrl-C starts over.
+    fn read_input(&mut self, lines: &mut impl LineReader) -> io::Result<Option<String>> {
+        let mut input = String::new();
+        loop {
+            let template = if input.is_empty() { &self.prompt.text } else { &self.prompt.continuation };
+            let prompt = self.render_prompt(template);
+            let line = match input.is_empty() {
+                true => lines.read_line(&prompt)?,
+                false => lines.read_indented_line(&prompt, &INDENT.repeat(block_depth(&input)))?,
+            };
+            let line = match line {
+                ReadLine::Line(line) => line,
+                ReadLine::Interrupted => {
+                    input.clear();
+                    continue;
+                }
+                ReadLine::Eof if input.is_empty() => return Ok(None),
+                ReadLine::Eof => return Ok(Some(input)),
+            };
+
+            if !input.is_empty() {
+                if line.trim().is_empty() {
+                    return Ok(Some(input));
+                }
+                input.push('\n');
+            }
+            input.push_str(&line);
+
+            if input.trim_start().starts_with(':') || !is_incomplete(&input) {
+                self.input_count += 1;
+                return Ok(Some(input));
+            }
+        }
+    }
+
+    fn render_prompt(&self, template: &str) -> String {
+        let line = (self.input_count + 1).to_string();
+        let prompt = template.replace("{mode}", self.mode.name()).replace("{line}", &line);
+        match self.prompt.color.as_deref().and_then(color_code).filter(|_| self.uses_colors) {
+            Some(color) => format!("{}{}{}", color, prompt, RESET),
+            None => prompt,
+        }
+    }
+
+    fn handle(&mut self, input: &str, lines: &mut impl LineReader) -> io::Result<()> {
+        if let Some(interrupt) = &self.interrupt {
+            interrupt.store(false, Ordering::Relaxed);
+        }
+        // Limits hold for each input like they do for each script.
+        self.evaluator.reset_steps();
+
+        // A bug in the interpreter should cost the input that hit it, not the whole session.
+        let output = panic::catch_unwind(AssertUnwindSafe(|| match input.trim().strip_prefix(':') {
+            Some(command) => self.run_command(command, lines),
+            None => Ok(self.run_code(input, self.mode)),
+        }))
+        .unwrap_or_else(|payload| Ok(format!("internal error: {}", panic_message(payload.as_ref()))))?;
+        if !output.is_empty() {
+            writeln!(self.output, "{}", output)?;
+        }
+        self.output.flush()
+    }
+
+    // Commands can read further lines, to ask for confirmation for example.
+    fn run_command(&mut self, command: &str, lines: &mut impl LineReader) -> io::Result<String> {
+        let (command, argument) = match command.trim().split_once(char::is_whitespace) {
+            Some((command, argument)) => (command, argument.trim()),
+            None => (command.trim(), ""),
+        };
+
+        if let Some(mode) = Mode::from_name(command) {
+            return Ok(self.run_code(argument, mode));
+        }
+
+        let output = match command {
+            "help" => String::from(HELP),
+            "mode" if argument.is_empty() => format!("mode: {}", self.mode.name()),
+            "mode" => match Mode::from_name(argument) {
+                Some(mode) => {
+                    self.mode = mode;
+                    String::new()
+                }
+                None => format!("unknown mode: {} (expected tokens, ast or eval)", argument),
+            },
+            "quit" | "q" => {
+                self.is_running = false;
+                String::new()
+            }
+            "env" => self
+                .env
+                .borrow()
+                .bindings()
+                .iter()
+                .filter(|(name, _)| !is_result_variable(&name.name()))
+                .map(|(name, value)| format!("{} = {}", name, value.inspect(InspectOptions::default())))
+                .collect::<Vec<String>>()
+                .join("\n"),
+            "bindings" => self.list_bindings(argument),
+            "load" | "save" if self.is_sandboxed => format!(":{} is not available in the sandbox", command),
+            "load" if argument.is_empty() => String::from("usage: :load <file>"),
+            "load" => self.load(argument),
+            "save" if argument.is_empty() => String::from("usage: :save <file>"),
+            "save" => {
+                let uses_results = self.saved_inputs.iter().any(|input| uses_result_variables(&input.source));
+                let mut script = String::new();
+                for input in &self.saved_inputs {
+                    match (&input.binding_result, uses_results) {
+                        (Some(binding_result), true) => script += binding_result,
+                        _ => script += &input.source,
+                    }
+                    script.push('\n');
+                }
+                match fs::write(argument, script) {
+                    Ok(()) => format!("saved {} inputs to {}", self.saved_inputs.len(), argument),
+                    Err(error) => self.paint(&format!("cannot write {}: {}", argument, error), RED),
+                }
+            }
+            "time" if argument.is_empty() => format!("timing: {}", if self.shows_timing { "on" } else { "off" }),
+            "time" => match argument {
+                "on" | "off" => {
+                    self.shows_timing = argument == "on";
+                    String::new()
+                }
+                _ => String::from("usage: :time on|off"),
+            },
+            "paste" => {
+                writeln!(self.output, "pasting, end with a line holding only `.` or with Ctrl-D")?;
+                let mut block = vec![];
+                loop {
+                    match lines.read_line("")? {
+                        ReadLine::Line(line) if line.trim() == "." => break,
+                        ReadLine::Line(line) => block.push(line),
+                        ReadLine::Eof => break,
+                        ReadLine::Interrupted => return Ok(String::from("paste cancelled")),
+                    }
+                }
+                self.run_code(&block.join("\n"), self.mode)
+            }
+            "show" if argument != "full" => String::from("usage: :show full"),
+            "show" => match self.recent_results.first() {
+                Some(result) => self.render(&result.clone(), usize::MAX),
+                None => String::from("no result to show yet"),
+            },
+            "set" => match argument.split_once(char::is_whitespace) {
+                Some(("keybindings", name)) => match Keybindings::from_name(name.trim()) {
+                    Some(keybindings) => {
+                        lines.set_keybindings(keybindings);
+                        format!("keybindings: {}", keybindings.name())
+                    }
+                    None => format!("unknown keybindings: {} (expected emacs or vi)", name.trim()),
+                },
+                _ => String::from("usage: :set keybindings emacs|vi"),
+            },
+            "doc" if argument.is_empty() => {
+                let builtins = self.evaluator.builtins();
+                let width = builtins.iter().map(|builtin| builtin.name.len()).max().unwrap_or(0);
+                builtins
+                    .iter()
+                    .map(|builtin| format!("{:width$}  {}", builtin.name, builtin.description, width = width))
+                    .collect::<Vec<String>>()
+                    .join("\n")
+            }
+            "doc" => match self.evaluator.builtins().into_iter().find(|builtin| builtin.name == argument) {
+                Some(builtin) => format!("{}\n  {}", builtin.signature(), builtin.description),
+                None => format!("no builtin named {} (type :doc for a list)", argument),
+            },
+            "type" if argument.is_empty() => String::from("usage: :type <code>"),
+            "type" => self.describe_type(argument),
+            "reset" => match lines.read_line("forget every binding made in this session? [y/N] ")? {
+                ReadLine::Line(answer) if matches!(answer.trim(), "y" | "yes") => {
+                    self.env.borrow_mut().restore(&self.initial_bindings);
+                    self.recent_results.clear();
+                    self.saved_inputs.clear();
+                    String::from("session reset")
+                }
+                _ => String::from("reset cancelled"),
+            },
+            "clear" => String::from(CLEAR_SCREEN),
+            command => format!("unknown command: :{} (type :help for a list)", command),
+        };
+        Ok(output)
+    }
+
+    // Returns what should be shown for the input, which is empty when there is nothing worth
+    // printing, like the `null` a `let` statement evaluates to.
+    fn run_code(&mut self, input: &str, mode: Mode) -> String {
+        match mode {
+            Mode::Tokens => format!(
+                "{:?}",
+                tokenize(input).iter().map(|token| format!("{:?}", token)).collect::<Vec<String>>()
+            ),
+            Mode::Ast => match parse(input) {
+                Ok(program) => join(&program.statements, "\n"),
+                Err(errors) => errors.join("\n"),
+            },
+            Mode::Eval if self.shows_timing => {
+                let (started, steps) = (Instant::now(), self.evaluator.steps());
+                let output = self.evaluate(input);
+                let timing = format!("({:?}, {})", started.elapsed(), plural(self.evaluator.steps() - steps, "step"));
+                [output, timing].iter().filter(|text| !text.is_empty()).cloned().collect::<Vec<_>>().join("\n")
+            }
+            Mode::Eval => self.evaluate(input),
+        }
+    }
+
+    fn evaluate(&mut self, input: &str) -> String {
+        let program = match parse_with_spans(input) {
+            Ok(program) => program,
+            Err(errors) => {
+                let errors: Vec<String> =
+                    errors.iter().map(|error| self.describe_error(input, Some(error.span), &error.message)).collect();
+                return errors.join("\n");
+            }
+        };
+
+        let result = self.evaluator.eval_program(&program, &self.env);
+        let binding_result = match &result {
+            Object::Error(_) | Object::Null => None,
+            _ => bind_result(input, &program, (self.recent_results.len() + 1).min(RECENT_RESULTS)),
+        };
+        let source = match result {
+            Object::Error(_) => self.statements_before_error(input, &program),
+            _ => input,
+        };
+        if !source.is_empty() {
+            self.saved_inputs.push(SavedInput { source: String::from(source), binding_result });
+        }
+
+        match result {
+            Object::Null => String::new(),
+            result @ Object::Error(_) => {
+                // The outermost call is the one made by this input, the others may be in code
+                // entered earlier.
+                let span = self.evaluator.stack_trace().last().map(|frame| frame.span);
+                let mut lines = vec![self.describe_error(input, span, &result.to_string())];
+                lines.extend(collapse_repeats(self.evaluator.stack_trace().iter().map(|frame| format!("  {}", frame))));
+                lines.join("\n")
+            }
+            result => {
+                self.remember_result(&result);
+                self.render(&result, self.page_height.unwrap_or(usize::MAX))
+            }
+        }
+    }
+
+    // Inspects a result in at most `max_lines` lines, showing fewer elements of its arrays and
+    // hashes until it fits.
+    fn render(&self, result: &Object, max_lines: usize) -> String {
+        let mut options = InspectOptions::default();
+        let mut rendered = result.inspect(options);
+        if rendered.lines().count() > max_lines {
+            // Room for the brackets and the line saying what was left out.
+            options.max_elements = max_lines.saturating_sub(3).max(1);
+            rendered = result.inspect(options);
+            while rendered.lines().count() > max_lines && options.max_elements > 1 {
+                options.max_elements /= 2;
+                rendered = result.inspect(options);
+            }
+            rendered += " (:show full)";
+        }
+
+        match self.uses_colors {
+            true => highlight(&rendered, usize::MAX),
+            false => rendered,
+        }
+    }
+
+    // Evaluates a file in the session's environment, so everything it defines can be used at the
+    // prompt afterwards. Errors are reported at their position in the file.
+    fn load(&mut self, path: &str) -> String {
+        let source = match fs::read_to_string(path) {
+            Ok(source) => source,
+            Err(error) => return self.paint(&format!("cannot read {}: {}", path, error), RED),
+        };
+        let source = skip_shebang(&source);
+        let result = self.run_script(source, path);
+        let ran = match (&result, parse_with_spans(source)) {
+            (Ok(_), _) => source.trim_end(),
+            (Err(ScriptError::Runtime { .. }), Ok(program)) => self.statements_before_error(source, &program),
+            _ => "",
+        };
+        if !ran.is_empty() {
+            let source = format!("// :load {}\n{}", path, ran);
+            self.saved_inputs.push(SavedInput { source, binding_result: None });
+        }
+        match result {
+            Ok(_) => String::new(),
+            Err(error @ ScriptError::Runtime { .. }) => error.to_string(),
+            Err(error) => self.paint(&error.to_string(), RED),
+        }
+    }
+
+    // The statements of `program`, the code in `source`, that ran before the one the last error
+    // happened in, which keeps the bindings they made. The error is in the outermost call, the one
+    // `source` made, if it passed through one.
+    fn statements_before_error<'a>(&self, source: &'a str, program: &Program) -> &'a str {
+        let location = match self.evaluator.stack_trace().last() {
+            Some(frame) => Some(frame.span),
+            None => self.evaluator.error_span(),
+        };
+        let start = |span: &Span| (span.line, span.column);
+        let failed =
+            location.and_then(|location| program.spans.iter().rposition(|span| start(span) <= start(&location)));
+        match failed.and_then(|index| offset_of(source, program.spans[index])) {
+            Some(offset) => source[..offset].trim_end(),
+            None => "",
+        }
+    }
+
+    // Evaluates a whole program in the session's environment at once, the way `:load` does a
+    // file, and returns the program's value. The error says where it happened, with positions
+    // prefixed by `name`: a runtime error is placed at the innermost call it passed through, if it
+    // passed through one.
+    pub fn run_script(&mut self, source: &str, name: &str) -> Result<Object, ScriptError> {
+        let program = parse_with_spans(source).map_err(|errors| syntax_error(name, errors))?;
+        self.run_program(&program, name)
+    }
+
+    // Runs a test file, see `TestOutcome`. Every top-level `let test_... = fn() { ... }` is called in
+    // turn, with the bindings put back to what the file itself left before each call so that tests
+    // cannot see what other tests did. A file without test functions is a test of its own.
+    pub fn run_tests(&mut self, source: &str, name: &str) -> Result<Vec<TestOutcome>, ScriptError> {
+        let program = parse_with_spans(source).map_err(|errors| syntax_error(name, errors))?;
+        Ok(self.run_parsed_tests(&program, source, name))
+    }
+
+    // Like `run_tests`, for a test file that was already parsed into `program`.
+    pub fn run_parsed_tests(&mut self, program: &Program, source: &str, name: &str) -> Vec<TestOutcome> {
+        if let Err(error) = self.run_program(program, name) {
+            return vec![TestOutcome { name: String::from(name), failure: Some(error) }];
+        }
+
+        let tests = self.find_functions(source, "test_");
+        if tests.is_empty() {
+            return vec![TestOutcome { name: String::from(name), failure: None }];
+        }
+        let bindings = self.env.borrow().snapshot();
+        let mut outcomes = vec![];
+        for (test, span) in tests {
+            self.env.borrow_mut().restore(&bindings);
+            outcomes.push(TestOutcome {
+                name: format!("{}::{}", name, test),
+                failure: self.call_function(test, span, name).err(),
+            });
+        }
+        outcomes
+    }
+
+    // The functions the program in `source` bound to names starting with `prefix` at the top level,
+    // once it ran, in the order they are defined in and with where each one's definition starts.
+    pub fn find_functions(&self, source: &str, prefix: &str) -> Vec<(Symbol, Span)> {
+        let tokens = tokenize_with_spans(source);
+        let mut functions: Vec<(Symbol, Span)> = vec![];
+        for pair in tokens.windows(2) {
+            if let [(Token::Let, span), (Token::Identifier(name), _)] = pair {
+                let name = Symbol::intern(name);
+                let is_function = matches!(self.env.borrow().get(name), Some(Object::Function(_)));
+                let is_new = functions.iter().all(|(function, _)| *function != name);
+                if name.name().starts_with(prefix) && is_function && is_new {
+                    functions.push((name, *span));
+                }
+            }
+        }
+        functions
+    }
+
+    // Calls a function without arguments which `find_functions` found, the way `run_script` runs a
+    // program. `span` is where the call is said to be in stack traces.
+    pub fn call_function(&mut self, function: Symbol, span: Span, name: &str) -> Result<Object, ScriptError> {
+        let call = Expression::Call { function: Box::new(Expression::Identifier(function)), arguments: vec![], span };
+        let program = Program { statements: vec![Statement::Expression(call)], spans: vec![span] };
+        self.run_program(&program, name)
+    }
+
+    // Runs a program `monkey compile` compiled, on the virtual machine and with the session's
+    // evaluator, and reports an error as `run_script` does. Its globals are its own, not the
+    // session's bindings.
+    pub fn run_bytecode(&mut self, bytecode: Bytecode, name: &str) -> Result<Object, ScriptError> {
+        self.evaluator.set_file(Some(name));
+        let result = Vm::new(bytecode).run(&mut self.evaluator);
+        self.evaluator.set_file(None);
+        self.check_result(result, name)
+    }
+
+    fn run_program(&mut self, program: &Program, name: &str) -> Result<Object, ScriptError> {
+        self.evaluator.set_file(Some(name));
+        let result = self.evaluator.eval_program(program, &self.env);
+        self.evaluator.set_file(None);
+        self.check_result(result, name)
+    }
+
+    // Places the error in the file of the code it happened in, which is `name` unless the code is that
+    // of a function another program defined.
+    fn check_result(&self, result: Object, name: &str) -> Result<Object, ScriptError> {
+        match result {
+            Object::Error(error) => {
+                let file_of = |frame: &Frame| String::from(frame.file.as_deref().unwrap_or(name));
+                let stack_trace = self.evaluator.stack_trace();
+                let (file, span) = match (self.evaluator.error_span(), stack_trace.first()) {
+                    (Some(span), _) => (String::from(self.evaluator.error_file().unwrap_or(name)), Some(span)),
+                    (None, Some(frame)) => (file_of(frame), Some(frame.span)),
+                    (None, None) => (String::from(name), None),
+                };
+                let stack = stack_trace.iter().map(|frame| (frame.function.clone(), file_of(frame), frame.span));
+                let report = Report::for_error(&file, span, &error, stack.collect());
+                let location = match span {
+                    Some(span) => format!("{}:{}", file, span),
+                    None => file,
+                };
+                let message = format!("ERROR: {}", with_code(&error.message, error.code));
+                let mut lines = vec![self.paint(&format!("{}: {}", location, message), RED)];
+                let stack =
+                    report.stack.iter().map(|(function, file, at)| format!("  at {} ({}:{})", function, file, at));
+                lines.extend(collapse_repeats(stack));
+                let limit = self.evaluator.exceeded_limit();
+                Err(ScriptError::Runtime { message: lines.join("\n"), report: Box::new(report), limit })
+            }
+            result => Ok(result),
+        }
+    }
+
+    fn describe_type(&mut self, input: &str) -> String {
+        let program = match parse(input) {
+            Ok(program) => program,
+            Err(errors) => return self.paint(&errors.join("\n"), RED),
+        };
+
+        let result = self.evaluator.eval_program(&program, &self.env);
+        let count = |count: usize, noun: &str| format!("{} ({})", result.type_name(), plural(count, noun));
+        match &result {
+            Object::Error(_) => self.paint(&result.to_string(), RED),
+            Object::String(string) => count(string.chars().count(), "character"),
+            Object::Array(elements) => count(elements.len(), "element"),
+            Object::Hash(pairs) => count(pairs.len(), "pair"),
+            Object::Range(start, end) => format!("RANGE ({}..{})", start, end),
+            Object::Function(function) => format!("FUNCTION fn({})", join(&function.parameters, ", ")),
+            Object::Builtin(builtin) => format!("BUILTIN {}", builtin.name),
+            _ => String::from(result.type_name()),
+        }
+    }
+
+    fn list_bindings(&self, pattern: &str) -> String {
+        let bindings: Vec<(Rc<str>, Object)> = self
+            .env
+            .borrow()
+            .bindings()
+            .into_iter()
+            .map(|(name, value)| (name.name(), value))
+            .filter(|(name, _)| !is_result_variable(name) && matches_pattern(pattern, name))
+            .collect();
+        if bindings.is_empty() {
+            return match pattern.is_empty() {
+                true => String::from("no bindings yet"),
+                false => format!("no bindings match {}", pattern),
+            };
+        }
+
+        let name_width = bindings.iter().map(|(name, _)| name.chars().count()).max().unwrap_or(0);
+        let type_width = bindings.iter().map(|(_, value)| value.type_name().len()).max().unwrap_or(0);
+        bindings
+            .iter()
+            .map(|(name, value)| {
+                format!("{:name_width$}  {:type_width$}  {}", name, value.type_name(), preview(value))
+                    .trim_end()
+                    .to_string()
+            })
+            .collect::<Vec<String>>()
+            .join("\n")
+    }
+
+    fn remember_result(&mut self, result: &Object) {
+        self.recent_results.insert(0, result.clone());
+        self.recent_results.truncate(RECENT_RESULTS);
+
+        let mut env = self.env.borrow_mut();
+        env.set(Symbol::intern("_"), result.clone());
+        for (index, result) in self.recent_results.iter().enumerate() {
+            env.set(Symbol::intern(&format!("_{}", index + 1)), result.clone());
+        }
+    }
+
+    // The message, beneath the line of the input the error is on with a caret pointing at it
+    // when the error has a position.
+    fn describe_error(&self, input: &str, span: Option<Span>, message: &str) -> String {
+        match span.and_then(|span| snippet(input, span)) {
+            Some((line, carets)) => format!("{}\n{}\n{}", line, self.paint(&carets, RED), self.paint(message, RED)),
+            None => self.paint(message, RED),
+        }
+    }
+
+    fn paint(&self, text: &str, color: &str) -> String {
+        match self.uses_colors {
+            true => format!("{}{}{}", color, text, RESET),
+            false => String::from(text),
+        }
+    }
+}
+
+// What the REPL greets an interactive session with: the version, the optional features this run has
+// turned on and where to find the commands.
+pub fn banner(features: &[&str]) -> String {
+    let mut banner = format!("Welcome to monkey interpreter {}!\n", env!("CARGO_PKG_VERSION"));
+    if !features.is_empty() {
+        banner += &format!("Enabled: {}\n", features.join(", "));
+    }
+    banner + "Type :help for a list of commands.\n"
+}
+
+const RECENT_RESULTS: usize = 9;
+
+// The limits `with_sandbox` puts on each input.
+const SANDBOX_STEPS: usize = 1_000_000;
+const SANDBOX_DEPTH: usize = 200;
+const SANDBOX_VALUE_SIZE: usize = 1_000_000;
+
+// Drops the `#!/usr/bin/env monkey` line an executable script starts with, keeping its line
+// break so that positions in the rest of the file stay the same.
+pub fn skip_shebang(source: &str) -> &str {
+    match source.starts_with("#!") {
+        true => &source[source.find('\n').unwrap_or(source.len())..],
+        false => source,
+    }
+}
+
+// What `monkey --tokens` prints for a program: every token with where it starts, one per line.
+pub fn list_tokens(source: &str, name: &str) -> Result<String, ScriptError> {
+    let (tokens, error) = tokenize_checked(source);
+    if let Some(error) = error {
+        let error = SyntaxError { message: error.message, code: error.code, span: error.span };
+        return Err(syntax_error(name, vec![error]));
+    }
+
+    let positions: Vec<String> = tokens.iter().map(|(_, span)| span.to_string()).collect();
+    let width = positions.iter().map(String::len).max().unwrap_or(0);
+    let lines: Vec<String> = tokens
+        .iter()
+        .zip(&positions)
+        .map(|((token, _), position)| format!("{:width$}  {:?}", position, token, width = width))
+        .collect();
+    Ok(lines.join("\n"))
+}
+
+// What `monkey --ast` prints for a program: each statement as the parser read it, on its own line.
+pub fn list_statements(source: &str, name: &str) -> Result<String, ScriptError> {
+    let program = parse_with_spans(source).map_err(|errors| syntax_error(name, errors))?;
+    Ok(join(&program.statements, "\n"))
+}
+
+// What `monkey ast` prints for a program, see `dump`.
+pub fn dump_syntax(source: &str, name: &str, format: DumpFormat) -> Result<String, ScriptError> {
+    let program = parse_with_spans(source).map_err(|errors| syntax_error(name, errors))?;
+    Ok(dump(&program, format))
+}
+
+// What `monkey doc` reads from a program, see `document`.
+pub fn document_program(source: &str, name: &str) -> Result<Vec<Item>, ScriptError> {
+    document(source).map_err(|errors| syntax_error(name, errors))
+}
+
+// What `monkey check` reports for a program: every error the lexer and the parser find in it.
+pub fn check_program(source: &str, name: &str) -> Result<(), ScriptError> {
+    parse_with_spans(source).map(|_| ()).map_err(|errors| syntax_error(name, errors))
+}
+
+// What `monkey lint` finds in a program, see `lint`.
+pub fn lint_program(source: &str, name: &str) -> Result<Vec<Warning>, ScriptError> {
+    lint(source).map_err(|errors| syntax_error(name, errors))
+}
+
+// What `monkey minify` prints for a program, see `minify`.
+pub fn minify_program(source: &str, name: &str) -> Result<String, ScriptError> {
+    minify(source).map_err(|errors| syntax_error(name, errors))
+}
+
+// A program with the marks `monkey test --coverage` finds its statements by, see `parse_with_marks`.
+pub fn parse_marked_program(source: &str, name: &str) -> Result<(Program, Vec<Span>), ScriptError> {
+    parse_with_marks(source).map_err(|errors| syntax_error(name, errors))
+}
+
+// What `monkey compile` saves for a program, with its constants folded first if `optimize`.
+pub fn compile_program(source: &str, name: &str, optimize: bool) -> Result<Bytecode, ScriptError> {
+    let mut program = parse_with_spans(source).map_err(|errors| syntax_error(name, errors))?;
+    if optimize {
+        program = fold_constants(&program);
+    }
+    compile(&program)
+        .map_err(|CompileError { message, code }| ScriptError::Compile { file: String::from(name), message, code })
+}
+
+// What `monkey fmt` prints for a program, see `format_source`.
+pub fn format_program(source: &str, name: &str) -> Result<String, ScriptError> {
+    format_source(source).map_err(|errors| syntax_error(name, errors))
+}
+
+fn syntax_error(name: &str, errors: Vec<SyntaxError>) -> ScriptError {
+    ScriptError::Syntax { file: String::from(name), errors }
+}
+
+// The lines of a stack trace, with a run of the same call, as deep recursion makes, written once
+// and followed by how many more times it was made. Two in a row are left as they are.
+fn collapse_repeats(lines: impl Iterator<Item = String>) -> Vec<String> {
+    let mut collapsed: Vec<String> = vec![];
+    let mut repeats = 0;
+    let flush = |collapsed: &mut Vec<String>, repeats: usize| match repeats {
+        0 => {}
+        1 => collapsed.push(collapsed[collapsed.len() - 1].clone()),
+        repeats => collapsed.push(format!("  ... (repeated {} times)", repeats)),
+    };
+    for line in lines {
+        if collapsed.last() == Some(&line) {
+            repeats += 1;
+            continue;
+        }
+        flush(&mut collapsed, repeats);
+        repeats = 0;
+        collapsed.push(line);
+    }
+    flush(&mut collapsed, repeats);
+    collapsed
+}
+
+fn plural(count: usize, noun: &str) -> String {
+    format!("{} {}{}", count, noun, if count == 1 { "" } else { "s" })
+}
+
+const PREVIEW_WIDTH: usize = 60;
+
+// The first line of a value as the REPL would show it, cut down to fit in a listing.
+fn preview(value: &Object) -> String {
+    let options = InspectOptions {
+        max_depth: 2,
+        width: usize::MAX,
+        max_elements: 3,
+    };
+    let inspected = value.inspect(options);
+    let line = inspected.lines().next().unwrap_or_default();
+    match line.chars().count() > PREVIEW_WIDTH || line.len() < inspected.len() {
+        true => format!("{}…", line.chars().take(PREVIEW_WIDTH - 1).collect::<String>()),
+        false => String::from(line),
+    }
+}
+
+// A pattern with `*` (any characters) or `?` (any one character) has to match the whole name,
+// any other pattern only its start.
+fn matches_pattern(pattern: &str, name: &str) -> bool {
+    if !pattern.contains(['*', '?']) {
+        return name.starts_with(pattern);
+    }
+
+    let pattern: Vec<char> = pattern.chars().collect();
+    let name: Vec<char> = name.chars().collect();
+    let (mut p, mut n) = (0, 0);
+    // Where the last `*` was and how much of the name it covers so far, to backtrack to.
+    let mut star = None;
+    while n < name.len() {
+        match pattern.get(p) {
+            Some('*') => {
+                star = Some((p, n));
+                p += 1;
+            }
+            Some(&character) if character == '?' || character == name[n] => {
+                p += 1;
+                n += 1;
+            }
+            _ => match star {
+                Some((star_p, star_n)) => {
+                    star = Some((star_p, star_n + 1));
+                    p = star_p + 1;
+                    n = star_n + 1;
+                }
+                None => return false,
+            },
+        }
+    }
+    pattern[p..].iter().all(|&character| character == '*')
+}
+
+// Where the token starting at `span` starts in `input`.
+fn offset_of(input: &str, span: Span) -> Option<usize> {
+    let index = tokenize_with_spans(input).iter().position(|(_, token_span)| *token_span == span)?;
+    tokenize_with_ranges(input).get(index).map(|(_, range)| range.start)
+}
+
+// The input with the value of its last statement bound to `_`, then the `count` results the session
+// now remembers bound to `_1` through `_9` the way `remember_result` does, the earlier ones moving
+// along first.
+fn bind_result(input: &str, program: &Program, count: usize) -> Option<String> {
+    let start = offset_of(input, *program.spans.last()?)?;
+    let tokens = tokenize_with_ranges(input);
+    let (last, end) = tokens.last()?;
+    let bound = match program.statements.last()? {
+        Statement::Expression(_) => format!("{}let _ = {}", &input[..start], &input[start..end.end]),
+        Statement::Return(_) => format!("{}let _ ={}", &input[..start], &input[start + "return".len()..end.end]),
+        _ => return None,
+    };
+    let terminator = if *last == Token::Semicolon { "" } else { ";" };
+    let mut moves: Vec<String> = (2..=count).rev().map(|index| format!("let _{} = _{};", index, index - 1)).collect();
+    moves.push(String::from("let _1 = _;"));
+    Some(format!("{}{}{}\n{}", bound, terminator, input[end.end..].trim_end(), moves.join(" ")))
+}
+
+// Whether the code uses the REPL's `_` result variables.
+fn uses_result_variables(source: &str) -> bool {
+    tokenize(source).iter().any(|token| matches!(token, Token::Identifier(name) if is_result_variable(name)))
+}
+
+// Whether the binding is one of the REPL's own `_` result variables rather than the user's.
+fn is_result_variable(name: &str) -> bool {
+    matches!(name.strip_prefix('_'), Some(rest) if rest.len() <= 1 && rest.chars().all(|digit| digit.is_ascii_digit()))
+}
+
+const RED: &str = "\x1b[31m";
+const RESET: &str = "\x1b[0m";
+
+// The line of the input `span` is on, with carets under the token starting there.
+fn snippet(input: &str, span: Span) -> Option<(String, String)> {
+    let line = input.lines().nth(span.line - 1)?;
+    let width = tokenize_with_spans(input)
+        .iter()
+        .position(|(_, token_span)| *token_span == span)
+        .and_then(|index| tokenize_with_ranges(input).get(index).map(|(_, range)| range.len()))
+        .unwrap_or(1);
+    Some((String::from(line), format!("{}{}", " ".repeat(span.column - 1), "^".repeat(width))))
+}
+
+// How many blocks are left open at the end of the input, for indenting the line that continues it.
+fn block_depth(input: &str) -> usize {
+    let mut depth: usize = 0;
+    for token in tokenize(input) {
+        match token {
+            Token::LeftBrace => depth += 1,
+            Token::RightBrace => depth = depth.saturating_sub(1),
+            _ => {}
+        }
+    }
+    depth
+}
+
+// Whether the input stops partway through, with brackets left open or the parser running out of
+// tokens mid-expression, so the REPL should keep reading lines instead of reporting an error.
+pub fn is_incomplete(input: &str) -> bool {
+    let mut depth = 0;
+    for token in tokenize(input) {
+        match token {
+            Token::LeftParen | Token::LeftBrace | Token::LeftBracket => depth += 1,
+            Token::RightParen | Token::RightBrace | Token::RightBracket => depth -= 1,
+            _ => {}
+        }
+    }
+    if depth > 0 {
+        return true;
+    }
+
+    match parse(input) {
+        Ok(_) => false,
+        Err(errors) => errors.iter().any(|error| error.ends_with("got end of input") || error == "unterminated string"),
+    }
+}
+
+fn panic_message(payload: &(dyn Any + Send)) -> &str {
+    match payload.downcast_ref::<&str>() {
+        Some(message) => message,
+        None => payload.downcast_ref::<String>().map_or("unknown panic", String::as_str),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::builtins::standard_builtins;
+    use crate::random::Random;
+
+    #[test]
+    fn it_works_on_banner() {
+        let version = env!("CARGO_PKG_VERSION");
+        assert_eq!(
+            banner(&[]),
+            format!("Welcome to monkey interpreter {}!\nType :help for a list of commands.\n", version)
+        );
+        assert_eq!(
+            banner(&["colors", "filesystem access"]),
+            format!(
+                "Welcome to monkey interpreter {}!\nEnabled: colors, filesystem access\nType :help for a list of commands.\n",
+                version
+            )
+        );
+    }
+    use std::collections::VecDeque;
+
+    #[derive(Clone, Default)]
+    struct SharedOutput(Rc<RefCell<Vec<u8>>>);
+
+    impl Write for SharedOutput {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.borrow_mut().write(buf)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    // Lines typed at the prompt, with the prompts shown for them recorded in the transcript.
+    struct ScriptedLines {
+        lines: VecDeque<String>,
+        output: SharedOutput,
+    }
+
+    impl LineReader for ScriptedLines {
+        fn read_line(&mut self, prompt: &str) -> io::Result<ReadLine> {
+            let line = self.lines.pop_front();
+            match line {
+                Some(line) if line == "^C" => {
+                    writeln!(self.output, "{}^C", prompt)?;
+                    Ok(ReadLine::Interrupted)
+                }
+                Some(line) => {
+                    writeln!(self.output, "{}{}", prompt, line)?;
+                    Ok(ReadLine::Line(line))
+                }
+                None => Ok(ReadLine::Eof),
+            }
+        }
+
+        fn set_keybindings(&mut self, keybindings: Keybindings) {
+            writeln!(self.output, "<{} keybindings>", keybindings.name()).unwrap();
+        }
+
+        // Types each line after the indentation, taking a level back off for a leading `}` the
+        // way the editor does.
+        fn read_indented_line(&mut self, prompt: &str, indent: &str) -> io::Result<ReadLine> {
+            let indent = match self.lines.front() {
+                Some(line) if line.starts_with('}') => indent.strip_prefix(INDENT).unwrap_or(indent),
+                _ => indent,
+            };
+            let prompt = format!("{}{}", prompt, indent);
+            match self.read_line(&prompt)? {
+                ReadLine::Line(line) => Ok(ReadLine::Line(format!("{}{}", indent, line))),
+                line => Ok(line),
+            }
+        }
+    }
+
+    // Runs a whole session with the REPL `make_repl` builds around an evaluator, and returns its
+    // transcript, prompts and typed lines included.
+    fn session_with(make_repl: impl FnOnce(Evaluator) -> Repl, input: &str) -> String {
+        let output = SharedOutput::default();
+        let mut repl = make_repl(Evaluator::new().with_output(output.clone())).with_output(output.clone());
+        let mut lines = ScriptedLines {
+            lines: input.lines().map(String::from).collect(),
+            output: output.clone(),
+        };
+        repl.run(&mut lines).unwrap();
+        let transcript = String::from_utf8(output.0.borrow().clone()).unwrap();
+        transcript
+    }
+
+    fn session(input: &str) -> String {
+        session_with(Repl::new, input)
+    }
+
+    // Each of these once crashed the REPL or could, the session has to answer them all and still
+    // work at the end. The random token soup leaves out loops so that every input finishes.
+    #[test]
+    fn it_survives_adversarial_input() {
+        let nested = |open: &str, close: &str| format!("{}1{}", open.repeat(1000), close.repeat(1000));
+        let mut inputs = vec![
+            String::from("99999999999"),
+            String::from("-2147483647 - 1"),
+            String::from("[1][2147483647]"),
+            String::from("int(\"99999999999\")"),
+            String::from("len(-2147483647..2147483647)"),
+            String::from("1 / 0"),
+            String::from("٣ + 1"),
+            String::from("\"\\"),
+            String::from("@"),
+            String::from("break"),
+            String::from("format(\"{\")"),
+            String::from("let f = fn(x) { 1 + x(x) }; f(f)"),
+            String::from("map(0..10, fn(x) { x(x) })"),
+            nested("(", ")"),
+            nested("[", "]"),
+            nested("-", ""),
+            nested("fn() { ", " }"),
+            "1 + ".repeat(1000) + "1",
+        ];
+        let pieces = [
+            "1", "0", "-", "+", "*", "/", "%", "==", "(", ")", "[", "]", "{", "}", ",", ":", ";", "..", "\"a\"", "x", "f",
+            "fn(x)", "if", "else", "return", "true", "null", "len", "push", "[]", "{}",
+        ];
+        let mut random = Random::new(159);
+        for _ in 0..300 {
+            let length = random.below(12) + 1;
+            let soup: Vec<&str> = (0..length).map(|_| pieces[random.below(pieces.len() as u64) as usize]).collect();
+            inputs.push(soup.join(" "));
+        }
+        // Unfinished inputs wait for more, the blank line after each one submits it as it is.
+        let input = inputs.iter().map(|input| format!("{}\n\n", input)).collect::<String>() + "1 + 1";
+
+        // The default recursion limit needs more stack than the test harness hands its threads.
+        let transcript = std::thread::Builder::new()
+            .stack_size(64 * 1024 * 1024)
+            .spawn(move || session(&input))
+            .unwrap()
+            .join()
+            .unwrap();
+        assert!(transcript.contains("integer literal too large: 99999999999"));
+        assert!(transcript.contains("expression nested too deeply"));
+        assert!(!transcript.contains("internal error"));
+        assert!(transcript.ends_with(">> 1 + 1\n2\n"), "{}", transcript);
+    }
+
+    #[test]
+    fn it_lists_bindings() {
+        let transcript = session(
+            ":bindings\nlet user_count = 3;\nlet admin_count = 1;\nlet names = [\"ann\", \"bob\", \"cy\", \"dee\"];\n\
+             let greet = fn(name) { \"hi \" + name };\nlet story = \"once upon a time there was a monkey who wrote an interpreter\";\n\
+             1 + 1\n:bindings\n:bindings *_count\n:bindings na\n:bindings ?ser*\n:bindings zz",
+        );
+        assert_eq!(
+            transcript.lines().filter(|line| !line.starts_with(">> let")).collect::<Vec<&str>>(),
+            Vec::from([
+                ">> :bindings",
+                "no bindings yet",
+                ">> 1 + 1",
+                "2",
+                ">> :bindings",
+                "admin_count  INTEGER   1",
+                "greet        FUNCTION  fn(name) { (\"hi \" + name) }",
+                "names        ARRAY     [\"ann\", \"bob\", \"cy\", … 1 more element]",
+                "story        STRING    \"once upon a time there was a monkey who wrote an interpret…",
+                "user_count   INTEGER   3",
+                ">> :bindings *_count",
+                "admin_count  INTEGER  1",
+                "user_count   INTEGER  3",
+                ">> :bindings na",
+                "names  ARRAY  [\"ann\", \"bob\", \"cy\", … 1 more element]",
+                ">> :bindings ?ser*",
+                "user_count  INTEGER  3",
+                ">> :bindings zz",
+                "no bindings match zz",
+            ])
+        );
+    }
+
+    #[test]
+    fn it_matches_binding_patterns() {
+        let tests = vec![
+            ("", "anything", true),
+            ("us", "user", true),
+            ("us", "a_user", false),
+            ("*_count", "user_count", true),
+            ("*_count", "user_counts", false),
+            ("u*r*t", "user_count", true),
+            ("?", "x", true),
+            ("?", "xy", false),
+            ("a*b*c", "abxbc", true),
+            ("a*b*c", "abxbd", false),
+            ("*", "", true),
+        ];
+
+        for (pattern, name, expected) in tests {
+            assert_eq!(matches_pattern(pattern, name), expected, "{} {}", pattern, name);
+        }
+    }
+
+    #[test]
+    fn it_keeps_bindings_across_inputs() {
+        assert_eq!(
+            session("let x = 5;\nx * 2\nlet greet = fn(name) { \"hi \" + name }\n[greet(\"monkey\"), x]"),
+            ">> let x = 5;\n>> x * 2\n10\n>> let greet = fn(name) { \"hi \" + name }\n>> [greet(\"monkey\"), x]\n\
+             [\"hi monkey\", 5]\n"
+        );
+    }
+
+    #[test]
+    fn it_reports_errors() {
+        assert_eq!(
+            session("let = 5\nlet f = fn() { 1 / 0 };\nf()\ny"),
+            ">> let = 5\nlet = 5\n    ^\nexpected identifier, got <=, =>\n>> let f = fn() { 1 / 0 };\n>> f()\n\
+             f()\n^\nERROR: division by zero\n  at f (1:1)\n>> y\nERROR: identifier not found: y\n"
+        );
+    }
+
+    #[test]
+    fn it_points_at_errors_in_their_line() {
+        assert_eq!(
+            session("let add = fn(a, b) {\na + b\n};\n[1,\n  add(1)]\nlet x = 1 @ 2\nlet s = \"open\n\n"),
+            ">> let add = fn(a, b) {\n..   a + b\n.. };\n>> [1,\n..   add(1)]\n  add(1)]\n  ^^^\n\
+             ERROR: wrong number of arguments: expected 2, got 1\n  at add (2:3)\n>> let x = 1 @ 2\nlet x = 1 @ 2\n\
+             \x20         ^\nunexpected character `@`\n>> let s = \"open\n.. \nlet s = \"open\n        ^\nunterminated string\n"
+        );
+    }
+
+    #[test]
+    fn it_colors_results_and_errors() {
+        assert_eq!(
+            session_with(|evaluator| Repl::new(evaluator).with_colors(), "[1, \"a\", true]\nlet = 1\n1 + true"),
+            ">> [1, \"a\", true]\n[\x1b[33m1\x1b[0m, \x1b[32m\"a\"\x1b[0m, \x1b[33mtrue\x1b[0m]\n>> let = 1\n\
+             let = 1\n\x1b[31m    ^\x1b[0m\n\x1b[31mexpected identifier, got <=, =>\x1b[0m\n>> 1 + true\n\
+             \x1b[31mERROR: type mismatch: INTEGER + BOOLEAN\x1b[0m\n"
+        );
+        assert_eq!(
+            session_with(
+                |evaluator| Repl::new(evaluator).with_colors(),
+                "let f = fn() { 1 / 0 };\nlet g = fn() { f() };\n[1,\n  g()]"
+            ),
+            ">> let f = fn() { 1 / 0 };\n>> let g = fn() { f() };\n>> [1,\n..   g()]\n\
+             \x20 g()]\n\x1b[31m  ^\x1b[0m\n\x1b[31mERROR: division by zero\x1b[0m\n  at f (1:16)\n  at g (2:3)\n"
+        );
+    }
+
+    #[test]
+    fn it_binds_recent_results() {
+        assert_eq!(
+            session("_\n1 + 1\n_ * 10\nlet x = 5;\nputs(_)\n[_, _1, _2]\n_3\n_9\n:env"),
+            ">> _\nERROR: identifier not found: _\n>> 1 + 1\n2\n>> _ * 10\n20\n>> let x = 5;\n>> puts(_)\n20\n\
+             >> [_, _1, _2]\n[20, 20, 2]\n>> _3\n2\n>> _9\nERROR: identifier not found: _9\n>> :env\nx = 5\n"
+        );
+    }
+
+    #[test]
+    fn it_loads_files() {
+        let directory = std::env::temp_dir().join(format!("monkey_load_test_{}", std::process::id()));
+        fs::create_dir_all(&directory).unwrap();
+        let helpers = directory.join("helpers.monkey");
+        let source = "let double = fn(x) { x * 2 };\nlet fail = fn() {\n  1 / 0\n};\nputs(\"loaded\")\n";
+        fs::write(&helpers, source).unwrap();
+        let broken = directory.join("broken.monkey");
+        fs::write(&broken, "let x = 1;\nlet = 2;\n").unwrap();
+        let failing = directory.join("failing.monkey");
+        fs::write(&failing, "let y = 1;\n\n  fail()").unwrap();
+
+        let input = format!(
+            ":load {}\ndouble(21)\n:load {}\n:load {}\ny\n:load {}",
+            helpers.display(),
+            broken.display(),
+            failing.display(),
+            directory.join("missing.monkey").display()
+        );
+        let transcript = session(&input);
+        let lines: Vec<&str> = transcript.lines().filter(|line| !line.starts_with(">> ")).collect();
+        let error = format!("{}:2:5: expected identifier, got <=, => [E0004]", broken.display());
+        assert_eq!(lines[..3], ["loaded", "42", &error]);
+        assert_eq!(
+            lines[3..6],
+            [
+                &format!("{}:3:3: ERROR: division by zero [E0105]", helpers.display()),
+                &format!("  at fail ({}:3:3)", failing.display()),
+                "1"
+            ]
+        );
+        assert!(lines[6].starts_with(&format!("cannot read {}: ", directory.join("missing.monkey").display())));
+
+        fs::remove_dir_all(&directory).unwrap();
+    }
+
+    #[test]
+    fn it_saves_the_inputs_that_succeeded() {
+        let file = std::env::temp_dir().join(format!("monkey_save_test_{}.monkey", std::process::id()));
+        let input = format!(
+            "let double = fn(x) {{\nx * 2\n}}\ndouble(true)\nlet = 1\n:mode ast\n1 + 1\n:mode eval\ndouble(2)\n:save {}",
+            file.display()
+        );
+        assert!(session(&input).ends_with(&format!("saved 2 inputs to {}\n", file.display())));
+        assert_eq!(fs::read_to_string(&file).unwrap(), "let double = fn(x) {\n  x * 2\n}\ndouble(2)\n");
+        assert!(session(&format!(":load {}\ndouble(5)", file.display())).ends_with(">> double(5)\n10\n"));
+        fs::remove_file(&file).unwrap();
+    }
+
+    #[test]
+    fn it_saves_sessions_that_run_as_scripts() {
+        let directory = std::env::temp_dir().join(format!("monkey_save_script_test_{}", std::process::id()));
+        fs::create_dir_all(&directory).unwrap();
+        let (helpers, file) = (directory.join("helpers.monkey"), directory.join("session.monkey"));
+        fs::write(&helpers, "let double = fn(x) { x * 2 };\nlet y = 3;\n1 / 0;\nlet z = 4;\n").unwrap();
+        let input = format!(
+            ":load {}\nlet b = 2; 1 / 0\ndouble(b)\n_ + y // one more\nreturn [_, _1, _2]\n:save {}",
+            helpers.display(),
+            file.display()
+        );
+        assert!(session(&input).ends_with(&format!("saved 5 inputs to {}\n", file.display())));
+        assert_eq!(
+            fs::read_to_string(&file).unwrap(),
+            format!(
+                "// :load {}\nlet double = fn(x) {{ x * 2 }};\nlet y = 3;\nlet b = 2;\n\
+                 let _ = double(b);\nlet _1 = _;\nlet _ = _ + y; // one more\nlet _2 = _1; let _1 = _;\n\
+                 let _ = [_, _1, _2];\nlet _3 = _2; let _2 = _1; let _1 = _;\n",
+                helpers.display()
+            )
+        );
+
+        let mut repl = Repl::new(Evaluator::new());
+        let script = fs::read_to_string(&file).unwrap();
+        assert!(repl.run_script(&script, &file.display().to_string()).is_ok());
+        let result = repl.run_script("[b, _, _1, _2, _3]", "check").unwrap();
+        assert_eq!(result.to_string(), "[2, [7, 7, 4], [7, 7, 4], 7, 4]");
+
+        fs::remove_dir_all(&directory).unwrap();
+    }
+
+    #[test]
+    fn it_resets_after_confirmation() {
+        assert_eq!(
+            session("let x = 1;\nlet f = fn() { x };\n:reset\nno\nf()\n:reset\ny\nx\nf\nlet x = 2;\n:env"),
+            ">> let x = 1;\n>> let f = fn() { x };\n>> :reset\nforget every binding made in this session? [y/N] no\n\
+             reset cancelled\n>> f()\n1\n>> :reset\nforget every binding made in this session? [y/N] y\nsession reset\n\
+             >> x\nERROR: identifier not found: x\n>> f\nERROR: identifier not found: f\n>> let x = 2;\n>> :env\nx = 2\n"
+        );
+    }
+
+    #[test]
+    fn it_loads_preludes_into_the_initial_bindings() {
+        let prelude = std::env::temp_dir().join(format!("monkey_prelude_test_{}.monkey", std::process::id()));
+        fs::write(&prelude, "let square = fn(x) { x * x };\n").unwrap();
+
+        let path = prelude.display().to_string();
+        let transcript = session_with(
+            |evaluator| {
+                let mut repl = Repl::new(evaluator);
+                repl.load_prelude(&path).unwrap();
+                repl
+            },
+            "square(3)\n:reset\ny\nsquare(4)",
+        );
+        assert_eq!(
+            transcript,
+            ">> square(3)\n9\n>> :reset\nforget every binding made in this session? [y/N] y\nsession reset\n>> square(4)\n16\n"
+        );
+        fs::remove_file(&prelude).unwrap();
+    }
+
+    #[test]
+    fn it_indents_continued_blocks() {
+        assert_eq!(
+            session("let f = fn(x) {\nif (x) {\n1\n}\n}\nf(true)"),
+            ">> let f = fn(x) {\n..   if (x) {\n..     1\n..   }\n.. }\n>> f(true)\n1\n"
+        );
+    }
+
+    #[test]
+    fn it_shortens_results_taller_than_the_page() {
+        let transcript = session_with(
+            |evaluator| Repl::new(evaluator).with_page_height(6),
+            ":show full\n[1, 2]\nmap(0..10000, fn(x) { x * 1000 })\n:show full\n:show",
+        );
+        let lines: Vec<&str> = transcript.lines().collect();
+        assert_eq!(
+            lines[..7],
+            [
+                ">> :show full",
+                "no result to show yet",
+                ">> [1, 2]",
+                "[1, 2]",
+                ">> map(0..10000, fn(x) { x * 1000 })",
+                "[0, 1000, 2000, … 9,997 more elements] (:show full)",
+                ">> :show full",
+            ]
+        );
+        assert_eq!(lines[7..10008].first(), Some(&"["));
+        assert_eq!(lines[7..10008].last(), Some(&"  9999000"));
+        assert_eq!(lines[10008..], ["]", ">> :show", "usage: :show full"]);
+    }
+
+    #[test]
+    fn it_documents_builtins() {
+        let transcript = session(":doc len\n:doc nope\n:doc");
+        let lines: Vec<&str> = transcript.lines().collect();
+        assert_eq!(
+            lines[..5],
+            [
+                ">> :doc len",
+                "len(value)",
+                "  Counts the characters of a string or the elements of an array or hash.",
+                ">> :doc nope",
+                "no builtin named nope (type :doc for a list)",
+            ]
+        );
+        assert_eq!(lines[6], "args      Returns the script's command line arguments as an array of strings.");
+        assert_eq!(lines[7], "assert    Fails with where it was called when the condition is falsy.");
+        assert_eq!(lines.len(), 6 + standard_builtins().len());
+    }
+
+    #[test]
+    fn it_sandboxes_the_session() {
+        let input = "input()\nread_file(\"/etc/passwd\")\n:load /etc/passwd\n:save out.monkey\nwhile (true) {}\n\
+                     let f = fn(n) { 1 + f(n) }; f(0)\nlet s = \"a\"; while (true) { s = s + s }\n1 + 1";
+        // The default recursion limit needs more stack than the test harness hands its threads.
+        let transcript = std::thread::Builder::new()
+            .stack_size(64 * 1024 * 1024)
+            .spawn(move || {
+                session_with(|evaluator| Repl::new(evaluator.with_filesystem_access()).with_sandbox(), input)
+            })
+            .unwrap()
+            .join()
+            .unwrap();
+        let errors: Vec<&str> =
+            transcript.lines().filter(|line| !line.starts_with(">> ") && !line.starts_with("  ")).collect();
+        assert_eq!(
+            errors,
+            [
+                "ERROR: identifier not found: input",
+                "ERROR: identifier not found: read_file",
+                ":load is not available in the sandbox",
+                ":save is not available in the sandbox",
+                "ERROR: evaluation budget exceeded",
+                "let f = fn(n) { 1 + f(n) }; f(0)",
+                "ERROR: maximum recursion depth exceeded",
+                "ERROR: memory limit exceeded: STRING of 1048576 bytes (at most 1000000)",
+                "2",
+            ]
+        );
+    }
+
+    #[test]
+    fn it_runs_scripts_as_one_program() {
+        let output = SharedOutput::default();
+        let mut repl = Repl::new(Evaluator::new().with_output(output.clone()));
+        let mut run = |source: &str| {
+            repl.run_script(source, "<stdin>").map(|value| value.to_string()).map_err(|error| error.to_string())
+        };
+        assert_eq!(run("let x = 1;\nputs(x + 2)"), Ok(String::from("null")));
+        assert_eq!(run("[x, \"a\"]"), Ok(String::from("[1, \"a\"]")));
+        assert_eq!(
+            run("let f = fn() { 1 / 0 };\nputs(x)\nf()"),
+            Err(String::from("<stdin>:1:16: ERROR: division by zero [E0105]\n  at f (<stdin>:3:1)"))
+        );
+        assert_eq!(run("1 / 0"), Err(String::from("<stdin>:1:1: ERROR: division by zero [E0105]")));
+        assert_eq!(
+            run("error(\"division by zero\")"),
+            Err(String::from("<stdin>:1:1: ERROR: division by zero [E0123]\n  at error (<stdin>:1:1)"))
+        );
+        assert_eq!(
+            run("let = 1"),
+            Err(String::from("<stdin>:1:5: expected identifier, got <=, => [E0004]"))
+        );
+        let reports = repl.run_script("error(\"first\\nsecond: part\")", "a.monkey").unwrap_err().reports();
+        assert_eq!(
+            reports.iter().map(|report| (report.message.as_str(), report.code)).collect::<Vec<_>>(),
+            [("first\nsecond: part", Some("E0123"))]
+        );
+        assert_eq!(String::from_utf8(output.0.borrow().clone()).unwrap(), "3\n1\n");
+
+        let mut repl = Repl::new(Evaluator::new().with_max_depth(5));
+        let run = |repl: &mut Repl, source: &str| repl.run_script(source, "<stdin>").map_err(|error| error.to_string());
+        assert_eq!(
+            run(&mut repl, "let f = fn(n) { 1 + f(n) };\nf(0)").err(),
+            Some(String::from(
+                "<stdin>:1:21: ERROR: maximum recursion depth exceeded [E0114]\n  at f (<stdin>:1:21)\n  \
+                 ... (repeated 4 times)\n  at f (<stdin>:2:1)"
+            ))
+        );
+        let input = "let f = fn(n) { if (n == 0) { 1 / 0 } else { 1 + f(n - 1) } };\nf(2)";
+        assert_eq!(
+            run(&mut repl, input).err(),
+            Some(String::from(
+                "<stdin>:1:31: ERROR: division by zero [E0105]\n  at f (<stdin>:1:50)\n  at f (<stdin>:1:50)\n  \
+                 at f (<stdin>:2:1)"
+            ))
+        );
+    }
+
+    #[test]
+    fn it_places_errors_in_the_module_they_happen_in() {
+        let mut repl = Repl::new(Evaluator::new());
+        let util = "let double = fn(x) { 1 / 0 };\nlet twice = fn(x) { double(x) };\nlet last = fn(x) { double(x) }";
+        assert!(repl.run_script(util, "src/util.monkey").is_ok());
+        let error = repl.run_script("let y = 1;\ntwice(y)", "src/main.monkey").unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "src/util.monkey:1:22: ERROR: division by zero [E0105]\n  at double (src/util.monkey:2:21)\n  \
+             at twice (src/main.monkey:2:1)"
+        );
+        let report = &error.reports()[0];
+        assert_eq!((report.file.as_str(), report.span), ("src/util.monkey", Some(Span { line: 1, column: 22 })));
+        let frame = (String::from("twice"), String::from("src/main.monkey"), Span { line: 2, column: 1 });
+        assert_eq!(report.stack[1], frame);
+
+        // A tail call is placed where it is made too.
+        let error = repl.run_script("last(3)", "src/main.monkey").unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "src/util.monkey:1:22: ERROR: division by zero [E0105]\n  at double (src/util.monkey:3:20)\n  \
+             at last (src/main.monkey:1:1)"
+        );
+    }
+
+    #[test]
+    fn it_runs_compiled_programs() {
+        let output = SharedOutput::default();
+        let mut repl = Repl::new(Evaluator::new().with_output(output.clone()));
+        let bytecode = compile_program("let f = fn() { 1 / 0 };\nputs(2 + 3)\nf()", "a.monkey", true).unwrap();
+        assert_eq!(
+            repl.run_bytecode(bytecode, "a.mbc").map_err(|error| error.to_string()).err(),
+            Some(String::from("a.mbc:1:16: ERROR: division by zero [E0105]\n  at f (a.mbc:3:1)"))
+        );
+        assert_eq!(String::from_utf8(output.0.borrow().clone()).unwrap(), "5\n");
+        assert_eq!(
+            compile_program("break", "a.monkey", false).map_err(|error| error.to_string()).err(),
+            Some(String::from("a.monkey: break outside loop [E0113]"))
+        );
+    }
+
+    #[test]
+    fn it_runs_tests() {
+        let source = "\
+let total = 0;
+let test_adds = fn() { total = total + 1; assert(total == 1) };
+let test_adds_again = fn() { total = total + 1; assert(total == 1, \"isolated\") };
+let helper = fn() { let test_nested = 1; test_nested };
+let test_fails = fn() {
+  assert(helper() == 2, \"helper\")
+};
+let test_value = 5;";
+        let outcome = |name: &str, failure: Option<&str>| (String::from(name), failure.map(String::from));
+        let run = |source: &str, name: &str| {
+            let outcomes = Repl::new(Evaluator::new()).run_tests(source, name).map_err(|error| error.to_string())?;
+            let outcomes = outcomes.into_iter().map(|TestOutcome { name, failure }| {
+                (name, failure.map(|failure| failure.to_string()))
+            });
+            Ok::<_, String>(outcomes.collect::<Vec<_>>())
+        };
+        assert_eq!(
+            run(source, "a_test.monkey"),
+            Ok(Vec::from([
+                outcome("a_test.monkey::test_adds", None),
+                outcome("a_test.monkey::test_adds_again", None),
+                outcome(
+                    "a_test.monkey::test_fails",
+                    Some(
+                        "a_test.monkey:6:3: ERROR: assertion failed at 6:3: helper [E0119]\n  at assert \
+                         (a_test.monkey:6:3)\n  at test_fails (a_test.monkey:5:1)"
+                    ),
+                ),
+            ]))
+        );
+
+        let run = |source: &str| run(source, "b_test.monkey");
+        assert_eq!(run("assert(1 < 2)"), Ok(Vec::from([outcome("b_test.monkey", None)])));
+        assert_eq!(
+            run("let test_a = fn() { 1 };\n1 / 0"),
+            Ok(Vec::from([outcome("b_test.monkey", Some("b_test.monkey:2:1: ERROR: division by zero [E0105]"))]))
+        );
+        assert_eq!(run("let = 1"), Err(String::from("b_test.monkey:1:5: expected identifier, got <=, => [E0004]")));
+    }
+
+    #[test]
+    fn it_skips_shebang_lines() {
+        assert_eq!(skip_shebang("#!/usr/bin/env monkey\nputs(1)"), "\nputs(1)");
+        assert_eq!(skip_shebang("#!/usr/bin/env monkey"), "");
+        assert_eq!(skip_shebang("puts(1)\n#!"), "puts(1)\n#!");
+        assert_eq!(
+            list_tokens(skip_shebang("#!/usr/bin/env monkey\nx"), "a.monkey"),
+            Ok(String::from("2:1  <identifier, x>"))
+        );
+    }
+
+    #[test]
+    fn it_lists_tokens_and_statements() {
+        assert_eq!(
+            list_tokens("let x = 10;\nx", "a.monkey"),
+            Ok(String::from(
+                "1:1   <let, let>\n1:5   <identifier, x>\n1:7   <=, =>\n1:9   <integer, 10>\n1:11  <;, ;>\n\
+                 2:1   <identifier, x>"
+            ))
+        );
+        assert_eq!(
+            list_tokens("1 @", "a.monkey").map_err(|error| error.to_string()),
+            Err(String::from("a.monkey:1:3: unexpected character `@` [E0003]"))
+        );
+        assert_eq!(
+            list_statements("let x = 1 + 2 * 3;\nputs(x)", "a.monkey"),
+            Ok(String::from("let x = (1 + (2 * 3));\nputs(x)"))
+        );
+        assert_eq!(
+            list_statements("let = 1;\n1 +", "a.monkey").map_err(|error| error.to_string()),
+            Err(String::from(
+                "a.monkey:1:5: expected identifier, got <=, => [E0004]\na.monkey:2:3: expected expression, got end \
+                 of input [E0005]"
+            ))
+        );
+    }
+
+    #[test]
+    fn it_checks_programs() {
+        assert_eq!(check_program("let x = 1;\nputs(x)", "a.monkey"), Ok(()));
+        assert_eq!(
+            check_program("let x = ;\nlet = 2;\nx @ 1", "a.monkey").map_err(|error| error.to_string()),
+            Err(String::from(
+                "a.monkey:1:9: expected expression, got <;, ;> [E0005]\na.monkey:2:5: expected identifier, got <=, \
+                 => [E0004]\na.monkey:3:3: unexpected character `@` [E0003]"
+            ))
+        );
+        let reports = check_program("let = 1", "a: b.monkey").unwrap_err().reports();
+        assert_eq!(
+            (reports[0].file.as_str(), reports[0].span, reports[0].code),
+            ("a: b.monkey", Some(Span { line: 1, column: 5 }), Some("E0004"))
+        );
+        // Nothing is evaluated, so errors a run would hit are not reported.
+        assert_eq!(check_program("let x = 1 / 0;\nundefined()", "a.monkey"), Ok(()));
+    }
+
+    #[test]
+    fn it_switches_keybindings() {
+        assert_eq!(
+            session(":set keybindings vi\n:set keybindings ed\n:set\n:set keybindings emacs"),
+            ">> :set keybindings vi\n<vi keybindings>\nkeybindings: vi\n>> :set keybindings ed\n\
+             unknown keybindings: ed (expected emacs or vi)\n>> :set\nusage: :set keybindings emacs|vi\n\
+             >> :set keybindings emacs\n<emacs keybindings>\nkeybindings: emacs\n"
+        );
+    }
+
+    #[test]
+    fn it_describes_types() {
+        let transcript = session(
+            "let add = fn(a, b) { a + b };\n:type add\n:type [1, 2, 3]\n:type {\"a\": 1}\n:type \"héllo\"\n:type \"a\"\n\
+             :type 0..10\n:type len\n:type add(1, 2)\n:type null\n:type add(1)\n:type\n_",
+        );
+        let lines: Vec<&str> = transcript.lines().filter(|line| !line.starts_with(">> ")).collect();
+        assert_eq!(
+            lines,
+            [
+                "FUNCTION fn(a, b)",
+                "ARRAY (3 elements)",
+                "HASH (1 pair)",
+                "STRING (5 characters)",
+                "STRING (1 character)",
+                "RANGE (0..10)",
+                "BUILTIN len",
+                "INTEGER",
+                "NULL",
+                "ERROR: wrong number of arguments: expected 2, got 1",
+                "usage: :type <code>",
+                "ERROR: identifier not found: _",
+            ]
+        );
+    }
+
+    #[test]
+    fn it_shows_timing() {
+        let transcript = session(":time\n:time on\n1 + 2\nlet x = 1;\n:time off\n1 + 2\n:time maybe");
+        let lines: Vec<&str> = transcript.lines().filter(|line| !line.starts_with(">> ")).collect();
+        assert_eq!(lines[..2], ["timing: off", "3"]);
+        assert!(lines[2].starts_with('(') && lines[2].ends_with(", 3 steps)"));
+        assert!(lines[3].ends_with(", 1 step)"));
+        assert_eq!(lines[4..], ["3", "usage: :time on|off"]);
+    }
+
+    #[test]
+    fn it_pastes_blocks() {
+        assert_eq!(
+            session(":paste\nlet f = fn(x) {\n\n  x + 1\n}\nf(1)\n.\nf(2)\n:paste\n1 +\n^C\n:paste\n1 + 2"),
+            ">> :paste\npasting, end with a line holding only `.` or with Ctrl-D\nlet f = fn(x) {\n\n  x + 1\n}\nf(1)\n.\n2\n\
+             >> f(2)\n3\n>> :paste\npasting, end with a line holding only `.` or with Ctrl-D\n1 +\n^C\npaste cancelled\n\
+             >> :paste\npasting, end with a line holding only `.` or with Ctrl-D\n1 + 2\n3\n"
+        );
+    }
+
+    #[test]
+    fn it_renders_configured_prompts() {
+        let prompt = Prompt {
+            text: String::from("[{line}] {mode}> "),
+            continuation: String::from("[{line}] ...> "),
+            color: Some(String::from("cyan")),
+        };
+        assert_eq!(
+            session_with(|evaluator| Repl::new(evaluator).with_prompt(prompt.clone()), "1\n:mode ast\n[1,\n2]"),
+            "[1] eval> 1\n1\n[2] eval> :mode ast\n[3] ast> [1,\n[3] ...> 2]\n[1, 2]\n"
+        );
+        assert_eq!(
+            session_with(|evaluator| Repl::new(evaluator).with_prompt(prompt).with_colors(), "null"),
+            "\x1b[36m[1] eval> \x1b[0mnull\n"
+        );
+    }
+
+    #[test]
+    fn it_shows_tokens_in_tokens_mode() {
+        assert_eq!(
+            session_with(|evaluator| Repl::new(evaluator).with_mode(Mode::Tokens), "let x = 5"),
+            ">> let x = 5\n[\"<let, let>\", \"<identifier, x>\", \"<=, =>\", \"<integer, 5>\"]\n"
+        );
+    }
+
+    #[test]
+    fn it_continues_incomplete_input() {
+        assert_eq!(
+            session("let f = fn(x) {\nx * 2\n}\nf(4)\n[1,\n\n(1 +\n^C\n1"),
+            ">> let f = fn(x) {\n..   x * 2\n.. }\n>> f(4)\n8\n>> [1,\n.. \n[1,\n  ^\nexpected expression, got end of input\n\
+             >> (1 +\n.. ^C\n>> 1\n1\n"
+        );
+    }
+
+    #[test]
+    fn it_runs_commands() {
+        assert_eq!(session(":help").lines().nth(1), Some("Enter monkey code to evaluate it, or one of these commands:"));
+        assert_eq!(
+            session("let b = [1];\nlet a = \"x\";\n:env\n:nope\n:quit\n1"),
+            ">> let b = [1];\n>> let a = \"x\";\n>> :env\na = \"x\"\nb = [1]\n>> :nope\n\
+             unknown command: :nope (type :help for a list)\n>> :quit\n"
+        );
+        assert_eq!(session(":clear"), ">> :clear\n\x1b[2J\x1b[H\n");
+    }
+
+    #[test]
+    fn it_switches_modes() {
+        assert_eq!(
+            session(":mode\n:mode ast\n1 + 2 * 3; let x = -1\n:eval 1 + 2 * 3\n:tokens x\n:mode vm\n:mode eval\nx"),
+            ">> :mode\nmode: eval\n>> :mode ast\n>> 1 + 2 * 3; let x = -1\n(1 + (2 * 3))\nlet x = (-1);\n\
+             >> :eval 1 + 2 * 3\n7\n>> :tokens x\n[\"<identifier, x>\"]\n>> :mode vm\n\
+             unknown mode: vm (expected tokens, ast or eval)\n>> :mode eval\n>> x\nERROR: identifier not found: x\n"
+        );
+    }
+
+    #[test]
+    fn it_works_on_incomplete_input() {
+        assert!(is_incomplete("fn(x) {"));
+        assert!(is_incomplete("let add = fn(a, b) {\n  a +"));
+        assert_eq!(block_depth("let f = fn() {\n  if (x) {"), 2);
+        assert_eq!(block_depth("{ \"}\" }}"), 0);
+        assert!(is_incomplete("[1, 2,"));
+        assert!(is_incomplete("let x ="));
+        assert!(is_incomplete("if (x > 1) { 1 } else"));
+        assert!(is_incomplete("puts(\"multiline\n"));
+
+        assert!(!is_incomplete(""));
+        assert!(!is_incomplete("let x = 5;"));
+        assert!(!is_incomplete("fn(x) {\n  x * 2\n}(3)"));
+        assert!(!is_incomplete("let = 5"));
+        assert!(!is_incomplete("1 + 2)"));
+    }
+}
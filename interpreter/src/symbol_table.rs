@@ -0,0 +1,276 @@
+use std::collections::HashMap;
+
+use crate::interner::Symbol;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Scope {
+    Global,
+    // A parameter or `let` of the function being compiled, kept in its frame.
+    Local,
+    // A local of an enclosing function that this one uses.
+    Free,
+    Builtin,
+}
+
+// Where the compiler finds a name, the index being the slot in its scope.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Binding {
+    pub scope: Scope,
+    pub index: usize,
+}
+
+#[derive(Default)]
+struct Block {
+    // The names defined in the block, with what they were bound to before it.
+    defined: Vec<(Symbol, Option<Binding>)>,
+    // As for a function.
+    declared: Vec<Symbol>,
+}
+
+// The names defined in the program, or in the function being compiled. A function's table encloses
+// the table of the function it is defined in, and the outermost one holds the globals and builtins.
+#[derive(Default)]
+pub struct SymbolTable {
+    outer: Option<Box<SymbolTable>>,
+    bindings: HashMap<Symbol, Binding>,
+    // The names of the slots, by index.
+    names: Vec<Symbol>,
+    // What the free bindings of this table are in the enclosing one, by free index.
+    free: Vec<Binding>,
+    // The names the function defines somewhere, for functions in it to use them before that.
+    declared: Vec<Symbol>,
+    // Every block being compiled, innermost last.
+    blocks: Vec<Block>,
+    // How many locals the program's frame has, for the names its blocks define.
+    program_locals: usize,
+}
+
+impl SymbolTable {
+    pub fn new() -> Self {
+        SymbolTable::default()
+    }
+
+    pub fn new_enclosed(outer: SymbolTable) -> Self {
+        SymbolTable { outer: Some(Box::new(outer)), ..SymbolTable::default() }
+    }
+
+    // The table this one encloses, once done with this one.
+    pub fn into_outer(self) -> Option<SymbolTable> {
+        self.outer.map(|outer| *outer)
+    }
+
+    // Defining a name again in the same table gives it the slot it had, as `let` does to a binding
+    // of the same environment in the evaluator. In a block, the first definition of a name gets a
+    // new slot instead, for the name to go back to what it was once the block ends. The names the
+    // program's blocks define are locals of its frame, for closures to capture them as they
+    // capture the locals of a function.
+    pub fn define(&mut self, name: Symbol) -> Binding {
+        let scope = if self.outer.is_none() && self.blocks.is_empty() { Scope::Global } else { Scope::Local };
+        if let Some(block) = self.blocks.last_mut() {
+            if block.defined.iter().all(|(defined, _)| *defined != name) {
+                block.defined.push((name, self.bindings.get(&name).copied()));
+                return self.bind(name, scope);
+            }
+        }
+        match self.bindings.get(&name) {
+            Some(binding) if binding.scope == scope => *binding,
+            _ => self.bind(name, scope),
+        }
+    }
+
+    fn bind(&mut self, name: Symbol, scope: Scope) -> Binding {
+        let index = if self.outer.is_none() && scope == Scope::Local {
+            self.program_locals += 1;
+            self.program_locals - 1
+        } else {
+            self.names.push(name);
+            self.names.len() - 1
+        };
+        let binding = Binding { scope, index };
+        self.bindings.insert(name, binding);
+        binding
+    }
+
+    // The names the function defines, wherever in its body, which the functions in it can use
+    // before the function gets to defining them, as they can in the evaluator.
+    pub fn declare(&mut self, names: Vec<Symbol>) {
+        self.declared = names;
+    }
+
+    // The names defined from here to the matching `leave_block` are the block's own, as they are
+    // to the environment the evaluator makes for every iteration of a `for` loop. The block
+    // declares names as a function does.
+    pub fn enter_block(&mut self, declared: Vec<Symbol>) {
+        self.blocks.push(Block { defined: vec![], declared });
+    }
+
+    // The slots of the names the block defined.
+    pub fn leave_block(&mut self) -> Vec<usize> {
+        let block = self.blocks.pop().expect("a block was entered");
+        let mut slots = vec![];
+        for (name, outer) in block.defined.into_iter().rev() {
+            let binding = match outer {
+                Some(binding) => self.bindings.insert(name, binding),
+                None => self.bindings.remove(&name),
+            };
+            slots.extend(binding.map(|binding| binding.index));
+        }
+        slots
+    }
+
+    pub fn define_builtin(&mut self, index: usize, name: Symbol) -> Binding {
+        let binding = Binding { scope: Scope::Builtin, index };
+        self.bindings.insert(name, binding);
+        binding
+    }
+
+    // Defines the name among the globals, however deep in functions and blocks this table is.
+    pub fn define_global(&mut self, name: Symbol) -> Binding {
+        match &mut self.outer {
+            Some(outer) => outer.define_global(name),
+            None => match self.bindings.get(&name) {
+                Some(binding) if binding.scope == Scope::Global => *binding,
+                _ => self.bind(name, Scope::Global),
+            },
+        }
+    }
+
+    // A global slot for the name that the name does not resolve to, for the compiler to refer to a
+    // variable that is never set.
+    pub fn reserve_global(&mut self, name: Symbol) -> usize {
+        match &mut self.outer {
+            Some(outer) => outer.reserve_global(name),
+            None => {
+                self.names.push(name);
+                self.names.len() - 1
+            }
+        }
+    }
+
+    // A local of an enclosing function is made a free binding of every function in between.
+    pub fn resolve(&mut self, name: Symbol) -> Option<Binding> {
+        if let Some(binding) = self.bindings.get(&name) {
+            return Some(*binding);
+        }
+        let binding = self.outer.as_mut()?.resolve_enclosing(name)?;
+        match binding.scope {
+            Scope::Global | Scope::Builtin => Some(binding),
+            Scope::Local | Scope::Free => Some(self.define_free(binding, name)),
+        }
+    }
+
+    // A name a function in this one uses, which this one or its innermost block declares, is
+    // defined right away, for the function to capture what gets set to it later.
+    fn resolve_enclosing(&mut self, name: Symbol) -> Option<Binding> {
+        if !self.bindings.contains_key(&name) {
+            if self.blocks.last().is_some_and(|block| block.declared.contains(&name)) {
+                self.define(name);
+            } else if self.outer.is_some() && self.declared.contains(&name) {
+                self.bind(name, Scope::Local);
+            }
+        }
+        self.resolve(name)
+    }
+
+    fn define_free(&mut self, original: Binding, name: Symbol) -> Binding {
+        self.free.push(original);
+        let binding = Binding { scope: Scope::Free, index: self.free.len() - 1 };
+        self.bindings.insert(name, binding);
+        binding
+    }
+
+    pub fn names(&self) -> &[Symbol] {
+        &self.names
+    }
+
+    pub fn program_locals(&self) -> usize {
+        self.program_locals
+    }
+
+    pub fn free(&self) -> &[Binding] {
+        &self.free
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn binding(scope: Scope, index: usize) -> Option<Binding> {
+        Some(Binding { scope, index })
+    }
+
+    #[test]
+    fn it_defines_and_resolves_names() {
+        let (a, b, c) = (Symbol::intern("a"), Symbol::intern("b"), Symbol::intern("c"));
+        let mut global = SymbolTable::new();
+        global.define(a);
+        global.define_builtin(3, Symbol::intern("len"));
+        let mut local = SymbolTable::new_enclosed(global);
+        local.define(b);
+        local.define(c);
+        assert_eq!(local.define(b), Binding { scope: Scope::Local, index: 0 });
+        assert_eq!(local.resolve(a), binding(Scope::Global, 0));
+        assert_eq!(local.resolve(c), binding(Scope::Local, 1));
+        assert_eq!(local.resolve(Symbol::intern("len")), binding(Scope::Builtin, 3));
+        assert_eq!(local.resolve(Symbol::intern("d")), None);
+        assert_eq!(local.define_global(b), Binding { scope: Scope::Global, index: 1 });
+        assert_eq!(local.reserve_global(a), 2);
+        assert_eq!(local.names(), [b, c]);
+        assert_eq!(local.into_outer().unwrap().names(), [a, b, a]);
+    }
+
+    #[test]
+    fn it_restores_names_after_blocks() {
+        let (a, b) = (Symbol::intern("a"), Symbol::intern("b"));
+        let mut global = SymbolTable::new();
+        global.define(a);
+        global.enter_block(vec![]);
+        assert_eq!(global.define(a), Binding { scope: Scope::Local, index: 0 });
+        assert_eq!(global.define(a), Binding { scope: Scope::Local, index: 0 });
+        assert_eq!(global.define(b), Binding { scope: Scope::Local, index: 1 });
+        assert_eq!(global.define_global(Symbol::intern("c")), Binding { scope: Scope::Global, index: 1 });
+        let mut local = SymbolTable::new_enclosed(global);
+        assert_eq!(local.resolve(b), binding(Scope::Free, 0));
+        let mut global = local.into_outer().unwrap();
+        assert_eq!(global.leave_block(), [1, 0]);
+        assert_eq!(global.resolve(a), binding(Scope::Global, 0));
+        assert_eq!(global.resolve(b), None);
+        assert_eq!(global.resolve(Symbol::intern("c")), binding(Scope::Global, 1));
+        assert_eq!(global.program_locals(), 2);
+    }
+
+    #[test]
+    fn it_defines_declared_names_for_inner_functions() {
+        let (a, b) = (Symbol::intern("a"), Symbol::intern("b"));
+        let mut outer = SymbolTable::new_enclosed(SymbolTable::new());
+        outer.declare(vec![a]);
+        outer.enter_block(vec![b]);
+        assert_eq!(outer.resolve(a), None);
+        let mut inner = SymbolTable::new_enclosed(outer);
+        assert_eq!(inner.resolve(a), binding(Scope::Free, 0));
+        assert_eq!(inner.resolve(b), binding(Scope::Free, 1));
+        let mut outer = inner.into_outer().unwrap();
+        assert_eq!(outer.define(b), Binding { scope: Scope::Local, index: 1 });
+        assert_eq!(outer.leave_block(), [1]);
+        assert_eq!(outer.define(a), Binding { scope: Scope::Local, index: 0 });
+    }
+
+    #[test]
+    fn it_resolves_free_names() {
+        let (a, b, c) = (Symbol::intern("a"), Symbol::intern("b"), Symbol::intern("c"));
+        let mut global = SymbolTable::new();
+        global.define(a);
+        let mut first = SymbolTable::new_enclosed(global);
+        first.define(b);
+        let mut second = SymbolTable::new_enclosed(first);
+        second.define(c);
+        let mut third = SymbolTable::new_enclosed(second);
+        assert_eq!(third.resolve(a), binding(Scope::Global, 0));
+        assert_eq!(third.resolve(c), binding(Scope::Free, 0));
+        assert_eq!(third.resolve(b), binding(Scope::Free, 1));
+        assert_eq!(third.free(), [Binding { scope: Scope::Local, index: 0 }, Binding { scope: Scope::Free, index: 0 }]);
+        let second = third.into_outer().unwrap();
+        assert_eq!(second.free(), [Binding { scope: Scope::Local, index: 0 }]);
+    }
+}
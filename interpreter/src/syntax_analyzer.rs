@@ -0,0 +1,696 @@
+use std::rc::Rc;
+
+use crate::abstract_syntax_tree::{BlockStatement, Expression, InfixOperator, PrefixOperator, Program, Statement};
+use crate::diagnostics::{
+    EXPECTED_EXPRESSION, EXPECTED_IDENTIFIER, EXPECTED_TOKEN, INVALID_ASSIGNMENT_TARGET, NESTED_TOO_DEEPLY,
+};
+use crate::interner::Symbol;
+use crate::lexical_analyzer::{tokenize_checked, Span, Token};
+
+pub fn parse(input: &str) -> Result<Program, Vec<String>> {
+    parse_with_spans(input).map_err(|errors| errors.into_iter().map(|error| error.message).collect())
+}
+
+// Like `parse`, telling where in the input each error was found.
+pub fn parse_with_spans(input: &str) -> Result<Program, Vec<SyntaxError>> {
+    parse_with_marks(input).map(|(program, _)| program)
+}
+
+// Like `parse_with_spans`, also returning where every statement starts and ends and where every
+// block opens and closes, in the order the parser met them. Comments are not part of the syntax
+// tree, these are what the formatter uses to put them back where they were.
+pub fn parse_with_marks(input: &str) -> Result<(Program, Vec<Span>), Vec<SyntaxError>> {
+    let (tokens, lexical_error) = tokenize_checked(input);
+    let mut parser = Parser::new(tokens);
+    let program = parser.parse_program();
+
+    // Where the lexer gave up the parser sees the input end, which is not worth reporting on
+    // top of the reason it gave up.
+    if let Some(error) = lexical_error {
+        parser.errors.retain(|error| !error.message.ends_with("got end of input"));
+        parser.errors.push(SyntaxError { message: error.message, code: error.code, span: error.span });
+        parser.errors.sort_by_key(|error| (error.span.line, error.span.column));
+    }
+
+    if parser.errors.is_empty() {
+        Ok((program, parser.marks))
+    } else {
+        Err(parser.errors)
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct SyntaxError {
+    pub message: String,
+    pub code: &'static str,
+    // The token the parser stopped at, or the last token when it ran out of input.
+    pub span: Span,
+}
+
+#[derive(Clone, Copy, PartialEq, PartialOrd)]
+enum Precedence {
+    Lowest,
+    Assign,
+    Or,
+    And,
+    Equals,
+    LessGreater,
+    Range,
+    Sum,
+    Product,
+    Prefix,
+    Call,
+    Index,
+}
+
+fn precedence_of(token: &Token) -> Precedence {
+    match token {
+        Token::Assignment => Precedence::Assign,
+        Token::Or => Precedence::Or,
+        Token::And => Precedence::And,
+        Token::Equals | Token::NotEquals => Precedence::Equals,
+        Token::LessThan | Token::GreaterThan => Precedence::LessGreater,
+        Token::Range => Precedence::Range,
+        Token::Plus | Token::Minus => Precedence::Sum,
+        Token::Star | Token::Slash => Precedence::Product,
+        Token::LeftParen => Precedence::Call,
+        Token::LeftBracket => Precedence::Index,
+        _ => Precedence::Lowest,
+    }
+}
+
+fn infix_operator_of(token: &Token) -> Option<InfixOperator> {
+    match token {
+        Token::Plus => Some(InfixOperator::Plus),
+        Token::Minus => Some(InfixOperator::Minus),
+        Token::Star => Some(InfixOperator::Star),
+        Token::Slash => Some(InfixOperator::Slash),
+        Token::Equals => Some(InfixOperator::Equals),
+        Token::NotEquals => Some(InfixOperator::NotEquals),
+        Token::LessThan => Some(InfixOperator::LessThan),
+        Token::GreaterThan => Some(InfixOperator::GreaterThan),
+        Token::And => Some(InfixOperator::And),
+        Token::Or => Some(InfixOperator::Or),
+        Token::Range => Some(InfixOperator::Range),
+        _ => None,
+    }
+}
+
+// How deeply expressions may nest, counting every operator applied to the expression before it.
+// Parsing, evaluating and even dropping a syntax tree recurse through it, so this is what keeps
+// `((((...))))` from overflowing the stack.
+const MAX_NESTING: usize = 256;
+
+struct Parser {
+    tokens: Vec<Token>,
+    spans: Vec<Span>,
+    position: usize,
+    errors: Vec<SyntaxError>,
+    depth: usize,
+    marks: Vec<Span>,
+}
+
+impl Parser {
+    fn new(tokens: Vec<(Token, Span)>) -> Self {
+        let (tokens, spans) = tokens.into_iter().unzip();
+        Parser {
+            tokens,
+            spans,
+            position: 0,
+            errors: vec![],
+            depth: 0,
+            marks: vec![],
+        }
+    }
+
+    fn current_token(&self) -> Option<&Token> {
+        self.tokens.get(self.position)
+    }
+
+    // Past the end of the input this is where the last token starts.
+    fn current_span(&self) -> Span {
+        match self.spans.get(self.position).or(self.spans.last()) {
+            Some(span) => *span,
+            None => Span { line: 1, column: 1 },
+        }
+    }
+
+    fn is_current_token(&self, token: &Token) -> bool {
+        self.current_token() == Some(token)
+    }
+
+    fn advance(&mut self) {
+        self.position += 1;
+    }
+
+    fn expect(&mut self, token: Token) -> Option<()> {
+        if self.is_current_token(&token) {
+            self.advance();
+            return Some(());
+        }
+
+        self.error(EXPECTED_TOKEN, format!("expected {:?}, got {}", token, self.describe_current_token()));
+        None
+    }
+
+    fn skip_semicolon(&mut self) {
+        if self.is_current_token(&Token::Semicolon) {
+            self.advance();
+        }
+    }
+
+    fn describe_current_token(&self) -> String {
+        match self.current_token() {
+            None => String::from("end of input"),
+            Some(token) => format!("{:?}", token),
+        }
+    }
+
+    fn error(&mut self, code: &'static str, message: String) {
+        let span = self.current_span();
+        self.errors.push(SyntaxError { message, code, span });
+    }
+
+    // After an error, skip the rest of the broken statement so parsing can carry on
+    // and report every problem in the input at once.
+    fn synchronize(&mut self) {
+        while let Some(token) = self.current_token() {
+            let is_semicolon = *token == Token::Semicolon;
+            self.advance();
+            if is_semicolon {
+                break;
+            }
+        }
+    }
+
+    fn parse_program(&mut self) -> Program {
+        let (mut statements, mut spans) = (vec![], vec![]);
+        while self.current_token().is_some() {
+            let span = self.current_span();
+            match self.parse_statement() {
+                Some(statement) => {
+                    statements.push(statement);
+                    spans.push(span);
+                }
+                None => self.synchronize(),
+            }
+        }
+
+        Program { statements, spans }
+    }
+
+    fn parse_statement(&mut self) -> Option<Statement> {
+        self.marks.push(self.current_span());
+        let statement = match self.current_token() {
+            Some(Token::Let) => self.parse_let_statement()?,
+            Some(Token::Return) => {
+                self.advance();
+                Statement::Return(self.parse_expression(Precedence::Lowest)?)
+            }
+            Some(Token::Break) => {
+                self.advance();
+                Statement::Break
+            }
+            Some(Token::Continue) => {
+                self.advance();
+                Statement::Continue
+            }
+            _ => Statement::Expression(self.parse_expression(Precedence::Lowest)?),
+        };
+        self.skip_semicolon();
+        self.marks.push(self.spans[self.position - 1]);
+
+        Some(statement)
+    }
+
+    fn parse_let_statement(&mut self) -> Option<Statement> {
+        self.expect(Token::Let)?;
+        let name = self.parse_identifier()?;
+        self.expect(Token::Assignment)?;
+        let value = self.parse_expression(Precedence::Lowest)?;
+
+        Some(Statement::Let(name, value))
+    }
+
+    fn parse_identifier(&mut self) -> Option<Symbol> {
+        if let Some(Token::Identifier(name)) = self.current_token() {
+            let name = Symbol::intern(name);
+            self.advance();
+            return Some(name);
+        }
+
+        self.error(EXPECTED_IDENTIFIER, format!("expected identifier, got {}", self.describe_current_token()));
+        None
+    }
+
+    fn parse_block_statement(&mut self) -> Option<BlockStatement> {
+        self.marks.push(self.current_span());
+        self.expect(Token::LeftBrace)?;
+
+        let (mut statements, mut spans) = (vec![], vec![]);
+        while !self.is_current_token(&Token::RightBrace) {
+            if self.current_token().is_none() {
+                self.error(EXPECTED_TOKEN, String::from("expected <}, }>, got end of input"));
+                return None;
+            }
+            spans.push(self.current_span());
+            statements.push(self.parse_statement()?);
+        }
+        self.marks.push(self.current_span());
+        self.advance();
+
+        Some(BlockStatement { statements, spans })
+    }
+
+    fn parse_expression(&mut self, precedence: Precedence) -> Option<Expression> {
+        let depth = self.depth;
+        let expression = self.parse_nested_expression(precedence);
+        self.depth = depth;
+        expression
+    }
+
+    fn parse_nested_expression(&mut self, precedence: Precedence) -> Option<Expression> {
+        self.enter_nesting()?;
+        let start = self.current_span();
+        let mut left = self.parse_prefix_expression()?;
+
+        while let Some(token) = self.current_token() {
+            if precedence >= precedence_of(token) {
+                break;
+            }
+
+            self.enter_nesting()?;
+            let token = self.current_token()?;
+            left = match token {
+                Token::LeftParen => self.parse_call_expression(left, start)?,
+                Token::LeftBracket => self.parse_index_expression(left)?,
+                Token::Assignment => self.parse_assignment_expression(left)?,
+                _ => self.parse_infix_expression(left)?,
+            };
+        }
+
+        Some(left)
+    }
+
+    // Past the limit the rest of the input is skipped, every enclosing expression would only
+    // report its own missing closing bracket.
+    fn enter_nesting(&mut self) -> Option<()> {
+        if self.depth >= MAX_NESTING {
+            self.error(NESTED_TOO_DEEPLY, String::from("expression nested too deeply"));
+            self.position = self.tokens.len();
+            return None;
+        }
+        self.depth += 1;
+        Some(())
+    }
+
+    fn parse_prefix_expression(&mut self) -> Option<Expression> {
+        let token = match self.current_token() {
+            None => {
+                self.error(EXPECTED_EXPRESSION, String::from("expected expression, got end of input"));
+                return None;
+            }
+            Some(token) => token.clone(),
+        };
+
+        match token {
+            Token::Identifier(name) => {
+                self.advance();
+                Some(Expression::Identifier(Symbol::intern(&name)))
+            }
+            Token::Integer(value) => {
+                self.advance();
+                Some(Expression::Integer(value))
+            }
+            Token::Float(value) => {
+                self.advance();
+                Some(Expression::Float(value))
+            }
+            Token::Boolean(value) => {
+                self.advance();
+                Some(Expression::Boolean(value))
+            }
+            Token::String(value) => {
+                self.advance();
+                Some(Expression::String(value))
+            }
+            Token::Null => {
+                self.advance();
+                Some(Expression::Null)
+            }
+            Token::LeftBracket => {
+                self.advance();
+                Some(Expression::Array(self.parse_expression_list(Token::RightBracket)?))
+            }
+            Token::LeftBrace => self.parse_hash_literal(),
+            Token::Bang => self.parse_prefix_operator(PrefixOperator::Bang),
+            Token::Minus => self.parse_prefix_operator(PrefixOperator::Minus),
+            Token::LeftParen => {
+                self.advance();
+                let expression = self.parse_expression(Precedence::Lowest)?;
+                self.expect(Token::RightParen)?;
+                Some(expression)
+            }
+            Token::If => self.parse_if_expression(),
+            Token::While => self.parse_while_expression(),
+            Token::For => self.parse_for_expression(),
+            Token::Try => self.parse_try_expression(),
+            Token::Fn => self.parse_function_literal(),
+            _ => {
+                self.error(EXPECTED_EXPRESSION, format!("expected expression, got {:?}", token));
+                None
+            }
+        }
+    }
+
+    fn parse_prefix_operator(&mut self, operator: PrefixOperator) -> Option<Expression> {
+        self.advance();
+        let right = self.parse_expression(Precedence::Prefix)?;
+
+        Some(Expression::Prefix(operator, Box::new(right)))
+    }
+
+    fn parse_infix_expression(&mut self, left: Expression) -> Option<Expression> {
+        let token = self.current_token()?.clone();
+        let operator = infix_operator_of(&token)?;
+        self.advance();
+        let right = self.parse_expression(precedence_of(&token))?;
+
+        Some(Expression::Infix(operator, Box::new(left), Box::new(right)))
+    }
+
+    fn parse_assignment_expression(&mut self, target: Expression) -> Option<Expression> {
+        let name = match target {
+            Expression::Identifier(name) => name,
+            target => {
+                self.error(INVALID_ASSIGNMENT_TARGET, format!("invalid assignment target: {}", target));
+                return None;
+            }
+        };
+        self.expect(Token::Assignment)?;
+        // Assignment is right associative, `a = b = 1` assigns to `b` first.
+        let value = self.parse_expression(Precedence::Lowest)?;
+
+        Some(Expression::Assign(name, Box::new(value)))
+    }
+
+    fn parse_if_expression(&mut self) -> Option<Expression> {
+        self.expect(Token::If)?;
+        let condition = self.parse_expression(Precedence::Lowest)?;
+        let consequence = self.parse_block_statement()?;
+
+        let mut alternative = None;
+        if self.is_current_token(&Token::Else) {
+            self.advance();
+            alternative = Some(self.parse_block_statement()?);
+        }
+
+        Some(Expression::If {
+            condition: Box::new(condition),
+            consequence,
+            alternative,
+        })
+    }
+
+    fn parse_while_expression(&mut self) -> Option<Expression> {
+        self.expect(Token::While)?;
+        let condition = self.parse_expression(Precedence::Lowest)?;
+        let body = self.parse_block_statement()?;
+
+        Some(Expression::While {
+            condition: Box::new(condition),
+            body,
+        })
+    }
+
+    // `try { ... } catch (e) { ... }`
+    fn parse_try_expression(&mut self) -> Option<Expression> {
+        self.expect(Token::Try)?;
+        let body = self.parse_block_statement()?;
+        self.expect(Token::Catch)?;
+        self.expect(Token::LeftParen)?;
+        let variable = self.parse_identifier()?;
+        self.expect(Token::RightParen)?;
+        let handler = self.parse_block_statement()?;
+
+        Some(Expression::Try { body, variable, handler })
+    }
+
+    // `for x in iterable { ... }`, or `for a, b in iterable { ... }` for index/element and
+    // key/value pairs.
+    fn parse_for_expression(&mut self) -> Option<Expression> {
+        self.expect(Token::For)?;
+        let mut variables = vec![self.parse_identifier()?];
+        if self.is_current_token(&Token::Comma) {
+            self.advance();
+            variables.push(self.parse_identifier()?);
+        }
+        self.expect(Token::In)?;
+        let iterable = self.parse_expression(Precedence::Lowest)?;
+        let body = self.parse_block_statement()?;
+
+        Some(Expression::For {
+            variables,
+            iterable: Box::new(iterable),
+            body,
+        })
+    }
+
+    fn parse_hash_literal(&mut self) -> Option<Expression> {
+        self.expect(Token::LeftBrace)?;
+
+        let mut pairs = vec![];
+        while !self.is_current_token(&Token::RightBrace) {
+            if !pairs.is_empty() {
+                self.expect(Token::Comma)?;
+            }
+            let key = self.parse_expression(Precedence::Lowest)?;
+            self.expect(Token::Colon)?;
+            let value = self.parse_expression(Precedence::Lowest)?;
+            pairs.push((key, value));
+        }
+        self.advance();
+
+        Some(Expression::Hash(pairs))
+    }
+
+    fn parse_function_literal(&mut self) -> Option<Expression> {
+        self.expect(Token::Fn)?;
+        self.expect(Token::LeftParen)?;
+
+        let mut parameters = vec![];
+        while !self.is_current_token(&Token::RightParen) {
+            if !parameters.is_empty() {
+                self.expect(Token::Comma)?;
+            }
+            parameters.push(self.parse_identifier()?);
+        }
+        self.advance();
+
+        let body = self.parse_block_statement()?;
+
+        Some(Expression::Function { parameters, body: Rc::new(body) })
+    }
+
+    fn parse_call_expression(&mut self, function: Expression, span: Span) -> Option<Expression> {
+        self.expect(Token::LeftParen)?;
+        let arguments = self.parse_expression_list(Token::RightParen)?;
+
+        Some(Expression::Call {
+            function: Box::new(function),
+            arguments,
+            span,
+        })
+    }
+
+    fn parse_index_expression(&mut self, left: Expression) -> Option<Expression> {
+        self.expect(Token::LeftBracket)?;
+        let index = self.parse_expression(Precedence::Lowest)?;
+        self.expect(Token::RightBracket)?;
+
+        Some(Expression::Index(Box::new(left), Box::new(index)))
+    }
+
+    // Parses comma separated expressions up to and including `end`, the opening token has
+    // already been consumed.
+    fn parse_expression_list(&mut self, end: Token) -> Option<Vec<Expression>> {
+        let mut expressions = vec![];
+        while !self.is_current_token(&end) {
+            if !expressions.is_empty() {
+                self.expect(Token::Comma)?;
+            }
+            expressions.push(self.parse_expression(Precedence::Lowest)?);
+        }
+        self.advance();
+
+        Some(expressions)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse_to_string(input: &str) -> String {
+        parse(input).unwrap().to_string()
+    }
+
+    #[test]
+    fn it_works_on_operator_precedence() {
+        assert_eq!(parse_to_string("-a * b"), "((-a) * b)");
+        assert_eq!(parse_to_string("!-a"), "(!(-a))");
+        assert_eq!(parse_to_string("a + b * c - d / e"), "((a + (b * c)) - (d / e))");
+        assert_eq!(parse_to_string("(a + b) * c"), "((a + b) * c)");
+        assert_eq!(parse_to_string("1 < 2 == true != false"), "(((1 < 2) == true) != false)");
+        assert_eq!(parse_to_string("a || b && c == d"), "(a || (b && (c == d)))");
+        assert_eq!(parse_to_string("a && b || c && d"), "((a && b) || (c && d))");
+    }
+
+    #[test]
+    fn it_works_on_let_and_return_statements() {
+        assert_eq!(parse_to_string("let x = 1 + 2; return x;"), "let x = (1 + 2); return x;");
+    }
+
+    #[test]
+    fn it_works_on_if_else_expression() {
+        assert_eq!(
+            parse_to_string("if (x < y) { x } else { y }"),
+            "if (x < y) { x } else { y }",
+        );
+    }
+
+    #[test]
+    fn it_works_on_while_expression() {
+        assert_eq!(
+            parse_to_string("while (i < 10) { i = i + 1; }"),
+            "while (i < 10) { (i = (i + 1)) }",
+        );
+    }
+
+    #[test]
+    fn it_works_on_break_and_continue_statements() {
+        assert_eq!(
+            parse_to_string("while (true) { if (x) { break; } continue }"),
+            "while true { if x { break; } continue; }",
+        );
+    }
+
+    #[test]
+    fn it_works_on_array_hash_and_index_expressions() {
+        assert_eq!(parse_to_string("[1, 2 * 3, [], {}]"), "[1, (2 * 3), [], {}]");
+        assert_eq!(parse_to_string("{1: true, 2 + 3: [4]}"), "{1: true, (2 + 3): [4]}");
+        assert_eq!(parse_to_string("a * [1, 2][b + 1]"), "(a * ([1, 2][(b + 1)]))");
+        assert_eq!(parse_to_string("f(x)[0][1]"), "((f(x)[0])[1])");
+    }
+
+    #[test]
+    fn it_works_on_string_literal() {
+        assert_eq!(parse_to_string(r#""a" + "b\"c\n""#), r#"("a" + "b\"c\n")"#);
+    }
+
+    #[test]
+    fn it_works_on_float_literal() {
+        assert_eq!(parse_to_string("-1.5 * 2.0"), "((-1.5) * 2.0)");
+    }
+
+    #[test]
+    fn it_works_on_try_expression() {
+        assert_eq!(parse_to_string("try { f(1) } catch (e) { e }"), "try { f(1) } catch (e) { e }");
+        assert_eq!(
+            parse("try { } catch e { }").unwrap_err(),
+            Vec::from(["expected <(, (>, got <identifier, e>"]),
+        );
+    }
+
+    #[test]
+    fn it_works_on_null_literal() {
+        assert_eq!(parse_to_string("x == null"), "(x == null)");
+    }
+
+    #[test]
+    fn it_works_on_range_expression() {
+        assert_eq!(parse_to_string("0..n + 1"), "(0 .. (n + 1))");
+        assert_eq!(parse_to_string("a..b < c"), "((a .. b) < c)");
+    }
+
+    #[test]
+    fn it_works_on_for_expression() {
+        assert_eq!(parse_to_string("for x in xs { puts(x) }"), "for x in xs { puts(x) }");
+        assert_eq!(parse_to_string("for k, v in {1: 2} { k + v }"), "for k, v in {1: 2} { (k + v) }");
+        assert_eq!(parse_to_string("for i in 0..10 { }"), "for i in (0 .. 10) { }");
+        assert_eq!(
+            parse("for in xs { }").unwrap_err(),
+            Vec::from(["expected identifier, got <in, in>"]),
+        );
+    }
+
+    #[test]
+    fn it_works_on_function_literal_and_call() {
+        assert_eq!(
+            parse_to_string("let add = fn(a, b) { a + b; }; add(1, 2 * 3)"),
+            "let add = fn(a, b) { (a + b) }; add(1, (2 * 3))",
+        );
+        assert_eq!(parse_to_string("fn() { }()"), "fn() { }()");
+    }
+
+    #[test]
+    fn it_works_on_assignment_expression() {
+        assert_eq!(parse_to_string("x = y = 1 + 2"), "(x = (y = (1 + 2)))");
+        assert_eq!(parse_to_string("x = x || y"), "(x = (x || y))");
+        assert_eq!(parse("1 + x = 2").unwrap_err(), Vec::from(["invalid assignment target: (1 + x)"]));
+    }
+
+    #[test]
+    fn it_reports_errors() {
+        assert_eq!(
+            parse("let = 5; let x 5; 1 +").unwrap_err(),
+            Vec::from([
+                "expected identifier, got <=, =>",
+                "expected <=, =>, got <integer, 5>",
+                "expected expression, got end of input",
+            ]),
+        );
+    }
+
+    #[test]
+    fn it_reports_lexical_errors() {
+        assert_eq!(parse("let x = 1 + @").unwrap_err(), Vec::from(["unexpected character `@`"]));
+        assert_eq!(parse("let = \"abc").unwrap_err(), Vec::from(["expected identifier, got <=, =>", "unterminated string"]));
+    }
+
+    #[test]
+    fn it_reports_where_errors_are() {
+        assert_eq!(
+            parse_with_spans("let x = 1;\nlet = 5;\nx +").unwrap_err(),
+            Vec::from([
+                SyntaxError {
+                    message: String::from("expected identifier, got <=, =>"),
+                    code: EXPECTED_IDENTIFIER,
+                    span: Span { line: 2, column: 5 }
+                },
+                SyntaxError {
+                    message: String::from("expected expression, got end of input"),
+                    code: EXPECTED_EXPRESSION,
+                    span: Span { line: 3, column: 3 }
+                },
+            ]),
+        );
+    }
+
+    #[test]
+    fn it_limits_nesting() {
+        let nested = |open: &str, close: &str, depth: usize| format!("{}1{}", open.repeat(depth), close.repeat(depth));
+        for (open, close) in [("(", ")"), ("[", "]"), ("-", ""), ("f(", ")")] {
+            assert!(parse(&nested(open, close, 120)).is_ok(), "{}", open);
+            assert_eq!(parse(&nested(open, close, 300)).unwrap_err(), Vec::from(["expression nested too deeply"]), "{}", open);
+        }
+        assert_eq!(parse(&"1 + ".repeat(300)).unwrap_err(), Vec::from(["expression nested too deeply"]));
+    }
+
+    #[test]
+    fn it_marks_statements_and_blocks() {
+        let (_, marks) = parse_with_marks("let x = 1;\nif (x) {\n  puts(x)\n}").unwrap();
+        let marks: Vec<String> = marks.iter().map(|span| span.to_string()).collect();
+        assert_eq!(marks, Vec::from(["1:1", "1:10", "2:1", "2:8", "3:3", "3:9", "4:1", "4:1"]));
+    }
+}
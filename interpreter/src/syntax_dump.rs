@@ -0,0 +1,368 @@
+use crate::abstract_syntax_tree::{quote_string, BlockStatement, Expression, Program, Statement};
+use crate::interner::Symbol;
+
+// How `monkey ast` writes a syntax tree out.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum DumpFormat {
+    // One object per node, with its kind under "type", for other programs to read.
+    Json,
+    // One list per top-level statement and line.
+    Sexpr,
+    // Indented with box-drawing lines, for people to read.
+    Tree,
+}
+
+impl DumpFormat {
+    pub fn from_name(name: &str) -> Option<DumpFormat> {
+        match name {
+            "json" => Some(DumpFormat::Json),
+            "sexpr" => Some(DumpFormat::Sexpr),
+            "tree" => Some(DumpFormat::Tree),
+            _ => None,
+        }
+    }
+}
+
+// A node in a shape all the formats can be written from: what kind it is, and its fields in the
+// order they appear in the source.
+struct Node {
+    kind: &'static str,
+    fields: Vec<(&'static str, Value)>,
+}
+
+enum Value {
+    Node(Node),
+    List(Vec<Value>),
+    // A name or an operator.
+    Word(String),
+    String(String),
+    Number(String),
+    Boolean(bool),
+    // An `else` that is not there.
+    Missing,
+}
+
+pub fn dump(program: &Program, format: DumpFormat) -> String {
+    let statements: Vec<Value> = program.statements.iter().map(statement).collect();
+    match format {
+        DumpFormat::Json => {
+            let mut json = String::new();
+            write_json(&node("Program", vec![("statements", Value::List(statements))]), &mut json);
+            json
+        }
+        DumpFormat::Sexpr => statements.iter().map(sexpr).collect::<Vec<String>>().join("\n"),
+        DumpFormat::Tree => {
+            let root = branch(&Node { kind: "Program", fields: vec![("statements", Value::List(statements))] }, None);
+            let mut lines = vec![root.label.clone()];
+            write_branches(&root, "", &mut lines);
+            lines.join("\n")
+        }
+    }
+}
+
+// Writes a string as a JSON string literal.
+pub fn quote_json(data: &str) -> String {
+    let mut quoted = String::from("\"");
+    for character in data.chars() {
+        match character {
+            '"' => quoted.push_str("\\\""),
+            '\\' => quoted.push_str("\\\\"),
+            '\n' => quoted.push_str("\\n"),
+            '\r' => quoted.push_str("\\r"),
+            '\t' => quoted.push_str("\\t"),
+            character if character.is_control() => quoted.push_str(&format!("\\u{:04x}", character as u32)),
+            character => quoted.push(character),
+        }
+    }
+    quoted.push('"');
+    quoted
+}
+
+fn node(kind: &'static str, fields: Vec<(&'static str, Value)>) -> Value {
+    Value::Node(Node { kind, fields })
+}
+
+fn word(word: impl ToString) -> Value {
+    Value::Word(word.to_string())
+}
+
+fn block(block: &BlockStatement) -> Value {
+    node("Block", vec![("statements", Value::List(block.statements.iter().map(statement).collect()))])
+}
+
+fn list(expressions: &[Expression]) -> Value {
+    Value::List(expressions.iter().map(expression).collect())
+}
+
+fn words(words: &[Symbol]) -> Value {
+    Value::List(words.iter().map(word).collect())
+}
+
+fn statement(statement: &Statement) -> Value {
+    match statement {
+        Statement::Let(name, value) => node("Let", vec![("name", word(name)), ("value", expression(value))]),
+        Statement::Return(value) => node("Return", vec![("value", expression(value))]),
+        Statement::Break => node("Break", vec![]),
+        Statement::Continue => node("Continue", vec![]),
+        Statement::Expression(value) => node("ExpressionStatement", vec![("expression", expression(value))]),
+    }
+}
+
+fn expression(value: &Expression) -> Value {
+    match value {
+        Expression::Identifier(name) => node("Identifier", vec![("name", word(name))]),
+        Expression::Integer(value) => node("Integer", vec![("value", Value::Number(value.to_string()))]),
+        // JSON has no numbers for infinities.
+        Expression::Float(value) if value.is_finite() => {
+            node("Float", vec![("value", Value::Number(format!("{:?}", value)))])
+        }
+        Expression::Float(value) => node("Float", vec![("value", word(value))]),
+        Expression::Boolean(value) => node("Boolean", vec![("value", Value::Boolean(*value))]),
+        Expression::String(value) => node("String", vec![("value", Value::String(value.clone()))]),
+        Expression::Null => node("Null", vec![]),
+        Expression::Array(elements) => node("Array", vec![("elements", list(elements))]),
+        Expression::Hash(pairs) => {
+            let pairs = pairs
+                .iter()
+                .map(|(key, value)| node("Pair", vec![("key", expression(key)), ("value", expression(value))]))
+                .collect();
+            node("Hash", vec![("pairs", Value::List(pairs))])
+        }
+        Expression::Prefix(operator, right) => {
+            node("Prefix", vec![("operator", word(operator)), ("right", expression(right))])
+        }
+        Expression::Infix(operator, left, right) => node(
+            "Infix",
+            vec![("operator", word(operator)), ("left", expression(left)), ("right", expression(right))],
+        ),
+        Expression::Assign(name, value) => node("Assign", vec![("name", word(name)), ("value", expression(value))]),
+        Expression::If { condition, consequence, alternative } => node(
+            "If",
+            vec![
+                ("condition", expression(condition)),
+                ("consequence", block(consequence)),
+                ("alternative", alternative.as_ref().map_or(Value::Missing, block)),
+            ],
+        ),
+        Expression::While { condition, body } => {
+            node("While", vec![("condition", expression(condition)), ("body", block(body))])
+        }
+        Expression::For { variables, iterable, body } => node(
+            "For",
+            vec![("variables", words(variables)), ("iterable", expression(iterable)), ("body", block(body))],
+        ),
+        Expression::Try { body, variable, handler } => {
+            node("Try", vec![("body", block(body)), ("variable", word(variable)), ("handler", block(handler))])
+        }
+        Expression::Function { parameters, body } => {
+            node("Function", vec![("parameters", words(parameters)), ("body", block(body))])
+        }
+        Expression::Call { function, arguments, .. } => {
+            node("Call", vec![("function", expression(function)), ("arguments", list(arguments))])
+        }
+        Expression::Index(left, index) => {
+            node("Index", vec![("left", expression(left)), ("index", expression(index))])
+        }
+    }
+}
+
+fn write_json(value: &Value, json: &mut String) {
+    match value {
+        Value::Node(node) => {
+            json.push_str("{\"type\": ");
+            json.push_str(&quote_json(node.kind));
+            for (name, value) in &node.fields {
+                json.push_str(&format!(", {}: ", quote_json(name)));
+                write_json(value, json);
+            }
+            json.push('}');
+        }
+        Value::List(items) => {
+            json.push('[');
+            for (index, item) in items.iter().enumerate() {
+                if index > 0 {
+                    json.push_str(", ");
+                }
+                write_json(item, json);
+            }
+            json.push(']');
+        }
+        Value::Word(text) | Value::String(text) => json.push_str(&quote_json(text)),
+        Value::Number(number) => json.push_str(number),
+        Value::Boolean(value) => json.push_str(&value.to_string()),
+        Value::Missing => json.push_str("null"),
+    }
+}
+
+// A node is its kind in kebab case followed by its fields, leaving out a missing `else`. The items
+// of a node that is only a list, like a block, go straight into the node's own list.
+fn sexpr(value: &Value) -> String {
+    match value {
+        Value::Node(node) => {
+            let mut kind = String::new();
+            for character in node.kind.chars() {
+                if character.is_uppercase() && !kind.is_empty() {
+                    kind.push('-');
+                }
+                kind.push(character.to_ascii_lowercase());
+            }
+            let mut parts = vec![kind];
+            match &node.fields[..] {
+                [(_, Value::List(items))] => parts.extend(items.iter().map(sexpr)),
+                fields => parts.extend(
+                    fields.iter().filter(|(_, value)| !matches!(value, Value::Missing)).map(|(_, value)| sexpr(value)),
+                ),
+            }
+            format!("({})", parts.join(" "))
+        }
+        Value::List(items) => format!("({})", items.iter().map(sexpr).collect::<Vec<String>>().join(" ")),
+        Value::Word(text) | Value::Number(text) => text.clone(),
+        Value::String(text) => quote_string(text),
+        Value::Boolean(value) => value.to_string(),
+        Value::Missing => String::from("nil"),
+    }
+}
+
+// A line of the tree and the ones that hang off it.
+struct Branch {
+    label: String,
+    children: Vec<Branch>,
+}
+
+// Names, operators, literal values and lists of those go on the node's own line. A node with one
+// field that does not is followed by what is in it, one with more by its fields under their names.
+fn branch(node: &Node, field: Option<&str>) -> Branch {
+    let mut label = format!("{}{}", field.map(|field| format!("{}: ", field)).unwrap_or_default(), node.kind);
+    let mut below = vec![];
+    for (name, value) in &node.fields {
+        match value {
+            Value::Missing => {}
+            value if is_inline(value) => label += &format!(" {}", inline(value)),
+            value => below.push((*name, value)),
+        }
+    }
+    let children = match below[..] {
+        [(_, Value::List(items))] => items.iter().map(|item| value_branch(item, None)).collect(),
+        [(_, value)] => vec![value_branch(value, None)],
+        _ => below.iter().map(|(name, value)| value_branch(value, Some(name))).collect(),
+    };
+    Branch { label, children }
+}
+
+fn value_branch(value: &Value, field: Option<&str>) -> Branch {
+    match value {
+        Value::Node(node) => branch(node, field),
+        Value::List(items) => Branch {
+            label: match items.is_empty() {
+                true => format!("{} (none)", field.unwrap_or_default()),
+                false => String::from(field.unwrap_or_default()),
+            },
+            children: items.iter().map(|item| value_branch(item, None)).collect(),
+        },
+        value => Branch { label: inline(value), children: vec![] },
+    }
+}
+
+fn is_inline(value: &Value) -> bool {
+    match value {
+        Value::Node(_) | Value::Missing => false,
+        Value::List(items) => !items.is_empty() && items.iter().all(is_inline),
+        _ => true,
+    }
+}
+
+fn inline(value: &Value) -> String {
+    match value {
+        Value::List(items) => format!("({})", items.iter().map(inline).collect::<Vec<String>>().join(", ")),
+        value => sexpr(value),
+    }
+}
+
+fn write_branches(branch: &Branch, prefix: &str, lines: &mut Vec<String>) {
+    for (index, child) in branch.children.iter().enumerate() {
+        let is_last = index + 1 == branch.children.len();
+        lines.push(format!("{}{}{}", prefix, if is_last { "└── " } else { "├── " }, child.label));
+        write_branches(child, &format!("{}{}", prefix, if is_last { "    " } else { "│   " }), lines);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::syntax_analyzer::parse_with_spans;
+
+    fn dump_source(source: &str, format: DumpFormat) -> String {
+        dump(&parse_with_spans(source).unwrap(), format)
+    }
+
+    #[test]
+    fn it_dumps_json() {
+        assert_eq!(
+            dump_source("let x = -1.5; puts(\"a\\n\", [true])", DumpFormat::Json),
+            "{\"type\": \"Program\", \"statements\": [\
+             {\"type\": \"Let\", \"name\": \"x\", \"value\": {\"type\": \"Prefix\", \"operator\": \"-\", \
+             \"right\": {\"type\": \"Float\", \"value\": 1.5}}}, \
+             {\"type\": \"ExpressionStatement\", \"expression\": {\"type\": \"Call\", \
+             \"function\": {\"type\": \"Identifier\", \"name\": \"puts\"}, \"arguments\": [\
+             {\"type\": \"String\", \"value\": \"a\\n\"}, \
+             {\"type\": \"Array\", \"elements\": [{\"type\": \"Boolean\", \"value\": true}]}]}}]}"
+        );
+        assert_eq!(
+            dump_source("if (x) { 1 }", DumpFormat::Json),
+            "{\"type\": \"Program\", \"statements\": [{\"type\": \"ExpressionStatement\", \"expression\": \
+             {\"type\": \"If\", \"condition\": {\"type\": \"Identifier\", \"name\": \"x\"}, \"consequence\": \
+             {\"type\": \"Block\", \"statements\": [{\"type\": \"ExpressionStatement\", \"expression\": \
+             {\"type\": \"Integer\", \"value\": 1}}]}, \"alternative\": null}}]}"
+        );
+        assert_eq!(quote_json("tab\there \"\u{1}\""), "\"tab\\there \\\"\\u0001\\\"\"");
+    }
+
+    #[test]
+    fn it_dumps_sexprs() {
+        assert_eq!(
+            dump_source("let add = fn(a, b) { a + b };\nadd(1, {\"k\": null})[0]", DumpFormat::Sexpr),
+            "(let add (function (a b) (block (expression-statement (infix + (identifier a) (identifier b))))))\n\
+             (expression-statement (index (call (identifier add) ((integer 1) (hash (pair (string \"k\") (null))))) \
+             (integer 0)))"
+        );
+        assert_eq!(
+            dump_source("for k, v in h { break } other", DumpFormat::Sexpr),
+            "(expression-statement (for (k v) (identifier h) (block (break))))\n\
+             (expression-statement (identifier other))"
+        );
+    }
+
+    #[test]
+    fn it_dumps_trees() {
+        assert_eq!(
+            dump_source("let f = fn(n) { if (n < 2) { n } else { f(n - 1) } };\nf()", DumpFormat::Tree),
+            "\
+Program
+├── Let f
+│   └── Function (n)
+│       └── Block
+│           └── ExpressionStatement
+│               └── If
+│                   ├── condition: Infix <
+│                   │   ├── left: Identifier n
+│                   │   └── right: Integer 2
+│                   ├── consequence: Block
+│                   │   └── ExpressionStatement
+│                   │       └── Identifier n
+│                   └── alternative: Block
+│                       └── ExpressionStatement
+│                           └── Call
+│                               ├── function: Identifier f
+│                               └── arguments
+│                                   └── Infix -
+│                                       ├── left: Identifier n
+│                                       └── right: Integer 1
+└── ExpressionStatement
+    └── Call
+        ├── function: Identifier f
+        └── arguments (none)"
+        );
+        assert_eq!(dump_source("", DumpFormat::Tree), "Program");
+        assert_eq!(dump_source("try { } catch (e) { \"x\" }", DumpFormat::Tree).lines().nth(2), Some("    └── Try e"));
+    }
+}
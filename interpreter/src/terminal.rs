@@ -0,0 +1,331 @@
+use std::io::{self, IsTerminal, Read};
+use std::process::{Command, Stdio};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, OnceLock};
+
+pub fn is_interactive() -> bool {
+    io::stdin().is_terminal() && io::stdout().is_terminal()
+}
+
+// Whether standard input comes from a pipe or a file, so there is a program to run rather than
+// someone to prompt.
+pub fn is_input_piped() -> bool {
+    !io::stdin().is_terminal()
+}
+
+// What `--color` asks for.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ColorChoice {
+    Auto,
+    Always,
+    Never,
+}
+
+impl ColorChoice {
+    pub fn from_name(name: &str) -> Option<ColorChoice> {
+        match name {
+            "auto" => Some(ColorChoice::Auto),
+            "always" => Some(ColorChoice::Always),
+            "never" => Some(ColorChoice::Never),
+            _ => None,
+        }
+    }
+
+    // Whether to color what goes to the stream. Left to itself that is only a terminal, and not
+    // when the user opted out through the `NO_COLOR` convention.
+    pub fn applies_to(self, stream: &impl IsTerminal) -> bool {
+        match self {
+            ColorChoice::Auto => {
+                stream.is_terminal() && std::env::var_os("NO_COLOR").is_none_or(|value| value.is_empty())
+            }
+            ColorChoice::Always => true,
+            ColorChoice::Never => false,
+        }
+    }
+}
+
+// The rows and columns of the terminal, asked of `stty` like `RawMode` does.
+pub fn size() -> Option<(usize, usize)> {
+    let size = stty(&["size"])?;
+    let mut numbers = size.split_whitespace().map(|number| number.parse().ok());
+    match (numbers.next()??, numbers.next()??) {
+        (0, _) | (_, 0) => None,
+        size => Some(size),
+    }
+}
+
+// Clears the screen and moves the cursor to the top left.
+pub const CLEAR_SCREEN: &str = "\x1b[2J\x1b[H";
+
+// Switches back to the terminal's own colors after `color_code`.
+pub const RESET: &str = "\x1b[0m";
+
+// The escape sequence switching the foreground to a named color.
+pub fn color_code(name: &str) -> Option<&'static str> {
+    match name {
+        "black" => Some("\x1b[30m"),
+        "red" => Some("\x1b[31m"),
+        "green" => Some("\x1b[32m"),
+        "yellow" => Some("\x1b[33m"),
+        "blue" => Some("\x1b[34m"),
+        "magenta" => Some("\x1b[35m"),
+        "cyan" => Some("\x1b[36m"),
+        "white" => Some("\x1b[37m"),
+        _ => None,
+    }
+}
+
+static INTERRUPTED: OnceLock<Arc<AtomicBool>> = OnceLock::new();
+
+// Makes Ctrl-C set the returned flag instead of killing the process, for handing to
+// `Evaluator::with_interrupt`.
+pub fn catch_interrupts() -> Arc<AtomicBool> {
+    let interrupted = INTERRUPTED.get_or_init(|| {
+        #[cfg(unix)]
+        unsafe {
+            signal(SIGINT, on_interrupt);
+        }
+        Arc::new(AtomicBool::new(false))
+    });
+    Arc::clone(interrupted)
+}
+
+#[cfg(unix)]
+const SIGINT: i32 = 2;
+
+#[cfg(unix)]
+extern "C" {
+    fn signal(signal: i32, handler: extern "C" fn(i32)) -> usize;
+}
+
+#[cfg(unix)]
+extern "C" fn on_interrupt(_: i32) {
+    if let Some(interrupted) = INTERRUPTED.get() {
+        interrupted.store(true, Ordering::Relaxed);
+    }
+}
+
+// Switches the terminal to reading one key at a time without echo for as long as it lives. Ctrl-C
+// arrives as a key rather than a signal in the meantime, so editing can cancel just the line.
+// There is no terminal crate to lean on, so this goes through `stty`, which every Unix-like
+// system has; where it fails the guard simply does nothing and callers fall back to reading
+// whole lines.
+pub struct RawMode {
+    saved_settings: String,
+}
+
+impl RawMode {
+    pub fn enable() -> Option<RawMode> {
+        let saved_settings = stty(&["-g"])?;
+        stty(&["-icanon", "-echo", "-isig", "min", "1"])?;
+        Some(RawMode {
+            saved_settings: saved_settings.trim().to_string(),
+        })
+    }
+}
+
+impl Drop for RawMode {
+    fn drop(&mut self) {
+        stty(&[&self.saved_settings]);
+    }
+}
+
+fn stty(arguments: &[&str]) -> Option<String> {
+    let output = Command::new("stty")
+        .args(arguments)
+        .stdin(Stdio::inherit())
+        .stderr(Stdio::null())
+        .output()
+        .ok()?;
+    if !output.status.success() {
+        return None;
+    }
+    String::from_utf8(output.stdout).ok()
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Key {
+    Char(char),
+    // A letter pressed together with Ctrl, lowercase.
+    Ctrl(char),
+    Enter,
+    Tab,
+    Backspace,
+    Delete,
+    Left,
+    Right,
+    Up,
+    Down,
+    Home,
+    End,
+    Escape,
+}
+
+// Decodes the bytes a terminal sends for key presses, including VT100 escape sequences for
+// the arrow and navigation keys.
+pub struct KeyReader<R: Read> {
+    input: R,
+    pending: Option<u8>,
+}
+
+impl<R: Read> KeyReader<R> {
+    pub fn new(input: R) -> Self {
+        KeyReader { input, pending: None }
+    }
+
+    fn read_byte(&mut self) -> io::Result<Option<u8>> {
+        if let Some(byte) = self.pending.take() {
+            return Ok(Some(byte));
+        }
+
+        let mut byte = [0];
+        match self.input.read(&mut byte)? {
+            0 => Ok(None),
+            _ => Ok(Some(byte[0])),
+        }
+    }
+
+    // Returns `None` at the end of the input.
+    pub fn read_key(&mut self) -> io::Result<Option<Key>> {
+        let byte = match self.read_byte()? {
+            None => return Ok(None),
+            Some(byte) => byte,
+        };
+
+        let key = match byte {
+            b'\r' | b'\n' => Key::Enter,
+            b'\t' => Key::Tab,
+            0x7f | 0x08 => Key::Backspace,
+            0x1b => self.read_escape_sequence()?,
+            0x01..=0x1a => Key::Ctrl((b'a' + byte - 1) as char),
+            byte if byte < 0x80 => Key::Char(byte as char),
+            byte => self.read_utf8(byte)?,
+        };
+
+        Ok(Some(key))
+    }
+
+    fn read_escape_sequence(&mut self) -> io::Result<Key> {
+        let introducer = match self.read_byte()? {
+            Some(byte @ (b'[' | b'O')) => byte,
+            Some(byte) => {
+                self.pending = Some(byte);
+                return Ok(Key::Escape);
+            }
+            None => return Ok(Key::Escape),
+        };
+
+        let mut parameter = String::new();
+        loop {
+            let byte = match self.read_byte()? {
+                None => return Ok(Key::Escape),
+                Some(byte) => byte,
+            };
+
+            let key = match (introducer, byte) {
+                (_, b'A') => Key::Up,
+                (_, b'B') => Key::Down,
+                (_, b'C') => Key::Right,
+                (_, b'D') => Key::Left,
+                (_, b'H') => Key::Home,
+                (_, b'F') => Key::End,
+                (b'[', b'0'..=b'9' | b';') => {
+                    parameter.push(byte as char);
+                    continue;
+                }
+                (b'[', b'~') => match parameter.as_str() {
+                    "1" | "7" => Key::Home,
+                    "4" | "8" => Key::End,
+                    "3" => Key::Delete,
+                    _ => Key::Escape,
+                },
+                _ => Key::Escape,
+            };
+            return Ok(key);
+        }
+    }
+
+    fn read_utf8(&mut self, first: u8) -> io::Result<Key> {
+        let length = match first {
+            0xc0..=0xdf => 2,
+            0xe0..=0xef => 3,
+            0xf0..=0xf7 => 4,
+            _ => return Ok(Key::Char(char::REPLACEMENT_CHARACTER)),
+        };
+
+        let mut bytes = vec![first];
+        while bytes.len() < length {
+            match self.read_byte()? {
+                Some(byte) => bytes.push(byte),
+                None => break,
+            }
+        }
+
+        let character = std::str::from_utf8(&bytes)
+            .ok()
+            .and_then(|text| text.chars().next())
+            .unwrap_or(char::REPLACEMENT_CHARACTER);
+        Ok(Key::Char(character))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn read_keys(input: &[u8]) -> Vec<Key> {
+        let mut reader = KeyReader::new(input);
+        let mut keys = vec![];
+        while let Some(key) = reader.read_key().unwrap() {
+            keys.push(key);
+        }
+        keys
+    }
+
+    #[test]
+    fn it_works_on_color_choices() {
+        let file = std::fs::File::open(env!("CARGO_MANIFEST_DIR")).unwrap();
+        assert_eq!(ColorChoice::from_name("always"), Some(ColorChoice::Always));
+        assert_eq!(ColorChoice::from_name("yes"), None);
+        assert!(ColorChoice::Always.applies_to(&file));
+        assert!(!ColorChoice::Never.applies_to(&file));
+        assert!(!ColorChoice::Auto.applies_to(&file));
+    }
+
+    #[test]
+    fn it_decodes_plain_and_control_keys() {
+        assert_eq!(
+            read_keys(b"a1\x01\x0b\x7f\t\r"),
+            [
+                Key::Char('a'),
+                Key::Char('1'),
+                Key::Ctrl('a'),
+                Key::Ctrl('k'),
+                Key::Backspace,
+                Key::Tab,
+                Key::Enter
+            ]
+        );
+        assert_eq!(read_keys("é→".as_bytes()), [Key::Char('é'), Key::Char('→')]);
+    }
+
+    #[test]
+    fn it_decodes_escape_sequences() {
+        assert_eq!(
+            read_keys(b"\x1b[A\x1b[B\x1b[C\x1b[D\x1b[H\x1b[F\x1bOH\x1b[1~\x1b[4~\x1b[3~"),
+            [
+                Key::Up,
+                Key::Down,
+                Key::Right,
+                Key::Left,
+                Key::Home,
+                Key::End,
+                Key::Home,
+                Key::Home,
+                Key::End,
+                Key::Delete
+            ]
+        );
+        assert_eq!(read_keys(b"\x1bx\x1b[1;5C"), [Key::Escape, Key::Char('x'), Key::Right]);
+    }
+}
@@ -0,0 +1,280 @@
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::io::Write;
+use std::rc::Rc;
+
+use crate::abstract_syntax_tree::{BlockStatement, Expression, Statement};
+use crate::environment::Environment;
+use crate::hooks::{Call, Hooks};
+use crate::lexical_analyzer::Span;
+use crate::object::{InspectOptions, Object};
+use crate::syntax_analyzer::parse_with_spans;
+
+// What `--trace` prints.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum TraceMode {
+    // Every expression as it is evaluated, and the value of those that are not literals.
+    Nodes,
+    // Every function call with its arguments, and what it returned.
+    Calls,
+}
+
+impl TraceMode {
+    pub fn from_name(name: &str) -> Option<TraceMode> {
+        match name {
+            "nodes" => Some(TraceMode::Nodes),
+            "calls" => Some(TraceMode::Calls),
+            _ => None,
+        }
+    }
+}
+
+// Where lines get cut off, so that a function literal or a big array does not fill the screen.
+const TRACE_WIDTH: usize = 100;
+
+// Writes the trace indented by how deep evaluation is at the time.
+pub struct Tracer {
+    mode: TraceMode,
+    output: Box<dyn Write>,
+    // The expressions entered and not exited yet. An expression in tail position is never
+    // exited, it goes when the expression around it does.
+    expressions: Vec<*const Expression>,
+    // The calls that have not returned yet, and whether each is of a builtin.
+    calls: Vec<bool>,
+    // The name of the function called at every call site of the program, as far as it has one.
+    names: HashMap<Span, String>,
+}
+
+impl Tracer {
+    // `source` is the program being traced, for the names of the functions it calls.
+    pub fn new(mode: TraceMode, source: &str, output: impl Write + 'static) -> Self {
+        Tracer { mode, output: Box::new(output), expressions: vec![], calls: vec![], names: call_names(source) }
+    }
+
+    fn write(&mut self, depth: usize, text: &str) {
+        let mut line = format!("{}{}", "  ".repeat(depth), text);
+        if let Some((index, _)) = line.char_indices().nth(TRACE_WIDTH) {
+            line.truncate(index);
+            line += "...";
+        }
+        let _ = writeln!(self.output, "{}", line);
+    }
+}
+
+impl Hooks for Tracer {
+    fn on_eval_enter(&mut self, expression: &Expression, _env: &Rc<RefCell<Environment>>) {
+        if self.mode == TraceMode::Nodes {
+            self.write(self.expressions.len(), &expression.to_string());
+            self.expressions.push(expression);
+        }
+    }
+
+    fn on_eval_exit(&mut self, expression: &Expression, _env: &Rc<RefCell<Environment>>, result: &Object) {
+        if self.mode != TraceMode::Nodes {
+            return;
+        }
+        let pointer: *const Expression = expression;
+        let Some(depth) = self.expressions.iter().rposition(|entered| *entered == pointer) else {
+            return;
+        };
+        self.expressions.truncate(depth);
+        let is_literal = matches!(
+            expression,
+            Expression::Integer(_)
+                | Expression::Float(_)
+                | Expression::Boolean(_)
+                | Expression::String(_)
+                | Expression::Null
+        );
+        if !is_literal {
+            self.write(depth + 1, &format!("=> {}", show(result)));
+        }
+    }
+
+    fn on_call(&mut self, _call: Option<Call>, function: &Object, arguments: &[Object], span: Span) {
+        if self.mode != TraceMode::Calls {
+            return;
+        }
+        let name = callee_name(&self.names, function, span, self.calls.last() == Some(&true));
+        let arguments: Vec<String> = arguments.iter().map(show).collect();
+        self.write(self.calls.len(), &format!("{}({})", name, arguments.join(", ")));
+        self.calls.push(matches!(function, Object::Builtin(_)));
+    }
+
+    fn on_return(&mut self, result: &Object) {
+        if self.mode == TraceMode::Calls && self.calls.pop().is_some() {
+            self.write(self.calls.len() + 1, &format!("=> {}", show(result)));
+        }
+    }
+}
+
+// The name of the function called at every call site of a program, for the calls of a function
+// by the name it was defined under.
+pub fn call_names(source: &str) -> HashMap<Span, String> {
+    let mut names = HashMap::new();
+    if let Ok(program) = parse_with_spans(source) {
+        statement_names(&program.statements, &mut names);
+    }
+    names
+}
+
+// The name of a function being called at `span`, see `call_names`. Builtins like `map` call
+// functions from the call site of the builtin itself, so those go without a name.
+pub fn callee_name(names: &HashMap<Span, String>, function: &Object, span: Span, is_from_builtin: bool) -> String {
+    match function {
+        Object::Builtin(builtin) => String::from(builtin.name),
+        _ if is_from_builtin => String::from("<anonymous>"),
+        _ => names.get(&span).cloned().unwrap_or_else(|| String::from("<anonymous>")),
+    }
+}
+
+// A value on one line, strings quoted.
+fn show(value: &Object) -> String {
+    value.inspect(InspectOptions { width: usize::MAX, ..InspectOptions::default() })
+}
+
+fn statement_names(statements: &[Statement], names: &mut HashMap<Span, String>) {
+    for statement in statements {
+        match statement {
+            Statement::Let(_, value) | Statement::Return(value) | Statement::Expression(value) => {
+                expression_names(value, names)
+            }
+            Statement::Break | Statement::Continue => {}
+        }
+    }
+}
+
+fn block_names(block: &BlockStatement, names: &mut HashMap<Span, String>) {
+    statement_names(&block.statements, names)
+}
+
+fn expression_names(expression: &Expression, names: &mut HashMap<Span, String>) {
+    match expression {
+        Expression::Identifier(_)
+        | Expression::Integer(_)
+        | Expression::Float(_)
+        | Expression::Boolean(_)
+        | Expression::String(_)
+        | Expression::Null => {}
+        Expression::Array(elements) => elements.iter().for_each(|element| expression_names(element, names)),
+        Expression::Hash(pairs) => {
+            for (key, value) in pairs {
+                expression_names(key, names);
+                expression_names(value, names);
+            }
+        }
+        Expression::Prefix(_, right) | Expression::Assign(_, right) => expression_names(right, names),
+        Expression::Infix(_, left, right) | Expression::Index(left, right) => {
+            expression_names(left, names);
+            expression_names(right, names);
+        }
+        Expression::If { condition, consequence, alternative } => {
+            expression_names(condition, names);
+            block_names(consequence, names);
+            if let Some(alternative) = alternative {
+                block_names(alternative, names);
+            }
+        }
+        Expression::While { condition, body } => {
+            expression_names(condition, names);
+            block_names(body, names);
+        }
+        Expression::For { iterable, body, .. } => {
+            expression_names(iterable, names);
+            block_names(body, names);
+        }
+        Expression::Try { body, handler, .. } => {
+            block_names(body, names);
+            block_names(handler, names);
+        }
+        Expression::Function { body, .. } => block_names(body, names),
+        Expression::Call { function, arguments, span } => {
+            if let Expression::Identifier(name) = function.as_ref() {
+                names.insert(*span, name.to_string());
+            }
+            expression_names(function, names);
+            arguments.iter().for_each(|argument| expression_names(argument, names));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::evaluator::Evaluator;
+    use std::io;
+
+    struct SharedOutput(Rc<RefCell<Vec<u8>>>);
+
+    impl Write for SharedOutput {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.borrow_mut().write(buf)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    fn trace(mode: TraceMode, source: &str) -> String {
+        let output = Rc::new(RefCell::new(vec![]));
+        let tracer = Tracer::new(mode, source, SharedOutput(Rc::clone(&output)));
+        let mut evaluator = Evaluator::new().with_hooks(tracer).with_output(io::sink());
+        let env = Rc::new(RefCell::new(Environment::new()));
+        evaluator.eval_program(&parse_with_spans(source).unwrap(), &env);
+        let trace = String::from_utf8(output.borrow().clone()).unwrap();
+        trace
+    }
+
+    #[test]
+    fn it_traces_nodes() {
+        assert_eq!(
+            trace(TraceMode::Nodes, "let x = 2; x * (x + 1)"),
+            "\
+2
+(x * (x + 1))
+  x
+    => 2
+  (x + 1)
+    x
+      => 2
+    1
+    => 3
+  => 6
+"
+        );
+        // The tail call is left when the call around it is.
+        assert_eq!(
+            trace(TraceMode::Nodes, "let f = fn() { len(\"\") }; f()").lines().last(),
+            Some("  => 0")
+        );
+    }
+
+    #[test]
+    fn it_traces_calls() {
+        let source = "\
+let count = fn(n) { if (n > 0) { count(n - 1) } else { n } };
+let twice = fn(f, x) { f(f(x)) };
+count(1);
+map([1], fn(x) { twice(len, \"ab\") })";
+        assert_eq!(
+            trace(TraceMode::Calls, source),
+            "\
+count(1)
+  count(0)
+    => 0
+  => 0
+map([1], fn(x) { twice(len, \"ab\") })
+  <anonymous>(1)
+    twice(builtin function len, \"ab\")
+      len(\"ab\")
+        => 2
+      len(2)
+        => ERROR: argument to `len` not supported, got INTEGER
+      => ERROR: argument to `len` not supported, got INTEGER
+    => ERROR: argument to `len` not supported, got INTEGER
+  => ERROR: argument to `len` not supported, got INTEGER
+"
+        );
+    }
+}
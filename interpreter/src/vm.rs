@@ -0,0 +1,815 @@
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use crate::abstract_syntax_tree::{InfixOperator, PrefixOperator};
+use crate::builtins::all_builtin_names;
+use crate::code::{read_operand, CallSite, Capture, Opcode};
+use crate::compiler::Bytecode;
+use crate::diagnostics::{
+    IDENTIFIER_NOT_FOUND, NOT_A_FUNCTION, NOT_ITERABLE, UNDECLARED_ASSIGNMENT, UNUSABLE_HASH_KEY, WRONG_ARGUMENT_COUNT,
+};
+use crate::evaluator::{
+    eval_index_expression, eval_infix_expression, eval_prefix_expression, Evaluator, Frame as TraceFrame,
+    FunctionCaller,
+};
+use crate::interner::Symbol;
+use crate::lexical_analyzer::Span;
+use crate::object::{Closure, CompiledFunction, HashPairs, Object};
+
+// A call being run, or the program. The locals are the slots of the stack from `base` on, with the
+// function called just under them.
+struct Frame {
+    closure: Rc<Closure>,
+    ip: usize,
+    base: usize,
+    // The locals that closures captured, by slot. They are kept here from then on, and their slot
+    // of the stack goes unused.
+    cells: Vec<Option<Rc<RefCell<Object>>>>,
+    // The tail call that made the frame, taking the place of the frame that made it, for stack
+    // traces to have it as the evaluator's do.
+    tail_call: Option<CallSite>,
+}
+
+impl Frame {
+    fn new(closure: Rc<Closure>, base: usize) -> Self {
+        Frame { closure, ip: 0, base, cells: vec![], tail_call: None }
+    }
+
+    fn cell(&self, index: usize) -> Option<&Rc<RefCell<Object>>> {
+        self.cells.get(index).and_then(Option::as_ref)
+    }
+
+    // The call the frame is in the middle of, or that just failed, which ends where the frame is.
+    fn call_site(&self) -> Option<&CallSite> {
+        let calls = &self.closure.function.calls;
+        let offset = self.ip.checked_sub(Opcode::Call.definition().operand_widths[0] + 1)?;
+        calls.binary_search_by_key(&offset, |site| site.offset).ok().map(|index| &calls[index])
+    }
+
+    // Where the statement the frame is in the middle of, or that just failed, starts.
+    fn statement(&self) -> Option<Span> {
+        let statements = &self.closure.function.statements;
+        let index = statements.partition_point(|(offset, _)| *offset < self.ip);
+        index.checked_sub(1).map(|index| statements[index].1)
+    }
+
+    fn trace(&self, evaluator: &mut Evaluator) {
+        if let Some(site) = self.call_site() {
+            evaluator.push_frame(TraceFrame { function: site.function.clone(), span: site.span, file: None });
+        }
+    }
+
+    fn trace_tail_call(&self, evaluator: &mut Evaluator) {
+        if let Some(site) = &self.tail_call {
+            evaluator.push_frame(TraceFrame { function: site.function.clone(), span: site.span, file: None });
+        }
+    }
+}
+
+// The body of a `try` being run: where its handler starts, in the frame it is in, and how many
+// frames, values and calls of the stack trace there were when it started, to go back to.
+struct Handler {
+    frames: usize,
+    stack: usize,
+    trace: usize,
+    target: usize,
+}
+
+// What every call of a program shares, the ones builtins make back into it too.
+struct Context {
+    constants: Vec<Object>,
+    // Unset until the `let` that defines them runs.
+    globals: RefCell<Vec<Option<Object>>>,
+    global_names: Vec<Symbol>,
+    // The names of the builtins by the index the compiler gave them.
+    builtins: Vec<Symbol>,
+}
+
+// Runs bytecode on a stack of operands. The operators are the evaluator's own, and so are the
+// limits and builtins, so a program gives the same result, or fails with the same error, whichever
+// of the two runs it.
+pub struct Vm {
+    context: Rc<Context>,
+    stack: Vec<Object>,
+    frames: Vec<Frame>,
+    // The `try`s being run, innermost last.
+    handlers: Vec<Handler>,
+}
+
+impl Vm {
+    pub fn new(bytecode: Bytecode) -> Self {
+        let context = Context {
+            constants: bytecode.constants,
+            globals: RefCell::new(vec![None; bytecode.globals.len()]),
+            global_names: bytecode.globals,
+            builtins: all_builtin_names().into_iter().map(Symbol::intern).collect(),
+        };
+        let program = CompiledFunction {
+            instructions: bytecode.instructions,
+            locals: bytecode.locals,
+            parameters: 0,
+            free: vec![],
+            source: String::new(),
+            calls: bytecode.calls,
+            statements: bytecode.statements,
+        };
+        let frame = Frame::new(Rc::new(Closure { function: Rc::new(program), free: vec![] }), 0);
+        let stack = vec![Object::Null; bytecode.locals];
+        Vm { context: Rc::new(context), stack, frames: vec![frame], handlers: vec![] }
+    }
+
+    // Runs the program to its end and returns the value it leaves, or the error that stopped it.
+    // The step budget, time limit and memory limit of `evaluator` apply, each instruction taking
+    // a step, and so does its depth limit. An error leaves the calls it stopped in as the
+    // evaluator's stack trace.
+    pub fn run(&mut self, evaluator: &mut Evaluator) -> Object {
+        evaluator.start_program();
+        let context = Rc::clone(&self.context);
+        let caller: FunctionCaller = Rc::new(move |evaluator, function, arguments| {
+            Vm::call_back(Rc::clone(&context), evaluator, function, arguments)
+        });
+        let outer_caller = evaluator.replace_function_caller(Some(caller));
+        let result = self.resume(evaluator);
+        evaluator.replace_function_caller(outer_caller);
+        result
+    }
+
+    // Calls one of the program's functions for a builtin it was passed to, as `map` is.
+    fn call_back(context: Rc<Context>, evaluator: &mut Evaluator, function: Object, arguments: Vec<Object>) -> Object {
+        let count = arguments.len();
+        let mut vm = Vm { context, stack: vec![function], frames: vec![], handlers: vec![] };
+        vm.stack.extend(arguments);
+        match vm.call(evaluator, count) {
+            Ok(()) => vm.resume(evaluator),
+            Err(error) => error,
+        }
+    }
+
+    fn resume(&mut self, evaluator: &mut Evaluator) -> Object {
+        let mut result = self.execute(evaluator);
+        while let Err(error) = &result {
+            if !self.catch(evaluator, error) {
+                break;
+            }
+            result = self.execute(evaluator);
+        }
+        if let (Err(_), Some(span)) = (&result, self.frames.last().and_then(Frame::statement)) {
+            evaluator.set_error_span(span);
+        }
+        // The calls an error stopped in the middle, innermost first. Every frame but the innermost
+        // one is in the middle of calling the one after it.
+        let innermost = self.frames.len().saturating_sub(1);
+        for (index, frame) in self.frames.drain(..).enumerate().rev() {
+            if index < innermost {
+                frame.trace(evaluator);
+            }
+            if result.is_err() {
+                frame.trace_tail_call(evaluator);
+            }
+            if frame.base > 0 {
+                evaluator.leave_call();
+            }
+        }
+        match result {
+            Ok(()) => self.stack.pop().unwrap_or(Object::Null),
+            Err(error) => error,
+        }
+    }
+
+    fn execute(&mut self, evaluator: &mut Evaluator) -> Result<(), Object> {
+        while let Some(frame) = self.frames.last_mut() {
+            let instructions = &frame.closure.function.instructions.0;
+            let Some(&byte) = instructions.get(frame.ip) else {
+                // Only the program ends without returning.
+                self.frames.pop();
+                continue;
+            };
+            let opcode = Opcode::from_byte(byte).ok_or_else(|| Object::malformed(format!("unknown opcode {}", byte)))?;
+            let mut operands = [0; 2];
+            let mut offset = frame.ip + 1;
+            for (operand, width) in operands.iter_mut().zip(opcode.definition().operand_widths) {
+                *operand = read_operand(&instructions[offset..], *width);
+                offset += width;
+            }
+            frame.ip = offset;
+            let base = frame.base;
+            if let Some(error) = evaluator.take_step() {
+                return Err(error);
+            }
+
+            match opcode {
+                Opcode::Constant => self.push(evaluator, self.context.constants[operands[0]].clone())?,
+                Opcode::Pop => {
+                    self.pop();
+                }
+                Opcode::Dup => {
+                    let value = self.stack.last().cloned().expect("the value is there");
+                    self.stack.push(value);
+                }
+                Opcode::Add => self.infix(evaluator, InfixOperator::Plus)?,
+                Opcode::Subtract => self.infix(evaluator, InfixOperator::Minus)?,
+                Opcode::Multiply => self.infix(evaluator, InfixOperator::Star)?,
+                Opcode::Divide => self.infix(evaluator, InfixOperator::Slash)?,
+                Opcode::Equal => self.infix(evaluator, InfixOperator::Equals)?,
+                Opcode::NotEqual => self.infix(evaluator, InfixOperator::NotEquals)?,
+                Opcode::LessThan => self.infix(evaluator, InfixOperator::LessThan)?,
+                Opcode::GreaterThan => self.infix(evaluator, InfixOperator::GreaterThan)?,
+                Opcode::Range => self.infix(evaluator, InfixOperator::Range)?,
+                Opcode::Minus => {
+                    let right = self.pop();
+                    self.push(evaluator, eval_prefix_expression(PrefixOperator::Minus, right))?
+                }
+                Opcode::Bang => {
+                    let right = self.pop();
+                    self.push(evaluator, eval_prefix_expression(PrefixOperator::Bang, right))?
+                }
+                Opcode::True => self.push(evaluator, Object::Boolean(true))?,
+                Opcode::False => self.push(evaluator, Object::Boolean(false))?,
+                Opcode::Null => self.push(evaluator, Object::Null)?,
+                Opcode::Array => {
+                    let elements = self.stack.split_off(self.stack.len() - operands[0]);
+                    self.push(evaluator, Object::Array(Rc::new(elements)))?
+                }
+                Opcode::Hash => {
+                    let elements = self.stack.split_off(self.stack.len() - 2 * operands[0]);
+                    let mut hash = HashPairs::new();
+                    let mut elements = elements.into_iter();
+                    while let (Some(key), Some(value)) = (elements.next(), elements.next()) {
+                        match key.hash_key() {
+                            Some(hash_key) => hash.insert(hash_key, value),
+                            None => {
+                                let message = format!("unusable as hash key: {}", key.type_name());
+                                return Err(Object::error(UNUSABLE_HASH_KEY, message));
+                            }
+                        }
+                    }
+                    self.push(evaluator, Object::Hash(Rc::new(hash)))?
+                }
+                Opcode::Index => {
+                    let index = self.pop();
+                    let left = self.pop();
+                    self.push(evaluator, eval_index_expression(left, index))?
+                }
+                Opcode::GetGlobal => {
+                    let value = self.context.globals.borrow()[operands[0]].clone();
+                    match value {
+                        Some(value) => self.stack.push(value),
+                        None => {
+                            let name = self.context.global_names[operands[0]];
+                            return Err(Object::error(IDENTIFIER_NOT_FOUND, format!("identifier not found: {}", name)));
+                        }
+                    }
+                }
+                Opcode::SetGlobal => {
+                    let value = self.pop();
+                    self.context.globals.borrow_mut()[operands[0]] = Some(value);
+                }
+                Opcode::AssignGlobal => {
+                    let mut globals = self.context.globals.borrow_mut();
+                    if globals[operands[0]].is_none() {
+                        let name = self.context.global_names[operands[0]];
+                        let message = format!("assignment to undeclared identifier: {}", name);
+                        return Err(Object::error(UNDECLARED_ASSIGNMENT, message));
+                    }
+                    globals[operands[0]] = self.stack.last().cloned();
+                }
+                Opcode::GetLocal => {
+                    let value = match self.frame().cell(operands[0]) {
+                        Some(cell) => cell.borrow().clone(),
+                        None => self.stack[base + operands[0]].clone(),
+                    };
+                    self.stack.push(value)
+                }
+                Opcode::SetLocal => {
+                    let value = self.pop();
+                    self.set_local(operands[0], value)
+                }
+                Opcode::AssignLocal => {
+                    let value = self.stack.last().cloned().expect("the value was just pushed");
+                    self.set_local(operands[0], value)
+                }
+                Opcode::ResetLocal => {
+                    if let Some(cell) = self.frames.last_mut().expect("a frame is running").cells.get_mut(operands[0]) {
+                        *cell = None;
+                    }
+                }
+                Opcode::GetFree => {
+                    let value = self.frame().closure.free[operands[0]].borrow().clone();
+                    self.stack.push(value)
+                }
+                Opcode::AssignFree => {
+                    let value = self.stack.last().cloned().expect("the value was just pushed");
+                    *self.frame().closure.free[operands[0]].borrow_mut() = value;
+                }
+                Opcode::Closure => {
+                    let Object::CompiledFunction(function) = &self.context.constants[operands[0]] else {
+                        return Err(Object::error(NOT_A_FUNCTION, String::from("not a function constant")));
+                    };
+                    let function = Rc::clone(function);
+                    let free = function.free.iter().map(|capture| self.capture(evaluator, *capture)).collect();
+                    self.push(evaluator, Object::Closure(Rc::new(Closure { function, free })))?
+                }
+                Opcode::GetBuiltin => {
+                    let name = self.context.builtins[operands[0]];
+                    match evaluator.builtin(name) {
+                        Some(builtin) => self.stack.push(Object::Builtin(builtin.clone())),
+                        None => {
+                            let message = format!("identifier not found: {}", name);
+                            return Err(Object::error(IDENTIFIER_NOT_FOUND, message));
+                        }
+                    }
+                }
+                Opcode::Call | Opcode::TailCall => {
+                    let frames = self.frames.len();
+                    let called = match opcode {
+                        Opcode::Call => self.call(evaluator, operands[0]),
+                        _ => self.tail_call(evaluator, operands[0]),
+                    };
+                    if let Err(error) = called {
+                        // Unless it happened in a statement of the function, the error is the call's.
+                        if let Some(site) = self.frame().call_site() {
+                            evaluator.set_error_span(site.span);
+                        }
+                        self.frame().trace(evaluator);
+                        // A tail call that fails takes the place of the frame as well, in the trace.
+                        if opcode == Opcode::TailCall {
+                            self.frames.last_mut().expect("the frame calling is there").tail_call = None;
+                        }
+                        return Err(error);
+                    }
+                    // What a builtin returned is the value of the call, which goes over the memory
+                    // limit in the code making the call, as in the evaluator, and not in the builtin.
+                    let is_builtin = match opcode {
+                        Opcode::Call => self.frames.len() == frames,
+                        _ => self.frames.len() < frames,
+                    };
+                    if is_builtin {
+                        let value = self.pop();
+                        self.push(evaluator, value)?;
+                    }
+                }
+                Opcode::ReturnValue => self.return_value(evaluator),
+                Opcode::SetupTry => {
+                    let (frames, stack, trace) = (self.frames.len(), self.stack.len(), evaluator.stack_trace().len());
+                    self.handlers.push(Handler { frames, stack, trace, target: operands[0] })
+                }
+                Opcode::PopTry => {
+                    self.handlers.pop();
+                }
+                Opcode::Jump => self.jump(operands[0]),
+                Opcode::JumpIfFalse => {
+                    if !self.pop().is_truthy() {
+                        self.jump(operands[0]);
+                    }
+                }
+                Opcode::Iterate => {
+                    let [.., iterable, Object::Integer(position)] = &self.stack[..] else {
+                        return Err(Object::malformed(String::from("nothing to iterate over")));
+                    };
+                    let position = *position as usize;
+                    match iteration_values(iterable, position, operands[1])? {
+                        Some(values) => {
+                            let next = Object::Integer(position as i32 + 1);
+                            *self.stack.last_mut().expect("the position is there") = next;
+                            self.stack.extend(values);
+                        }
+                        None => self.jump(operands[0]),
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn frame(&self) -> &Frame {
+        self.frames.last().expect("a frame is running")
+    }
+
+    fn set_local(&mut self, index: usize, value: Object) {
+        let frame = self.frames.last().expect("a frame is running");
+        match frame.cell(index) {
+            Some(cell) => *cell.borrow_mut() = value,
+            None => self.stack[frame.base + index] = value,
+        }
+    }
+
+    // A local captured the first time moves to a cell, for the closure and the frame to share it.
+    fn capture(&mut self, evaluator: &mut Evaluator, capture: Capture) -> Rc<RefCell<Object>> {
+        let frame = self.frames.last_mut().expect("a frame is running");
+        match capture {
+            Capture::Free(index) => Rc::clone(&frame.closure.free[index]),
+            Capture::Local(index) => {
+                if frame.cells.len() <= index {
+                    frame.cells.resize(index + 1, None);
+                }
+                if let Some(cell) = &frame.cells[index] {
+                    return Rc::clone(cell);
+                }
+                let cell = Rc::new(RefCell::new(self.stack[frame.base + index].clone()));
+                frame.cells[index] = Some(Rc::clone(&cell));
+                evaluator.track_cell(&cell);
+                cell
+            }
+        }
+    }
+
+    fn jump(&mut self, target: usize) {
+        self.frames.last_mut().expect("a frame is running").ip = target;
+    }
+
+    // Calls what is under the `count` arguments on top of the stack: a function of the program by
+    // giving it a frame, and a builtin right away, leaving its result to be checked by the caller.
+    fn call(&mut self, evaluator: &mut Evaluator, count: usize) -> Result<(), Object> {
+        match self.stack[self.stack.len() - 1 - count].clone() {
+            Object::Closure(closure) => {
+                let function = &closure.function;
+                check_arguments(function, count)?;
+                if let Some(error) = evaluator.enter_call() {
+                    return Err(error);
+                }
+                let base = self.stack.len() - count;
+                self.stack.resize(base + function.locals, Object::Null);
+                self.frames.push(Frame::new(closure, base));
+                Ok(())
+            }
+            Object::Builtin(builtin) => {
+                let arguments = self.stack.split_off(self.stack.len() - count);
+                self.pop();
+                let result = (builtin.function)(evaluator, arguments)?;
+                self.stack.push(result);
+                Ok(())
+            }
+            other => Err(Object::error(NOT_A_FUNCTION, format!("not a function: {}", other.type_name()))),
+        }
+    }
+
+    // Calls a function in the frame of the one making the call, which returns what it returns. The
+    // depth does not change, the call being in place of the one it is made in.
+    fn tail_call(&mut self, evaluator: &mut Evaluator, count: usize) -> Result<(), Object> {
+        let start = self.stack.len() - 1 - count;
+        let Object::Closure(closure) = &self.stack[start] else {
+            self.call(evaluator, count)?;
+            self.return_value(evaluator);
+            return Ok(());
+        };
+        let closure = Rc::clone(closure);
+        check_arguments(&closure.function, count)?;
+        let frame = self.frames.pop().expect("the frame calling is there");
+        let tail_call = frame.call_site().cloned();
+        self.stack.drain(frame.base - 1..start);
+        self.stack.resize(frame.base + closure.function.locals, Object::Null);
+        self.frames.push(Frame { tail_call, ..Frame::new(closure, frame.base) });
+        Ok(())
+    }
+
+    // Returning from the body of a `try` ends it.
+    fn return_value(&mut self, evaluator: &mut Evaluator) {
+        let value = self.pop();
+        let frame = self.frames.pop().expect("the frame returning is there");
+        if frame.base > 0 {
+            evaluator.leave_call();
+        }
+        let frames = self.frames.len();
+        self.handlers.retain(|handler| handler.frames <= frames);
+        self.stack.truncate(frame.base.saturating_sub(1));
+        self.stack.push(value);
+    }
+
+    // Goes on with the handler of the innermost `try`, unless the error is not in one or cannot be
+    // caught, leaving the calls made inside the `try` as the evaluator does.
+    fn catch(&mut self, evaluator: &mut Evaluator, error: &Object) -> bool {
+        let Object::Error(error) = error else {
+            return false;
+        };
+        if !evaluator.can_catch() {
+            return false;
+        }
+        let Some(handler) = self.handlers.pop() else {
+            return false;
+        };
+        for frame in self.frames.drain(handler.frames..) {
+            if frame.base > 0 {
+                evaluator.leave_call();
+            }
+        }
+        self.stack.truncate(handler.stack);
+        self.stack.push(Object::String(error.message.clone()));
+        evaluator.catch_error(handler.trace);
+        self.jump(handler.target);
+        true
+    }
+
+    fn infix(&mut self, evaluator: &mut Evaluator, operator: InfixOperator) -> Result<(), Object> {
+        let right = self.pop();
+        let left = self.pop();
+        self.push(evaluator, eval_infix_expression(operator, left, right))
+    }
+
+    // Errors stop the program rather than being pushed, and so does a value over the memory limit.
+    fn push(&mut self, evaluator: &mut Evaluator, value: Object) -> Result<(), Object> {
+        match evaluator.check_size(value) {
+            error @ Object::Error(_) => Err(error),
+            value => {
+                self.stack.push(value);
+                Ok(())
+            }
+        }
+    }
+
+    fn pop(&mut self) -> Object {
+        self.stack.pop().expect("the compiler only pops what it pushed")
+    }
+}
+
+fn check_arguments(function: &CompiledFunction, count: usize) -> Result<(), Object> {
+    match function.parameters == count {
+        true => Ok(()),
+        false => Err(Object::error(WRONG_ARGUMENT_COUNT, format!(
+            "wrong number of arguments: expected {}, got {}",
+            function.parameters, count
+        ))),
+    }
+}
+
+// The values of the loop variables for the item at `position` of `iterable`, unless it has no more.
+fn iteration_values(iterable: &Object, position: usize, variables: usize) -> Result<Option<Vec<Object>>, Object> {
+    let values = match (iterable, variables) {
+        (Object::Array(elements), 1) => elements.get(position).map(|element| vec![element.clone()]),
+        (Object::Array(elements), _) => {
+            elements.get(position).map(|element| vec![Object::Integer(position as i32), element.clone()])
+        }
+        (Object::Hash(pairs), 1) => pairs.get_index(position).map(|(key, _)| vec![key.to_object()]),
+        (Object::Hash(pairs), _) => {
+            pairs.get_index(position).map(|(key, value)| vec![key.to_object(), value.clone()])
+        }
+        (Object::Range(start, end), 1) => {
+            let value = *start as i64 + position as i64;
+            (value < *end as i64).then(|| vec![Object::Integer(value as i32)])
+        }
+        (Object::Range(_, _), _) => {
+            return Err(Object::error(NOT_ITERABLE, String::from("a RANGE yields one loop variable, got 2")))
+        }
+        (iterable, _) => return Err(Object::error(NOT_ITERABLE, format!("not iterable: {}", iterable.type_name()))),
+    };
+    Ok(values)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::compiler::compile;
+    use crate::environment::Environment;
+    use crate::evaluator::evaluate;
+    use crate::syntax_analyzer::parse;
+    use std::alloc::{GlobalAlloc, Layout, System};
+    use std::cell::Cell;
+
+    // Counts the bytes each thread has allocated and not freed, for tests to put a heap limit on
+    // what they run without seeing the tests running next to them.
+    struct ThreadAllocator;
+
+    thread_local! {
+        static HEAP_IN_USE: Cell<isize> = const { Cell::new(0) };
+    }
+
+    fn count(bytes: isize) {
+        let _ = HEAP_IN_USE.try_with(|in_use| in_use.set(in_use.get() + bytes));
+    }
+
+    unsafe impl GlobalAlloc for ThreadAllocator {
+        unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+            count(layout.size() as isize);
+            System.alloc(layout)
+        }
+
+        unsafe fn dealloc(&self, pointer: *mut u8, layout: Layout) {
+            count(-(layout.size() as isize));
+            System.dealloc(pointer, layout)
+        }
+    }
+
+    #[global_allocator]
+    static ALLOCATOR: ThreadAllocator = ThreadAllocator;
+
+    // An evaluator that stops once the thread has `max_bytes` more in use than when it was made.
+    fn with_heap_limit(max_bytes: usize) -> Evaluator {
+        let start = HEAP_IN_USE.with(Cell::get);
+        Evaluator::new().with_heap_limit(max_bytes, move || HEAP_IN_USE.with(Cell::get).saturating_sub(start) as usize)
+    }
+
+    fn run(input: &str) -> String {
+        run_with(&mut Evaluator::new(), input)
+    }
+
+    fn run_with(evaluator: &mut Evaluator, input: &str) -> String {
+        let bytecode = compile(&parse(input).unwrap()).unwrap();
+        Vm::new(bytecode).run(evaluator).to_string()
+    }
+
+    // The programs both run, which have to give the same result on each.
+    const PROGRAMS: [&str; 88] = [
+        "1 + 2 * 3 - 4 / 2",
+        "-(5 + 5) * 2.5",
+        "1.5 / 0.0",
+        "7 / 0",
+        "2147483647 + 1",
+        "1 + true",
+        "\"mon\" + \"key\"",
+        "\"a\" - \"b\"",
+        "!5 == !!false",
+        "1 < 2 == (2 > 1)",
+        "[1, 2] == [1, 2]",
+        "1 == 1.0",
+        "[1, [2, 3]][1][0]",
+        "[1, 2][5]",
+        "{\"a\": 1, true: 2}[true]",
+        "{[1]: 2}",
+        "x",
+        "let x = 2; let y = x * x; y + x",
+        "let x = 1; x = x + 1; x",
+        "y = 1",
+        "0..3",
+        "let add = fn(a, b) { a + b }; add(1, 2)",
+        "let f = fn(x) { let y = x * 2; y = y + 1; y }; f(3) + f(4)",
+        "let f = fn() { return 1; 2 }; f()",
+        "let g = fn() { h() }; let h = fn() { 3 }; g()",
+        "fn(x) { x }",
+        "type(fn() {})",
+        "fn(a) { a }(1, 2)",
+        "5()",
+        "len(\"four\") + len([1])",
+        "map([1, 2], fn(x) { x * 2 })",
+        "map([1, 2], fn(x) { x + true })",
+        "len = 1",
+        "if (1 < 2) { 10 } else { 20 }",
+        "if (false) { 10 }",
+        "if (0) { 1 } else { let x = 2; x }; x",
+        "let i = 0; let s = 0; while (i < 5) { i = i + 1; if (i == 2) { continue; } s = s + i }; s",
+        "let i = 0; while (true) { i = i + 1; if (i > 3) { break; } }; i",
+        "let s = 0; for i in 0..5 { if (i == 3) { break; } s = s + i }; s",
+        "let s = \"\"; for i, v in [\"a\", \"b\"] { s = s + v + str(i) }; s",
+        "let s = \"\"; for k, v in {\"a\": 1, \"b\": 2} { s = s + k + str(v) }; s",
+        "let s = 0; for k in {1: 2, 3: 4} { s = s * 10 + k }; s",
+        "for i, v in 0..2 {}",
+        "for x in 5 {}",
+        "let x = 1; for i in 0..2 { let x = i }; x",
+        "for i in 0..2 { let y = i }; y",
+        "let f = fn(a) { let t = 0; for x in a { if (x > 2) { return x; } t = t + x }; t }; [f([1, 2]), f([1, 5, 2])]",
+        "let adder = fn(a) { fn(b) { a + b } }; adder(1)(2)",
+        "let f = fn(a) { fn(b) { fn(c) { a + b + c } } }; f(1)(2)(3)",
+        "let counter = fn() { let c = 0; fn() { c = c + 1; c } }; let next = counter(); next(); next(); next()",
+        "let f = fn() { let x = 1; let get = fn() { x }; x = 2; get() }; f()",
+        "let f = fn() { let g = fn() { h() }; let h = fn() { 5 }; g() }; f()",
+        "let f = fn() { let fact = fn(n) { if (n < 2) { 1 } else { n * fact(n - 1) } }; fact(5) }; f()",
+        "let a = 0; let b = 0; for i in 0..3 { b = a; a = fn() { i } }; [a(), b()]",
+        "let a = 0; let b = 0; for i in 0..3 { let j = i * 2; b = a; a = fn() { j } }; [a(), b()]",
+        "let f = fn() { let a = 0; let b = 0; for i in 0..2 { b = a; a = fn() { i = i + 9 } }; [b(), b(), a()] }; f()",
+        "let x = 1; let f = fn() { x }; x = 2; f()",
+        "let f = fn(x) { let g = fn() { x = x + 1 }; g(); g(); x }; f(1)",
+        "let make = fn() { let n = 0; [fn() { n = n + 1 }, fn() { n }] }; let p = make(); p[0](); p[0](); p[1]()",
+        "let f = fn() { let s = 0; let i = 0; while (i < 3) { let g = fn() { i }; s = s + g(); i = i + 1 }; s }; f()",
+        "let a = 0; let b = 0; for i in 0..2 { let g = fn() { h() }; let h = fn() { i }; b = a; a = g }; [a(), b()]",
+        "let x = 0; let fs = map([1, 2], fn(n) { fn() { x = x + n; x } }); fs[0](); fs[1]()",
+        "let f = fn(a) { fn() { a } }; f(1) == f(1)",
+        "let f = fn(a) { fn() { a } }; let g = f(1); g == g",
+        "let f = fn() { for i in 0..3 { for j in 0..3 { if (j == 1) { break; } if (i == 2) { return i; } } } }; f()",
+        "let count = fn(n, acc) { if (n == 0) { acc } else { count(n - 1, acc + 1) } }; count(100000, 0)",
+        "let f = fn(n) { if (n > 0) { return g(n - 1); } else { n } }; let g = fn(n) { f(n) }; f(5000)",
+        "let f = fn(n) { if (n > 0) { f(n - 1) } else { len(n) } }; f(50)",
+        "[1 && 2, null && 1, false && x, 0 || 1, null || false, false || null, \"a\" || x, true && false || 3]",
+        "let n = 0; let f = fn(v) { n = n + 1; v }; [false && f(1), true || f(2), true && f(3), f(null) || f(4), n]",
+        "(1 + true) || 2",
+        "let f = fn(xs) { let s = 0; for x in xs { if (x > 1 && x < 3 || x == 0) { s = s + x } }; s }; f([0, 1, 2, 3])",
+        "try { 1 / 0 } catch (e) { e }",
+        "try { 1 } catch (e) { 2 }",
+        "let f = fn() { try { return 1; } catch (e) { 2 }; 3 }; f()",
+        "let x = 0; try { x = 1; x + true; x = 2 } catch (e) { x = x + 10 }; x",
+        "try { 1 / 0 } catch (e) { e + 1 }",
+        "try { 1 / 0 } catch (e) { 1 }; e",
+        "try { try { 1 / 0 } catch (e) { e + 1 } } catch (e) { e }",
+        "let f = fn(n) { if (n == 0) { 1 / 0 } else { [f(n - 1)] } }; [1, try { f(3) } catch (e) { e }, 2]",
+        "let f = fn(n) { if (n == 0) { 1 / 0 } else { [f(n - 1)] } }; for i in 0..200 { try { f(9) } catch (e) {} }; 1",
+        "let g = fn(n) { if (n == 0) { 1 / 0 } else { g(n - 1) } }; try { g(5) } catch (e) { e }",
+        "let s = 0; for x in 0..4 { try { if (x == 1) { continue; } if (x > 2) { break } s = s + x } catch (e) {} }; s",
+        "for x in 0..2 { try { break; } catch (e) { } }; 1 + true",
+        "let f = fn() { try { return 1; } catch (e) { 2 } }; f(); 1 + true",
+        "let a = null; for x in 0..2 { try { error(str(x)) } catch (e) { let y = x; a = a || fn() { [e, y] } } }; a()",
+        "try { map([1, 0], fn(x) { 1 / x }) } catch (e) { e }",
+        "map([1, 0], fn(x) { try { 1 / x } catch (e) { 0 } })",
+    ];
+
+    #[test]
+    fn it_runs_programs_like_the_evaluator() {
+        for program in PROGRAMS {
+            let env = Rc::new(RefCell::new(Environment::new()));
+            let expected = evaluate(&parse(program).unwrap(), &env).to_string();
+            assert_eq!(run(program), expected, "{}", program);
+        }
+    }
+
+    #[test]
+    fn it_frees_closures_that_capture_themselves_like_the_evaluator() {
+        let program = "let make = fn() { let g = fn() { g }; g }; let i = 0; while (i < 50000) { make(); i = i + 1 }; \
+                       i";
+        let env = Rc::new(RefCell::new(Environment::new()));
+        let expected = with_heap_limit(4 << 20).eval_program(&parse(program).unwrap(), &env).to_string();
+        assert_eq!(expected, "50000");
+        assert_eq!(run_with(&mut with_heap_limit(4 << 20), program), expected);
+    }
+
+    #[test]
+    fn it_traces_errors_like_the_evaluator() {
+        let programs = [
+            "let f = fn() { 1 + true }; let g = fn() { [f()] }; 1 + g()",
+            "let f = fn(x) { x }; f(len(1))",
+            "map([1], fn(x) { x() })",
+            "let f = fn() { 1 }; f(2)",
+            "let f = fn() { g }; let g = f(); g",
+            "let f = fn(n) { [f(n + 1)] }; f(0)",
+            "let x = if (true) { let y = 1; y } else { 2 } + true",
+            "let f = fn() {\n  let a = 1;\n  if (a > 0) { a }\n  a + true\n};\nputs(1)\nf()",
+            "let s = 0;\nfor x in [1, \"a\"] {\n  s = s + x\n}",
+            "let f = fn(n) { if (n > 0) { f(n - 1) } else { 1 + true } }; let g = fn() { [f(3)] }; g()",
+            "let f = fn(n) { if (n > 0) { f(n - 1) } else { n(1) } }; let g = fn() { f(3) }; g()",
+            "let f = fn(n) { if (n > 0) { f(n - 1) } else { g(1) } }; let g = fn() { 1 }; f(2)",
+            "let f = fn(n) { if (n > 0) { return f(n - 1); } n }; f(30)",
+            "let f = fn() { 1 + true }; try { f() } catch (e) { 0 }; let g = fn() { f() + 1 }; g()",
+            "let f = fn() { try { 1 / 0 } catch (e) { e + 1 } }; [f()]",
+        ];
+        for program in PROGRAMS.iter().chain(&programs) {
+            let mut evaluator = Evaluator::new().with_max_depth(20);
+            evaluator.eval_program(&parse(program).unwrap(), &Rc::new(RefCell::new(Environment::new())));
+            let (expected, span) = (evaluator.stack_trace().to_vec(), evaluator.error_span());
+            run_with(&mut evaluator, program);
+            assert_eq!(evaluator.stack_trace(), expected, "{}", program);
+            assert_eq!(evaluator.error_span(), span, "{}", program);
+        }
+        // A value over the memory limit is the error of the code it is the value of, even when a
+        // builtin made it.
+        let programs = [
+            "try { map(0..100000, fn(x) { x }) } catch (e) {}",
+            "let f = fn() { map(0..100, fn(x) { x }) };\nlet g = fn() { let a = f(); a };\ng()",
+            "let f = fn() { let a = map(0..100, fn(x) { x }); a };\nf()",
+            "let f = fn(x) { map(0..20, fn(y) { y }) };\nmap(0..4, f)",
+        ];
+        for program in programs {
+            let mut evaluator = Evaluator::new().with_memory_limit(10);
+            let result = evaluator.eval_program(&parse(program).unwrap(), &Rc::new(RefCell::new(Environment::new())));
+            assert!(result.to_string().starts_with("ERROR: memory limit exceeded"), "{}", program);
+            let (expected, span) = (evaluator.stack_trace().to_vec(), evaluator.error_span());
+            run_with(&mut evaluator, program);
+            assert_eq!(evaluator.stack_trace(), expected, "{}", program);
+            assert_eq!(evaluator.error_span(), span, "{}", program);
+        }
+        let mut evaluator = Evaluator::new();
+        run_with(&mut evaluator, "let f = fn() { 1 + true }; let g = fn() { [f()] }; 1 + g()");
+        let trace: Vec<String> = evaluator.stack_trace().iter().map(|frame| frame.to_string()).collect();
+        assert_eq!(trace, ["at f (1:44)", "at g (1:56)"]);
+        assert_eq!(evaluator.error_span(), Some(Span { line: 1, column: 16 }));
+        run_with(&mut evaluator, "let s = 0;\nfor x in [1, \"a\"] {\n  s = s + x\n}");
+        assert_eq!(evaluator.error_span(), Some(Span { line: 3, column: 3 }));
+        run_with(&mut evaluator, "1");
+        assert!(evaluator.stack_trace().is_empty());
+        assert_eq!(evaluator.error_span(), None);
+    }
+
+    #[test]
+    fn it_runs_expressions() {
+        assert_eq!(run("1 + 2 * 3"), "7");
+        assert_eq!(run("{\"a\": [1, 2.5]}"), "{\"a\": [1, 2.5]}");
+        assert_eq!(run("let a = 1; a = 2; let b = a"), "null");
+        assert_eq!(run(""), "null");
+        // What the expression around a `continue` or `break` pushed is taken off the stack.
+        assert_eq!(run("let s = 0; for x in [1, 2, 3] { s = s + [x, if (x == 2) { continue; }][0] }; s"), "4");
+        assert_eq!(run("for x in [1, 2] { puts(x, if (true) { break; }) }"), "null");
+    }
+
+    #[test]
+    fn it_keeps_to_the_evaluators_limits() {
+        let mut evaluator = Evaluator::new().with_step_budget(5);
+        assert_eq!(run_with(&mut evaluator, "1 + 2 + 3 + 4"), "ERROR: evaluation budget exceeded");
+        let mut evaluator = Evaluator::new().with_memory_limit(3);
+        assert_eq!(
+            run_with(&mut evaluator, "[1, 2, 3, 4]"),
+            "ERROR: memory limit exceeded: ARRAY of 4 elements (at most 3)"
+        );
+        let mut evaluator = Evaluator::new().with_step_budget(1000);
+        assert_eq!(run_with(&mut evaluator, "while (true) {}"), "ERROR: evaluation budget exceeded");
+        let mut evaluator = Evaluator::new().with_max_depth(100);
+        assert_eq!(
+            run_with(&mut evaluator, "let f = fn(n) { 1 + f(n + 1) }; f(0)"),
+            "ERROR: maximum recursion depth exceeded"
+        );
+        assert_eq!(run_with(&mut evaluator, "let f = fn() { 1 }; f() + f()"), "2");
+        // Running into a limit stops the program, even in a `try`.
+        assert_eq!(
+            run_with(&mut evaluator, "let f = fn(n) { 1 + f(n) }; try { f(0) } catch (e) { 0 }"),
+            "ERROR: maximum recursion depth exceeded"
+        );
+        let mut evaluator = Evaluator::new().with_step_budget(1000);
+        assert_eq!(
+            run_with(&mut evaluator, "while (true) { try { while (true) { } } catch (e) { } }"),
+            "ERROR: evaluation budget exceeded"
+        );
+    }
+}
@@ -0,0 +1,80 @@
+use std::fs;
+use std::path::PathBuf;
+use std::time::{Duration, SystemTime};
+
+// When each watched file was last modified and how long it was, so `monkey watch` can tell that
+// one was changed, created or removed since. The length catches a change made within the same tick
+// of a file system's clock.
+pub struct Watcher {
+    files: Vec<PathBuf>,
+    states: Vec<Option<(SystemTime, u64)>>,
+}
+
+impl Watcher {
+    pub fn new(files: Vec<PathBuf>) -> Self {
+        let states = states(&files);
+        Watcher { files, states }
+    }
+
+    pub fn files(&self) -> &[PathBuf] {
+        &self.files
+    }
+
+    pub fn has_changed(&self) -> bool {
+        states(&self.files) != self.states
+    }
+
+    // Checks the files every `interval` and returns once one of them changed.
+    pub fn wait(&self, interval: Duration) {
+        while !self.has_changed() {
+            std::thread::sleep(interval);
+        }
+    }
+}
+
+fn states(files: &[PathBuf]) -> Vec<Option<(SystemTime, u64)>> {
+    files
+        .iter()
+        .map(|file| fs::metadata(file).and_then(|metadata| Ok((metadata.modified()?, metadata.len()))).ok())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_notices_files_being_changed_created_and_removed() {
+        let directory = std::env::temp_dir().join(format!("monkey_watch_test_{}", std::process::id()));
+        fs::create_dir_all(&directory).unwrap();
+        let (script, module) = (directory.join("script.monkey"), directory.join("module.monkey"));
+        fs::write(&script, "1").unwrap();
+
+        let watcher = Watcher::new(vec![script.clone(), module.clone()]);
+        assert!(!watcher.has_changed());
+        fs::write(&script, "1 + 1").unwrap();
+        assert!(watcher.has_changed());
+
+        let watcher = Watcher::new(vec![script.clone(), module.clone()]);
+        fs::write(&module, "let x = 1;").unwrap();
+        assert!(watcher.has_changed());
+
+        let watcher = Watcher::new(vec![script.clone(), module.clone()]);
+        fs::remove_file(&module).unwrap();
+        assert!(watcher.has_changed());
+        watcher.wait(Duration::from_millis(1));
+
+        fs::remove_dir_all(&directory).unwrap();
+    }
+
+    #[test]
+    fn it_ignores_files_left_as_they_were() {
+        let file = std::env::temp_dir().join(format!("monkey_watch_same_test_{}.monkey", std::process::id()));
+        fs::write(&file, "1").unwrap();
+        let watcher = Watcher::new(vec![file.clone()]);
+        let _ = fs::read_to_string(&file).unwrap();
+        assert!(!watcher.has_changed());
+        assert_eq!(watcher.files(), std::slice::from_ref(&file));
+        fs::remove_file(&file).unwrap();
+    }
+}